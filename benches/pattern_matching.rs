@@ -1,6 +1,7 @@
 use criterion::{BenchmarkId, Criterion, black_box, criterion_group, criterion_main};
 use glob::Pattern;
 use std::path::Path;
+use vibewatch::PatternFilter;
 
 fn pattern_matching_benchmark(c: &mut Criterion) {
     let mut group = c.benchmark_group("pattern_matching");
@@ -182,10 +183,44 @@ fn glob_alternatives_benchmark(c: &mut Criterion) {
     group.finish();
 }
 
+fn reload_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("reload");
+
+    // A long pattern list, as in a large --exclude-from file
+    let include: Vec<String> = (0..100)
+        .map(|i| format!("src/module_{i}/**/*.rs"))
+        .collect();
+    let exclude: Vec<String> = (0..100)
+        .map(|i| format!("**/generated_{i}/**/*.{{rs,toml}}"))
+        .collect();
+
+    // Compile every pattern from scratch, as a reload did before caching
+    group.bench_function("full_rebuild", |b| {
+        b.iter(|| {
+            let filter =
+                PatternFilter::new(black_box(include.clone()), black_box(exclude.clone())).unwrap();
+            black_box(filter);
+        });
+    });
+
+    // Reload the same list, reusing the compiled patterns
+    let mut filter = PatternFilter::new(include.clone(), exclude.clone()).unwrap();
+    group.bench_function("cached_rebuild", |b| {
+        b.iter(|| {
+            filter
+                .rebuild(black_box(include.clone()), black_box(exclude.clone()))
+                .unwrap();
+        });
+    });
+
+    group.finish();
+}
+
 criterion_group!(
     benches,
     pattern_matching_benchmark,
     exclude_pattern_benchmark,
-    glob_alternatives_benchmark
+    glob_alternatives_benchmark,
+    reload_benchmark
 );
 criterion_main!(benches);
@@ -0,0 +1,699 @@
+use crate::debounce::{DebounceMode, DebounceWindows};
+use crate::output::{ColorChoice, Output};
+use crate::watch_limit::WatchLimitMode;
+use crate::watcher::{CommandConfig, EntryFilter, EventKinds, FileWatcher, WorkingDir};
+use anyhow::{Context, Result};
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Default debounce delay, matching the CLI's `--debounce` default
+const DEFAULT_DEBOUNCE_MS: u64 = 100;
+
+/// Chainable construction of a [`FileWatcher`]
+///
+/// Patterns and settings are only validated by [`WatcherBuilder::build`], so
+/// every mistake surfaces in one place:
+///
+/// ```no_run
+/// # async fn run() -> anyhow::Result<()> {
+/// let mut watcher = vibewatch::WatcherBuilder::new("src")
+///     .include("*.rs")
+///     .exclude("target/**")
+///     .on_modify("cargo check")
+///     .debounce(250)
+///     .build()?;
+/// watcher.start_watching().await
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct WatcherBuilder {
+    directory: PathBuf,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    commands: CommandConfig,
+    debounce_ms: u64,
+    debounce_create: Option<u64>,
+    debounce_modify: Option<u64>,
+    debounce_delete: Option<u64>,
+    max_debounce: Option<u64>,
+    debounce_mode: DebounceMode,
+    debounce_per_command_group: bool,
+    batch: bool,
+    run_on_start: bool,
+    stdin_control: bool,
+    restart: bool,
+    fail_fast: bool,
+    once: bool,
+    gitignore: bool,
+    ignore_files: Vec<PathBuf>,
+    match_absolute: bool,
+    match_basename: bool,
+    poll_interval: Option<Duration>,
+    watch_limit: WatchLimitMode,
+    channel_capacity: Option<usize>,
+    recursive: bool,
+    canonicalize: bool,
+    follow_symlinks: bool,
+    entries: EntryFilter,
+    event_kinds: EventKinds,
+    skip_unchanged: bool,
+    ignore_dir_modify: bool,
+    min_size: Option<u64>,
+    strict_utf8: bool,
+    sort_paths: bool,
+    wait_for_close: bool,
+    retry_watch: bool,
+    max_depth: Option<usize>,
+    exclude_dirs: Vec<String>,
+    quiet: bool,
+    /// How often to print `--stats` counters, if at all
+    stats_interval: Option<Duration>,
+    summary_on_exit: bool,
+    /// How often to print a "still watching" line, if at all
+    heartbeat: Option<Duration>,
+    result_log: Option<PathBuf>,
+    exclude_writes_for: Option<Duration>,
+    min_interval: Option<Duration>,
+    exec_delay: Option<Duration>,
+    coalesce_global: Option<Duration>,
+    max_output_lines: Option<usize>,
+    output: Output,
+    /// Prefix for every printed line, empty for none
+    name: String,
+    color: ColorChoice,
+    /// Template for the text line printed for each file change
+    format: Option<String>,
+    /// Set when waiting for commands on shutdown, with the optional time limit
+    wait_on_exit: Option<Option<Duration>>,
+}
+
+impl WatcherBuilder {
+    /// Start building a watcher for `directory` (watched recursively by default)
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            commands: CommandConfig::default(),
+            debounce_ms: DEFAULT_DEBOUNCE_MS,
+            debounce_create: None,
+            debounce_modify: None,
+            debounce_delete: None,
+            max_debounce: None,
+            debounce_mode: DebounceMode::default(),
+            debounce_per_command_group: false,
+            batch: false,
+            run_on_start: false,
+            stdin_control: false,
+            restart: false,
+            fail_fast: false,
+            once: false,
+            gitignore: false,
+            ignore_files: Vec::new(),
+            match_absolute: false,
+            match_basename: false,
+            poll_interval: None,
+            watch_limit: WatchLimitMode::default(),
+            channel_capacity: None,
+            recursive: true,
+            canonicalize: true,
+            follow_symlinks: false,
+            entries: EntryFilter::default(),
+            event_kinds: EventKinds::default(),
+            skip_unchanged: false,
+            ignore_dir_modify: false,
+            min_size: None,
+            strict_utf8: false,
+            sort_paths: false,
+            wait_for_close: false,
+            retry_watch: false,
+            max_depth: None,
+            exclude_dirs: Vec::new(),
+            quiet: false,
+            stats_interval: None,
+            summary_on_exit: false,
+            heartbeat: None,
+            result_log: None,
+            exclude_writes_for: None,
+            min_interval: None,
+            exec_delay: None,
+            coalesce_global: None,
+            max_output_lines: None,
+            output: Output::default(),
+            name: String::new(),
+            color: ColorChoice::default(),
+            format: None,
+            wait_on_exit: None,
+        }
+    }
+
+    /// Only watch files matching this glob pattern (can be called repeatedly)
+    pub fn include(mut self, pattern: impl Into<String>) -> Self {
+        self.include.push(pattern.into());
+        self
+    }
+
+    /// Ignore files matching this glob pattern (can be called repeatedly)
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    /// Also ignore files ignored by .gitignore files in the directory
+    pub fn gitignore(mut self, gitignore: bool) -> Self {
+        self.gitignore = gitignore;
+        self
+    }
+
+    /// Also ignore files matching the rules of this gitignore-syntax file, like
+    /// `.ignore` (can be called repeatedly)
+    pub fn ignore_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ignore_files.push(path.into());
+        self
+    }
+
+    /// Match include/exclude patterns against file names instead of relative paths
+    pub fn match_basename(mut self, match_basename: bool) -> Self {
+        self.match_basename = match_basename;
+        self
+    }
+
+    /// Match include/exclude patterns against absolute paths instead of relative ones
+    pub fn match_absolute(mut self, match_absolute: bool) -> Self {
+        self.match_absolute = match_absolute;
+        self
+    }
+
+    /// Add a command to run when files are created (can be called repeatedly)
+    pub fn on_create(mut self, command: impl Into<String>) -> Self {
+        self.commands.on_create.push(command.into());
+        self
+    }
+
+    /// Add a command to run when files are modified (can be called repeatedly)
+    pub fn on_modify(mut self, command: impl Into<String>) -> Self {
+        self.commands.on_modify.push(command.into());
+        self
+    }
+
+    /// Add a command to run when files are deleted (can be called repeatedly)
+    pub fn on_delete(mut self, command: impl Into<String>) -> Self {
+        self.commands.on_delete.push(command.into());
+        self
+    }
+
+    /// Add a command to run for any event without a more specific command
+    pub fn on_change(mut self, command: impl Into<String>) -> Self {
+        self.commands.on_change.push(command.into());
+        self
+    }
+
+    /// Run `command` instead of the on_* commands for files matching `pattern`
+    ///
+    /// Routes are tried in the order they are added; the first match wins.
+    pub fn route(mut self, pattern: impl Into<String>, command: impl Into<String>) -> Self {
+        self.commands.routes.push((pattern.into(), command.into()));
+        self
+    }
+
+    /// Directory commands run in (defaults to the watched directory)
+    pub fn working_dir(mut self, working_dir: WorkingDir) -> Self {
+        self.commands.working_dir = Some(working_dir);
+        self
+    }
+
+    /// Replace the whole command configuration, for settings without a shortcut
+    pub fn commands(mut self, commands: CommandConfig) -> Self {
+        self.commands = commands;
+        self
+    }
+
+    /// Ignore events while commands run and for `window` after they finish
+    pub fn exclude_writes_for(mut self, window: Duration) -> Self {
+        self.exclude_writes_for = Some(window);
+        self
+    }
+
+    /// Run each command at most once per `interval`
+    pub fn min_interval(mut self, interval: Duration) -> Self {
+        self.min_interval = Some(interval);
+        self
+    }
+
+    /// Wait `delay` between handling a change and starting each of its commands
+    pub fn exec_delay(mut self, delay: Duration) -> Self {
+        self.exec_delay = Some(delay);
+        self
+    }
+
+    /// Run each command without path placeholders at most once per `window`
+    pub fn coalesce_global(mut self, window: Duration) -> Self {
+        self.coalesce_global = Some(window);
+        self
+    }
+
+    /// Print at most `max_lines` lines of each command run's output
+    pub fn max_output_lines(mut self, max_lines: usize) -> Self {
+        self.max_output_lines = Some(max_lines);
+        self
+    }
+
+    /// Debounce delay in milliseconds (0 disables debouncing)
+    pub fn debounce(mut self, debounce_ms: u64) -> Self {
+        self.debounce_ms = debounce_ms;
+        self
+    }
+
+    /// Debounce delay for create events, overriding `debounce`
+    pub fn debounce_create(mut self, debounce_ms: u64) -> Self {
+        self.debounce_create = Some(debounce_ms);
+        self
+    }
+
+    /// Debounce delay for modify events, overriding `debounce`
+    pub fn debounce_modify(mut self, debounce_ms: u64) -> Self {
+        self.debounce_modify = Some(debounce_ms);
+        self
+    }
+
+    /// Debounce delay for delete events (deletes aren't debounced by default)
+    pub fn debounce_delete(mut self, debounce_ms: u64) -> Self {
+        self.debounce_delete = Some(debounce_ms);
+        self
+    }
+
+    /// Fire a file's events after at most this many milliseconds, even if
+    /// it keeps changing
+    pub fn max_debounce(mut self, debounce_ms: u64) -> Self {
+        self.max_debounce = Some(debounce_ms);
+        self
+    }
+
+    /// Hold file changes until the file stops being written
+    pub fn wait_for_close(mut self, wait_for_close: bool) -> Self {
+        self.wait_for_close = wait_for_close;
+        self
+    }
+
+    /// Keep commands running as long-lived processes, restarting them on each trigger
+    pub fn restart(mut self, restart: bool) -> Self {
+        self.restart = restart;
+        self
+    }
+
+    /// Read `run`, `stats` and `quit` commands from stdin while watching
+    pub fn stdin_control(mut self, stdin_control: bool) -> Self {
+        self.stdin_control = stdin_control;
+        self
+    }
+
+    /// Wait for the watched directory to come back if it is deleted, instead of stopping
+    pub fn retry_watch(mut self, retry_watch: bool) -> Self {
+        self.retry_watch = retry_watch;
+        self
+    }
+
+    /// When debounced commands fire
+    pub fn debounce_mode(mut self, mode: DebounceMode) -> Self {
+        self.debounce_mode = mode;
+        self
+    }
+
+    /// Debounce each path separately for every command it resolves to
+    pub fn debounce_per_command_group(mut self, per_command_group: bool) -> Self {
+        self.debounce_per_command_group = per_command_group;
+        self
+    }
+
+    /// Run each command once per settled burst of changes
+    pub fn batch(mut self, batch: bool) -> Self {
+        self.batch = batch;
+        self
+    }
+
+    /// Run the on_change command once as soon as watching starts
+    pub fn run_on_start(mut self, run_on_start: bool) -> Self {
+        self.run_on_start = run_on_start;
+        self
+    }
+
+    /// Stop watching with an error as soon as any command fails
+    pub fn fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+
+    /// Stop after the first matching file change, once its commands have finished
+    pub fn once(mut self, once: bool) -> Self {
+        self.once = once;
+        self
+    }
+
+    /// Poll the directory every `interval` instead of using native OS events
+    pub fn poll(mut self, interval: Duration) -> Self {
+        self.poll_interval = Some(interval);
+        self
+    }
+
+    /// What to do when the tree likely exceeds the inotify watch limit (Linux only)
+    pub fn watch_limit(mut self, mode: WatchLimitMode) -> Self {
+        self.watch_limit = mode;
+        self
+    }
+
+    /// Buffer at most `capacity` OS events, dropping new ones while it is full
+    pub fn channel_capacity(mut self, capacity: usize) -> Self {
+        self.channel_capacity = Some(capacity);
+        self
+    }
+
+    /// Watch subdirectories too (the default), or only the directory's direct children
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /// Show paths below the directory with symlinks resolved (the default), or
+    /// as the directory was given
+    pub fn canonicalize(mut self, canonicalize: bool) -> Self {
+        self.canonicalize = canonicalize;
+        self
+    }
+
+    /// Follow symlinks into their targets (off by default)
+    pub fn follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// Only report changes to files, or only to directories (both by default)
+    pub fn entries(mut self, entries: EntryFilter) -> Self {
+        self.entries = entries;
+        self
+    }
+
+    /// Only process these kinds of events (all but `access` by default)
+    pub fn event_kinds(mut self, event_kinds: EventKinds) -> Self {
+        self.event_kinds = event_kinds;
+        self
+    }
+
+    /// Ignore modifications that leave a file's content unchanged
+    pub fn skip_unchanged(mut self, skip_unchanged: bool) -> Self {
+        self.skip_unchanged = skip_unchanged;
+        self
+    }
+
+    /// Drop modify events on directories, such as a parent's timestamp changing
+    /// when a file inside it is written
+    pub fn ignore_dir_modify(mut self, ignore_dir_modify: bool) -> Self {
+        self.ignore_dir_modify = ignore_dir_modify;
+        self
+    }
+
+    /// Skip files smaller than `bytes`, checked before the patterns
+    pub fn min_size(mut self, bytes: u64) -> Self {
+        self.min_size = Some(bytes);
+        self
+    }
+
+    /// Skip events for paths that aren't valid UTF-8 instead of only warning about them
+    pub fn strict_utf8(mut self, strict_utf8: bool) -> Self {
+        self.strict_utf8 = strict_utf8;
+        self
+    }
+
+    /// Handle the paths of a multi-path event in sorted order
+    pub fn sort_paths(mut self, sort_paths: bool) -> Self {
+        self.sort_paths = sort_paths;
+        self
+    }
+
+    /// Never watch directories with this name, at any depth (can be called repeatedly)
+    pub fn exclude_dir(mut self, name: impl Into<String>) -> Self {
+        self.exclude_dirs.push(name.into());
+        self
+    }
+
+    /// Ignore files more than `max_depth` directory levels below the directory
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Don't print command output, file change lines or status messages
+    pub fn quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// Print event and command counters every `interval` and on shutdown
+    pub fn stats(mut self, interval: Duration) -> Self {
+        self.stats_interval = Some(interval);
+        self
+    }
+
+    /// Print a summary of changes, commands run and time spent running them on shutdown
+    pub fn summary_on_exit(mut self, summary_on_exit: bool) -> Self {
+        self.summary_on_exit = summary_on_exit;
+        self
+    }
+
+    /// Print a "still watching" line every `interval`; zero disables it
+    pub fn heartbeat(mut self, interval: Duration) -> Self {
+        self.heartbeat = (!interval.is_zero()).then_some(interval);
+        self
+    }
+
+    /// Append a JSON line for every finished command to `path`
+    pub fn result_log(mut self, path: impl Into<PathBuf>) -> Self {
+        self.result_log = Some(path.into());
+        self
+    }
+
+    /// How file events and command results are reported
+    pub fn output(mut self, output: Output) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Prefix every printed line with `[name]`, to tell instances apart
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Print each file change as `template` instead of the default line, e.g.
+    /// `"{event_type} {relative_path}"`
+    pub fn format(mut self, template: impl Into<String>) -> Self {
+        self.format = Some(template.into());
+        self
+    }
+
+    /// When text event lines are colored
+    pub fn color(mut self, color: ColorChoice) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// On shutdown, wait for running commands, giving up after `timeout` if set
+    pub fn wait_on_exit(mut self, timeout: Option<Duration>) -> Self {
+        self.wait_on_exit = Some(timeout);
+        self
+    }
+
+    /// Validate the settings and create the watcher
+    ///
+    /// Fails if the directory doesn't exist, a pattern or the shell is invalid,
+    /// a .gitignore or ignore file can't be read or the result log can't be opened.
+    pub fn build(self) -> Result<FileWatcher> {
+        let mut watcher = FileWatcher::new(
+            self.directory,
+            self.include,
+            self.exclude,
+            self.commands,
+            self.debounce_ms,
+            false,
+            self.quiet,
+        )?
+        .with_debounce_windows(DebounceWindows {
+            default: Duration::from_millis(self.debounce_ms),
+            create: self.debounce_create.map(Duration::from_millis),
+            modify: self.debounce_modify.map(Duration::from_millis),
+            delete: self.debounce_delete.map(Duration::from_millis),
+            max: self.max_debounce.map(Duration::from_millis),
+        })
+        .with_debounce_mode(self.debounce_mode)
+        .with_debounce_per_command_group(self.debounce_per_command_group)
+        .with_batch(self.batch)
+        .with_run_on_start(self.run_on_start)
+        .with_fail_fast(self.fail_fast)
+        .with_recursive(self.recursive)
+        .with_canonicalize(self.canonicalize)
+        .with_follow_symlinks(self.follow_symlinks)
+        .with_entries(self.entries)
+        .with_event_kinds(self.event_kinds)
+        .with_skip_unchanged(self.skip_unchanged)
+        .with_ignore_dir_modify(self.ignore_dir_modify)
+        .with_strict_utf8(self.strict_utf8)
+        .with_sort_paths(self.sort_paths)
+        .with_wait_for_close(self.wait_for_close)
+        .with_retry_watch(self.retry_watch)
+        .with_stdin_control(self.stdin_control)
+        .with_restart(self.restart)
+        .with_summary_on_exit(self.summary_on_exit)
+        .with_output(self.output)
+        .with_name(&self.name)
+        .with_color(self.color);
+
+        if let Some(format) = self.format {
+            watcher = watcher.with_format(format);
+        }
+
+        if let Some(timeout) = self.wait_on_exit {
+            watcher = watcher.with_wait_on_exit(timeout);
+        }
+        watcher = watcher.with_once(self.once);
+
+        if let Some(max_depth) = self.max_depth {
+            watcher = watcher.with_max_depth(max_depth);
+        }
+
+        if let Some(bytes) = self.min_size {
+            watcher = watcher.with_min_size(bytes);
+        }
+
+        if !self.exclude_dirs.is_empty() {
+            watcher = watcher.with_excluded_dirs(self.exclude_dirs);
+        }
+
+        if let Some(window) = self.exclude_writes_for {
+            watcher = watcher.with_exclude_writes_for(window);
+        }
+
+        if let Some(interval) = self.min_interval {
+            watcher = watcher.with_min_interval(interval);
+        }
+        if let Some(delay) = self.exec_delay {
+            watcher = watcher.with_exec_delay(delay);
+        }
+        if let Some(window) = self.coalesce_global {
+            watcher = watcher.with_coalesce_global(window);
+        }
+        if let Some(max_lines) = self.max_output_lines {
+            watcher = watcher.with_max_output_lines(max_lines);
+        }
+
+        if let Some(interval) = self.stats_interval {
+            watcher = watcher.with_stats(interval);
+        }
+
+        if let Some(interval) = self.heartbeat {
+            watcher = watcher.with_heartbeat(interval);
+        }
+
+        if let Some(path) = &self.result_log {
+            watcher = watcher.with_result_log(path)?;
+        }
+
+        if let Some(capacity) = self.channel_capacity {
+            let capacity =
+                NonZeroUsize::new(capacity).context("Channel capacity must be greater than 0")?;
+            watcher = watcher.with_channel_capacity(capacity);
+        }
+
+        if let Some(interval) = self.poll_interval {
+            watcher = watcher.with_poll(interval);
+        }
+        watcher = watcher.with_watch_limit(self.watch_limit);
+
+        if self.match_absolute {
+            watcher = watcher.with_match_absolute();
+        }
+
+        if self.match_basename {
+            watcher = watcher.with_match_basename();
+        }
+
+        let watcher = watcher.with_ignore_files(&self.ignore_files)?;
+        if self.gitignore {
+            watcher.with_gitignore()
+        } else {
+            Ok(watcher)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_builder_builds_watcher() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let watcher = WatcherBuilder::new(temp_dir.path())
+            .include("*.rs")
+            .include("*.toml")
+            .exclude("target/**")
+            .on_modify("cargo check")
+            .debounce(0)
+            .build();
+
+        assert!(watcher.is_ok());
+    }
+
+    #[test]
+    fn test_builder_validates_patterns() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let err = WatcherBuilder::new(temp_dir.path())
+            .include("[invalid")
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("include patterns"));
+
+        let err = WatcherBuilder::new(temp_dir.path())
+            .exclude("[invalid")
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("exclude patterns"));
+    }
+
+    #[test]
+    fn test_builder_rejects_zero_channel_capacity() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let err = WatcherBuilder::new(temp_dir.path())
+            .channel_capacity(0)
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("greater than 0"));
+    }
+
+    #[test]
+    fn test_builder_rejects_missing_directory() {
+        let err = WatcherBuilder::new("/nonexistent/vibewatch/dir")
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("Path does not exist"));
+    }
+
+    #[test]
+    fn test_builder_shortcuts_set_commands() {
+        let builder = WatcherBuilder::new(".")
+            .on_create("a")
+            .on_modify("b")
+            .on_delete("c")
+            .on_change("d")
+            .on_change("e");
+
+        assert_eq!(builder.commands.on_create, ["a"]);
+        assert_eq!(builder.commands.on_modify, ["b"]);
+        assert_eq!(builder.commands.on_delete, ["c"]);
+        assert_eq!(builder.commands.on_change, ["d", "e"]);
+        assert_eq!(builder.debounce_ms, DEFAULT_DEBOUNCE_MS);
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Placeholders whose value depends on the changed file
+const PATH_PLACEHOLDERS: &[&str] = &[
+    "file_path",
+    "relative_path",
+    "absolute_path",
+    "old_path",
+    "new_path",
+    "file_name",
+    "file_stem",
+    "file_ext",
+    "changed_files",
+    "matched_pattern",
+];
+
+/// Whether a command template uses any placeholder describing the changed file
+pub fn uses_path(template: &str) -> bool {
+    PATH_PLACEHOLDERS
+        .iter()
+        .any(|name| template.contains(&format!("{{{}}}", name)))
+}
+
+/// Runs each path-independent command once per window, for `--coalesce-global`
+///
+/// A command like `cargo build` does the same thing whichever file changed,
+/// so a burst touching many files only needs one run. The first run of such
+/// a command opens a window; runs requested until it closes replace each
+/// other, and the latest one is released when the window ends. Commands using
+/// a path placeholder pass straight through.
+///
+/// Whether a template uses a path is worked out once per template. All
+/// methods take the current time so the timing logic can be tested without
+/// sleeping.
+#[derive(Debug)]
+pub struct Coalescer<T> {
+    window: Duration,
+    state: Mutex<State<T>>,
+}
+
+#[derive(Debug)]
+struct State<T> {
+    /// Whether each template seen so far is path-independent
+    path_independent: HashMap<String, bool>,
+    /// Held run per template, with when its window closes
+    pending: HashMap<String, (Instant, T)>,
+}
+
+impl<T> Coalescer<T> {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            state: Mutex::new(State {
+                path_independent: HashMap::new(),
+                pending: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Hold a run of `template` until its window closes, or return it to run now
+    ///
+    /// Only path-independent templates are held.
+    pub fn hold(&self, template: &str, run: T, now: Instant) -> Option<T> {
+        let mut state = self.lock();
+        let path_independent = *state
+            .path_independent
+            .entry(template.to_string())
+            .or_insert_with(|| !uses_path(template));
+        if !path_independent {
+            return Some(run);
+        }
+
+        let due = match state.pending.get(template) {
+            Some((due, _)) => {
+                log::debug!("Coalescing command into the pending run: {}", template);
+                *due
+            }
+            None => now + self.window,
+        };
+        state.pending.insert(template.to_string(), (due, run));
+        None
+    }
+
+    /// Take the runs whose window has closed, in the order their windows close
+    pub fn ready(&self, now: Instant) -> Vec<(String, T)> {
+        let mut state = self.lock();
+        let due: Vec<String> = state
+            .pending
+            .iter()
+            .filter(|(_, (due, _))| *due <= now)
+            .map(|(template, _)| template.clone())
+            .collect();
+
+        let mut ready: Vec<(Instant, String, T)> = due
+            .into_iter()
+            .filter_map(|template| {
+                let (due, run) = state.pending.remove(&template)?;
+                Some((due, template, run))
+            })
+            .collect();
+        ready.sort_by_key(|(due, _, _)| *due);
+        ready
+            .into_iter()
+            .map(|(_, template, run)| (template, run))
+            .collect()
+    }
+
+    /// Take every held run, whether or not its window has closed
+    pub fn drain(&self) -> Vec<(String, T)> {
+        self.lock()
+            .pending
+            .drain()
+            .map(|(template, (_, run))| (template, run))
+            .collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    const WINDOW: Duration = Duration::from_millis(100);
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[rstest]
+    #[case("cargo build", false)]
+    #[case("make {event_type}", false)]
+    #[case("echo {timestamp} {count}", false)]
+    #[case("rustfmt {file_path}", true)]
+    #[case("echo {relative_path}", true)]
+    #[case("mv {old_path} {new_path}", true)]
+    #[case("cc {file_stem}.c", true)]
+    #[case("prettier --write {changed_files}", true)]
+    fn test_uses_path(#[case] template: &str, #[case] expected: bool) {
+        assert_eq!(uses_path(template), expected);
+    }
+
+    #[test]
+    fn test_path_less_command_fires_once_per_burst() {
+        let coalescer = Coalescer::new(WINDOW);
+        let start = Instant::now();
+
+        for (i, offset) in [0, 10, 20, 90].into_iter().enumerate() {
+            assert!(
+                coalescer
+                    .hold("cargo build", i, start + ms(offset))
+                    .is_none()
+            );
+        }
+        assert!(coalescer.ready(start + ms(99)).is_empty());
+
+        // Later events don't extend the window, and the latest run wins
+        assert_eq!(
+            coalescer.ready(start + ms(100)),
+            vec![("cargo build".to_string(), 3)]
+        );
+        assert!(coalescer.ready(start + ms(500)).is_empty());
+
+        // The next event opens a new window
+        assert!(coalescer.hold("cargo build", 4, start + ms(500)).is_none());
+        assert_eq!(coalescer.ready(start + ms(600)).len(), 1);
+    }
+
+    #[test]
+    fn test_path_command_fires_per_file() {
+        let coalescer = Coalescer::new(WINDOW);
+        let start = Instant::now();
+
+        for i in 0..3 {
+            assert_eq!(coalescer.hold("rustfmt {file_path}", i, start), Some(i));
+        }
+        assert!(coalescer.drain().is_empty());
+    }
+
+    #[test]
+    fn test_templates_are_coalesced_separately() {
+        let coalescer = Coalescer::new(WINDOW);
+        let start = Instant::now();
+
+        coalescer.hold("cargo build", 1, start);
+        coalescer.hold("cargo test", 2, start + ms(50));
+        assert_eq!(
+            coalescer.ready(start + ms(100)),
+            vec![("cargo build".to_string(), 1)]
+        );
+        assert_eq!(coalescer.drain(), vec![("cargo test".to_string(), 2)]);
+    }
+}
@@ -0,0 +1,311 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer};
+use std::path::{Path, PathBuf};
+
+/// Watch rules loaded from a TOML config file
+///
+/// Keys mirror the CLI flags. Top-level keys describe a single watcher, or act
+/// as defaults for every entry of a `[[watch]]` array when one is present:
+///
+/// ```toml
+/// exclude = ["target/**"]
+///
+/// [[watch]]
+/// directory = "frontend"
+/// on_change = "npm test"
+///
+/// [[watch]]
+/// directory = "backend"
+/// include = ["*.rs"]
+/// on_modify = "cargo check"
+/// ```
+///
+/// Commands take a single string or an array of commands that all run, in
+/// order, like a repeated `--on-modify` flag.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WatchConfig {
+    pub directory: Option<PathBuf>,
+    pub include: Option<Vec<String>>,
+    pub exclude: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub on_create: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub on_modify: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub on_delete: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub on_change: Option<Vec<String>>,
+    /// Milliseconds, from a number or a string with a unit such as `"2s"`
+    #[serde(default, deserialize_with = "millis")]
+    pub debounce: Option<u64>,
+    /// Independent watchers; only allowed at the top level
+    #[serde(default)]
+    pub watch: Vec<WatchConfig>,
+}
+
+/// A command given as a single string or as an array of commands
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// Deserialize a string or an array of strings into a list of commands
+fn one_or_many<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Vec<String>>, D::Error> {
+    Ok(Some(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(command) => vec![command],
+        OneOrMany::Many(commands) => commands,
+    }))
+}
+
+/// A duration given as a number of milliseconds or as a string with a unit
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Millis {
+    Number(u64),
+    Text(String),
+}
+
+/// Deserialize a duration the way duration flags parse it
+fn millis<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    match Millis::deserialize(deserializer)? {
+        Millis::Number(millis) => Ok(Some(millis)),
+        Millis::Text(text) => parse_millis(&text)
+            .map(Some)
+            .map_err(|e| serde::de::Error::custom(format!("invalid duration '{}': {}", text, e))),
+    }
+}
+
+/// Parse a duration into milliseconds, as the CLI's duration flags do
+///
+/// Accepts a unit suffix (`500ms`, `2s`, `1m`, `1h`); a bare number is milliseconds.
+pub fn parse_millis(value: &str) -> Result<u64, String> {
+    parse_duration(value, "ms")
+}
+
+/// Parse a number with an optional unit suffix into milliseconds
+///
+/// A bare number is in `default_unit`.
+pub fn parse_duration(value: &str, default_unit: &str) -> Result<u64, String> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let number: u64 = number
+        .parse()
+        .map_err(|_| "expected a number, optionally followed by ms, s, m or h".to_string())?;
+    let scale = match if unit.is_empty() { default_unit } else { unit } {
+        "ms" => 1,
+        "s" => 1000,
+        "m" => 60 * 1000,
+        "h" => 60 * 60 * 1000,
+        _ => return Err(format!("unknown unit '{}', expected ms, s, m or h", unit)),
+    };
+    number
+        .checked_mul(scale)
+        .ok_or_else(|| format!("'{}' is too long", value))
+}
+
+impl WatchConfig {
+    /// Fill unset values from `defaults`
+    fn or(self, defaults: &WatchConfig) -> WatchConfig {
+        WatchConfig {
+            directory: self.directory.or_else(|| defaults.directory.clone()),
+            include: self.include.or_else(|| defaults.include.clone()),
+            exclude: self.exclude.or_else(|| defaults.exclude.clone()),
+            on_create: self.on_create.or_else(|| defaults.on_create.clone()),
+            on_modify: self.on_modify.or_else(|| defaults.on_modify.clone()),
+            on_delete: self.on_delete.or_else(|| defaults.on_delete.clone()),
+            on_change: self.on_change.or_else(|| defaults.on_change.clone()),
+            debounce: self.debounce.or(defaults.debounce),
+            watch: Vec::new(),
+        }
+    }
+}
+
+/// Parse config file contents into one entry per watcher
+///
+/// Relative directories are resolved against `base_dir` (the config file's
+/// directory), so a config behaves the same wherever vibewatch is started.
+pub fn parse(contents: &str, base_dir: &Path) -> Result<Vec<WatchConfig>> {
+    let mut config: WatchConfig = toml::from_str(contents)?;
+
+    let watchers = if config.watch.is_empty() {
+        vec![config]
+    } else {
+        let entries = std::mem::take(&mut config.watch);
+        if entries.iter().any(|entry| !entry.watch.is_empty()) {
+            anyhow::bail!("Nested [[watch]] tables are not supported");
+        }
+        entries.into_iter().map(|entry| entry.or(&config)).collect()
+    };
+
+    Ok(watchers
+        .into_iter()
+        .map(|mut watcher| {
+            watcher.directory = watcher.directory.map(|dir| base_dir.join(dir));
+            watcher
+        })
+        .collect())
+}
+
+/// Load a config file into one entry per watcher
+pub fn load(path: &Path) -> Result<Vec<WatchConfig>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+
+    parse(&contents, base_dir).with_context(|| format!("Invalid config file: {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_single_watcher() {
+        let watchers = parse(
+            r#"
+            directory = "src"
+            include = ["*.rs"]
+            exclude = ["target/**"]
+            on_modify = "cargo check"
+            debounce = 250
+            "#,
+            Path::new("/project"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            watchers,
+            vec![WatchConfig {
+                directory: Some(PathBuf::from("/project/src")),
+                include: Some(vec!["*.rs".to_string()]),
+                exclude: Some(vec!["target/**".to_string()]),
+                on_modify: Some(vec!["cargo check".to_string()]),
+                debounce: Some(250),
+                ..Default::default()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_watch_array_inherits_top_level_defaults() {
+        let watchers = parse(
+            r#"
+            exclude = ["node_modules/**"]
+            debounce = 50
+
+            [[watch]]
+            directory = "frontend"
+            on_change = "npm test"
+
+            [[watch]]
+            directory = "/abs/backend"
+            exclude = []
+            on_modify = "cargo check"
+            "#,
+            Path::new("/project"),
+        )
+        .unwrap();
+
+        assert_eq!(watchers.len(), 2);
+        assert_eq!(
+            watchers[0].directory,
+            Some(PathBuf::from("/project/frontend"))
+        );
+        assert_eq!(
+            watchers[0].exclude,
+            Some(vec!["node_modules/**".to_string()])
+        );
+        assert_eq!(watchers[0].debounce, Some(50));
+        assert_eq!(watchers[0].on_change, Some(vec!["npm test".to_string()]));
+
+        // Absolute directories are kept, and entries can clear inherited lists
+        assert_eq!(watchers[1].directory, Some(PathBuf::from("/abs/backend")));
+        assert_eq!(watchers[1].exclude, Some(vec![]));
+        assert_eq!(watchers[1].on_change, None);
+    }
+
+    #[test]
+    fn test_parse_command_arrays() {
+        let watchers = parse(
+            r#"
+            on_modify = ["cargo fmt", "cargo check"]
+            on_delete = []
+            "#,
+            Path::new("."),
+        )
+        .unwrap();
+
+        assert_eq!(
+            watchers[0].on_modify,
+            Some(vec!["cargo fmt".to_string(), "cargo check".to_string()])
+        );
+        assert_eq!(watchers[0].on_delete, Some(vec![]));
+        assert_eq!(watchers[0].on_create, None);
+
+        let err = parse("on_modify = 1\n", Path::new(".")).unwrap_err();
+        assert!(err.to_string().contains("line 1"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_debounce_with_unit() {
+        let watchers = parse("debounce = \"2s\"\n", Path::new(".")).unwrap();
+        assert_eq!(watchers[0].debounce, Some(2000));
+
+        let watchers = parse("debounce = \"250\"\n", Path::new(".")).unwrap();
+        assert_eq!(watchers[0].debounce, Some(250));
+
+        let err = parse("directory = \".\"\ndebounce = \"2 days\"\n", Path::new(".")).unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("line 2"), "{}", message);
+        assert!(message.contains("invalid duration '2 days'"), "{}", message);
+    }
+
+    #[test]
+    fn test_parse_unknown_key_reports_line() {
+        let err = parse(
+            "directory = \".\"\non_modfy = \"cargo check\"\n",
+            Path::new("."),
+        )
+        .unwrap_err();
+
+        let message = err.to_string();
+        assert!(message.contains("line 2"), "{}", message);
+        assert!(message.contains("on_modfy"), "{}", message);
+    }
+
+    #[test]
+    fn test_parse_invalid_toml_reports_line() {
+        let err = parse("directory = \".\"\ninclude = [\"*.rs\"\n", Path::new(".")).unwrap_err();
+
+        assert!(err.to_string().contains("line 2"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_rejects_nested_watch() {
+        let err = parse(
+            "[[watch]]\ndirectory = \"a\"\n[[watch.watch]]\ndirectory = \"b\"\n",
+            Path::new("."),
+        )
+        .unwrap_err();
+
+        assert!(err.to_string().contains("Nested [[watch]]"));
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        let err = load(Path::new("/nonexistent/vibewatch.toml")).unwrap_err();
+
+        assert!(
+            err.to_string()
+                .contains("Failed to read config file: /nonexistent/vibewatch.toml")
+        );
+    }
+}
@@ -0,0 +1,970 @@
+use notify::event::{AccessKind, AccessMode, ModifyKind, RenameMode};
+use notify::{Event, EventKind};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// When a debounced command fires relative to a burst of events
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DebounceMode {
+    /// Fire once the path has been quiet for the whole window
+    #[default]
+    Trailing,
+    /// Fire on the first event, then ignore the path for the rest of the window
+    Leading,
+}
+
+/// Debounce window for each event type
+///
+/// Create, modify and delete events can each get their own window. Create
+/// and modify events without one use the default; deletes without one are
+/// not debounced, so cleanup commands run promptly.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DebounceWindows {
+    pub default: Duration,
+    pub create: Option<Duration>,
+    pub modify: Option<Duration>,
+    pub delete: Option<Duration>,
+    /// Longest a path may stay pending, even if events keep arriving for it
+    pub max: Option<Duration>,
+}
+
+impl DebounceWindows {
+    /// Use `window` for every event type
+    pub fn new(window: Duration) -> Self {
+        Self {
+            default: window,
+            ..Self::default()
+        }
+    }
+
+    /// The window for an event kind, falling back to the default
+    ///
+    /// Deletes fall back to no window instead: a delete flushes whatever was
+    /// pending for its path and fires right away.
+    pub fn for_kind(&self, kind: &EventKind) -> Duration {
+        let window = match kind {
+            EventKind::Create(_) => self.create,
+            EventKind::Modify(_) | EventKind::Access(AccessKind::Close(AccessMode::Write)) => {
+                self.modify
+            }
+            EventKind::Remove(_) => return self.delete.unwrap_or(Duration::ZERO),
+            _ => None,
+        };
+        window.unwrap_or(self.default)
+    }
+
+    /// The longest window of any event type
+    pub(crate) fn longest(&self) -> Duration {
+        [self.create, self.modify, self.delete]
+            .into_iter()
+            .flatten()
+            .fold(self.default, Duration::max)
+    }
+}
+
+/// The default window followed by the windows that are set, e.g. `100ms, delete 10ms`
+impl std::fmt::Display for DebounceWindows {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.default)?;
+        let windows = [
+            ("create", self.create),
+            ("modify", self.modify),
+            ("delete", self.delete),
+            ("max", self.max),
+        ];
+        for (name, window) in windows {
+            if let Some(window) = window {
+                write!(f, ", {} {:?}", name, window)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Coalesces rapid events per path
+///
+/// In trailing mode a path keeps only its latest event, except that a create
+/// followed by modifications stays a create: editors often write a new file
+/// in several steps, and it should fire `on_create` once, not `on_modify`.
+/// A delete followed by the path coming back isn't merged either: both the
+/// delete and the new event fire, in that order.
+///
+/// Each event waits for the window of its own type, see [`DebounceWindows`].
+/// With a maximum set, a path that never goes quiet (e.g. a log being
+/// appended to) still fires once it has been pending that long.
+///
+/// Events fed with [`Debouncer::push_grouped`] are keyed on their path and
+/// the command group they resolve to, so each group gets its own window.
+/// That keeps up to one pending event per path *per group* instead of per
+/// path; groups are the distinct command lists configured, so memory still
+/// grows with the number of changed paths, times a small constant.
+///
+/// All methods take the current time so the timing logic can be tested
+/// without sleeping.
+#[derive(Debug)]
+pub struct Debouncer {
+    mode: DebounceMode,
+    windows: DebounceWindows,
+    /// Trailing mode: latest events per key
+    pending: HashMap<DebounceKey, Pending>,
+    /// Leading mode: when each key fired, suppressing events until the window ends
+    fired: HashMap<DebounceKey, Instant>,
+}
+
+/// What events are debounced under: a path, and optionally the command group
+/// the event resolves to
+type DebounceKey = (PathBuf, Option<String>);
+
+/// Events for one key waiting for their debounce period
+#[derive(Debug)]
+struct Pending {
+    /// Events to fire, in order: a single merged event, or a delete and what
+    /// came after it when the path was recreated within the window
+    events: Vec<Event>,
+    /// When the path's first event of this burst arrived
+    first_seen: Instant,
+    /// When the latest event arrived
+    last_updated: Instant,
+    /// How many events arrived after the first one, each restarting the window
+    refreshes: u32,
+}
+
+impl Pending {
+    /// The event whose type decides the window
+    fn latest(&self) -> &Event {
+        // Never empty: every entry is created with an event
+        &self.events[self.events.len() - 1]
+    }
+
+    /// Describe how the event waited, for the debug log when it fires
+    fn describe(&self, now: Instant) -> String {
+        format!(
+            "{}ms after its first event, {}ms after its last, {} refresh(es)",
+            now.duration_since(self.first_seen).as_millis(),
+            now.duration_since(self.last_updated).as_millis(),
+            self.refreshes
+        )
+    }
+}
+
+impl Debouncer {
+    /// Debounce every event type with the same window
+    pub fn new(mode: DebounceMode, window: Duration) -> Self {
+        Self::with_windows(mode, DebounceWindows::new(window))
+    }
+
+    /// Debounce each event type with its own window
+    pub fn with_windows(mode: DebounceMode, windows: DebounceWindows) -> Self {
+        Self {
+            mode,
+            windows,
+            pending: HashMap::new(),
+            fired: HashMap::new(),
+        }
+    }
+
+    /// Feed an event, returning the events to process right away
+    pub fn push(&mut self, event: Event, now: Instant) -> Vec<Event> {
+        self.push_grouped(event, now, |_, _| None)
+    }
+
+    /// Feed an event, debouncing it separately for each command group
+    ///
+    /// `group` names the commands an event for a path resolves to (`None` for
+    /// no commands). Events for the same path but different groups don't merge
+    /// or suppress each other, e.g. a modify running `lint` and a delete
+    /// running `build` both fire.
+    pub fn push_grouped(
+        &mut self,
+        event: Event,
+        now: Instant,
+        group: impl Fn(&Path, &EventKind) -> Option<String>,
+    ) -> Vec<Event> {
+        if self.windows.longest().is_zero() {
+            // No debouncing - process immediately
+            return vec![event];
+        }
+
+        match self.mode {
+            DebounceMode::Trailing => {
+                let mut immediate = Vec::new();
+                for path in debounce_keys(&event) {
+                    let key = (path.clone(), group(path, &event.kind));
+                    let (first_seen, refreshes, mut events) = match self.pending.remove(&key) {
+                        Some(pending) => {
+                            (pending.first_seen, pending.refreshes + 1, pending.events)
+                        }
+                        None => (now, 0, Vec::new()),
+                    };
+                    if append_event(&mut events, event.clone()) {
+                        log::debug!(
+                            "Path came back after a pending delete, keeping both events: {}",
+                            path.display()
+                        );
+                    }
+                    let window = self.windows.for_kind(&events[events.len() - 1].kind);
+                    if window.is_zero() {
+                        // Not debounced for this event type - process immediately
+                        immediate.extend(events);
+                    } else {
+                        if refreshes == 0 {
+                            log::debug!(
+                                "Debouncing event for: {} ({}ms window)",
+                                path.display(),
+                                window.as_millis()
+                            );
+                        } else {
+                            log::debug!(
+                                "Debounce refreshed for: {} (refresh {}, {}ms after its first event)",
+                                path.display(),
+                                refreshes,
+                                now.duration_since(first_seen).as_millis()
+                            );
+                        }
+                        self.pending.insert(
+                            key,
+                            Pending {
+                                events,
+                                first_seen,
+                                last_updated: now,
+                                refreshes,
+                            },
+                        );
+                    }
+                }
+                immediate
+            }
+            DebounceMode::Leading => {
+                let window = self.windows.for_kind(&event.kind);
+                let keys: Vec<DebounceKey> = debounce_keys(&event)
+                    .iter()
+                    .map(|path| (path.clone(), group(path, &event.kind)))
+                    .collect();
+                let suppressed = keys.iter().all(|key| {
+                    self.fired
+                        .get(key)
+                        .is_some_and(|fired| now.duration_since(*fired) < window)
+                });
+
+                if suppressed {
+                    log::debug!(
+                        "Suppressing event within debounce window: {:?}",
+                        event.paths
+                    );
+                    return Vec::new();
+                }
+
+                for key in keys {
+                    self.fired.insert(key, now);
+                }
+                vec![event]
+            }
+        }
+    }
+
+    /// Take the events whose debounce period has elapsed
+    pub fn ready(&mut self, now: Instant) -> Vec<Event> {
+        let longest = self.windows.longest();
+        self.fired
+            .retain(|_, fired| now.duration_since(*fired) < longest);
+
+        let ready_keys: Vec<DebounceKey> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| self.is_settled(pending, now))
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        ready_keys
+            .into_iter()
+            .filter_map(|key| {
+                let pending = self.pending.remove(&key)?;
+                log::debug!(
+                    "Debounce period elapsed for: {} ({})",
+                    key.0.display(),
+                    pending.describe(now)
+                );
+                Some(pending.events)
+            })
+            .flatten()
+            .collect()
+    }
+
+    /// Take every pending event once the whole burst has settled
+    ///
+    /// Unlike `ready`, nothing is released while any path is still inside its
+    /// window, so a burst across several files comes out as one batch. A path
+    /// that reaches the maximum pending time releases the whole batch.
+    pub fn ready_batch(&mut self, now: Instant) -> Vec<Event> {
+        let settled = self
+            .pending
+            .values()
+            .all(|pending| self.is_quiet(pending, now))
+            || self
+                .pending
+                .values()
+                .any(|pending| self.is_overdue(pending, now));
+
+        if !settled {
+            return Vec::new();
+        }
+
+        let mut batch: Vec<Pending> = self
+            .pending
+            .drain()
+            .map(|((path, _), pending)| {
+                log::debug!(
+                    "Debounce batch releasing: {} ({})",
+                    path.display(),
+                    pending.describe(now)
+                );
+                pending
+            })
+            .collect();
+        // Keep the batch in the order the events arrived
+        batch.sort_by_key(|pending| pending.last_updated);
+        batch
+            .into_iter()
+            .flat_map(|pending| pending.events)
+            .collect()
+    }
+
+    /// Number of events waiting for their debounce period
+    pub fn pending_len(&self) -> usize {
+        self.pending
+            .values()
+            .map(|pending| pending.events.len())
+            .sum()
+    }
+
+    /// Whether a pending event is quiet or has waited the maximum time
+    fn is_settled(&self, pending: &Pending, now: Instant) -> bool {
+        self.is_quiet(pending, now) || self.is_overdue(pending, now)
+    }
+
+    /// Whether a pending event has been quiet for its type's whole window
+    fn is_quiet(&self, pending: &Pending, now: Instant) -> bool {
+        now.duration_since(pending.last_updated) >= self.windows.for_kind(&pending.latest().kind)
+    }
+
+    /// Whether a pending event has waited longer than the maximum allows
+    fn is_overdue(&self, pending: &Pending, now: Instant) -> bool {
+        self.windows
+            .max
+            .is_some_and(|max| now.duration_since(pending.first_seen) >= max)
+    }
+}
+
+/// Add a newer event to the events pending for a path
+///
+/// It is merged into the latest pending event, unless that is a delete and
+/// the newer event means the path exists again, e.g. a file deleted and
+/// recreated within the window. Then both are kept, to fire in order, and
+/// true is returned. Deleting the path once more leaves only the first delete
+/// pending, so there are never more than two events.
+fn append_event(events: &mut Vec<Event>, newer: Event) -> bool {
+    let Some(latest) = events.pop() else {
+        events.push(newer);
+        return false;
+    };
+
+    let is_remove = |event: &Event| matches!(event.kind, EventKind::Remove(_));
+    if is_remove(&latest) && !is_remove(&newer) {
+        events.push(latest);
+        events.push(newer);
+        return true;
+    }
+
+    let merged = merge_events(latest, newer);
+    if !(is_remove(&merged) && events.last().is_some_and(is_remove)) {
+        events.push(merged);
+    }
+    false
+}
+
+/// Combine a pending event with a newer one for the same path
+///
+/// Create outranks modify, so the newer event keeps the create kind. Any
+/// other sequence (e.g. create then delete) is represented by the newer event.
+fn merge_events(pending: Event, mut newer: Event) -> Event {
+    if matches!(pending.kind, EventKind::Create(_)) && is_content_change(&newer.kind) {
+        newer.kind = pending.kind;
+    }
+    newer
+}
+
+/// Whether an event kind only reports changed content or metadata
+///
+/// Renames change which path exists, so they are not merged into a create.
+fn is_content_change(kind: &EventKind) -> bool {
+    match kind {
+        EventKind::Modify(ModifyKind::Name(_)) => false,
+        EventKind::Modify(_) => true,
+        // inotify reports finished writes as a close, handled as a modify
+        EventKind::Access(AccessKind::Close(AccessMode::Write)) => true,
+        _ => false,
+    }
+}
+
+/// Paths an event is debounced under
+///
+/// A move carries [source, destination]; it is keyed by destination so it fires once.
+fn debounce_keys(event: &Event) -> &[PathBuf] {
+    let is_move = matches!(
+        event.kind,
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both))
+    ) && event.paths.len() == 2;
+
+    if is_move {
+        &event.paths[1..]
+    } else {
+        &event.paths[..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, DataChange, RemoveKind};
+    use rstest::rstest;
+
+    const WINDOW: Duration = Duration::from_millis(100);
+
+    fn modify(path: &str) -> Event {
+        Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Any)))
+            .add_path(PathBuf::from(path))
+    }
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn test_zero_window_passes_events_through() {
+        for mode in [DebounceMode::Trailing, DebounceMode::Leading] {
+            let mut debouncer = Debouncer::new(mode, Duration::ZERO);
+            let now = Instant::now();
+
+            assert_eq!(debouncer.push(modify("/w/a"), now).len(), 1);
+            assert_eq!(debouncer.push(modify("/w/a"), now).len(), 1);
+            assert!(debouncer.ready(now).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_trailing_fires_once_after_quiet_window() {
+        let mut debouncer = Debouncer::new(DebounceMode::Trailing, WINDOW);
+        let start = Instant::now();
+
+        assert!(debouncer.push(modify("/w/a"), start).is_empty());
+        assert!(debouncer.push(modify("/w/a"), start + ms(60)).is_empty());
+        assert_eq!(debouncer.pending_len(), 1);
+
+        // The window restarts with every event
+        assert!(debouncer.ready(start + ms(120)).is_empty());
+
+        let fired = debouncer.ready(start + ms(160));
+        assert_eq!(fired.len(), 1);
+        assert_eq!(debouncer.pending_len(), 0);
+        assert!(debouncer.ready(start + ms(500)).is_empty());
+    }
+
+    #[test]
+    fn test_refreshes_are_counted_until_flush() {
+        let mut debouncer = Debouncer::new(DebounceMode::Trailing, WINDOW);
+        let start = Instant::now();
+        let key = (PathBuf::from("/w/a"), None);
+
+        debouncer.push(modify("/w/a"), start);
+        assert_eq!(debouncer.pending[&key].refreshes, 0);
+        for (i, offset) in [20, 40, 60].into_iter().enumerate() {
+            debouncer.push(modify("/w/a"), start + ms(offset));
+            assert_eq!(debouncer.pending[&key].refreshes, i as u32 + 1);
+        }
+
+        let pending = &debouncer.pending[&key];
+        assert_eq!(pending.first_seen, start);
+        assert_eq!(
+            pending.describe(start + ms(160)),
+            "160ms after its first event, 100ms after its last, 3 refresh(es)"
+        );
+
+        // Firing forgets the path, so its next burst counts from zero
+        assert_eq!(debouncer.ready(start + ms(160)).len(), 1);
+        debouncer.push(modify("/w/a"), start + ms(200));
+        assert_eq!(debouncer.pending[&key].refreshes, 0);
+    }
+
+    #[test]
+    fn test_leading_fires_immediately_then_suppresses() {
+        let mut debouncer = Debouncer::new(DebounceMode::Leading, WINDOW);
+        let start = Instant::now();
+
+        assert_eq!(debouncer.push(modify("/w/a"), start).len(), 1);
+        assert!(debouncer.push(modify("/w/a"), start + ms(30)).is_empty());
+        assert!(debouncer.push(modify("/w/a"), start + ms(99)).is_empty());
+
+        // Suppressed events are swallowed, never fired later
+        assert!(debouncer.ready(start + ms(150)).is_empty());
+        assert_eq!(debouncer.pending_len(), 0);
+
+        // Once the window has passed, the next event fires right away again
+        assert_eq!(debouncer.push(modify("/w/a"), start + ms(100)).len(), 1);
+    }
+
+    #[test]
+    fn test_leading_window_is_per_path() {
+        let mut debouncer = Debouncer::new(DebounceMode::Leading, WINDOW);
+        let start = Instant::now();
+
+        assert_eq!(debouncer.push(modify("/w/a"), start).len(), 1);
+        assert_eq!(debouncer.push(modify("/w/b"), start + ms(10)).len(), 1);
+        assert!(debouncer.push(modify("/w/a"), start + ms(20)).is_empty());
+    }
+
+    /// `--on-modify lint --on-change build`: modifies run lint, anything else build
+    fn lint_or_build(_: &Path, kind: &EventKind) -> Option<String> {
+        let command = match kind {
+            EventKind::Modify(_) => "lint",
+            _ => "build",
+        };
+        Some(command.to_string())
+    }
+
+    fn create(path: &str) -> Event {
+        Event::new(EventKind::Create(CreateKind::File)).add_path(PathBuf::from(path))
+    }
+
+    #[test]
+    fn test_trailing_groups_debounce_independently() {
+        let mut debouncer = Debouncer::new(DebounceMode::Trailing, WINDOW);
+        let start = Instant::now();
+
+        assert!(
+            debouncer
+                .push_grouped(create("/w/a"), start, lint_or_build)
+                .is_empty()
+        );
+        assert!(
+            debouncer
+                .push_grouped(modify("/w/a"), start + ms(60), lint_or_build)
+                .is_empty()
+        );
+        assert_eq!(debouncer.pending_len(), 2);
+
+        // The build window started with the create and isn't extended by the modify
+        let fired = debouncer.ready(start + ms(100));
+        assert_eq!(fired.len(), 1);
+        assert!(matches!(fired[0].kind, EventKind::Create(_)));
+
+        let fired = debouncer.ready(start + ms(160));
+        assert_eq!(fired.len(), 1);
+        assert!(matches!(fired[0].kind, EventKind::Modify(_)));
+        assert_eq!(debouncer.pending_len(), 0);
+    }
+
+    #[test]
+    fn test_same_group_still_merges() {
+        let mut debouncer = Debouncer::new(DebounceMode::Trailing, WINDOW);
+        let start = Instant::now();
+
+        debouncer.push_grouped(modify("/w/a"), start, lint_or_build);
+        debouncer.push_grouped(modify("/w/a"), start + ms(60), lint_or_build);
+        assert_eq!(debouncer.pending_len(), 1);
+        assert_eq!(debouncer.ready(start + ms(160)).len(), 1);
+    }
+
+    #[test]
+    fn test_leading_groups_suppress_independently() {
+        let mut debouncer = Debouncer::new(DebounceMode::Leading, WINDOW);
+        let start = Instant::now();
+
+        assert_eq!(
+            debouncer
+                .push_grouped(modify("/w/a"), start, lint_or_build)
+                .len(),
+            1
+        );
+        // Another group for the same path isn't suppressed by the lint window
+        assert_eq!(
+            debouncer
+                .push_grouped(create("/w/a"), start + ms(10), lint_or_build)
+                .len(),
+            1
+        );
+        assert!(
+            debouncer
+                .push_grouped(modify("/w/a"), start + ms(20), lint_or_build)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_ready_batch_waits_for_whole_burst() {
+        let mut debouncer = Debouncer::new(DebounceMode::Trailing, WINDOW);
+        let start = Instant::now();
+
+        debouncer.push(modify("/w/a"), start);
+        debouncer.push(modify("/w/b"), start + ms(40));
+        debouncer.push(modify("/w/c"), start + ms(80));
+
+        // /w/a is past its window, but the burst is still going
+        assert!(debouncer.ready_batch(start + ms(120)).is_empty());
+        assert_eq!(debouncer.pending_len(), 3);
+
+        let batch = debouncer.ready_batch(start + ms(180));
+        let paths: Vec<_> = batch.iter().map(|e| e.paths[0].clone()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("/w/a"),
+                PathBuf::from("/w/b"),
+                PathBuf::from("/w/c")
+            ]
+        );
+        assert_eq!(debouncer.pending_len(), 0);
+    }
+
+    #[test]
+    fn test_create_then_modify_stays_create() {
+        let mut debouncer = Debouncer::new(DebounceMode::Trailing, WINDOW);
+        let start = Instant::now();
+        let create =
+            Event::new(EventKind::Create(CreateKind::File)).add_path(PathBuf::from("/w/a"));
+        let close_write = Event::new(EventKind::Access(AccessKind::Close(AccessMode::Write)))
+            .add_path(PathBuf::from("/w/a"));
+
+        debouncer.push(create, start);
+        debouncer.push(modify("/w/a"), start + ms(10));
+        debouncer.push(close_write, start + ms(20));
+
+        let fired = debouncer.ready(start + ms(120));
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].kind, EventKind::Create(CreateKind::File));
+    }
+
+    #[rstest]
+    #[case(
+        EventKind::Modify(ModifyKind::Data(DataChange::Any)),
+        EventKind::Create(CreateKind::File),
+        EventKind::Create(CreateKind::File)
+    )]
+    #[case(
+        EventKind::Create(CreateKind::File),
+        EventKind::Remove(RemoveKind::File),
+        EventKind::Remove(RemoveKind::File)
+    )]
+    #[case(
+        EventKind::Create(CreateKind::File),
+        EventKind::Modify(ModifyKind::Name(RenameMode::From)),
+        EventKind::Modify(ModifyKind::Name(RenameMode::From))
+    )]
+    #[case(
+        EventKind::Remove(RemoveKind::File),
+        EventKind::Modify(ModifyKind::Data(DataChange::Any)),
+        EventKind::Modify(ModifyKind::Data(DataChange::Any))
+    )]
+    fn test_merge_keeps_newer_event_otherwise(
+        #[case] pending: EventKind,
+        #[case] newer: EventKind,
+        #[case] expected: EventKind,
+    ) {
+        let merged = merge_events(Event::new(pending), Event::new(newer));
+        assert_eq!(merged.kind, expected);
+    }
+
+    fn windows() -> DebounceWindows {
+        DebounceWindows {
+            default: WINDOW,
+            create: Some(ms(300)),
+            modify: None,
+            delete: Some(Duration::ZERO),
+            max: None,
+        }
+    }
+
+    #[rstest]
+    #[case(EventKind::Create(CreateKind::File), ms(300))]
+    #[case(EventKind::Modify(ModifyKind::Data(DataChange::Any)), WINDOW)]
+    #[case(EventKind::Access(AccessKind::Close(AccessMode::Write)), WINDOW)]
+    #[case(EventKind::Remove(RemoveKind::File), Duration::ZERO)]
+    #[case(EventKind::Any, WINDOW)]
+    fn test_windows_for_kind(#[case] kind: EventKind, #[case] expected: Duration) {
+        assert_eq!(windows().for_kind(&kind), expected);
+    }
+
+    #[rstest]
+    #[case(None, Duration::ZERO)]
+    #[case(Some(ms(300)), ms(300))]
+    fn test_delete_window_defaults_to_zero(
+        #[case] delete: Option<Duration>,
+        #[case] expected: Duration,
+    ) {
+        let windows = DebounceWindows {
+            delete,
+            ..DebounceWindows::new(WINDOW)
+        };
+        assert_eq!(
+            windows.for_kind(&EventKind::Remove(RemoveKind::File)),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_delete_after_create_fires_immediately() {
+        let mut debouncer = Debouncer::new(DebounceMode::Trailing, WINDOW);
+        let start = Instant::now();
+        let create =
+            Event::new(EventKind::Create(CreateKind::File)).add_path(PathBuf::from("/w/tmp"));
+        let remove =
+            Event::new(EventKind::Remove(RemoveKind::File)).add_path(PathBuf::from("/w/tmp"));
+
+        assert!(debouncer.push(create, start).is_empty());
+
+        // The delete flushes the pending create instead of waiting out the window
+        let fired = debouncer.push(remove, start + ms(10));
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].kind, EventKind::Remove(RemoveKind::File));
+        assert_eq!(debouncer.pending_len(), 0);
+        assert!(debouncer.ready(start + ms(500)).is_empty());
+    }
+
+    /// Debounces deletes too, so a delete can be pending when the path comes back
+    fn delete_windows() -> DebounceWindows {
+        DebounceWindows {
+            delete: Some(WINDOW),
+            ..DebounceWindows::new(WINDOW)
+        }
+    }
+
+    fn remove(path: &str) -> Event {
+        Event::new(EventKind::Remove(RemoveKind::File)).add_path(PathBuf::from(path))
+    }
+
+    #[test]
+    fn test_delete_then_create_fires_both_in_order() {
+        let mut debouncer = Debouncer::with_windows(DebounceMode::Trailing, delete_windows());
+        let start = Instant::now();
+
+        debouncer.push(remove("/w/a"), start);
+        debouncer.push(create("/w/a"), start + ms(10));
+        debouncer.push(modify("/w/a"), start + ms(20));
+        assert_eq!(debouncer.pending_len(), 2);
+
+        assert!(debouncer.ready(start + ms(119)).is_empty());
+        let kinds: Vec<_> = debouncer
+            .ready(start + ms(120))
+            .into_iter()
+            .map(|event| event.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                EventKind::Remove(RemoveKind::File),
+                EventKind::Create(CreateKind::File)
+            ]
+        );
+        assert_eq!(debouncer.pending_len(), 0);
+    }
+
+    #[test]
+    fn test_create_then_delete_fires_only_the_delete() {
+        let mut debouncer = Debouncer::with_windows(DebounceMode::Trailing, delete_windows());
+        let start = Instant::now();
+
+        debouncer.push(create("/w/a"), start);
+        debouncer.push(remove("/w/a"), start + ms(10));
+        assert_eq!(debouncer.pending_len(), 1);
+
+        let fired = debouncer.ready(start + ms(110));
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].kind, EventKind::Remove(RemoveKind::File));
+    }
+
+    #[test]
+    fn test_delete_create_delete_fires_one_delete() {
+        let mut debouncer = Debouncer::with_windows(DebounceMode::Trailing, delete_windows());
+        let start = Instant::now();
+
+        debouncer.push(remove("/w/a"), start);
+        debouncer.push(create("/w/a"), start + ms(10));
+        debouncer.push(remove("/w/a"), start + ms(20));
+        assert_eq!(debouncer.pending_len(), 1);
+        assert_eq!(debouncer.ready(start + ms(120)).len(), 1);
+    }
+
+    #[test]
+    fn test_recreate_flushes_both_when_new_event_is_not_debounced() {
+        let windows = DebounceWindows {
+            create: Some(Duration::ZERO),
+            ..delete_windows()
+        };
+        let mut debouncer = Debouncer::with_windows(DebounceMode::Trailing, windows);
+        let start = Instant::now();
+
+        assert!(debouncer.push(remove("/w/a"), start).is_empty());
+        let fired = debouncer.push(create("/w/a"), start + ms(10));
+        assert_eq!(fired.len(), 2);
+        assert_eq!(fired[0].kind, EventKind::Remove(RemoveKind::File));
+        assert_eq!(fired[1].kind, EventKind::Create(CreateKind::File));
+    }
+
+    #[test]
+    fn test_leading_never_suppresses_deletes() {
+        let mut debouncer = Debouncer::new(DebounceMode::Leading, WINDOW);
+        let start = Instant::now();
+        let remove =
+            Event::new(EventKind::Remove(RemoveKind::File)).add_path(PathBuf::from("/w/a"));
+
+        assert_eq!(debouncer.push(modify("/w/a"), start).len(), 1);
+        assert_eq!(debouncer.push(remove, start + ms(10)).len(), 1);
+    }
+
+    #[test]
+    fn test_ready_uses_window_of_each_event_type() {
+        let mut debouncer = Debouncer::with_windows(DebounceMode::Trailing, windows());
+        let start = Instant::now();
+        let create =
+            Event::new(EventKind::Create(CreateKind::File)).add_path(PathBuf::from("/w/new"));
+
+        debouncer.push(create, start);
+        debouncer.push(modify("/w/a"), start);
+
+        // The modify uses the default window, the create its longer one
+        let fired = debouncer.ready(start + ms(150));
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].paths, vec![PathBuf::from("/w/a")]);
+        assert_eq!(debouncer.pending_len(), 1);
+
+        assert!(debouncer.ready(start + ms(299)).is_empty());
+        let fired = debouncer.ready(start + ms(300));
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].kind, EventKind::Create(CreateKind::File));
+    }
+
+    #[test]
+    fn test_zero_window_for_event_type_passes_it_through() {
+        let mut debouncer = Debouncer::with_windows(DebounceMode::Trailing, windows());
+        let start = Instant::now();
+        let remove =
+            Event::new(EventKind::Remove(RemoveKind::File)).add_path(PathBuf::from("/w/a"));
+
+        assert!(debouncer.push(modify("/w/a"), start).is_empty());
+
+        // The delete replaces the pending modify and fires right away
+        let fired = debouncer.push(remove, start + ms(10));
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].kind, EventKind::Remove(RemoveKind::File));
+        assert_eq!(debouncer.pending_len(), 0);
+    }
+
+    #[test]
+    fn test_ready_batch_waits_for_longest_pending_window() {
+        let mut debouncer = Debouncer::with_windows(DebounceMode::Trailing, windows());
+        let start = Instant::now();
+        let create =
+            Event::new(EventKind::Create(CreateKind::File)).add_path(PathBuf::from("/w/new"));
+
+        debouncer.push(create, start);
+        debouncer.push(modify("/w/a"), start + ms(50));
+
+        assert!(debouncer.ready_batch(start + ms(200)).is_empty());
+        assert_eq!(debouncer.ready_batch(start + ms(300)).len(), 2);
+    }
+
+    #[test]
+    fn test_leading_suppresses_for_window_of_event_type() {
+        let mut debouncer = Debouncer::with_windows(DebounceMode::Leading, windows());
+        let start = Instant::now();
+        let create =
+            Event::new(EventKind::Create(CreateKind::File)).add_path(PathBuf::from("/w/a"));
+
+        assert_eq!(debouncer.push(modify("/w/a"), start).len(), 1);
+        assert!(debouncer.push(modify("/w/a"), start + ms(50)).is_empty());
+        // Still inside the create window, even though the modify window has passed
+        debouncer.ready(start + ms(150));
+        assert!(debouncer.push(create, start + ms(150)).is_empty());
+        assert_eq!(debouncer.push(modify("/w/a"), start + ms(150)).len(), 1);
+    }
+
+    #[test]
+    fn test_max_fires_path_that_keeps_updating() {
+        let windows = DebounceWindows {
+            max: Some(ms(250)),
+            ..DebounceWindows::new(WINDOW)
+        };
+        let mut debouncer = Debouncer::with_windows(DebounceMode::Trailing, windows);
+        let start = Instant::now();
+
+        // A write every 50ms never leaves a 100ms quiet period
+        for step in 0..5 {
+            let now = start + ms(step * 50);
+            assert!(debouncer.push(modify("/w/log"), now).is_empty());
+            assert!(debouncer.ready(now).is_empty());
+        }
+
+        let fired = debouncer.ready(start + ms(250));
+        assert_eq!(fired.len(), 1);
+        assert_eq!(debouncer.pending_len(), 0);
+
+        // The next write starts a new burst with its own maximum
+        debouncer.push(modify("/w/log"), start + ms(260));
+        assert!(debouncer.ready(start + ms(340)).is_empty());
+        assert_eq!(debouncer.ready(start + ms(360)).len(), 1);
+    }
+
+    #[test]
+    fn test_without_max_path_that_keeps_updating_waits() {
+        let mut debouncer = Debouncer::new(DebounceMode::Trailing, WINDOW);
+        let start = Instant::now();
+
+        for step in 0..20 {
+            debouncer.push(modify("/w/log"), start + ms(step * 50));
+            assert!(debouncer.ready(start + ms(step * 50)).is_empty());
+        }
+        assert_eq!(debouncer.pending_len(), 1);
+    }
+
+    #[test]
+    fn test_max_releases_batch_while_burst_continues() {
+        let windows = DebounceWindows {
+            max: Some(ms(200)),
+            ..DebounceWindows::new(WINDOW)
+        };
+        let mut debouncer = Debouncer::with_windows(DebounceMode::Trailing, windows);
+        let start = Instant::now();
+
+        debouncer.push(modify("/w/a"), start);
+        debouncer.push(modify("/w/b"), start + ms(150));
+        debouncer.push(modify("/w/a"), start + ms(180));
+
+        assert!(debouncer.ready_batch(start + ms(199)).is_empty());
+        assert_eq!(debouncer.ready_batch(start + ms(200)).len(), 2);
+    }
+
+    #[test]
+    fn test_move_is_keyed_by_destination() {
+        let mut debouncer = Debouncer::new(DebounceMode::Trailing, WINDOW);
+        let start = Instant::now();
+        let moved = Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+            .add_path(PathBuf::from("/w/old"))
+            .add_path(PathBuf::from("/w/new"));
+
+        debouncer.push(moved, start);
+
+        assert_eq!(debouncer.pending_len(), 1);
+        assert_eq!(debouncer.ready(start + WINDOW).len(), 1);
+    }
+
+    #[test]
+    fn test_windows_display_shows_only_set_windows() {
+        assert_eq!(DebounceWindows::new(WINDOW).to_string(), "100ms");
+        let windows = DebounceWindows {
+            delete: Some(Duration::from_millis(10)),
+            max: Some(Duration::from_secs(5)),
+            ..DebounceWindows::new(WINDOW)
+        };
+        assert_eq!(windows.to_string(), "100ms, delete 10ms, max 5s");
+    }
+}
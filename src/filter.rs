@@ -1,110 +1,483 @@
 use anyhow::{Context, Result};
 use glob::{Pattern, PatternError};
-use std::path::Path;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 /// Handles include/exclude pattern matching for file watching
 #[derive(Debug)]
 pub struct PatternFilter {
     include_patterns: Vec<Pattern>,
     exclude_patterns: Vec<Pattern>,
+    /// Rules from .gitignore files, keyed by their directory relative to the
+    /// watch root and ordered deepest first
+    gitignores: Vec<(PathBuf, Gitignore)>,
+    /// Rules from `--ignore-file` files, anchored at the watch root
+    ignore_files: Option<Gitignore>,
+    /// Root joined onto relative paths before matching patterns, if any
+    absolute_root: Option<PathBuf>,
+    /// Match patterns against file names only
+    basename: bool,
+    /// Compiled form of every current pattern, reused by `rebuild`
+    compiled: HashMap<String, Pattern>,
+}
+
+/// Why `PatternFilter` watches or ignores a path, see `PatternFilter::explain`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterDecision {
+    /// Matched this exclude pattern
+    Excluded(String),
+    /// Ignored by a .gitignore file
+    Gitignored,
+    /// Matched this rule of an ignore file
+    IgnoreFile { rule: String, file: PathBuf },
+    /// Include patterns are configured and none matched
+    NotIncluded,
+    /// Matched this include pattern
+    Included(String),
+    /// No include patterns are configured and no exclude pattern matched
+    Unfiltered,
+}
+
+impl FilterDecision {
+    pub fn is_watched(&self) -> bool {
+        matches!(
+            self,
+            FilterDecision::Included(_) | FilterDecision::Unfiltered
+        )
+    }
+}
+
+impl std::fmt::Display for FilterDecision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FilterDecision::Excluded(pattern) => {
+                write!(f, "matches exclude pattern '{}'", pattern)
+            }
+            FilterDecision::Gitignored => f.write_str("ignored by .gitignore"),
+            FilterDecision::IgnoreFile { rule, file } => {
+                write!(f, "matches rule '{}' in {}", rule, file.display())
+            }
+            FilterDecision::NotIncluded => f.write_str("matches no include pattern"),
+            FilterDecision::Included(pattern) => {
+                write!(f, "matches include pattern '{}'", pattern)
+            }
+            FilterDecision::Unfiltered => {
+                f.write_str("no include patterns and no exclude pattern matches")
+            }
+        }
+    }
 }
 
 /// Expand brace patterns like "*.{rs,toml}" into ["*.rs", "*.toml"]
-fn expand_braces(pattern: &str) -> Vec<String> {
+///
+/// Every brace group is expanded, nested ones included, so "{src,tests}/*.{rs,toml}"
+/// gives four patterns. Braces and commas inside a character class such as
+/// `[{,]` are literal, which lets classes and braces share a pattern, as in
+/// "file[0-9].{rs,toml}". Unbalanced braces are left as they are.
+pub(crate) fn expand_braces(pattern: &str) -> Vec<String> {
     // Look for pattern like "prefix{ext1,ext2,ext3}suffix"
-    if let Some(start) = pattern.find('{')
-        && let Some(end) = pattern.find('}')
-        && start < end
-    {
-        let prefix = &pattern[..start];
-        let suffix = &pattern[end + 1..];
-        let extensions = &pattern[start + 1..end];
-
-        return extensions
-            .split(',')
-            .map(|ext| format!("{}{}{}", prefix, ext.trim(), suffix))
-            .collect();
+    let Some((start, end, alternatives)) = find_brace_group(pattern, 0) else {
+        // No braces found, return original pattern
+        return vec![pattern.to_string()];
+    };
+    let prefix = &pattern[..start];
+    let suffix = &pattern[end + 1..];
+
+    // The suffix and the alternatives may hold more groups
+    alternatives
+        .into_iter()
+        .flat_map(|alternative| {
+            expand_braces(&format!("{}{}{}", prefix, alternative.trim(), suffix))
+        })
+        .collect()
+}
+
+/// Find the first brace group at or after `from`, outside character classes
+///
+/// Returns the positions of its braces and its comma-separated alternatives,
+/// which may contain nested groups. A `{` that is never closed is literal.
+fn find_brace_group(pattern: &str, from: usize) -> Option<(usize, usize, Vec<&str>)> {
+    let bytes = pattern.as_bytes();
+    let mut open = 0;
+    let mut depth = 0;
+    let mut alternatives = Vec::new();
+    let mut alternative_start = 0;
+
+    let mut i = from;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'[' => {
+                if let Some(end) = class_end(bytes, i) {
+                    i = end + 1;
+                    continue;
+                }
+            }
+            b'{' => {
+                if depth == 0 {
+                    open = i;
+                    alternative_start = i + 1;
+                }
+                depth += 1;
+            }
+            b',' if depth == 1 => {
+                alternatives.push(&pattern[alternative_start..i]);
+                alternative_start = i + 1;
+            }
+            b'}' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    alternatives.push(&pattern[alternative_start..i]);
+                    return Some((open, i, alternatives));
+                }
+            }
+            _ => {}
+        }
+        i += 1;
     }
 
-    // No braces found, return original pattern
-    vec![pattern.to_string()]
+    // Look for a group after the unclosed brace, e.g. the second one in "{a/{b,c}"
+    if depth > 0 {
+        return find_brace_group(pattern, open + 1);
+    }
+    None
+}
+
+/// Position of the `]` closing the character class opened at `open`
+///
+/// As in `glob::Pattern`, a `]` right after `[` or `[!` belongs to the class.
+/// None when the class is never closed; glob then rejects the pattern.
+fn class_end(bytes: &[u8], open: usize) -> Option<usize> {
+    let mut first = open + 1;
+    if bytes.get(first) == Some(&b'!') {
+        first += 1;
+    }
+    if bytes.get(first) == Some(&b']') {
+        first += 1;
+    }
+    let length = bytes.get(first..)?.iter().position(|&byte| byte == b']')?;
+    Some(first + length)
 }
 
 impl PatternFilter {
     /// Create a new pattern filter with include and exclude patterns
+    ///
+    /// A leading `!` negates a pattern, moving it to the other list: `!test_*.rs`
+    /// as an include is an exclude, and vice versa. Excludes always win over
+    /// includes, so a negated include overrides any positive include.
     pub fn new(include_patterns: Vec<String>, exclude_patterns: Vec<String>) -> Result<Self> {
+        let mut filter = Self {
+            include_patterns: Vec::new(),
+            exclude_patterns: Vec::new(),
+            gitignores: Vec::new(),
+            ignore_files: None,
+            absolute_root: None,
+            basename: false,
+            compiled: HashMap::new(),
+        };
+        filter.rebuild(include_patterns, exclude_patterns)?;
+        Ok(filter)
+    }
+
+    /// Replace the include and exclude patterns, as if created with `new`
+    ///
+    /// Patterns that were already in use are reused instead of compiled again,
+    /// which keeps frequent reloads of long pattern lists cheap. .gitignore rules
+    /// and the absolute or basename matching mode are kept. On error the filter is unchanged.
+    pub fn rebuild(
+        &mut self,
+        include_patterns: Vec<String>,
+        exclude_patterns: Vec<String>,
+    ) -> Result<()> {
+        let (include_patterns, exclude_patterns) =
+            Self::route_negations(include_patterns, exclude_patterns);
+
         // Expand brace patterns before compilation
-        let expanded_include: Vec<String> = include_patterns
+        let include_patterns = self
+            .compile_patterns(Self::expand_patterns(include_patterns, "include"))
+            .context("Failed to compile include patterns")?;
+
+        let exclude_patterns = self
+            .compile_patterns(Self::expand_patterns(exclude_patterns, "exclude"))
+            .context("Failed to compile exclude patterns")?;
+
+        // Only the current patterns are cached, so removed ones don't pile up
+        self.compiled = include_patterns
             .iter()
-            .flat_map(|p| {
-                let expanded = expand_braces(p);
-                if log::log_enabled!(log::Level::Debug) && expanded.len() > 1 {
-                    log::debug!("Expanded include pattern '{}' to {:?}", p, expanded);
-                }
-                expanded
-            })
+            .chain(&exclude_patterns)
+            .map(|pattern| (pattern.as_str().to_string(), pattern.clone()))
             .collect();
+        self.include_patterns = include_patterns;
+        self.exclude_patterns = exclude_patterns;
+        Ok(())
+    }
 
-        let expanded_exclude: Vec<String> = exclude_patterns
+    /// Expand the brace patterns in a list, logging each expansion
+    fn expand_patterns(patterns: Vec<String>, kind: &str) -> Vec<String> {
+        patterns
             .iter()
             .flat_map(|p| {
                 let expanded = expand_braces(p);
                 if log::log_enabled!(log::Level::Debug) && expanded.len() > 1 {
-                    log::debug!("Expanded exclude pattern '{}' to {:?}", p, expanded);
+                    log::debug!("Expanded {} pattern '{}' to {:?}", kind, p, expanded);
                 }
                 expanded
             })
-            .collect();
+            .collect()
+    }
 
-        let include_patterns = Self::compile_patterns(expanded_include)
-            .context("Failed to compile include patterns")?;
+    /// Move `!`-prefixed patterns to the opposite list, without the `!`
+    fn route_negations(
+        include_patterns: Vec<String>,
+        exclude_patterns: Vec<String>,
+    ) -> (Vec<String>, Vec<String>) {
+        let mut includes = Vec::with_capacity(include_patterns.len());
+        let mut excludes = Vec::with_capacity(exclude_patterns.len());
+
+        for pattern in include_patterns {
+            match pattern.strip_prefix('!') {
+                Some(negated) => excludes.push(negated.to_string()),
+                None => includes.push(pattern),
+            }
+        }
+        for pattern in exclude_patterns {
+            match pattern.strip_prefix('!') {
+                Some(negated) => includes.push(negated.to_string()),
+                None => excludes.push(pattern),
+            }
+        }
 
-        let exclude_patterns = Self::compile_patterns(expanded_exclude)
-            .context("Failed to compile exclude patterns")?;
+        (includes, excludes)
+    }
 
-        Ok(Self {
-            include_patterns,
-            exclude_patterns,
-        })
+    /// Also exclude files ignored by the .gitignore files under `root`
+    ///
+    /// Nested .gitignore files apply to their own directory, like in git.
+    /// Files are only read once, so .gitignore changes need a restart.
+    pub fn with_gitignore(mut self, root: &Path) -> Result<Self> {
+        let walker = ignore::WalkBuilder::new(root)
+            .hidden(false)
+            .require_git(false)
+            .parents(false)
+            .ignore(false)
+            .git_global(false)
+            .git_exclude(false)
+            .filter_entry(|entry| entry.file_name() != ".git")
+            .build();
+
+        for entry in walker {
+            let entry = entry.context("Failed to scan for .gitignore files")?;
+            if entry.file_name() != ".gitignore" || !entry.path().is_file() {
+                continue;
+            }
+
+            let dir = entry.path().parent().unwrap_or(root);
+            let mut builder = GitignoreBuilder::new(dir);
+            if let Some(e) = builder.add(entry.path()) {
+                return Err(e)
+                    .with_context(|| format!("Failed to read {}", entry.path().display()));
+            }
+            let gitignore = builder
+                .build()
+                .with_context(|| format!("Invalid {}", entry.path().display()))?;
+
+            let relative_dir = dir
+                .strip_prefix(root)
+                .unwrap_or(Path::new(""))
+                .to_path_buf();
+            log::debug!(
+                "Loaded {} .gitignore rules from {}",
+                gitignore.num_ignores(),
+                entry.path().display()
+            );
+            self.gitignores.push((relative_dir, gitignore));
+        }
+
+        // The deepest .gitignore that has an opinion on a path wins
+        self.gitignores
+            .sort_by_key(|(dir, _)| std::cmp::Reverse(dir.components().count()));
+
+        Ok(self)
+    }
+
+    /// Also exclude files matching the rules of gitignore-syntax `files`, like `.ignore`
+    ///
+    /// The rules of all files are combined, later files overriding earlier ones,
+    /// and anchored patterns like `/build/` are relative to `root`. They support
+    /// `!` to re-include and a trailing `/` to only match directories. Files are
+    /// only read once, so changes need a restart.
+    pub fn with_ignore_files(mut self, root: &Path, files: &[PathBuf]) -> Result<Self> {
+        if files.is_empty() {
+            return Ok(self);
+        }
+
+        let mut builder = GitignoreBuilder::new(root);
+        for file in files {
+            if let Some(e) = builder.add(file) {
+                return Err(e).with_context(|| format!("Failed to read {}", file.display()));
+            }
+        }
+        let rules = builder.build().context("Invalid ignore file")?;
+        log::debug!("Loaded {} ignore file rules", rules.len());
+        self.ignore_files = Some(rules);
+        Ok(self)
+    }
+
+    /// Match patterns against absolute paths under `root` instead of relative ones
+    ///
+    /// Paths passed in stay relative to `root`; they are joined onto it before
+    /// matching. Patterns starting with `**/` or without a `/`, like
+    /// `**/target/**` or `*.rs`, match the same files either way. .gitignore
+    /// rules always see the relative path.
+    pub fn with_absolute_paths(mut self, root: &Path) -> Self {
+        self.absolute_root = Some(root.to_path_buf());
+        self
+    }
+
+    /// Match patterns against each path's file name instead of the whole path
+    ///
+    /// `main.rs` then matches `src/bin/main.rs`, while patterns containing a
+    /// `/`, like `src/*.rs`, never match. Takes precedence over
+    /// `with_absolute_paths`. .gitignore rules always see the relative path.
+    pub fn with_basenames(mut self) -> Self {
+        self.basename = true;
+        self
     }
 
     /// Check if a file path should be watched based on include/exclude patterns
     pub fn should_watch(&self, path: &Path) -> bool {
-        let path_str = path.to_string_lossy();
+        let decision = self.explain(path);
+        if !decision.is_watched() {
+            log::debug!("File not watched, {}: {}", decision, path.display());
+        }
+        decision.is_watched()
+    }
+
+    /// Decide whether a path is watched, and which rule decided it
+    ///
+    /// Excludes win over ignore files, then .gitignore, then includes. A path
+    /// re-included by an ignore file's `!` rule skips .gitignore, like ripgrep's
+    /// `.ignore`. Patterns are reported as written after brace expansion.
+    pub fn explain(&self, path: &Path) -> FilterDecision {
+        let subject = self.match_subject(path);
+        let path_str = subject.to_string_lossy();
+
+        if let Some(pattern) = Self::first_match(&self.exclude_patterns, &path_str) {
+            return FilterDecision::Excluded(pattern.as_str().to_string());
+        }
+
+        let ignore_file = self
+            .ignore_files
+            .as_ref()
+            .map(|rules| rules.matched_path_or_any_parents(path, false));
+        if let Some(ignore::Match::Ignore(glob)) = ignore_file {
+            return FilterDecision::IgnoreFile {
+                rule: glob.original().to_string(),
+                file: glob.from().map(Path::to_path_buf).unwrap_or_default(),
+            };
+        }
 
-        // If file matches any exclude pattern, don't watch it
-        if self.matches_any_pattern(&self.exclude_patterns, &path_str) {
-            log::debug!("File excluded by pattern: {}", path_str);
-            return false;
+        // Files ignored by git are excluded as well, regardless of includes
+        let whitelisted = matches!(ignore_file, Some(ignore::Match::Whitelist(_)));
+        if !whitelisted && self.is_gitignored(path) {
+            return FilterDecision::Gitignored;
         }
 
         // If there are include patterns, file must match at least one
-        if !self.include_patterns.is_empty() {
-            let matches = self.matches_any_pattern(&self.include_patterns, &path_str);
-            if !matches {
-                log::debug!("File doesn't match include patterns: {}", path_str);
-            }
-            return matches;
+        if self.include_patterns.is_empty() {
+            return FilterDecision::Unfiltered;
         }
+        match Self::first_match(&self.include_patterns, &path_str) {
+            Some(pattern) => FilterDecision::Included(pattern.as_str().to_string()),
+            None => FilterDecision::NotIncluded,
+        }
+    }
 
-        // If no include patterns specified, watch everything (that doesn't match exclude)
-        true
+    /// The first include pattern matching a path, as written after brace expansion
+    ///
+    /// None when no include patterns are configured or none matches. Excludes
+    /// are not considered, so pair this with `should_watch`.
+    pub fn matched_include(&self, path: &Path) -> Option<&str> {
+        let subject = self.match_subject(path);
+        let path_str = subject.to_string_lossy();
+        self.include_patterns
+            .iter()
+            .find(|pattern| pattern.matches(&path_str))
+            .map(Pattern::as_str)
     }
 
-    /// Compile string patterns into glob Pattern objects
-    fn compile_patterns(patterns: Vec<String>) -> Result<Vec<Pattern>, PatternError> {
-        patterns.into_iter().map(|p| Pattern::new(&p)).collect()
+    /// The path patterns are matched against: the file name with `with_basenames`,
+    /// absolute with `with_absolute_paths`
+    fn match_subject<'a>(&self, path: &'a Path) -> Cow<'a, Path> {
+        if self.basename {
+            return Cow::Borrowed(path.file_name().map_or(path, Path::new));
+        }
+        match &self.absolute_root {
+            Some(root) => Cow::Owned(root.join(path)),
+            None => Cow::Borrowed(path),
+        }
     }
 
-    /// Check if path matches any of the given patterns
-    fn matches_any_pattern(&self, patterns: &[Pattern], path: &str) -> bool {
-        patterns.iter().any(|pattern| {
-            let matches = pattern.matches(path);
-            if matches {
-                log::debug!("Path '{}' matches pattern '{}'", path, pattern.as_str());
+    /// Check if a path relative to the watch root is ignored by a .gitignore
+    fn is_gitignored(&self, path: &Path) -> bool {
+        self.gitignores
+            .iter()
+            .filter_map(|(dir, gitignore)| {
+                let path = path.strip_prefix(dir).ok()?;
+                let matched = gitignore.matched_path_or_any_parents(path, false);
+                (!matched.is_none()).then(|| matched.is_ignore())
+            })
+            .next()
+            .unwrap_or(false)
+    }
+
+    /// Read glob patterns from a file, one per line
+    ///
+    /// Surrounding whitespace is trimmed, and blank lines and lines starting
+    /// with `#` are skipped. Each pattern is checked as it would be inline, so
+    /// an invalid one is reported with the file and line it came from.
+    pub fn read_patterns(path: &Path) -> Result<Vec<String>> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read pattern file: {}", path.display()))?;
+
+        let mut patterns = Vec::new();
+        for (index, line) in contents.lines().enumerate() {
+            let pattern = line.trim();
+            if pattern.is_empty() || pattern.starts_with('#') {
+                continue;
             }
-            matches
-        })
+
+            Self::new(vec![pattern.to_string()], Vec::new()).with_context(|| {
+                format!(
+                    "Invalid pattern '{}' at {}:{}",
+                    pattern,
+                    path.display(),
+                    index + 1
+                )
+            })?;
+            patterns.push(pattern.to_string());
+        }
+
+        Ok(patterns)
+    }
+
+    /// Compile string patterns into glob Pattern objects, reusing cached ones
+    fn compile_patterns(&self, patterns: Vec<String>) -> Result<Vec<Pattern>, PatternError> {
+        patterns
+            .into_iter()
+            .map(|p| match self.compiled.get(&p) {
+                Some(pattern) => Ok(pattern.clone()),
+                None => Pattern::new(&p),
+            })
+            .collect()
+    }
+
+    /// The first of the given patterns that matches a path
+    fn first_match<'a>(patterns: &'a [Pattern], path: &str) -> Option<&'a Pattern> {
+        let pattern = patterns.iter().find(|pattern| pattern.matches(path))?;
+        log::debug!("Path '{}' matches pattern '{}'", path, pattern.as_str());
+        Some(pattern)
     }
 }
 
@@ -147,6 +520,104 @@ mod tests {
         assert!(!filter.should_watch(&PathBuf::from("app.js")));
     }
 
+    #[rstest]
+    #[case(&["*.rs", "!test_*.rs"], &[], "main.rs", true)]
+    #[case(&["*.rs", "!test_*.rs"], &[], "test_main.rs", false)]
+    #[case(&["*.rs", "!test_*.rs"], &[], "README.md", false)]
+    #[case(&["!test_*.rs", "*.rs"], &[], "test_main.rs", false)]
+    #[case(&["!*.tmp"], &[], "notes.txt", true)]
+    #[case(&["!*.tmp"], &[], "notes.tmp", false)]
+    #[case(&[], &["!*.rs"], "main.rs", true)]
+    #[case(&[], &["!*.rs"], "README.md", false)]
+    #[case(&["src/**"], &["!*.rs"], "src/lib.rs", true)]
+    fn test_negated_patterns(
+        #[case] include: &[&str],
+        #[case] exclude: &[&str],
+        #[case] path: &str,
+        #[case] expected: bool,
+    ) {
+        let to_vec = |patterns: &[&str]| patterns.iter().map(|p| p.to_string()).collect();
+        let filter = PatternFilter::new(to_vec(include), to_vec(exclude)).unwrap();
+
+        assert_eq!(
+            filter.should_watch(&PathBuf::from(path)),
+            expected,
+            "include {:?}, exclude {:?}, path '{}'",
+            include,
+            exclude,
+            path
+        );
+    }
+
+    #[rstest]
+    #[case("src/main.rs", Some("*.rs"))]
+    #[case("Cargo.toml", Some("*.toml"))]
+    #[case("docs/guide.md", Some("docs/**"))]
+    #[case("docs/notes.rs", Some("*.rs"))]
+    #[case("README.txt", None)]
+    fn test_matched_include(#[case] path: &str, #[case] expected: Option<&str>) {
+        let filter = PatternFilter::new(
+            vec![
+                "*.rs".to_string(),
+                "*.toml".to_string(),
+                "docs/**".to_string(),
+            ],
+            vec![],
+        )
+        .unwrap();
+
+        assert_eq!(filter.matched_include(&PathBuf::from(path)), expected);
+    }
+
+    #[test]
+    fn test_matched_include_reports_expanded_pattern() {
+        let filter = PatternFilter::new(vec!["*.{ts,tsx}".to_string()], vec![]).unwrap();
+        assert_eq!(filter.matched_include(Path::new("App.tsx")), Some("*.tsx"));
+
+        let filter = PatternFilter::new(vec![], vec![]).unwrap();
+        assert_eq!(filter.matched_include(Path::new("App.tsx")), None);
+    }
+
+    #[test]
+    fn test_read_patterns_skips_blank_lines_and_comments() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file = temp_dir.path().join("patterns.txt");
+        std::fs::write(
+            &file,
+            "# Sources\n*.rs\n\n  *.{ts,tsx}  \n#*.md\n!test_*.rs\n",
+        )
+        .unwrap();
+
+        let patterns = PatternFilter::read_patterns(&file).unwrap();
+        assert_eq!(patterns, ["*.rs", "*.{ts,tsx}", "!test_*.rs"]);
+    }
+
+    #[test]
+    fn test_read_patterns_reports_file_and_line_of_invalid_pattern() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file = temp_dir.path().join("patterns.txt");
+        std::fs::write(&file, "*.rs\n# comment\n[invalid\n").unwrap();
+
+        let err = PatternFilter::read_patterns(&file).unwrap_err();
+        let message = format!("{:#}", err);
+        assert!(
+            message.contains(&format!("'[invalid' at {}:3", file.display())),
+            "{}",
+            message
+        );
+        assert!(
+            message.contains("Failed to compile include patterns"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn test_read_patterns_missing_file() {
+        let err = PatternFilter::read_patterns(Path::new("/nonexistent/patterns.txt")).unwrap_err();
+        assert!(err.to_string().contains("Failed to read pattern file"));
+    }
+
     #[test]
     fn test_no_patterns_watches_all() {
         let filter = PatternFilter::new(vec![], vec![]).unwrap();
@@ -397,6 +868,86 @@ mod tests {
         assert_eq!(expanded, vec!["*."]);
     }
 
+    #[rstest]
+    #[case("{a,b}/{c,d}", &["a/c", "a/d", "b/c", "b/d"])]
+    #[case(
+        "{src,tests}/{unit,e2e}/*.{rs,toml}",
+        &[
+            "src/unit/*.rs",
+            "src/unit/*.toml",
+            "src/e2e/*.rs",
+            "src/e2e/*.toml",
+            "tests/unit/*.rs",
+            "tests/unit/*.toml",
+            "tests/e2e/*.rs",
+            "tests/e2e/*.toml",
+        ]
+    )]
+    #[case("{a,b}/{c", &["a/{c", "b/{c"])]
+    #[case("{a/{c,d}", &["{a/c", "{a/d"])]
+    #[case("{a, b}-{ c,d }", &["a-c", "a-d", "b-c", "b-d"])]
+    fn test_brace_expansion_multiple_groups(#[case] pattern: &str, #[case] expected: &[&str]) {
+        assert_eq!(expand_braces(pattern), expected);
+    }
+
+    #[test]
+    fn test_filter_with_two_brace_groups() {
+        let filter =
+            PatternFilter::new(vec!["{src,tests}/*.{rs,toml}".to_string()], vec![]).unwrap();
+
+        assert!(filter.should_watch(&PathBuf::from("src/main.rs")));
+        assert!(filter.should_watch(&PathBuf::from("tests/Cargo.toml")));
+        assert!(!filter.should_watch(&PathBuf::from("benches/main.rs")));
+        assert!(!filter.should_watch(&PathBuf::from("src/README.md")));
+    }
+
+    #[rstest]
+    #[case("file[0-9].rs", &["file[0-9].rs"])]
+    #[case("file[0-9].{rs,toml}", &["file[0-9].rs", "file[0-9].toml"])]
+    #[case("[!._]*.{rs,toml}", &["[!._]*.rs", "[!._]*.toml"])]
+    #[case("[{]*.{rs,toml}", &["[{]*.rs", "[{]*.toml"])]
+    #[case("{[,]x,y}", &["[,]x", "y"])]
+    #[case("{a,[}]b}.rs", &["a.rs", "[}]b.rs"])]
+    #[case("[]{]{a,b}", &["[]{]a", "[]{]b"])]
+    #[case("[!]]{a,b}", &["[!]]a", "[!]]b"])]
+    #[case("{src,tests}/*.{rs,toml}", &["src/*.rs", "src/*.toml", "tests/*.rs", "tests/*.toml"])]
+    #[case("{a,b{c,d}}", &["a", "bc", "bd"])]
+    #[case("[{]", &["[{]"])]
+    #[case("*[.{rs", &["*[.{rs"])]
+    #[case("}{a,b}", &["}a", "}b"])]
+    fn test_brace_expansion_with_character_classes(
+        #[case] pattern: &str,
+        #[case] expected: &[&str],
+    ) {
+        assert_eq!(expand_braces(pattern), expected);
+    }
+
+    #[rstest]
+    #[case("file[0-9].{rs,toml}", "file3.rs", true)]
+    #[case("file[0-9].{rs,toml}", "file7.toml", true)]
+    #[case("file[0-9].{rs,toml}", "filex.rs", false)]
+    #[case("file[0-9].{rs,toml}", "file10.rs", false)]
+    #[case("file[!0-9].{rs,toml}", "filex.rs", true)]
+    #[case("file[!0-9].{rs,toml}", "file3.toml", false)]
+    #[case("{src,tests}/[a-c]*.rs", "tests/build.rs", true)]
+    #[case("{src,tests}/[a-c]*.rs", "src/main.rs", false)]
+    #[case("[{]*.{rs,toml}", "{braced.rs", true)]
+    #[case("[{]*.{rs,toml}", "plain.rs", false)]
+    fn test_character_classes_with_braces_match(
+        #[case] pattern: &str,
+        #[case] path: &str,
+        #[case] should_match: bool,
+    ) {
+        let filter = PatternFilter::new(vec![pattern.to_string()], vec![]).unwrap();
+        assert_eq!(
+            filter.should_watch(&PathBuf::from(path)),
+            should_match,
+            "Pattern '{}' with path '{}'",
+            pattern,
+            path
+        );
+    }
+
     #[test]
     fn test_filter_with_brace_expansion() {
         let filter = PatternFilter::new(vec!["*.{rs,toml}".to_string()], vec![]).unwrap();
@@ -519,4 +1070,291 @@ mod tests {
         assert!(filter.should_watch(&PathBuf::from("src/main.rs")));
         assert!(!filter.should_watch(&PathBuf::from("src/test_helper.rs")));
     }
+
+    #[test]
+    fn test_gitignore_excludes_ignored_files() {
+        let root = tempfile::TempDir::new().unwrap();
+        std::fs::write(root.path().join(".gitignore"), "*.log\n/build/\n").unwrap();
+        std::fs::create_dir(root.path().join("build")).unwrap();
+
+        let filter = PatternFilter::new(vec![], vec![])
+            .unwrap()
+            .with_gitignore(root.path())
+            .unwrap();
+
+        assert!(!filter.should_watch(&PathBuf::from("debug.log")));
+        assert!(!filter.should_watch(&PathBuf::from("src/debug.log")));
+        assert!(!filter.should_watch(&PathBuf::from("build/out.js")));
+        assert!(filter.should_watch(&PathBuf::from("src/main.rs")));
+        assert!(filter.should_watch(&PathBuf::from("src/build/mod.rs")));
+    }
+
+    #[test]
+    fn test_gitignore_takes_precedence_over_include() {
+        let root = tempfile::TempDir::new().unwrap();
+        std::fs::write(root.path().join(".gitignore"), "generated.rs\n").unwrap();
+
+        let filter = PatternFilter::new(vec!["*.rs".to_string()], vec![])
+            .unwrap()
+            .with_gitignore(root.path())
+            .unwrap();
+
+        assert!(!filter.should_watch(&PathBuf::from("generated.rs")));
+        assert!(filter.should_watch(&PathBuf::from("main.rs")));
+    }
+
+    #[test]
+    fn test_nested_gitignore_applies_to_its_directory() {
+        let root = tempfile::TempDir::new().unwrap();
+        std::fs::write(root.path().join(".gitignore"), "*.tmp\n").unwrap();
+        std::fs::create_dir(root.path().join("web")).unwrap();
+        std::fs::write(root.path().join("web/.gitignore"), "/dist/\n!keep.tmp\n").unwrap();
+
+        let filter = PatternFilter::new(vec![], vec![])
+            .unwrap()
+            .with_gitignore(root.path())
+            .unwrap();
+
+        // Anchored patterns are relative to the nested .gitignore
+        assert!(!filter.should_watch(&PathBuf::from("web/dist/app.js")));
+        assert!(filter.should_watch(&PathBuf::from("dist/app.js")));
+
+        // The nested file can re-include what a parent ignores
+        assert!(!filter.should_watch(&PathBuf::from("web/scratch.tmp")));
+        assert!(filter.should_watch(&PathBuf::from("web/keep.tmp")));
+    }
+
+    #[test]
+    fn test_ignore_file_negation_re_includes_path() {
+        let root = tempfile::TempDir::new().unwrap();
+        let ignore_file = root.path().join(".ignore");
+        std::fs::write(&ignore_file, "*.log\n!keep.log\ncache/\n").unwrap();
+
+        let filter = PatternFilter::new(vec![], vec![])
+            .unwrap()
+            .with_ignore_files(root.path(), std::slice::from_ref(&ignore_file))
+            .unwrap();
+
+        assert!(!filter.should_watch(&PathBuf::from("debug.log")));
+        assert!(!filter.should_watch(&PathBuf::from("logs/debug.log")));
+        assert!(filter.should_watch(&PathBuf::from("keep.log")));
+        assert!(filter.should_watch(&PathBuf::from("logs/keep.log")));
+        // Directory-only rules cover everything inside the directory
+        assert!(!filter.should_watch(&PathBuf::from("src/cache/data.bin")));
+        assert!(filter.should_watch(&PathBuf::from("src/cache.rs")));
+        assert_eq!(
+            filter.explain(&PathBuf::from("debug.log")),
+            FilterDecision::IgnoreFile {
+                rule: "*.log".to_string(),
+                file: ignore_file,
+            }
+        );
+    }
+
+    #[test]
+    fn test_ignore_file_precedence() {
+        let root = tempfile::TempDir::new().unwrap();
+        std::fs::write(root.path().join(".gitignore"), "*.log\n").unwrap();
+        let first = root.path().join("first.ignore");
+        let second = root.path().join("second.ignore");
+        std::fs::write(&first, "*.tmp\n").unwrap();
+        std::fs::write(&second, "!keep.tmp\n!keep.log\n").unwrap();
+
+        let filter = PatternFilter::new(vec![], vec!["secret.log".to_string()])
+            .unwrap()
+            .with_gitignore(root.path())
+            .unwrap()
+            .with_ignore_files(root.path(), &[first, second])
+            .unwrap();
+
+        // Later files override earlier ones
+        assert!(!filter.should_watch(&PathBuf::from("scratch.tmp")));
+        assert!(filter.should_watch(&PathBuf::from("keep.tmp")));
+        // A re-include beats .gitignore, but never an inline exclude
+        assert!(!filter.should_watch(&PathBuf::from("debug.log")));
+        assert!(filter.should_watch(&PathBuf::from("keep.log")));
+        assert!(!filter.should_watch(&PathBuf::from("secret.log")));
+    }
+
+    #[test]
+    fn test_missing_ignore_file_is_an_error() {
+        let root = tempfile::TempDir::new().unwrap();
+        let result = PatternFilter::new(vec![], vec![])
+            .unwrap()
+            .with_ignore_files(root.path(), &[root.path().join("missing")]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_gitignore_not_applied_by_default() {
+        let filter = PatternFilter::new(vec![], vec![]).unwrap();
+        assert!(filter.should_watch(&PathBuf::from("debug.log")));
+    }
+
+    #[rstest]
+    #[case("/srv/app/vendor/**", "vendor/lib.rs", false)]
+    #[case("/srv/app/vendor/**", "src/vendor/lib.rs", true)]
+    #[case("**/target/**", "target/debug/main.rs", false)]
+    #[case("*.tmp", "src/scratch.tmp", false)]
+    #[case("*.tmp", "src/main.rs", true)]
+    fn test_absolute_paths_match_excludes(
+        #[case] exclude: &str,
+        #[case] path: &str,
+        #[case] watched: bool,
+    ) {
+        let filter = PatternFilter::new(vec![], vec![exclude.to_string()])
+            .unwrap()
+            .with_absolute_paths(Path::new("/srv/app"));
+
+        assert_eq!(filter.should_watch(Path::new(path)), watched);
+    }
+
+    #[rstest]
+    #[case("main.rs", "src/bin/main.rs", false, true)]
+    #[case("main.rs", "main.rs", true, true)]
+    #[case("test_*", "tests/test_parser.rs", false, true)]
+    #[case("*.rs", "deep/dir/main.rs", true, true)]
+    #[case("src/*.rs", "src/main.rs", true, false)]
+    #[case("**/target/**", "target/debug/main.rs", true, false)]
+    fn test_basenames_versus_full_paths(
+        #[case] include: &str,
+        #[case] path: &str,
+        #[case] full_path_match: bool,
+        #[case] basename_match: bool,
+    ) {
+        let filter = PatternFilter::new(vec![include.to_string()], vec![]).unwrap();
+        assert_eq!(filter.should_watch(Path::new(path)), full_path_match);
+
+        let filter = filter.with_basenames();
+        assert_eq!(filter.should_watch(Path::new(path)), basename_match);
+    }
+
+    #[test]
+    fn test_basenames_apply_to_excludes_and_survive_rebuild() {
+        let mut filter = PatternFilter::new(vec![], vec!["Cargo.lock".to_string()])
+            .unwrap()
+            .with_basenames();
+        assert!(!filter.should_watch(Path::new("crates/core/Cargo.lock")));
+
+        filter.rebuild(vec!["mod.rs".to_string()], vec![]).unwrap();
+        assert!(filter.should_watch(Path::new("src/parser/mod.rs")));
+        assert_eq!(
+            filter.matched_include(Path::new("src/parser/mod.rs")),
+            Some("mod.rs")
+        );
+    }
+
+    #[test]
+    fn test_absolute_pattern_ignored_without_absolute_paths() {
+        let filter = PatternFilter::new(vec![], vec!["/srv/app/vendor/**".to_string()]).unwrap();
+        assert!(filter.should_watch(Path::new("vendor/lib.rs")));
+
+        // Unanchored patterns behave the same in both modes
+        let filter = PatternFilter::new(vec![], vec!["**/target/**".to_string()]).unwrap();
+        assert!(!filter.should_watch(Path::new("target/debug/main.rs")));
+    }
+
+    #[test]
+    fn test_absolute_paths_report_matched_include() {
+        let filter = PatternFilter::new(vec!["/srv/app/src/*.rs".to_string()], vec![])
+            .unwrap()
+            .with_absolute_paths(Path::new("/srv/app"));
+
+        assert_eq!(
+            filter.matched_include(Path::new("src/main.rs")),
+            Some("/srv/app/src/*.rs")
+        );
+        assert!(!filter.should_watch(Path::new("tests/it.rs")));
+    }
+
+    fn to_strings(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn test_rebuild_matches_like_new() {
+        let include = to_strings(&["*.{rs,toml}", "!test_*.rs"]);
+        let exclude = to_strings(&["target/**", "*.tmp"]);
+        let mut rebuilt =
+            PatternFilter::new(to_strings(&["*.rs"]), to_strings(&["*.tmp"])).unwrap();
+        rebuilt.rebuild(include.clone(), exclude.clone()).unwrap();
+        let fresh = PatternFilter::new(include, exclude).unwrap();
+
+        for path in [
+            "main.rs",
+            "Cargo.toml",
+            "test_main.rs",
+            "target/debug/main.rs",
+            "scratch.tmp",
+            "README.md",
+        ] {
+            let path = Path::new(path);
+            assert_eq!(
+                rebuilt.should_watch(path),
+                fresh.should_watch(path),
+                "{path:?}"
+            );
+            assert_eq!(rebuilt.matched_include(path), fresh.matched_include(path));
+        }
+    }
+
+    #[rstest]
+    #[case(&["*.rs"], &["target/**"], "target/main.rs", FilterDecision::Excluded("target/**".into()))]
+    #[case(&["*.rs"], &["target/**"], "src/main.rs", FilterDecision::Included("*.rs".into()))]
+    #[case(&["*.rs"], &[], "README.md", FilterDecision::NotIncluded)]
+    #[case(&[], &["*.tmp"], "README.md", FilterDecision::Unfiltered)]
+    #[case(&["*.{rs,toml}"], &[], "Cargo.toml", FilterDecision::Included("*.toml".into()))]
+    fn test_explain(
+        #[case] include: &[&str],
+        #[case] exclude: &[&str],
+        #[case] path: &str,
+        #[case] expected: FilterDecision,
+    ) {
+        let filter = PatternFilter::new(to_strings(include), to_strings(exclude)).unwrap();
+
+        let decision = filter.explain(Path::new(path));
+        assert_eq!(decision.is_watched(), filter.should_watch(Path::new(path)));
+        assert_eq!(decision, expected);
+    }
+
+    #[test]
+    fn test_explain_text() {
+        assert_eq!(
+            FilterDecision::Excluded("target/**".into()).to_string(),
+            "matches exclude pattern 'target/**'"
+        );
+        assert_eq!(
+            FilterDecision::Included("*.rs".into()).to_string(),
+            "matches include pattern '*.rs'"
+        );
+        assert_eq!(
+            FilterDecision::NotIncluded.to_string(),
+            "matches no include pattern"
+        );
+    }
+
+    #[test]
+    fn test_rebuild_caches_only_current_patterns() {
+        let mut filter = PatternFilter::new(to_strings(&["*.rs", "*.md"]), vec![]).unwrap();
+        filter
+            .rebuild(to_strings(&["*.rs"]), to_strings(&["*.{tmp,log}"]))
+            .unwrap();
+
+        let mut cached: Vec<&str> = filter.compiled.keys().map(String::as_str).collect();
+        cached.sort_unstable();
+        assert_eq!(cached, ["*.log", "*.rs", "*.tmp"]);
+    }
+
+    #[test]
+    fn test_rebuild_error_leaves_filter_unchanged() {
+        let mut filter = PatternFilter::new(to_strings(&["*.rs"]), vec![]).unwrap();
+        assert!(
+            filter
+                .rebuild(to_strings(&["*.md", "[invalid"]), vec![])
+                .is_err()
+        );
+
+        assert!(filter.should_watch(Path::new("main.rs")));
+        assert!(!filter.should_watch(Path::new("README.md")));
+    }
 }
@@ -0,0 +1,30 @@
+//! File watching with glob filtering and command execution
+//!
+//! This is the engine behind the `vibewatch` CLI. Build a [`FileWatcher`] with
+//! [`WatcherBuilder`] and run it with [`FileWatcher::start_watching`].
+
+mod builder;
+mod coalesce;
+pub mod config;
+pub mod debounce;
+mod filter;
+pub mod metrics;
+mod move_tracker;
+pub mod output;
+pub mod overlap;
+mod persistent_shell;
+mod restart;
+mod result_log;
+mod settle;
+mod shell_quote;
+mod stats;
+pub mod status_file;
+pub mod watch_limit;
+pub mod watcher;
+
+pub use builder::WatcherBuilder;
+pub use filter::PatternFilter;
+pub use watcher::{
+    CommandConfig, EntryFilter, EventContext, EventKinds, FileEvent, FileWatcher, WatchRules,
+    WorkingDir,
+};
@@ -1,219 +1,2292 @@
-use clap::Parser;
-use std::path::PathBuf;
+use anyhow::Context;
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser};
+use serde::Serialize;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
-mod filter;
-mod watcher;
+use vibewatch::config::{parse_duration, parse_millis};
+use vibewatch::{config, debounce, metrics, output, overlap, status_file, watch_limit, watcher};
+
+/// Poll interval used by --poll when --poll-interval isn't given
+const DEFAULT_POLL_INTERVAL_MS: u64 = 1000;
 
 // Help section headings
 const FILTERING_HELP: &str = "File Filtering";
 const COMMANDS_HELP: &str = "Command Execution";
 const GENERAL_HELP: &str = "General Options";
 
-#[derive(Parser)]
+#[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
 #[command(name = "vibewatch")]
 #[command(
     about = "A powerful file watcher with command execution",
     long_about = "vibewatch watches a directory for file changes and executes commands when events occur.\n\nIt supports glob patterns for precise filtering and template substitution for command execution.\nInspired by tools like watchexec, entr, and nodemon, but with a focus on simplicity and reliability.",
-    after_help = "EXAMPLES:\n\n  # Watch current directory and run tests on any change\n  vibewatch . --on-change 'npm test'\n\n  # Watch Rust files and format them when modified\n  vibewatch src --include '*.rs' --on-modify 'rustfmt {file_path}'\n\n  # Watch TypeScript files, exclude node_modules, run linter\n  vibewatch . --include '*.{ts,tsx}' --exclude 'node_modules/**' --on-modify 'npx eslint {file_path} --fix'\n\n  # Different commands for different events\n  vibewatch src --on-create 'git add {file_path}' --on-modify 'cargo check' --on-delete 'echo Removed: {relative_path}'\n\n  # Watch docs and rebuild on changes\n  vibewatch docs --include '*.md' --on-change 'mdbook build'\n\nTEMPLATES:\n  {file_path}      - Full path to the changed file\n  {relative_path}  - Path relative to watched directory\n  {absolute_path}  - Absolute path to the changed file\n  {event_type}     - Type of event (create, modify, delete)\n\nNOTE:\n  Commands are executed asynchronously. Multiple events may trigger\n  overlapping command executions."
+    after_help = "EXAMPLES:\n\n  # Watch current directory and run tests on any change\n  vibewatch . --on-change 'npm test'\n\n  # Watch Rust files and format them when modified\n  vibewatch src --include '*.rs' --on-modify 'rustfmt {file_path}'\n\n  # Watch TypeScript files, exclude node_modules, run linter\n  vibewatch . --include '*.{ts,tsx}' --exclude 'node_modules/**' --on-modify 'npx eslint {file_path} --fix'\n\n  # Different commands for different events\n  vibewatch src --on-create 'git add {file_path}' --on-modify 'cargo check' --on-delete 'echo Removed: {relative_path}'\n\n  # Watch docs and rebuild on changes\n  vibewatch docs --include '*.md' --on-change 'mdbook build'\n\n  # Load watch rules from a config file (CLI flags override file values)\n  vibewatch --config vibewatch.toml\n\nTEMPLATES:\n  {file_path}      - Full path to the changed file\n  {relative_path}  - Path relative to watched directory\n  {absolute_path}  - Absolute path to the changed file\n  {event_type}     - Type of event (create, modify, delete, move)\n  {old_path}       - Source path of a moved file (empty for other events)\n  {new_path}       - Destination path of a moved file (empty for other events)\n  {file_name}      - File name of the changed file (e.g. main.rs)\n  {file_stem}      - File name without its extension (e.g. main)\n  {file_ext}       - File extension without the dot (e.g. rs, empty if none)\n  {changed_files}  - Shell-quoted relative paths of all changed files (with --batch)\n  {timestamp}      - When the event was handled (RFC 3339, e.g. 2024-05-17T09:30:15.000+02:00)\n  {matched_pattern} - Include pattern the file matched (empty without --include)\n  {exit_code}      - Exit code of the finished command (--on-command-success/failure only)\n  {count}          - Number of the event, from 0 (resets when vibewatch restarts)\n  ${VAR}           - Value of environment variable VAR (empty if unset)\n  {{ and }}        - Literal { and } (e.g. jq '{{key: .val}}')\n\nNOTE:\n  Each --on-* flag can be repeated; all of its commands are started, in order.\n  Commands are executed asynchronously. Multiple events may trigger\n  overlapping command executions; use --no-overlap to prevent this."
 )]
 struct Args {
     /// Root directory to watch for file changes (recursively)
-    #[arg(value_name = "DIRECTORY")]
     #[arg(
-        help = "Path to directory to monitor. Can be relative (e.g., '.', 'src') or absolute. Watches all subdirectories recursively"
+        value_name = "DIRECTORY",
+        required_unless_present_any = ["config", "generate_completions"]
     )]
-    directory: PathBuf,
+    #[arg(
+        help = "Path to directory to monitor. Can be relative (e.g., '.', 'src') or absolute. Watches all subdirectories recursively\n\nA file path watches just that file; paths in templates are then relative to its directory\nWith --config, overrides the file's directory; not allowed with several [[watch]] entries"
+    )]
+    directory: Option<PathBuf>,
+
+    /// Load watch rules from a TOML config file
+    #[arg(short, long, value_name = "FILE", help_heading = GENERAL_HELP)]
+    #[arg(
+        help = "Load watch rules from a TOML config file\n\nKeys: directory, include, exclude, on_create, on_modify, on_delete, on_change, debounce\nUse [[watch]] tables to run several independent watchers; top-level keys are shared defaults\nFlags given on the command line override values from the file\nSend SIGHUP to re-read the file's patterns and commands without restarting"
+    )]
+    config: Option<PathBuf>,
 
     /// Exclude patterns (glob patterns to ignore)
     #[arg(short, long, value_name = "PATTERN", help_heading = FILTERING_HELP)]
     #[arg(
-        help = "Exclude files/directories matching these glob patterns\n\nExamples: 'node_modules/**', '.git/**', 'target/**', '*.tmp'\nCan be used multiple times to exclude different patterns"
+        help = "Exclude files/directories matching these glob patterns\n\nExamples: 'node_modules/**', '.git/**', 'target/**', '*.tmp'\nCan be used multiple times to exclude different patterns\nA leading '!' includes instead: --exclude '!*.rs' is --include '*.rs'"
     )]
     exclude: Vec<String>,
 
     /// Include patterns (glob patterns to watch)
     #[arg(short, long, value_name = "PATTERN", help_heading = FILTERING_HELP)]
     #[arg(
-        help = "Only watch files matching these glob patterns\n\nExamples: '*.rs', '**/*.js', 'src/**/*.{ts,tsx}', '*.{md,txt}'\nIf not specified, watches all files. Can be used multiple times"
+        help = "Only watch files matching these glob patterns\n\nExamples: '*.rs', '**/*.js', 'src/**/*.{ts,tsx}', '*.{md,txt}'\nIf not specified, watches all files. Can be used multiple times\nA leading '!' excludes instead, e.g. --include '*.rs' --include '!test_*.rs'\nExclusions always take precedence over inclusions"
     )]
     include: Vec<String>,
 
-    /// Enable verbose logging output
-    #[arg(short, long, help_heading = GENERAL_HELP)]
+    /// Files to read include patterns from
+    #[arg(long, value_name = "FILE", help_heading = FILTERING_HELP)]
+    #[arg(
+        help = "Read include patterns from FILE, one per line, in addition to --include\n\nBlank lines and lines starting with '#' are ignored. Can be used multiple times"
+    )]
+    include_from: Vec<PathBuf>,
+
+    /// Files to read exclude patterns from
+    #[arg(long, value_name = "FILE", help_heading = FILTERING_HELP)]
+    #[arg(
+        help = "Read exclude patterns from FILE, one per line, in addition to --exclude\n\nBlank lines and lines starting with '#' are ignored. Can be used multiple times"
+    )]
+    exclude_from: Vec<PathBuf>,
+
+    /// Only watch the directory's direct children
+    #[arg(long, help_heading = FILTERING_HELP)]
+    #[arg(
+        help = "Only watch files directly inside DIRECTORY, not its subdirectories\n\nMuch cheaper on huge trees, since subdirectories are never registered with the OS\nChanges inside subdirectories are missed entirely, even if they match --include"
+    )]
+    no_recursive: bool,
+
+    /// Ignore saves that don't change a file's content
+    #[arg(long, help_heading = FILTERING_HELP)]
+    #[arg(
+        help = "Skip modify events when the file's content is the same as last time it was seen\n\nFilters out no-op saves like an editor's 'save all'. Each changed file is read and hashed,\nand the first modification of a file that existed before watching started always counts"
+    )]
+    skip_unchanged: bool,
+
+    /// Ignore modify events on directories
+    #[arg(long, help_heading = FILTERING_HELP)]
+    #[arg(
+        help = "Drop modify events whose path is a directory, such as a parent directory's timestamp\nchanging when a file inside it is written, which some platforms report as a separate change\nRenames and permission or ownership changes of directories are still reported"
+    )]
+    ignore_dir_modify: bool,
+
+    /// Skip files smaller than this many bytes
+    #[arg(long, value_name = "BYTES", help_heading = FILTERING_HELP)]
+    #[arg(
+        help = "Skip events for files smaller than BYTES, such as empty lock and pid files\n\nChecked before --include and --exclude. Deletes, directories and files whose size\ncan't be read are never skipped. Example: --min-size 10"
+    )]
+    min_size: Option<u64>,
+
+    /// Skip paths that aren't valid UTF-8
+    #[arg(long, help_heading = FILTERING_HELP)]
+    #[arg(
+        help = "Skip events for paths that aren't valid UTF-8 instead of only warning about them\n\nSuch paths reach placeholders and pattern matching with their invalid bytes replaced by '\u{FFFD}',\nso commands could be given a file that doesn't exist"
+    )]
+    strict_utf8: bool,
+
+    /// Follow symlinks into their targets
+    #[arg(long, help_heading = FILTERING_HELP)]
+    #[arg(
+        help = "Watch inside symlinked directories and match symlinked files by their target's path\n\nSymlinks pointing outside DIRECTORY keep their own path\nDefault: symlinks are reported by their own path and symlinked directories aren't entered"
+    )]
+    follow_symlinks: bool,
+
+    /// Show paths below DIRECTORY as given instead of resolving symlinks
+    #[arg(long, help_heading = GENERAL_HELP)]
+    #[arg(
+        help = "Show paths below DIRECTORY as you gave it, instead of with symlinks resolved\n\nThe real location is still watched; {absolute_path}, printed events and --match-absolute\nuse the given path, while {file_path} stays the resolved one\nExample: vibewatch ./current --no-canonicalize, where current is a symlink"
+    )]
+    no_canonicalize: bool,
+
+    /// Only report changes to files
+    #[arg(long, conflicts_with = "dirs_only", help_heading = FILTERING_HELP)]
+    #[arg(
+        help = "Ignore changes to directories, such as editors creating and removing temporary directories\n\nOnly file changes are printed and run commands"
+    )]
+    files_only: bool,
+
+    /// Only report changes to directories
+    #[arg(long, help_heading = FILTERING_HELP)]
+    #[arg(
+        help = "Ignore changes to files; only directories being created, deleted, moved or changed are printed and run commands\n\nCan't be combined with --files-only"
+    )]
+    dirs_only: bool,
+
+    /// Kinds of events to process
+    #[arg(
+        long,
+        value_name = "KINDS",
+        default_value = "create,modify,metadata,delete",
+        help_heading = FILTERING_HELP
+    )]
+    #[arg(
+        help = "Only process these kinds of events, as a comma-separated list: create, modify, metadata,\ndelete, access, or all\n\nRenames count as modify; metadata covers permission, ownership and timestamp changes\naccess (files being opened or read) is off by default, as commands reading files trigger it too\nExample: --events create,delete or --events all"
+    )]
+    events: watcher::EventKinds,
+
+    /// Directory names to leave out of watching entirely
+    #[arg(long, value_name = "NAME", value_parser = parse_dir_name, help_heading = FILTERING_HELP)]
+    #[arg(
+        help = "Don't watch directories with this name at any depth, nor anything inside them\n\nUnlike --exclude 'node_modules/**', the directories are never registered with the OS,\nwhich saves one inotify watch per directory on Linux. Repeatable\nExample: --exclude-dir node_modules --exclude-dir .git"
+    )]
+    exclude_dir: Vec<String>,
+
+    /// Ignore files nested deeper than this
+    #[arg(long, value_name = "N", help_heading = FILTERING_HELP)]
+    #[arg(
+        help = "Ignore files more than N directory levels below DIRECTORY\n\n0 keeps only files directly inside DIRECTORY, 1 also their subdirectories' files, and so on\nDeeper directories are still registered with the OS; use --no-recursive to avoid that\nExample: --max-depth 2"
+    )]
+    max_depth: Option<usize>,
+
+    /// Skip files ignored by .gitignore
+    #[arg(long, help_heading = FILTERING_HELP)]
+    #[arg(
+        help = "Skip files ignored by the watched directory's .gitignore files (including nested ones)\n\nIgnored files are excluded even if they match --include\n.gitignore files are read once at startup"
+    )]
+    gitignore: bool,
+
+    /// Files with gitignore-style rules to skip files by
+    #[arg(long, value_name = "PATH", help_heading = FILTERING_HELP)]
+    #[arg(
+        help = "Skip files matching the rules in PATH, written like a .gitignore (e.g. ripgrep's .ignore)\n\nSupports '!' to re-include and a trailing '/' for directories; anchored rules like '/build/'\nare relative to DIRECTORY. Later files override earlier ones, a re-include overrides\n--gitignore, and --exclude always wins. Can be used multiple times"
+    )]
+    ignore_file: Vec<PathBuf>,
+
+    /// Match patterns against absolute paths
+    #[arg(long, help_heading = FILTERING_HELP)]
+    #[arg(
+        help = "Match --include and --exclude patterns against each file's absolute path instead of its path relative to DIRECTORY\n\nLets a pattern target one location, e.g. --exclude '/srv/app/vendor/**'\nPatterns like '**/target/**' or '*.rs' match the same files in both modes"
+    )]
+    match_absolute: bool,
+
+    /// Match patterns against file names only
+    #[arg(long, conflicts_with = "match_absolute", help_heading = FILTERING_HELP)]
+    #[arg(
+        help = "Match --include and --exclude patterns against each file's name instead of its path relative to DIRECTORY\n\n'main.rs' then matches src/bin/main.rs, and 'test_*' matches tests/test_parser.rs\nPatterns containing a '/', like 'src/*.rs', never match in this mode"
+    )]
+    match_basename: bool,
+
+    /// Print the files the filters match and exit
+    #[arg(long, visible_alias = "list-watched", help_heading = FILTERING_HELP)]
     #[arg(
-        help = "Show detailed debug information about file events, pattern matching, and command execution"
+        help = "Print the files in DIRECTORY that pass the filters, one per line, and exit without watching\n\nUse it to check --include, --exclude, --max-depth and --gitignore before running commands\nPaths are relative to DIRECTORY"
+    )]
+    list_matched: bool,
+
+    /// Explain whether a path is watched and exit
+    #[arg(long, value_name = "PATH", conflicts_with = "list_matched", help_heading = FILTERING_HELP)]
+    #[arg(
+        help = "Print whether changes to PATH would be handled and which rule decided it, then exit\n\nShows the --include or --exclude pattern that matched, .gitignore, --ignore-file, --exclude-dir or --max-depth\nRelative paths are taken relative to DIRECTORY. Example: --explain src/main.rs"
+    )]
+    explain: Option<PathBuf>,
+
+    /// Print the effective settings and exit
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "toml",
+        conflicts_with_all = ["list_matched", "explain"],
+        help_heading = GENERAL_HELP
+    )]
+    #[arg(
+        help = "Print the settings each watcher ends up with, after merging --config and command-line flags, then exit without watching\n\nFORMAT is toml (default) or json\nShows the canonical watched directory, patterns including those read from --include-from/--exclude-from, commands and debounce, timeout and filter settings\nExample: --print-config or --print-config=json"
+    )]
+    print_config: Option<ConfigFormat>,
+
+    /// Raise the log level, once per -v
+    #[arg(short = 'v', action = ArgAction::Count, help_heading = GENERAL_HELP)]
+    #[arg(
+        help = "Log more, repeatable: -v also logs the configured commands, -vv adds debug information\nabout file events, pattern matching and command execution, -vvv logs every raw event\nbefore filtering (useful to diagnose missed events)"
+    )]
+    verbosity: u8,
+
+    /// Append command results as JSON lines to this file
+    #[arg(long, value_name = "PATH", help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Append one JSON line per finished command to this file: command, exit_code, success,\nduration_ms, stdout_bytes and stderr_bytes (plus error if it couldn't run)\nWorks with or without --json; retries and hooks get a line each"
+    )]
+    result_log: Option<PathBuf>,
+
+    /// Also write log lines to this file
+    #[arg(long, value_name = "PATH", help_heading = GENERAL_HELP)]
+    #[arg(
+        help = "Also write timestamped log lines to this file, appending if it exists\n\nThe file additionally records every file event, command run and line of command output\n(stdout at info level, stderr at warn level), so it keeps a record beyond terminal scrollback"
+    )]
+    log_file: Option<PathBuf>,
+
+    /// Enable debug logging, same as -vv
+    #[arg(long, help_heading = GENERAL_HELP)]
+    #[arg(
+        help = "Show detailed debug information about file events, pattern matching, and command execution\n\nSame as -vv"
     )]
     verbose: bool,
 
-    /// Suppress command output (only show file change notifications)
+    /// Suppress command output, file change lines and status messages
     #[arg(short, long, help_heading = GENERAL_HELP)]
     #[arg(
-        help = "Don't display command output (stdout/stderr)\n\nStill shows file change events and errors. Useful when you only care about which files changed"
+        help = "Don't display command output (stdout/stderr), file change lines or status messages like the startup banner\n\nErrors are still logged, and --verbose still enables debug logging. --json lines are always printed\nUseful for keeping CI logs free of noise"
     )]
     quiet: bool,
 
+    /// When to color output
+    #[arg(
+        long,
+        value_name = "WHEN",
+        value_enum,
+        default_value_t = output::ColorChoice::Auto,
+        help_heading = GENERAL_HELP
+    )]
+    #[arg(
+        help = "Color file change labels: green for create, yellow for modify, red for delete\n\nauto: only when stdout is a terminal and NO_COLOR is not set\nalways: even when output is redirected\nnever: plain text"
+    )]
+    color: output::ColorChoice,
+
+    /// Print events and command results as JSON lines
+    #[arg(long, help_heading = GENERAL_HELP)]
+    #[arg(
+        help = "Print one JSON object per line to stdout instead of human-readable output\n\nEvent lines: type, timestamp, event_type, relative_path, absolute_path (and old_path for moves)\nCommand lines: type, timestamp, command, exit_code (and error if it failed to run)\nStatus messages and command output go to stderr"
+    )]
+    json: bool,
+
+    /// Template for the line printed for each file change
+    #[arg(long, value_name = "TEMPLATE", conflicts_with = "json", help_heading = GENERAL_HELP)]
+    #[arg(
+        help = "Print each file change as TEMPLATE instead of the default line, using the same\nplaceholders as commands (see TEMPLATES below)\nExample: --format '{event_type} {relative_path}'"
+    )]
+    format: Option<String>,
+
+    /// Label to prefix every output line with
+    #[arg(long, value_name = "LABEL", default_value = "", hide_default_value = true, help_heading = GENERAL_HELP)]
+    #[arg(
+        help = "Prefix every line vibewatch prints with [LABEL]: the banner, file change lines, command output and logs\n\nTells several vibewatch instances apart when their output is interleaved\n--json lines are not prefixed. Example: --name api"
+    )]
+    name: String,
+
     /// Debounce delay in milliseconds to coalesce rapid events
-    #[arg(long, value_name = "MS", default_value = "100", help_heading = GENERAL_HELP)]
+    #[arg(long, value_name = "MS", default_value = "100", value_parser = parse_millis, help_heading = GENERAL_HELP)]
     #[arg(
-        help = "Wait this many milliseconds before executing commands after an event\n\nHelps avoid command spam when files change rapidly (e.g., during saves)\nSet to 0 to disable debouncing. Default: 100ms"
+        help = "Wait this many milliseconds before executing commands after an event\n\nHelps avoid command spam when files change rapidly (e.g., during saves)\nAccepts a unit suffix too, e.g. 500ms, 2s or 1m\nSet to 0 to disable debouncing. Default: 100ms"
     )]
     debounce: u64,
 
+    /// Debounce delay in milliseconds for create events
+    #[arg(long, value_name = "MS", value_parser = parse_millis, help_heading = GENERAL_HELP)]
+    #[arg(
+        help = "Debounce create events for this many milliseconds instead of --debounce\n\nSet to 0 to run create commands immediately"
+    )]
+    debounce_create: Option<u64>,
+
+    /// Debounce delay in milliseconds for modify events
+    #[arg(long, value_name = "MS", value_parser = parse_millis, help_heading = GENERAL_HELP)]
+    #[arg(
+        help = "Debounce modify events for this many milliseconds instead of --debounce\n\nSet to 0 to run modify commands immediately"
+    )]
+    debounce_modify: Option<u64>,
+
+    /// Debounce delay in milliseconds for delete events (default: none)
+    #[arg(long, value_name = "MS", value_parser = parse_millis, help_heading = GENERAL_HELP)]
+    #[arg(
+        help = "Debounce delete events for this many milliseconds\n\nBy default deletes are not debounced: a delete cancels any pending event for the file and runs delete commands immediately"
+    )]
+    debounce_delete: Option<u64>,
+
+    /// Longest time in milliseconds an event can be debounced
+    #[arg(long, value_name = "MS", value_parser = nonzero(parse_millis), help_heading = GENERAL_HELP)]
+    #[arg(
+        help = "Run commands for a file after at most this many milliseconds, even if it keeps changing\n\nWithout this, a file written continuously (e.g. an appended log) never goes quiet and its commands never run"
+    )]
+    max_debounce: Option<u64>,
+
+    /// Wait for files to stop being written before running commands
+    #[arg(long, help_heading = GENERAL_HELP)]
+    #[arg(
+        help = "Hold create and modify events until the file's size and modification time stop changing\n\nChecked every 100ms after debouncing, so commands don't read a file that is still being written\n(e.g. a large copy or download). Delays those events by at least 100ms"
+    )]
+    wait_for_close: bool,
+
+    /// Wait for a deleted watched directory to come back
+    #[arg(long, help_heading = GENERAL_HELP)]
+    #[arg(
+        help = "Keep running when the watched directory is deleted, and watch it again once it is recreated\n\nWithout this, vibewatch exits with an error when the watched directory disappears"
+    )]
+    retry_watch: bool,
+
+    /// When debounced commands fire
+    #[arg(
+        long,
+        value_name = "MODE",
+        value_enum,
+        default_value_t = debounce::DebounceMode::Trailing,
+        help_heading = GENERAL_HELP
+    )]
+    #[arg(
+        help = "When to run commands for a burst of events on the same file\n\ntrailing: once the file has been quiet for the debounce period\nleading: immediately on the first event, ignoring further events for the debounce period"
+    )]
+    debounce_mode: debounce::DebounceMode,
+
+    /// Debounce each command group on its own
+    #[arg(long, help_heading = GENERAL_HELP)]
+    #[arg(
+        help = "Give every command a file's events resolve to its own debounce window\n\nWith e.g. --on-modify 'lint' --on-change 'build', a modify followed by a create of the same\nfile runs both lint and build instead of only the command of the merged event\nKeeps up to one pending event per file and command group, instead of per file"
+    )]
+    debounce_per_command_group: bool,
+
+    /// Run each command once per burst of changes
+    #[arg(long, help_heading = GENERAL_HELP)]
+    #[arg(
+        help = "Collect every change made within the debounce period and run each command once
+
+{changed_files} expands to the shell-quoted relative paths of all changed files
+Requires --debounce greater than 0 and the trailing debounce mode
+Example: --batch --on-change 'npx prettier --write {changed_files}'"
+    )]
+    batch: bool,
+
+    /// Handle the paths of a multi-path event in sorted order
+    #[arg(long, help_heading = GENERAL_HELP)]
+    #[arg(
+        help = "When one event reports several paths, handle them in sorted order instead of\nthe order the OS reported them, so commands run and log lines appear in a stable order"
+    )]
+    sort_paths: bool,
+
+    /// Poll for changes instead of using native OS events
+    #[arg(long, help_heading = GENERAL_HELP)]
+    #[arg(
+        help = "Detect changes by periodically scanning the directory instead of using native OS events\n\nUse on network filesystems (NFS, SMB) and Docker bind mounts where events are missed\nSlower and more CPU-intensive on large trees; tune with --poll-interval"
+    )]
+    poll: bool,
+
+    /// How often to scan for changes with --poll
+    #[arg(
+        long,
+        value_name = "MS",
+        requires = "poll",
+        value_parser = nonzero(parse_millis),
+        help_heading = GENERAL_HELP
+    )]
+    #[arg(help = "Scan for changes every MS milliseconds with --poll\n\nDefault: 1000ms")]
+    poll_interval: Option<u64>,
+
+    /// What to do when the tree exceeds the inotify watch limit
+    #[arg(
+        long,
+        value_name = "MODE",
+        value_enum,
+        default_value_t = watch_limit::WatchLimitMode::Warn,
+        help_heading = GENERAL_HELP
+    )]
+    #[arg(
+        help = "What to do when the watched tree has more directories than fs.inotify.max_user_watches\nallows (Linux only); past the limit, changes in some directories are silently missed\n\nwarn: log a warning suggesting a sysctl bump\nerror: refuse to start\noff: don't check"
+    )]
+    watch_descriptor_limit: watch_limit::WatchLimitMode,
+
+    /// Print event and command counters periodically
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "10",
+        value_parser = nonzero(parse_seconds),
+        help_heading = GENERAL_HELP
+    )]
+    #[arg(
+        help = "Print how many events were received, filtered out and acted on, and how many\ncommands ran and failed, every SECONDS (default: 10) and once more on exit\nPrinted even with --quiet. Example: --stats or --stats=60"
+    )]
+    stats: Option<u64>,
+
+    /// Print a summary of the session on exit
+    #[arg(long, help_heading = GENERAL_HELP)]
+    #[arg(
+        help = "Print a summary when vibewatch stops: file changes by type, commands run and failed,\nand the total time spent running commands\nNot printed with --quiet"
+    )]
+    summary_on_exit: bool,
+
+    /// Print a "still watching" line periodically
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value = "0",
+        value_parser = parse_seconds,
+        help_heading = GENERAL_HELP
+    )]
+    #[arg(
+        help = "Print a \"still watching (N events so far)\" line every SECONDS, to show vibewatch\nis alive when nothing changes for a long time\nNot printed with --quiet. Default: 0 (disabled). Example: --heartbeat 300"
+    )]
+    heartbeat: u64,
+
+    /// Most file events to buffer before dropping new ones
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(NonZeroUsize),
+        help_heading = GENERAL_HELP
+    )]
+    #[arg(
+        help = "Buffer at most N file events from the OS; while the buffer is full, new events are dropped\n\nCaps memory during huge bursts such as a large git checkout\nDropped events are counted and logged as a warning\nDefault: unbounded"
+    )]
+    channel_capacity: Option<NonZeroUsize>,
+
+    /// Address to serve Prometheus metrics on
+    #[arg(long, value_name = "ADDR", help_heading = GENERAL_HELP)]
+    #[arg(
+        help = "Serve Prometheus text-format metrics at http://ADDR/metrics\n\nExposes event and command counters, command durations and pending events\nExample: --metrics-addr 127.0.0.1:9185"
+    )]
+    metrics_addr: Option<SocketAddr>,
+
+    /// File to keep a JSON status in for supervisors
+    #[arg(long, value_name = "PATH", help_heading = GENERAL_HELP)]
+    #[arg(
+        help = "Keep a JSON status in PATH, rewritten every second while watching\n\nHolds uptime, events processed, the last event time and pending events\nThe file is replaced atomically; a stale updated_at means vibewatch is stuck or gone\nExample: --status-file /run/vibewatch/status.json"
+    )]
+    status_file: Option<PathBuf>,
+
     /// Command to execute when files are created
     #[arg(long, value_name = "COMMAND", help_heading = COMMANDS_HELP)]
     #[arg(
         help = "Run this command when NEW files are created\n\nTemplates: {file_path}, {relative_path}, {absolute_path}, {event_type}\nExample: --on-create 'git add {file_path}'"
     )]
-    on_create: Option<String>,
+    on_create: Vec<String>,
 
     /// Command to execute when files are modified
     #[arg(long, value_name = "COMMAND", help_heading = COMMANDS_HELP)]
     #[arg(
         help = "Run this command when EXISTING files are modified/updated\n\nTemplates: {file_path}, {relative_path}, {absolute_path}, {event_type}\nExample: --on-modify 'npx eslint {file_path} --fix'"
     )]
-    on_modify: Option<String>,
+    on_modify: Vec<String>,
 
     /// Command to execute when files are deleted
     #[arg(long, value_name = "COMMAND", help_heading = COMMANDS_HELP)]
     #[arg(
         help = "Run this command when files are DELETED/removed\n\nTemplates: {file_path}, {relative_path}, {absolute_path}, {event_type}\nExample: --on-delete 'echo File {relative_path} was removed'"
     )]
-    on_delete: Option<String>,
+    on_delete: Vec<String>,
+
+    /// Command to execute on ANY file change (fallback for all events)
+    #[arg(long, value_name = "COMMAND", help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Run this command for ANY file event (create/modify/delete)\n\nActs as fallback when specific --on-* commands are not set\nTemplates: {file_path}, {relative_path}, {absolute_path}, {event_type}\nExample: --on-change 'echo {event_type}: {relative_path}'"
+    )]
+    on_change: Vec<String>,
+
+    /// Command to execute when directories are created
+    #[arg(long, value_name = "COMMAND", help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Run this command when NEW directories are created\n\nFalls back to --on-create, then --on-change\nTemplates: {file_path}, {relative_path}, {absolute_path}, {event_type}\nExample: --on-create-dir 'chmod 755 {file_path}'"
+    )]
+    on_create_dir: Vec<String>,
+
+    /// Command to execute when directories are deleted
+    #[arg(long, value_name = "COMMAND", help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Run this command when directories are DELETED/removed\n\nFalls back to --on-delete, then --on-change\nTemplates: {file_path}, {relative_path}, {absolute_path}, {event_type}\nExample: --on-delete-dir 'echo Directory {relative_path} was removed'"
+    )]
+    on_delete_dir: Vec<String>,
+
+    /// Command to execute when files are moved within the watched directory
+    #[arg(long, visible_alias = "on-rename", value_name = "COMMAND", help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Run this command when a file is MOVED/renamed within the watched directory\n\nThe source and destination are reported as a single move instead of delete + create\nFalls back to --on-modify, then --on-change\nTemplates: {old_path} (source), {new_path} or {file_path} (destination), {relative_path}, {absolute_path}, {event_type}\nExample: --on-move 'echo {old_path} moved to {new_path}'"
+    )]
+    on_move: Vec<String>,
+
+    /// Command for files matching a pattern, as PATTERN:COMMAND
+    #[arg(long, value_name = "PATTERN:COMMAND", value_parser = parse_route, help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Run COMMAND instead of the --on-* commands for changed files matching PATTERN\n\nRepeatable; the first matching pattern wins, and unmatched files fall back to --on-*\nPatterns match the relative path, after --include/--exclude filtering\nExample: --run '*.rs:cargo check' --run '*.md:mdbook build'"
+    )]
+    run: Vec<(String, String)>,
+
+    /// Command to execute when the watched directory itself changes
+    #[arg(long, value_name = "COMMAND", help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Run this command for events on the watched directory itself (e.g. its metadata changing)\n\nSuch events are ignored by default and never trigger the other commands\n{relative_path} is '.' for these events\nExample: --on-root-change 'echo watched directory changed'"
+    )]
+    on_root_change: Vec<String>,
+
+    /// Command to execute after a command succeeds
+    #[arg(long, value_name = "COMMAND", help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Run this command after any other command exits successfully\n\nGets the same template variables as the command that ran, plus {exit_code}\nExample: --on-command-success 'notify-send \"Build passed\"'"
+    )]
+    on_command_success: Vec<String>,
+
+    /// Command to execute after a command fails
+    #[arg(long, value_name = "COMMAND", help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Run this command after any other command fails, times out or can't be started\n\nGets the same template variables as the command that ran, plus {exit_code}\n(empty if the command didn't exit on its own)\nExample: --on-command-failure 'notify-send \"Build failed ({exit_code})\"'"
+    )]
+    on_command_failure: Vec<String>,
+
+    /// Timeout in seconds for commands triggered by create events
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds, help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Kill commands triggered by create events after this many seconds\n\nSet to 0 to disable. Default: no timeout"
+    )]
+    timeout_create: Option<u64>,
+
+    /// Timeout in seconds for commands triggered by modify events
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds, help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Kill commands triggered by modify events after this many seconds\n\nSet to 0 to disable. Default: no timeout"
+    )]
+    timeout_modify: Option<u64>,
+
+    /// Timeout in seconds for commands triggered by delete events
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds, help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Kill commands triggered by delete events after this many seconds\n\nSet to 0 to disable. Default: no timeout"
+    )]
+    timeout_delete: Option<u64>,
+
+    /// Timeout in seconds for any command
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds, help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Kill any command still running after this many seconds\n\nPer-event timeouts (--timeout-create, --timeout-modify, --timeout-delete) take precedence\nSet to 0 to disable. Default: no timeout"
+    )]
+    command_timeout: Option<u64>,
+
+    /// Interpreter to run commands with
+    #[arg(long, value_name = "SHELL", help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Pass each command as a single argument to this interpreter, enabling pipes, && and redirects\n\nExamples: --shell 'sh -c', --shell 'bash -c', --shell 'cmd /C'\nPlaceholder values are quoted for the interpreter: POSIX shells, cmd or PowerShell\nWithin quotes in the command, e.g. \"{file_path}\", they are escaped instead\ncmd has no escape for %, so a file name like %PATH%.txt still expands there\nDefault ('none'): split the command into program and arguments and run it directly;\n'&&' and ';' still chain commands, e.g. 'cargo fmt && cargo test'"
+    )]
+    shell: Option<String>,
+
+    /// Prevent concurrent runs of the same command
+    #[arg(
+        long,
+        value_name = "MODE",
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "drop",
+        help_heading = COMMANDS_HELP
+    )]
+    #[arg(
+        help = "Never run two instances of the same command at once\n\ndrop (default): skip events that arrive while the command is running\nqueue: run once more after the current run, with the latest event\nExample: --no-overlap or --no-overlap=queue"
+    )]
+    no_overlap: Option<overlap::OverlapMode>,
+
+    /// Ignore events caused by the commands themselves
+    #[arg(long, value_name = "MS", value_parser = parse_millis, help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Ignore all file events while a command runs and for MS milliseconds after it finishes\n\nBreaks loops where a command writes into the watched directory (e.g. a formatter)\nTrade-off: your own edits during that time are missed too; prefer --exclude\nwhen the command's output files are known"
+    )]
+    exclude_writes_for: Option<u64>,
+
+    /// Shortest time in milliseconds between two runs of a command
+    #[arg(long, value_name = "MS", value_parser = parse_millis, help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Run each command at most once every MS milliseconds, skipping events in between\n\nUnlike --debounce, the wait counts from the command's last run and new events don't extend it\nExample: --min-interval 2000"
+    )]
+    min_interval: Option<u64>,
+
+    /// Fixed delay in milliseconds before running commands for a change
+    #[arg(
+        long,
+        value_name = "MS",
+        value_parser = parse_millis,
+        conflicts_with = "restart",
+        help_heading = COMMANDS_HELP
+    )]
+    #[arg(
+        help = "Wait MS milliseconds after a change is handled before running its commands\n\nUnlike --debounce, events aren't merged: every run is delayed on its own\nUseful when a sibling file is written shortly after the one that changed\nExample: --exec-delay 300"
+    )]
+    exec_delay: Option<u64>,
+
+    /// Run path-independent commands once per window of events
+    #[arg(long, value_name = "MS", value_parser = nonzero(parse_millis), help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Run commands that use no file placeholder (like {file_path} or {relative_path}) only once\nfor all events within MS milliseconds, however many files changed\n\nThe window opens with the first event and the command runs when it closes\nCommands using the changed file still run once per file\nExample: --coalesce-global 500 --on-change 'cargo build'"
+    )]
+    coalesce_global: Option<u64>,
+
+    /// Most commands to run at the same time
+    #[arg(long, value_name = "N", default_value_t = 0, help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Run at most N commands at the same time; further commands wait for one to finish\n\nKeeps bursts like a git checkout from spawning hundreds of processes\nDefault: 0 (no limit)"
+    )]
+    max_concurrent: usize,
+
+    /// Most lines of output to print per command run
+    #[arg(long, value_name = "N", help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Print at most N lines of each command run's output, then a \"... (M more lines)\" notice
+
+Output is printed line by line as the command writes it; stdout and stderr share the limit
+Keeps noisy commands like bundlers from flooding the terminal. Example: --max-output-lines 50"
+    )]
+    max_output_lines: Option<usize>,
+
+    /// Run commands one at a time, in event order
+    #[arg(long, help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Run commands one at a time, in the order their events arrived\n\nEach command, with its retries and hooks, finishes before the next one starts\nStricter than --no-overlap, which only keeps each command from overlapping itself"
+    )]
+    sequential: bool,
+
+    /// Keep commands running and restart them on each change
+    #[arg(
+        long,
+        conflicts_with_all = ["sequential", "no_overlap", "persistent_shell", "json"],
+        help_heading = COMMANDS_HELP
+    )]
+    #[arg(
+        help = "Treat commands as long-running processes, like a dev server: on each change, stop\nthe running process (SIGTERM, then SIGKILL after 5s) and start a fresh one\nOutput goes straight to the terminal; timeouts, retries and hooks don't apply\nExample: --restart --run-on-start --on-change 'cargo run'"
+    )]
+    restart: bool,
+
+    /// Times to retry a failed command
+    #[arg(long, value_name = "N", default_value_t = 0, help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Rerun a command up to N more times when it exits non-zero or fails to start\n\nHelps with commands that fail transiently, like network-dependent deploys\nTimed out commands are only retried with --retry-timeouts. Default: 0 (no retries)"
+    )]
+    retries: u32,
+
+    /// Delay in milliseconds between retries
+    #[arg(long, value_name = "MS", default_value_t = 1000, value_parser = parse_millis, help_heading = COMMANDS_HELP)]
+    #[arg(help = "Wait this many milliseconds before each retry (with --retries). Default: 1000")]
+    retry_delay: u64,
+
+    /// Also retry commands that timed out
+    #[arg(long, help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Also retry commands killed by --command-timeout or a per-event timeout (with --retries)"
+    )]
+    retry_timeouts: bool,
+
+    /// Directory to run commands in
+    #[arg(long, value_name = "PATH", help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Run commands in this directory\n\nUse '{dir_of_file}' to run each command in the directory of the changed file\nDefault: the watched directory\nExample: --working-dir . or --working-dir '{dir_of_file}'"
+    )]
+    working_dir: Option<watcher::WorkingDir>,
+
+    /// Pass event details to commands as environment variables
+    #[arg(long, help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Set VIBEWATCH_EVENT_TYPE, VIBEWATCH_FILE_PATH, VIBEWATCH_RELATIVE_PATH and\nVIBEWATCH_ABSOLUTE_PATH for each command, with the same values as the templates\nExample: --export-env --shell 'sh -c' --on-change 'echo $VIBEWATCH_EVENT_TYPE'"
+    )]
+    export_env: bool,
+
+    /// Run commands through a single long-lived shell process
+    #[arg(long, help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Feed commands to one long-lived 'sh' process instead of spawning a process per event\n\nAvoids process startup overhead for frequent triggers. Commands run one at a time and\nshare shell state (e.g. variables) between runs. Falls back to spawning when the shell can't start;\na command that kills the shell fails and isn't re-run"
+    )]
+    persistent_shell: bool,
+
+    /// Run the on-change command once at startup
+    #[arg(long, help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Run the --on-change command once when watching starts, before any file changes\n\nUseful for 'build, then rebuild on change' workflows\n{event_type} is 'start' and path templates like {file_path} are empty for this run"
+    )]
+    run_on_start: bool,
+
+    /// Read control commands from stdin while watching
+    #[arg(long, help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Read commands from stdin while watching, one per line:\n  run    run the --on-change command now, without a file change\n  stats  print event and command counters\n  quit   stop vibewatch\nExample: force a rebuild by typing 'run' and pressing Enter"
+    )]
+    stdin_control: bool,
+
+    /// Exit as soon as a command fails
+    #[arg(long, help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Stop watching and exit with a non-zero status as soon as any command fails\n\nA command fails when it exits non-zero, is killed, times out or can't be started\nDefault: report the failure and keep watching"
+    )]
+    fail_fast: bool,
+
+    /// Exit after the first matching file change
+    #[arg(long, help_heading = COMMANDS_HELP)]
+    #[arg(
+        help = "Exit after the first file change that passes the filters, once its commands have finished\n\nUseful in scripts, e.g. wait until any .rs file changes: --once --include '*.rs'\nWith --wait-on-exit=SECONDS, stop waiting for the commands after SECONDS\nWith several [[watch]] entries in --config, the first change seen by any of them stops all"
+    )]
+    once: bool,
+
+    /// Wait for running commands before exiting
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "0",
+        value_parser = parse_seconds,
+        help_heading = COMMANDS_HELP
+    )]
+    #[arg(
+        help = "On Ctrl+C or SIGTERM, let commands that are still running finish before exiting\n\nOptionally give up after SECONDS (0 or no value: wait as long as it takes)\nExample: --wait-on-exit or --wait-on-exit=30"
+    )]
+    wait_on_exit: Option<u64>,
+
+    /// Print a shell completion script and exit
+    #[arg(long, value_name = "SHELL", value_enum, hide = true)]
+    generate_completions: Option<clap_complete::Shell>,
+}
+
+impl Args {
+    /// How many times -v was given, counting --verbose as -vv
+    fn verbosity(&self) -> u8 {
+        if self.verbose {
+            self.verbosity.max(2)
+        } else {
+            self.verbosity
+        }
+    }
+}
+
+/// Log level for a -v count
+fn log_level(verbosity: u8) -> log::LevelFilter {
+    match verbosity {
+        0 | 1 => log::LevelFilter::Info,
+        2 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    }
+}
+
+/// Logger settings shared by the terminal and the `--log-file` logger
+fn logger_builder(name: &str, verbosity: u8) -> env_logger::Builder {
+    let mut logger = env_logger::Builder::from_default_env();
+    logger.filter_level(log_level(verbosity));
+    if !name.is_empty() {
+        let name = name.to_string();
+        logger.format(move |buf, record| {
+            writeln!(
+                buf,
+                "[{}] [{} {} {}] {}",
+                name,
+                buf.timestamp(),
+                record.level(),
+                record.target(),
+                record.args()
+            )
+        });
+    }
+    logger
+}
+
+/// The logger for the terminal
+fn terminal_logger(name: &str, verbosity: u8) -> env_logger::Builder {
+    let mut terminal = logger_builder(name, verbosity);
+    // Events and command output are already printed to the terminal
+    terminal.filter_module(output::RECORD_TARGET, log::LevelFilter::Off);
+    terminal
+}
+
+/// Open the `--log-file`, appending to it if it exists
+fn open_log_file(path: &Path) -> anyhow::Result<std::fs::File> {
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open log file: {}", path.display()))
+}
+
+/// Sends every log record to both the terminal and the `--log-file` logger
+struct TeeLogger {
+    terminal: env_logger::Logger,
+    file: env_logger::Logger,
+}
+
+impl TeeLogger {
+    fn new(name: &str, verbosity: u8, file: std::fs::File) -> Self {
+        let mut logger = logger_builder(name, verbosity);
+        logger
+            .target(env_logger::Target::Pipe(Box::new(file)))
+            .write_style(env_logger::WriteStyle::Never);
+        Self {
+            terminal: terminal_logger(name, verbosity).build(),
+            file: logger.build(),
+        }
+    }
+
+    fn init(self) {
+        log::set_max_level(self.terminal.filter().max(self.file.filter()));
+        // Only fails if a logger is already set, which main never does
+        let _ = log::set_boxed_logger(Box::new(self));
+    }
+}
+
+impl log::Log for TeeLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.terminal.enabled(metadata) || self.file.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        // Each logger applies its own filters
+        self.terminal.log(record);
+        self.file.log(record);
+    }
+
+    fn flush(&self) {
+        self.terminal.flush();
+        self.file.flush();
+    }
+}
+
+/// Log every command a watcher runs and what triggers it, shown with -v
+fn log_commands(args: &Args) {
+    let commands = [
+        ("create", &args.on_create),
+        ("modify", &args.on_modify),
+        ("delete", &args.on_delete),
+        ("change", &args.on_change),
+        ("create-dir", &args.on_create_dir),
+        ("delete-dir", &args.on_delete_dir),
+        ("move", &args.on_move),
+        ("root-change", &args.on_root_change),
+    ];
+    for (event, commands) in commands {
+        for command in commands {
+            log::info!("On {}: {}", event, command);
+        }
+    }
+    for (pattern, command) in &args.run {
+        log::info!("On {}: {}", pattern, command);
+    }
+}
+
+/// Parse an `--exclude-dir` name, which must be a single path component
+fn parse_dir_name(value: &str) -> Result<String, String> {
+    if value.is_empty() || value.contains(['/', '\\']) || value == "." || value == ".." {
+        return Err("expected a directory name like 'node_modules', not a path".to_string());
+    }
+    Ok(value.to_string())
+}
+
+/// Parse a `--run` route, splitting at the first colon
+fn parse_route(value: &str) -> Result<(String, String), String> {
+    match value.split_once(':') {
+        Some((pattern, command)) if !pattern.is_empty() && !command.trim().is_empty() => {
+            Ok((pattern.to_string(), command.to_string()))
+        }
+        _ => Err("expected PATTERN:COMMAND, e.g. '*.rs:cargo check'".to_string()),
+    }
+}
+
+/// Parse a duration flag into whole seconds; a bare number is seconds
+fn parse_seconds(value: &str) -> Result<u64, String> {
+    let millis = parse_duration(value, "s")?;
+    if millis % 1000 != 0 {
+        return Err(format!("'{}' is not a whole number of seconds", value));
+    }
+    Ok(millis / 1000)
+}
+
+/// Wrap a duration parser to reject 0
+fn nonzero(
+    parse: fn(&str) -> Result<u64, String>,
+) -> impl Fn(&str) -> Result<u64, String> + Clone + Send + Sync + 'static {
+    move |value| match parse(value)? {
+        0 => Err("must be greater than 0".to_string()),
+        parsed => Ok(parsed),
+    }
+}
+
+/// Check whether a flag was explicitly passed on the command line
+fn set_on_command_line(matches: &ArgMatches, id: &str) -> bool {
+    matches.value_source(id) == Some(ValueSource::CommandLine)
+}
+
+/// Expand `--config` into one set of arguments per configured watcher
+///
+/// Values from the file fill in every flag that was not given on the command line.
+/// A DIRECTORY argument is rejected with several watchers, which would all
+/// end up watching it.
+fn resolve_config(args: Args, matches: &ArgMatches) -> anyhow::Result<Vec<Args>> {
+    let Some(config_path) = &args.config else {
+        return Ok(vec![args]);
+    };
+
+    let watchers = config::load(config_path)?;
+    if watchers.len() > 1 && set_on_command_line(matches, "directory") {
+        anyhow::bail!(
+            "DIRECTORY can't be given with a config file that has {} [[watch]] entries; set 'directory' in each entry instead",
+            watchers.len()
+        );
+    }
+
+    Ok(watchers
+        .into_iter()
+        .map(|entry| {
+            let mut watcher_args = args.clone();
+            if !set_on_command_line(matches, "directory") {
+                watcher_args.directory = entry.directory.or(watcher_args.directory);
+            }
+            if !set_on_command_line(matches, "include") {
+                watcher_args.include = entry.include.unwrap_or(watcher_args.include);
+            }
+            if !set_on_command_line(matches, "exclude") {
+                watcher_args.exclude = entry.exclude.unwrap_or(watcher_args.exclude);
+            }
+            if !set_on_command_line(matches, "on_create") {
+                watcher_args.on_create = entry.on_create.unwrap_or(watcher_args.on_create);
+            }
+            if !set_on_command_line(matches, "on_modify") {
+                watcher_args.on_modify = entry.on_modify.unwrap_or(watcher_args.on_modify);
+            }
+            if !set_on_command_line(matches, "on_delete") {
+                watcher_args.on_delete = entry.on_delete.unwrap_or(watcher_args.on_delete);
+            }
+            if !set_on_command_line(matches, "on_change") {
+                watcher_args.on_change = entry.on_change.unwrap_or(watcher_args.on_change);
+            }
+            if !set_on_command_line(matches, "debounce") {
+                watcher_args.debounce = entry.debounce.unwrap_or(watcher_args.debounce);
+            }
+            watcher_args
+        })
+        .collect())
+}
+
+/// Patterns given inline followed by those read from pattern files
+fn collect_patterns(inline: &[String], files: &[PathBuf]) -> anyhow::Result<Vec<String>> {
+    let mut patterns = inline.to_vec();
+    for file in files {
+        patterns.extend(vibewatch::PatternFilter::read_patterns(file)?);
+    }
+    Ok(patterns)
+}
+
+/// Filter patterns and commands from the arguments, the part a reload can change
+///
+/// Pattern files are read here, so a reload picks up their changes too.
+fn watch_rules(args: &Args) -> anyhow::Result<vibewatch::WatchRules> {
+    Ok(vibewatch::WatchRules {
+        include: collect_patterns(&args.include, &args.include_from)?,
+        exclude: collect_patterns(&args.exclude, &args.exclude_from)?,
+        commands: watcher::CommandConfig {
+            on_create: args.on_create.clone(),
+            on_modify: args.on_modify.clone(),
+            on_delete: args.on_delete.clone(),
+            on_change: args.on_change.clone(),
+            on_create_dir: args.on_create_dir.clone(),
+            on_delete_dir: args.on_delete_dir.clone(),
+            on_move: args.on_move.clone(),
+            routes: args.run.clone(),
+            on_root_change: args.on_root_change.clone(),
+            on_command_success: args.on_command_success.clone(),
+            on_command_failure: args.on_command_failure.clone(),
+            shell: args.shell.clone(),
+            no_overlap: args.no_overlap,
+            max_concurrent: args.max_concurrent,
+            sequential: args.sequential,
+            retries: args.retries,
+            retry_delay_ms: args.retry_delay,
+            retry_timeouts: args.retry_timeouts,
+            working_dir: args.working_dir.clone(),
+            timeout_create: args.timeout_create,
+            timeout_modify: args.timeout_modify,
+            timeout_delete: args.timeout_delete,
+            command_timeout: args.command_timeout,
+            persistent_shell: args.persistent_shell,
+            export_env: args.export_env,
+        },
+    })
+}
+
+/// Output format of `--print-config`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ConfigFormat {
+    Toml,
+    Json,
+}
+
+/// The settings one watcher ends up with, printed by `--print-config`
+///
+/// Empty lists are left out, and unset options are null in JSON (TOML has no null).
+#[derive(Debug, Serialize)]
+struct EffectiveConfig {
+    directory: PathBuf,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    include: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    exclude: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    exclude_dir: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    ignore_file: Vec<PathBuf>,
+    gitignore: bool,
+    max_depth: Option<usize>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    on_create: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    on_modify: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    on_delete: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    on_change: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    on_create_dir: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    on_delete_dir: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    on_move: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    on_root_change: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    on_command_success: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    on_command_failure: Vec<String>,
+    /// Routes as given to `--run`, `PATTERN:COMMAND`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    run: Vec<String>,
+    shell: Option<String>,
+    working_dir: Option<String>,
+    debounce: u64,
+    debounce_mode: String,
+    debounce_create: Option<u64>,
+    debounce_modify: Option<u64>,
+    debounce_delete: Option<u64>,
+    max_debounce: Option<u64>,
+    command_timeout: Option<u64>,
+    timeout_create: Option<u64>,
+    timeout_modify: Option<u64>,
+    timeout_delete: Option<u64>,
+    retries: u32,
+    max_concurrent: usize,
+    sequential: bool,
+    persistent_shell: bool,
+}
+
+impl EffectiveConfig {
+    /// Resolve the settings of a watcher, reading pattern files like `watch_rules`
+    fn from_args(args: &Args) -> anyhow::Result<Self> {
+        let directory = args.directory.as_deref().context("No directory to watch")?;
+        let directory = directory
+            .canonicalize()
+            .with_context(|| format!("Failed to resolve directory: {}", directory.display()))?;
+        let rules = watch_rules(args)?;
+        let commands = rules.commands;
+
+        Ok(Self {
+            directory,
+            include: rules.include,
+            exclude: rules.exclude,
+            exclude_dir: args.exclude_dir.clone(),
+            ignore_file: args.ignore_file.clone(),
+            gitignore: args.gitignore,
+            max_depth: args.max_depth,
+            on_create: commands.on_create,
+            on_modify: commands.on_modify,
+            on_delete: commands.on_delete,
+            on_change: commands.on_change,
+            on_create_dir: commands.on_create_dir,
+            on_delete_dir: commands.on_delete_dir,
+            on_move: commands.on_move,
+            on_root_change: commands.on_root_change,
+            on_command_success: commands.on_command_success,
+            on_command_failure: commands.on_command_failure,
+            run: commands
+                .routes
+                .iter()
+                .map(|(pattern, command)| format!("{}:{}", pattern, command))
+                .collect(),
+            shell: commands.shell,
+            working_dir: commands.working_dir.map(|dir| match dir {
+                watcher::WorkingDir::Path(path) => path.display().to_string(),
+                watcher::WorkingDir::DirOfFile => "{dir_of_file}".to_string(),
+            }),
+            debounce: args.debounce,
+            debounce_mode: value_name(args.debounce_mode),
+            debounce_create: args.debounce_create,
+            debounce_modify: args.debounce_modify,
+            debounce_delete: args.debounce_delete,
+            max_debounce: args.max_debounce,
+            command_timeout: commands.command_timeout,
+            timeout_create: commands.timeout_create,
+            timeout_modify: commands.timeout_modify,
+            timeout_delete: commands.timeout_delete,
+            retries: commands.retries,
+            max_concurrent: commands.max_concurrent,
+            sequential: commands.sequential,
+            persistent_shell: commands.persistent_shell,
+        })
+    }
+}
+
+/// The name a value enum is written as on the command line
+fn value_name(value: impl clap::ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default()
+}
+
+/// Render the effective settings of every watcher
+///
+/// A single watcher is printed as one table, several as a `watch` array, the
+/// same shapes a config file uses.
+fn render_config(configs: &[EffectiveConfig], format: ConfigFormat) -> anyhow::Result<String> {
+    #[derive(Serialize)]
+    struct Watchers<'a> {
+        watch: &'a [EffectiveConfig],
+    }
+
+    let rendered = match (format, configs) {
+        (ConfigFormat::Toml, [config]) => toml::to_string(config)?,
+        (ConfigFormat::Toml, configs) => toml::to_string(&Watchers { watch: configs })?,
+        (ConfigFormat::Json, [config]) => serde_json::to_string_pretty(config)?,
+        (ConfigFormat::Json, configs) => {
+            serde_json::to_string_pretty(&Watchers { watch: configs })?
+        }
+    };
+    Ok(rendered)
+}
+
+/// Re-read the config file and return the rules for the watcher at `index`
+///
+/// Flags given on the command line still override the file, as at startup.
+fn reload_rules(
+    args: &Args,
+    matches: &ArgMatches,
+    index: usize,
+) -> anyhow::Result<vibewatch::WatchRules> {
+    let resolved = resolve_config(args.clone(), matches)?;
+    let entry = resolved.get(index).with_context(|| {
+        format!(
+            "Config file now defines {} watcher(s), but this is watcher {}",
+            resolved.len(),
+            index + 1
+        )
+    })?;
+    watch_rules(entry)
+}
+
+// Separate function for testability
+fn create_watcher_from_args(args: Args) -> anyhow::Result<watcher::FileWatcher> {
+    let rules = watch_rules(&args)?;
+    let directory = args
+        .directory
+        .context("No directory to watch: pass DIRECTORY or set 'directory' in the config file")?;
+
+    if args.run_on_start && args.on_change.is_empty() {
+        anyhow::bail!("--run-on-start requires an --on-change command");
+    }
+
+    if args.batch && (args.debounce == 0 || args.debounce_mode != debounce::DebounceMode::Trailing)
+    {
+        anyhow::bail!("--batch requires --debounce greater than 0 and --debounce-mode trailing");
+    }
+
+    let builder = vibewatch::WatcherBuilder::new(directory)
+        .commands(rules.commands)
+        .debounce(args.debounce)
+        .debounce_mode(args.debounce_mode)
+        .watch_limit(args.watch_descriptor_limit)
+        .debounce_per_command_group(args.debounce_per_command_group)
+        .batch(args.batch)
+        .run_on_start(args.run_on_start)
+        .stdin_control(args.stdin_control)
+        .restart(args.restart)
+        .fail_fast(args.fail_fast)
+        .once(args.once)
+        .gitignore(args.gitignore)
+        .match_absolute(args.match_absolute)
+        .match_basename(args.match_basename)
+        .recursive(!args.no_recursive)
+        .canonicalize(!args.no_canonicalize)
+        .follow_symlinks(args.follow_symlinks)
+        .event_kinds(args.events)
+        .entries(if args.files_only {
+            vibewatch::EntryFilter::FilesOnly
+        } else if args.dirs_only {
+            vibewatch::EntryFilter::DirsOnly
+        } else {
+            vibewatch::EntryFilter::All
+        })
+        .skip_unchanged(args.skip_unchanged)
+        .ignore_dir_modify(args.ignore_dir_modify)
+        .strict_utf8(args.strict_utf8)
+        .sort_paths(args.sort_paths)
+        .wait_for_close(args.wait_for_close)
+        .retry_watch(args.retry_watch)
+        .summary_on_exit(args.summary_on_exit)
+        .heartbeat(Duration::from_secs(args.heartbeat))
+        .quiet(args.quiet)
+        .color(args.color)
+        .name(args.name.as_str())
+        .output(if args.json {
+            output::Output::Json
+        } else {
+            output::Output::Text
+        });
+
+    let builder = match &args.result_log {
+        Some(path) => builder.result_log(path),
+        None => builder,
+    };
+
+    let builder = match args.debounce_create {
+        Some(debounce_ms) => builder.debounce_create(debounce_ms),
+        None => builder,
+    };
+    let builder = match args.debounce_modify {
+        Some(debounce_ms) => builder.debounce_modify(debounce_ms),
+        None => builder,
+    };
+    let builder = match args.debounce_delete {
+        Some(debounce_ms) => builder.debounce_delete(debounce_ms),
+        None => builder,
+    };
+
+    let builder = match args.max_debounce {
+        Some(debounce_ms) => builder.max_debounce(debounce_ms),
+        None => builder,
+    };
+
+    let builder = match args.channel_capacity {
+        Some(capacity) => builder.channel_capacity(capacity.get()),
+        None => builder,
+    };
+
+    let builder = args
+        .exclude_dir
+        .iter()
+        .fold(builder, |builder, name| builder.exclude_dir(name));
+
+    let builder = args
+        .ignore_file
+        .iter()
+        .fold(builder, |builder, path| builder.ignore_file(path));
+
+    let builder = match args.max_depth {
+        Some(max_depth) => builder.max_depth(max_depth),
+        None => builder,
+    };
+
+    let builder = if args.poll {
+        builder.poll(Duration::from_millis(
+            args.poll_interval.unwrap_or(DEFAULT_POLL_INTERVAL_MS),
+        ))
+    } else {
+        builder
+    };
+
+    let builder = match args.exclude_writes_for {
+        Some(millis) => builder.exclude_writes_for(Duration::from_millis(millis)),
+        None => builder,
+    };
+
+    let builder = match args.min_interval {
+        Some(millis) => builder.min_interval(Duration::from_millis(millis)),
+        None => builder,
+    };
+
+    let builder = match args.exec_delay {
+        Some(millis) => builder.exec_delay(Duration::from_millis(millis)),
+        None => builder,
+    };
+
+    let builder = match args.max_output_lines {
+        Some(lines) => builder.max_output_lines(lines),
+        None => builder,
+    };
+
+    let builder = match args.coalesce_global {
+        Some(millis) => builder.coalesce_global(Duration::from_millis(millis)),
+        None => builder,
+    };
+
+    let builder = match args.min_size {
+        Some(bytes) => builder.min_size(bytes),
+        None => builder,
+    };
+
+    let builder = match &args.format {
+        Some(format) => builder.format(format.as_str()),
+        None => builder,
+    };
+
+    let builder = match args.stats {
+        Some(seconds) => builder.stats(Duration::from_secs(seconds)),
+        None => builder,
+    };
+
+    let builder = match args.wait_on_exit {
+        Some(seconds) => builder.wait_on_exit((seconds > 0).then(|| Duration::from_secs(seconds))),
+        None => builder,
+    };
+
+    let builder = rules
+        .include
+        .into_iter()
+        .fold(builder, vibewatch::WatcherBuilder::include);
+    let builder = rules
+        .exclude
+        .into_iter()
+        .fold(builder, vibewatch::WatcherBuilder::exclude);
+
+    builder.build()
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    if let Some(shell) = args.generate_completions {
+        clap_complete::generate(
+            shell,
+            &mut Args::command(),
+            "vibewatch",
+            &mut std::io::stdout(),
+        );
+        return Ok(());
+    }
+
+    // Initialize logger
+    let verbosity = args.verbosity();
+    match &args.log_file {
+        Some(path) => TeeLogger::new(&args.name, verbosity, open_log_file(path)?).init(),
+        None => terminal_logger(&args.name, verbosity).init(),
+    }
+
+    log::info!("Starting vibewatch file watcher");
+
+    let metrics_addr = args.metrics_addr;
+    let status_path = args.status_file.clone();
+    let list_matched = args.list_matched;
+    let explain = args.explain.clone();
+    let print_config = args.print_config;
+    let once = args.once;
+    let reload_args = args.config.is_some().then(|| args.clone());
+    let watch_args = resolve_config(args, &matches)?;
+
+    // Create every watcher up front so configuration errors surface before any starts
+    let mut watchers = Vec::with_capacity(watch_args.len());
+    let mut effective_configs = Vec::new();
+    for (index, args) in watch_args.into_iter().enumerate() {
+        if let Some(directory) = &args.directory {
+            // A missing path is reported as an error when the watcher is created
+            let kind = if directory.is_dir() {
+                "directory"
+            } else if directory.is_file() {
+                "file"
+            } else {
+                "path"
+            };
+            log::info!("Watching {}: {}", kind, directory.display());
+        }
+
+        if !args.exclude.is_empty() {
+            log::info!("Exclude patterns: {:?}", args.exclude);
+        }
+
+        if !args.include.is_empty() {
+            log::info!("Include patterns: {:?}", args.include);
+        }
+
+        if verbosity >= 1 {
+            log_commands(&args);
+        }
+
+        if print_config.is_some() {
+            effective_configs.push(EffectiveConfig::from_args(&args)?);
+        }
+
+        let watcher = create_watcher_from_args(args)?;
+        // With a config file, SIGHUP re-reads it
+        let watcher = match &reload_args {
+            Some(reload_args) => {
+                let (reload_args, matches) = (reload_args.clone(), matches.clone());
+                watcher.with_reload(move || reload_rules(&reload_args, &matches, index))
+            }
+            None => watcher,
+        };
+        watchers.push(watcher);
+    }
+
+    if list_matched {
+        let mut stdout = std::io::stdout().lock();
+        for watcher in &watchers {
+            for path in watcher.matched_files()? {
+                writeln!(stdout, "{}", path.display())?;
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(format) = print_config {
+        let rendered = render_config(&effective_configs, format)?;
+        writeln!(std::io::stdout().lock(), "{}", rendered.trim_end())?;
+        return Ok(());
+    }
+
+    if let Some(path) = explain {
+        let mut stdout = std::io::stdout().lock();
+        for watcher in &watchers {
+            writeln!(stdout, "{}", watcher.explain(&path))?;
+        }
+        return Ok(());
+    }
+
+    if let Some(addr) = metrics_addr {
+        let listener = metrics::bind(addr).await?;
+        log::info!("Serving metrics on http://{}/metrics", addr);
+
+        let metrics = Arc::new(metrics::Metrics::default());
+        tokio::spawn(metrics::serve(listener, Arc::clone(&metrics)));
+        watchers = watchers
+            .into_iter()
+            .map(|watcher| watcher.with_metrics(Arc::clone(&metrics)))
+            .collect();
+    }
+
+    if let Some(path) = status_path {
+        let status_file = Arc::new(status_file::StatusFile::new(path));
+        watchers = watchers
+            .into_iter()
+            .map(|watcher| watcher.with_status_file(Arc::clone(&status_file)))
+            .collect();
+    }
+
+    // Every watcher sees each command typed on stdin
+    watcher::FileWatcher::share_stdin_control(&mut watchers);
+
+    // Run all watchers until Ctrl+C or SIGTERM; each one handles shutdown on its own
+    let mut running = tokio::task::JoinSet::new();
+    for mut watcher in watchers {
+        running.spawn(async move { watcher.start_watching().await });
+    }
+    while let Some(result) = running.join_next().await {
+        result.context("Watcher task panicked")??;
+        // The first change seen by any watcher ends a --once run
+        if once {
+            running.abort_all();
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+    use rstest::rstest;
+
+    #[test]
+    fn test_cli_verify_app() {
+        // Verify the CLI app is valid
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn test_args_basic_directory() {
+        let args = Args::parse_from(["vibewatch", "."]);
+        assert_eq!(args.directory, Some(PathBuf::from(".")));
+        assert!(args.exclude.is_empty());
+        assert!(args.include.is_empty());
+        assert!(!args.verbose);
+    }
+
+    #[test]
+    fn test_args_with_include_patterns() {
+        let args = Args::parse_from(["vibewatch", ".", "--include", "*.rs", "--include", "*.toml"]);
+        assert_eq!(args.directory, Some(PathBuf::from(".")));
+        assert_eq!(args.include, vec!["*.rs", "*.toml"]);
+    }
+
+    #[test]
+    fn test_args_with_exclude_patterns() {
+        let args = Args::parse_from([
+            "vibewatch",
+            ".",
+            "--exclude",
+            "target/**",
+            "--exclude",
+            "node_modules/**",
+        ]);
+        assert_eq!(args.exclude, vec!["target/**", "node_modules/**"]);
+    }
+
+    #[test]
+    fn test_args_with_verbose() {
+        let args = Args::parse_from(["vibewatch", ".", "--verbose"]);
+        assert!(args.verbose);
+        assert_eq!(args.verbosity(), 2);
+    }
+
+    #[rstest]
+    #[case(&["vibewatch", "."], 0)]
+    #[case(&["vibewatch", ".", "-v"], 1)]
+    #[case(&["vibewatch", ".", "-vv"], 2)]
+    #[case(&["vibewatch", ".", "-v", "-v", "-v"], 3)]
+    #[case(&["vibewatch", ".", "-v", "--verbose"], 2)]
+    #[case(&["vibewatch", ".", "-vvv", "--verbose"], 3)]
+    fn test_args_verbosity_count(#[case] argv: &[&str], #[case] expected: u8) {
+        assert_eq!(Args::parse_from(argv).verbosity(), expected);
+    }
+
+    #[test]
+    fn test_open_log_file_appends() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("vibewatch.log");
+        std::fs::write(&path, "earlier\n").unwrap();
+
+        writeln!(open_log_file(&path).unwrap(), "later").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "earlier\nlater\n");
+    }
+
+    #[test]
+    fn test_log_file_gets_event_records() {
+        use log::Log;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("vibewatch.log");
+        let logger = TeeLogger::new("", 0, open_log_file(&path).unwrap());
+
+        // What output::record_event logs for a created file
+        logger.log(
+            &log::Record::builder()
+                .target(output::RECORD_TARGET)
+                .level(log::Level::Info)
+                .args(format_args!("[CREATED] src/main.rs"))
+                .build(),
+        );
+        logger.flush();
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        assert!(
+            log.trim_end()
+                .ends_with("vibewatch::record] [CREATED] src/main.rs"),
+            "The log should record the event: {}",
+            log
+        );
+    }
+
+    #[test]
+    fn test_open_log_file_reports_unwritable_path() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("missing").join("vibewatch.log");
+
+        let err = open_log_file(&path).unwrap_err();
+        assert!(err.to_string().contains("Failed to open log file"));
+    }
+
+    #[rstest]
+    #[case(0, log::LevelFilter::Info)]
+    #[case(1, log::LevelFilter::Info)]
+    #[case(2, log::LevelFilter::Debug)]
+    #[case(3, log::LevelFilter::Trace)]
+    #[case(7, log::LevelFilter::Trace)]
+    fn test_log_level_for_verbosity(#[case] verbosity: u8, #[case] expected: log::LevelFilter) {
+        assert_eq!(log_level(verbosity), expected);
+    }
+
+    // Parameterized tests for command flags
+    #[rstest]
+    #[case("--on-create", "echo created", "on_create")]
+    #[case("--on-modify", "echo modified", "on_modify")]
+    #[case("--on-delete", "echo deleted", "on_delete")]
+    #[case("--on-change", "echo changed", "on_change")]
+    fn test_args_command_flags(
+        #[case] flag: &str,
+        #[case] command: &str,
+        #[case] field_name: &str,
+    ) {
+        let args = Args::parse_from(["vibewatch", ".", flag, command]);
+        let expected = vec![command.to_string()];
+
+        let actual = match field_name {
+            "on_create" => &args.on_create,
+            "on_modify" => &args.on_modify,
+            "on_delete" => &args.on_delete,
+            "on_change" => &args.on_change,
+            _ => panic!("Unknown field: {}", field_name),
+        };
+
+        assert_eq!(
+            actual, &expected,
+            "Flag {} with command '{}' should be parsed correctly",
+            flag, command
+        );
+    }
+
+    #[test]
+    fn test_args_per_event_timeouts() {
+        let args = Args::parse_from([
+            "vibewatch",
+            ".",
+            "--timeout-create",
+            "5",
+            "--timeout-modify",
+            "60",
+            "--timeout-delete",
+            "0",
+        ]);
+        assert_eq!(args.timeout_create, Some(5));
+        assert_eq!(args.timeout_modify, Some(60));
+        assert_eq!(args.timeout_delete, Some(0));
+    }
+
+    #[test]
+    fn test_args_command_timeout() {
+        let args = Args::parse_from(["vibewatch", "."]);
+        assert!(args.command_timeout.is_none());
+
+        let args = Args::parse_from(["vibewatch", ".", "--command-timeout", "30"]);
+        assert_eq!(args.command_timeout, Some(30));
+    }
+
+    #[test]
+    fn test_args_rejects_negative_timeout() {
+        let result = Args::try_parse_from(["vibewatch", ".", "--timeout-modify", "-1"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_args_repeated_command_flags_keep_order() {
+        let args = Args::parse_from([
+            "vibewatch",
+            ".",
+            "--on-modify",
+            "cargo clippy",
+            "--on-change",
+            "echo changed",
+            "--on-modify",
+            "notify-send saved",
+        ]);
+        assert_eq!(args.on_modify, ["cargo clippy", "notify-send saved"]);
+        assert_eq!(args.on_change, ["echo changed"]);
+    }
+
+    #[test]
+    fn test_args_on_root_change() {
+        let args = Args::parse_from(["vibewatch", "."]);
+        assert!(args.on_root_change.is_empty());
+
+        let args = Args::parse_from(["vibewatch", ".", "--on-root-change", "echo {relative_path}"]);
+        assert_eq!(args.on_root_change, ["echo {relative_path}"]);
+    }
+
+    #[test]
+    fn test_args_command_hooks() {
+        let args = Args::parse_from([
+            "vibewatch",
+            ".",
+            "--on-command-success",
+            "echo ok",
+            "--on-command-failure",
+            "echo failed {exit_code}",
+        ]);
+        assert_eq!(args.on_command_success, ["echo ok"]);
+        assert_eq!(args.on_command_failure, ["echo failed {exit_code}"]);
+    }
+
+    #[test]
+    fn test_args_dir_commands() {
+        let args = Args::parse_from([
+            "vibewatch",
+            ".",
+            "--on-create-dir",
+            "chmod 755 {file_path}",
+            "--on-delete-dir",
+            "echo gone",
+        ]);
+        assert_eq!(args.on_create_dir, ["chmod 755 {file_path}"]);
+        assert_eq!(args.on_delete_dir, ["echo gone"]);
+    }
+
+    #[rstest]
+    #[case("--on-move")]
+    #[case("--on-rename")]
+    fn test_args_on_move_and_rename_alias(#[case] flag: &str) {
+        let args = Args::parse_from(["vibewatch", ".", flag, "echo {old_path} {new_path}"]);
+        assert_eq!(args.on_move, ["echo {old_path} {new_path}"]);
+    }
+
+    #[test]
+    fn test_args_run_routes() {
+        let args = Args::parse_from([
+            "vibewatch",
+            ".",
+            "--run",
+            "*.rs:cargo check",
+            "--run",
+            "docs/**:echo {file_path}: changed",
+        ]);
+        assert_eq!(
+            args.run,
+            [
+                ("*.rs".to_string(), "cargo check".to_string()),
+                (
+                    "docs/**".to_string(),
+                    "echo {file_path}: changed".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case("cargo check")]
+    #[case(":cargo check")]
+    #[case("*.rs:")]
+    fn test_args_run_rejects_malformed_routes(#[case] route: &str) {
+        assert!(Args::try_parse_from(["vibewatch", ".", "--run", route]).is_err());
+    }
+
+    #[rstest]
+    #[case(&["vibewatch", "."], None)]
+    #[case(&["vibewatch", ".", "--stats"], Some(10))]
+    #[case(&["vibewatch", ".", "--stats=60"], Some(60))]
+    fn test_args_stats(#[case] argv: &[&str], #[case] expected: Option<u64>) {
+        let args = Args::parse_from(argv);
+        assert_eq!(args.stats, expected);
+    }
+
+    #[test]
+    fn test_args_rejects_zero_stats_interval() {
+        assert!(Args::try_parse_from(["vibewatch", ".", "--stats=0"]).is_err());
+    }
+
+    #[test]
+    fn test_args_exclude_writes_for() {
+        assert_eq!(
+            Args::parse_from(["vibewatch", "."]).exclude_writes_for,
+            None
+        );
+        let args = Args::parse_from(["vibewatch", ".", "--exclude-writes-for", "500"]);
+        assert_eq!(args.exclude_writes_for, Some(500));
+    }
+
+    #[test]
+    fn test_args_channel_capacity() {
+        assert_eq!(Args::parse_from(["vibewatch", "."]).channel_capacity, None);
+        let args = Args::parse_from(["vibewatch", ".", "--channel-capacity", "1024"]);
+        assert_eq!(args.channel_capacity.map(NonZeroUsize::get), Some(1024));
+        assert!(Args::try_parse_from(["vibewatch", ".", "--channel-capacity", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_args_min_interval() {
+        assert_eq!(Args::parse_from(["vibewatch", "."]).min_interval, None);
+        let args = Args::parse_from(["vibewatch", ".", "--min-interval", "2000"]);
+        assert_eq!(args.min_interval, Some(2000));
+    }
+
+    #[test]
+    fn test_args_exec_delay() {
+        assert_eq!(Args::parse_from(["vibewatch", "."]).exec_delay, None);
+        let args = Args::parse_from(["vibewatch", ".", "--exec-delay", "300"]);
+        assert_eq!(args.exec_delay, Some(300));
+        assert!(
+            Args::try_parse_from(["vibewatch", ".", "--exec-delay", "300", "--restart"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_args_coalesce_global() {
+        assert_eq!(Args::parse_from(["vibewatch", "."]).coalesce_global, None);
+        let args = Args::parse_from(["vibewatch", ".", "--coalesce-global", "500"]);
+        assert_eq!(args.coalesce_global, Some(500));
+        assert!(Args::try_parse_from(["vibewatch", ".", "--coalesce-global", "0"]).is_err());
+    }
+
+    #[rstest]
+    #[case(&["vibewatch", "."], None)]
+    #[case(&["vibewatch", ".", "--max-output-lines", "10"], Some(10))]
+    #[case(&["vibewatch", ".", "--max-output-lines", "0"], Some(0))]
+    fn test_args_max_output_lines(#[case] argv: &[&str], #[case] expected: Option<usize>) {
+        assert_eq!(Args::parse_from(argv).max_output_lines, expected);
+    }
+
+    #[rstest]
+    #[case(&["vibewatch", "."], 0)]
+    #[case(&["vibewatch", ".", "--max-concurrent", "4"], 4)]
+    fn test_args_max_concurrent(#[case] argv: &[&str], #[case] expected: usize) {
+        let args = Args::parse_from(argv);
+        assert_eq!(args.max_concurrent, expected);
+    }
+
+    #[test]
+    fn test_args_once() {
+        assert!(!Args::parse_from(["vibewatch", "."]).once);
+        assert!(Args::parse_from(["vibewatch", ".", "--once"]).once);
+    }
+
+    #[test]
+    fn test_args_retries() {
+        let args = Args::parse_from(["vibewatch", "."]);
+        assert_eq!(
+            (args.retries, args.retry_delay, args.retry_timeouts),
+            (0, 1000, false)
+        );
+
+        let args = Args::parse_from([
+            "vibewatch",
+            ".",
+            "--retries",
+            "3",
+            "--retry-delay",
+            "250",
+            "--retry-timeouts",
+        ]);
+        assert_eq!(
+            (args.retries, args.retry_delay, args.retry_timeouts),
+            (3, 250, true)
+        );
+    }
+
+    #[test]
+    fn test_args_wait_for_close() {
+        assert!(!Args::parse_from(["vibewatch", "."]).wait_for_close);
+        assert!(Args::parse_from(["vibewatch", ".", "--wait-for-close"]).wait_for_close);
+    }
+
+    #[rstest]
+    #[case(&["vibewatch", "."], 0)]
+    #[case(&["vibewatch", ".", "--heartbeat", "300"], 300)]
+    #[case(&["vibewatch", ".", "--heartbeat", "2m"], 120)]
+    fn test_args_heartbeat(#[case] argv: &[&str], #[case] expected: u64) {
+        assert_eq!(Args::parse_from(argv).heartbeat, expected);
+    }
+
+    #[test]
+    fn test_args_summary_on_exit() {
+        assert!(!Args::parse_from(["vibewatch", "."]).summary_on_exit);
+        assert!(Args::parse_from(["vibewatch", ".", "--summary-on-exit"]).summary_on_exit);
+    }
+
+    #[test]
+    fn test_args_result_log() {
+        assert!(Args::parse_from(["vibewatch", "."]).result_log.is_none());
+        let args = Args::parse_from(["vibewatch", ".", "--result-log", "results.jsonl"]);
+        assert_eq!(args.result_log, Some(PathBuf::from("results.jsonl")));
+    }
+
+    #[test]
+    fn test_args_match_basename() {
+        assert!(!Args::parse_from(["vibewatch", "."]).match_basename);
+        assert!(Args::parse_from(["vibewatch", ".", "--match-basename"]).match_basename);
+        assert!(
+            Args::try_parse_from(["vibewatch", ".", "--match-basename", "--match-absolute"])
+                .is_err()
+        );
+    }
+
+    #[rstest]
+    #[case("node_modules", true)]
+    #[case(".git", true)]
+    #[case("vendor/cache", false)]
+    #[case("..", false)]
+    #[case("", false)]
+    fn test_args_exclude_dir(#[case] name: &str, #[case] valid: bool) {
+        let args = Args::try_parse_from(["vibewatch", ".", "--exclude-dir", name]);
+        assert_eq!(args.is_ok(), valid);
+        if let Ok(args) = args {
+            assert_eq!(args.exclude_dir, [name]);
+        }
+    }
+
+    #[test]
+    fn test_args_format() {
+        assert_eq!(Args::parse_from(["vibewatch", "."]).format, None);
+        let args = Args::parse_from(["vibewatch", ".", "--format", "{event_type} {relative_path}"]);
+        assert_eq!(args.format.as_deref(), Some("{event_type} {relative_path}"));
+        assert!(
+            Args::try_parse_from(["vibewatch", ".", "--format", "{file_path}", "--json"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_args_restart() {
+        assert!(!Args::parse_from(["vibewatch", "."]).restart);
+        assert!(Args::parse_from(["vibewatch", ".", "--restart"]).restart);
+        for conflict in [
+            "--sequential",
+            "--no-overlap",
+            "--persistent-shell",
+            "--json",
+        ] {
+            assert!(Args::try_parse_from(["vibewatch", ".", "--restart", conflict]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_args_stdin_control() {
+        assert!(!Args::parse_from(["vibewatch", "."]).stdin_control);
+        assert!(Args::parse_from(["vibewatch", ".", "--stdin-control"]).stdin_control);
+    }
+
+    #[test]
+    fn test_args_retry_watch() {
+        assert!(!Args::parse_from(["vibewatch", "."]).retry_watch);
+        assert!(Args::parse_from(["vibewatch", ".", "--retry-watch"]).retry_watch);
+    }
+
+    #[test]
+    fn test_args_ignore_dir_modify() {
+        assert!(!Args::parse_from(["vibewatch", "."]).ignore_dir_modify);
+        assert!(Args::parse_from(["vibewatch", ".", "--ignore-dir-modify"]).ignore_dir_modify);
+    }
+
+    #[test]
+    fn test_args_sort_paths() {
+        assert!(!Args::parse_from(["vibewatch", "."]).sort_paths);
+        assert!(Args::parse_from(["vibewatch", ".", "--sort-paths"]).sort_paths);
+    }
+
+    #[test]
+    fn test_args_strict_utf8() {
+        assert!(!Args::parse_from(["vibewatch", "."]).strict_utf8);
+        assert!(Args::parse_from(["vibewatch", ".", "--strict-utf8"]).strict_utf8);
+    }
+
+    #[test]
+    fn test_args_min_size() {
+        assert_eq!(Args::parse_from(["vibewatch", "."]).min_size, None);
+        assert_eq!(
+            Args::parse_from(["vibewatch", ".", "--min-size", "10"]).min_size,
+            Some(10)
+        );
+        assert!(Args::try_parse_from(["vibewatch", ".", "--min-size", "-1"]).is_err());
+    }
+
+    #[test]
+    fn test_args_skip_unchanged() {
+        assert!(!Args::parse_from(["vibewatch", "."]).skip_unchanged);
+        assert!(Args::parse_from(["vibewatch", ".", "--skip-unchanged"]).skip_unchanged);
+    }
+
+    #[test]
+    fn test_args_explain() {
+        assert_eq!(Args::parse_from(["vibewatch", "."]).explain, None);
+        let args = Args::parse_from(["vibewatch", ".", "--explain", "src/main.rs"]);
+        assert_eq!(args.explain, Some(PathBuf::from("src/main.rs")));
+        assert!(
+            Args::try_parse_from(["vibewatch", ".", "--explain", "a", "--list-matched"]).is_err()
+        );
+    }
+
+    #[rstest]
+    #[case("bash", clap_complete::Shell::Bash)]
+    #[case("elvish", clap_complete::Shell::Elvish)]
+    #[case("fish", clap_complete::Shell::Fish)]
+    #[case("powershell", clap_complete::Shell::PowerShell)]
+    #[case("zsh", clap_complete::Shell::Zsh)]
+    fn test_args_generate_completions(#[case] shell: &str, #[case] expected: clap_complete::Shell) {
+        // No directory needed just to print completions
+        let args = Args::parse_from(["vibewatch", "--generate-completions", shell]);
+        assert_eq!(args.generate_completions, Some(expected));
+    }
+
+    #[test]
+    fn test_args_list_matched() {
+        assert!(!Args::parse_from(["vibewatch", "."]).list_matched);
+        assert!(Args::parse_from(["vibewatch", ".", "--list-matched"]).list_matched);
+        assert!(Args::parse_from(["vibewatch", ".", "--list-watched"]).list_matched);
+    }
+
+    #[test]
+    fn test_args_follow_symlinks() {
+        assert!(!Args::parse_from(["vibewatch", "."]).follow_symlinks);
+        assert!(Args::parse_from(["vibewatch", ".", "--follow-symlinks"]).follow_symlinks);
+    }
 
-    /// Command to execute on ANY file change (fallback for all events)
-    #[arg(long, value_name = "COMMAND", help_heading = COMMANDS_HELP)]
-    #[arg(
-        help = "Run this command for ANY file event (create/modify/delete)\n\nActs as fallback when specific --on-* commands are not set\nTemplates: {file_path}, {relative_path}, {absolute_path}, {event_type}\nExample: --on-change 'echo {event_type}: {relative_path}'"
-    )]
-    on_change: Option<String>,
-}
+    #[test]
+    fn test_args_no_canonicalize() {
+        assert!(!Args::parse_from(["vibewatch", "."]).no_canonicalize);
+        assert!(Args::parse_from(["vibewatch", ".", "--no-canonicalize"]).no_canonicalize);
+    }
 
-// Separate function for testability
-fn create_watcher_from_args(args: Args) -> anyhow::Result<watcher::FileWatcher> {
-    watcher::FileWatcher::new(
-        args.directory,
-        args.include,
-        args.exclude,
-        watcher::CommandConfig {
-            on_create: args.on_create,
-            on_modify: args.on_modify,
-            on_delete: args.on_delete,
-            on_change: args.on_change,
-        },
-        args.debounce,
-        args.verbose,
-        args.quiet,
-    )
-}
+    #[test]
+    fn test_args_metrics_addr() {
+        let args = Args::parse_from(["vibewatch", "."]);
+        assert!(args.metrics_addr.is_none());
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
+        let args = Args::parse_from(["vibewatch", ".", "--metrics-addr", "127.0.0.1:9185"]);
+        assert_eq!(args.metrics_addr, Some("127.0.0.1:9185".parse().unwrap()));
 
-    // Initialize logger
-    if args.verbose {
-        env_logger::Builder::from_default_env()
-            .filter_level(log::LevelFilter::Debug)
-            .init();
-    } else {
-        env_logger::Builder::from_default_env()
-            .filter_level(log::LevelFilter::Info)
-            .init();
+        let result = Args::try_parse_from(["vibewatch", ".", "--metrics-addr", "not-an-addr"]);
+        assert!(result.is_err());
     }
 
-    log::info!("Starting vibewatch file watcher");
-    log::info!("Watching directory: {}", args.directory.display());
+    #[test]
+    fn test_args_status_file() {
+        assert!(Args::parse_from(["vibewatch", "."]).status_file.is_none());
+        let args = Args::parse_from(["vibewatch", ".", "--status-file", "status.json"]);
+        assert_eq!(args.status_file, Some(PathBuf::from("status.json")));
+    }
 
-    if !args.exclude.is_empty() {
-        log::info!("Exclude patterns: {:?}", args.exclude);
+    #[rstest]
+    #[case(&["vibewatch", "."], None)]
+    #[case(&["vibewatch", ".", "--shell", "sh -c"], Some("sh -c"))]
+    #[case(&["vibewatch", ".", "--shell", "none"], Some("none"))]
+    fn test_args_shell(#[case] argv: &[&str], #[case] expected: Option<&str>) {
+        let args = Args::parse_from(argv);
+        assert_eq!(args.shell.as_deref(), expected);
     }
 
-    if !args.include.is_empty() {
-        log::info!("Include patterns: {:?}", args.include);
+    #[rstest]
+    #[case(&["vibewatch", "."], None)]
+    #[case(&["vibewatch", ".", "--no-overlap"], Some(overlap::OverlapMode::Drop))]
+    #[case(&["vibewatch", "--no-overlap", "."], Some(overlap::OverlapMode::Drop))]
+    #[case(&["vibewatch", ".", "--no-overlap=drop"], Some(overlap::OverlapMode::Drop))]
+    #[case(&["vibewatch", ".", "--no-overlap=queue"], Some(overlap::OverlapMode::Queue))]
+    fn test_args_no_overlap(#[case] argv: &[&str], #[case] expected: Option<overlap::OverlapMode>) {
+        let args = Args::parse_from(argv);
+        assert_eq!(args.no_overlap, expected);
+        assert_eq!(args.directory, Some(PathBuf::from(".")));
     }
 
-    // Create and start the file watcher
-    let mut watcher = create_watcher_from_args(args)?;
-    watcher.start_watching().await?;
+    #[rstest]
+    #[case(&["vibewatch", "."], None)]
+    #[case(&["vibewatch", ".", "--working-dir", "build"], Some(watcher::WorkingDir::Path(PathBuf::from("build"))))]
+    #[case(&["vibewatch", ".", "--working-dir", "{dir_of_file}"], Some(watcher::WorkingDir::DirOfFile))]
+    fn test_args_working_dir(#[case] argv: &[&str], #[case] expected: Option<watcher::WorkingDir>) {
+        let args = Args::parse_from(argv);
+        assert_eq!(args.working_dir, expected);
+    }
 
-    Ok(())
-}
+    #[rstest]
+    #[case(&["vibewatch", "."], None)]
+    #[case(&["vibewatch", ".", "--wait-on-exit"], Some(0))]
+    #[case(&["vibewatch", "--wait-on-exit", "."], Some(0))]
+    #[case(&["vibewatch", ".", "--wait-on-exit=30"], Some(30))]
+    fn test_args_wait_on_exit(#[case] argv: &[&str], #[case] expected: Option<u64>) {
+        let args = Args::parse_from(argv);
+        assert_eq!(args.wait_on_exit, expected);
+        assert_eq!(args.directory, Some(PathBuf::from(".")));
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use clap::CommandFactory;
-    use rstest::rstest;
+    #[rstest]
+    #[case(&["vibewatch", "."], watch_limit::WatchLimitMode::Warn)]
+    #[case(&["vibewatch", ".", "--watch-descriptor-limit", "error"], watch_limit::WatchLimitMode::Error)]
+    #[case(&["vibewatch", ".", "--watch-descriptor-limit", "off"], watch_limit::WatchLimitMode::Off)]
+    fn test_args_watch_descriptor_limit(
+        #[case] argv: &[&str],
+        #[case] expected: watch_limit::WatchLimitMode,
+    ) {
+        assert_eq!(Args::parse_from(argv).watch_descriptor_limit, expected);
+    }
+
+    #[rstest]
+    #[case(&["vibewatch", "."], debounce::DebounceMode::Trailing)]
+    #[case(&["vibewatch", ".", "--debounce-mode", "trailing"], debounce::DebounceMode::Trailing)]
+    #[case(&["vibewatch", ".", "--debounce-mode", "leading"], debounce::DebounceMode::Leading)]
+    fn test_args_debounce_mode(#[case] argv: &[&str], #[case] expected: debounce::DebounceMode) {
+        let args = Args::parse_from(argv);
+        assert_eq!(args.debounce_mode, expected);
+    }
 
     #[test]
-    fn test_cli_verify_app() {
-        // Verify the CLI app is valid
-        Args::command().debug_assert();
+    fn test_args_debounce_per_command_group() {
+        assert!(!Args::parse_from(["vibewatch", "."]).debounce_per_command_group);
+        assert!(
+            Args::parse_from(["vibewatch", ".", "--debounce-per-command-group"])
+                .debounce_per_command_group
+        );
     }
 
     #[test]
-    fn test_args_basic_directory() {
-        let args = Args::parse_from(["vibewatch", "."]);
-        assert_eq!(args.directory, PathBuf::from("."));
-        assert!(args.exclude.is_empty());
-        assert!(args.include.is_empty());
-        assert!(!args.verbose);
+    fn test_args_per_event_debounce() {
+        let args = Args::parse_from([
+            "vibewatch",
+            ".",
+            "--debounce-modify",
+            "250",
+            "--debounce-delete",
+            "0",
+        ]);
+        assert_eq!(args.debounce, 100);
+        assert_eq!(args.debounce_create, None);
+        assert_eq!(args.debounce_modify, Some(250));
+        assert_eq!(args.debounce_delete, Some(0));
+        assert_eq!(args.max_debounce, None);
     }
 
     #[test]
-    fn test_args_with_include_patterns() {
-        let args = Args::parse_from(["vibewatch", ".", "--include", "*.rs", "--include", "*.toml"]);
-        assert_eq!(args.directory, PathBuf::from("."));
-        assert_eq!(args.include, vec!["*.rs", "*.toml"]);
+    fn test_args_max_debounce() {
+        let args = Args::parse_from(["vibewatch", ".", "--max-debounce", "2000"]);
+        assert_eq!(args.max_debounce, Some(2000));
+        assert!(Args::try_parse_from(["vibewatch", ".", "--max-debounce", "0"]).is_err());
+    }
+
+    #[rstest]
+    #[case("500ms", 500)]
+    #[case("2s", 2000)]
+    #[case("1m", 60_000)]
+    #[case("1h", 3_600_000)]
+    #[case("500", 500)]
+    #[case("0", 0)]
+    fn test_parse_millis(#[case] value: &str, #[case] expected: u64) {
+        assert_eq!(parse_millis(value), Ok(expected));
+    }
+
+    #[rstest]
+    #[case("30", 30)]
+    #[case("30s", 30)]
+    #[case("2m", 120)]
+    #[case("2000ms", 2)]
+    fn test_parse_seconds(#[case] value: &str, #[case] expected: u64) {
+        assert_eq!(parse_seconds(value), Ok(expected));
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("ms")]
+    #[case("2x")]
+    #[case("1.5s")]
+    #[case("-5")]
+    #[case("5 s")]
+    #[case("99999999999999999999")]
+    #[case("99999999999999999h")]
+    fn test_parse_millis_rejects_invalid(#[case] value: &str) {
+        assert!(parse_millis(value).is_err(), "{:?}", value);
     }
 
     #[test]
-    fn test_args_with_exclude_patterns() {
+    fn test_args_duration_suffixes() {
         let args = Args::parse_from([
             "vibewatch",
             ".",
-            "--exclude",
-            "target/**",
-            "--exclude",
-            "node_modules/**",
+            "--debounce",
+            "2s",
+            "--debounce-modify",
+            "250ms",
+            "--command-timeout",
+            "1m",
         ]);
-        assert_eq!(args.exclude, vec!["target/**", "node_modules/**"]);
+        assert_eq!(args.debounce, 2000);
+        assert_eq!(args.debounce_modify, Some(250));
+        assert_eq!(args.command_timeout, Some(60));
+
+        let err = Args::try_parse_from(["vibewatch", ".", "--debounce", "2x"]).unwrap_err();
+        assert!(err.to_string().contains("unknown unit 'x'"), "{}", err);
+        assert!(Args::try_parse_from(["vibewatch", ".", "--command-timeout", "1500ms"]).is_err());
+        assert!(Args::try_parse_from(["vibewatch", ".", "--max-debounce", "0s"]).is_err());
     }
 
     #[test]
-    fn test_args_with_verbose() {
-        let args = Args::parse_from(["vibewatch", ".", "--verbose"]);
-        assert!(args.verbose);
+    fn test_args_rejects_unknown_debounce_mode() {
+        let result = Args::try_parse_from(["vibewatch", ".", "--debounce-mode", "both"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_args_persistent_shell() {
+        let args = Args::parse_from(["vibewatch", "."]);
+        assert!(!args.persistent_shell);
+
+        let args = Args::parse_from(["vibewatch", ".", "--persistent-shell"]);
+        assert!(args.persistent_shell);
     }
 
-    // Parameterized tests for command flags
     #[rstest]
-    #[case("--on-create", "echo created", "on_create")]
-    #[case("--on-modify", "echo modified", "on_modify")]
-    #[case("--on-delete", "echo deleted", "on_delete")]
-    #[case("--on-change", "echo changed", "on_change")]
-    fn test_args_command_flags(
-        #[case] flag: &str,
-        #[case] command: &str,
-        #[case] field_name: &str,
-    ) {
-        let args = Args::parse_from(["vibewatch", ".", flag, command]);
-        let expected = Some(command.to_string());
+    #[case(&["vibewatch", "."], false, None)]
+    #[case(&["vibewatch", ".", "--poll"], true, None)]
+    #[case(&["vibewatch", ".", "--poll", "--poll-interval", "250"], true, Some(250))]
+    fn test_args_poll(#[case] argv: &[&str], #[case] poll: bool, #[case] interval: Option<u64>) {
+        let args = Args::parse_from(argv);
+        assert_eq!(args.poll, poll);
+        assert_eq!(args.poll_interval, interval);
+    }
 
-        let actual = match field_name {
-            "on_create" => &args.on_create,
-            "on_modify" => &args.on_modify,
-            "on_delete" => &args.on_delete,
-            "on_change" => &args.on_change,
-            _ => panic!("Unknown field: {}", field_name),
-        };
+    #[rstest]
+    #[case(&["vibewatch", ".", "--poll-interval", "250"])]
+    #[case(&["vibewatch", ".", "--poll", "--poll-interval", "0"])]
+    fn test_args_rejects_invalid_poll_interval(#[case] argv: &[&str]) {
+        assert!(Args::try_parse_from(argv).is_err());
+    }
 
-        assert_eq!(
-            actual, &expected,
-            "Flag {} with command '{}' should be parsed correctly",
-            flag, command
-        );
+    #[rstest]
+    #[case(&["vibewatch", "."], watcher::EventKinds::default())]
+    #[case(&["vibewatch", ".", "--events", "all"], watcher::EventKinds::all())]
+    #[case(
+        &["vibewatch", ".", "--events", "create, delete"],
+        watcher::EventKinds {
+            create: true,
+            modify: false,
+            metadata: false,
+            delete: true,
+            access: false,
+        }
+    )]
+    fn test_args_events(#[case] argv: &[&str], #[case] expected: watcher::EventKinds) {
+        assert_eq!(Args::parse_from(argv).events, expected);
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("create,")]
+    #[case("rename")]
+    fn test_args_rejects_unknown_events(#[case] events: &str) {
+        assert!(Args::try_parse_from(["vibewatch", ".", "--events", events]).is_err());
+    }
+
+    #[test]
+    fn test_args_files_only_conflicts_with_dirs_only() {
+        let args = Args::parse_from(["vibewatch", ".", "--files-only"]);
+        assert!(args.files_only && !args.dirs_only);
+        assert!(Args::try_parse_from(["vibewatch", ".", "--files-only", "--dirs-only"]).is_err());
+    }
+
+    #[rstest]
+    #[case(&["vibewatch", "."], None)]
+    #[case(&["vibewatch", ".", "--max-depth", "0"], Some(0))]
+    #[case(&["vibewatch", ".", "--max-depth", "2"], Some(2))]
+    fn test_args_max_depth(#[case] argv: &[&str], #[case] expected: Option<usize>) {
+        let args = Args::parse_from(argv);
+        assert_eq!(args.max_depth, expected);
+    }
+
+    #[rstest]
+    #[case(&["vibewatch", "."], output::ColorChoice::Auto)]
+    #[case(&["vibewatch", ".", "--color", "always"], output::ColorChoice::Always)]
+    #[case(&["vibewatch", ".", "--color", "never"], output::ColorChoice::Never)]
+    fn test_args_color(#[case] argv: &[&str], #[case] expected: output::ColorChoice) {
+        let args = Args::parse_from(argv);
+        assert_eq!(args.color, expected);
+    }
+
+    #[test]
+    fn test_args_no_recursive() {
+        let args = Args::parse_from(["vibewatch", "."]);
+        assert!(!args.no_recursive);
+
+        let args = Args::parse_from(["vibewatch", ".", "--no-recursive"]);
+        assert!(args.no_recursive);
+    }
+
+    #[test]
+    fn test_args_export_env() {
+        let args = Args::parse_from(["vibewatch", "."]);
+        assert!(!args.export_env);
+
+        let args = Args::parse_from(["vibewatch", ".", "--export-env"]);
+        assert!(args.export_env);
     }
 
     #[test]
@@ -236,14 +2309,14 @@ mod tests {
             "echo changed",
         ]);
 
-        assert_eq!(args.directory, PathBuf::from("/tmp/watch"));
+        assert_eq!(args.directory, Some(PathBuf::from("/tmp/watch")));
         assert_eq!(args.include, vec!["*.rs"]);
         assert_eq!(args.exclude, vec!["target/**"]);
         assert!(args.verbose);
-        assert_eq!(args.on_create, Some("git add {file_path}".to_string()));
-        assert_eq!(args.on_modify, Some("cargo check".to_string()));
-        assert_eq!(args.on_delete, Some("echo removed".to_string()));
-        assert_eq!(args.on_change, Some("echo changed".to_string()));
+        assert_eq!(args.on_create, ["git add {file_path}"]);
+        assert_eq!(args.on_modify, ["cargo check"]);
+        assert_eq!(args.on_delete, ["echo removed"]);
+        assert_eq!(args.on_change, ["echo changed"]);
     }
 
     #[test]
@@ -252,7 +2325,7 @@ mod tests {
 
         assert_eq!(args.include, vec!["*.rs"]);
         assert_eq!(args.exclude, vec!["target/**"]);
-        assert!(args.verbose);
+        assert_eq!(args.verbosity, 1);
     }
 
     #[test]
@@ -290,7 +2363,7 @@ mod tests {
         let args = Args::parse_from(["vibewatch", path]);
         assert_eq!(
             args.directory,
-            PathBuf::from(expected),
+            Some(PathBuf::from(expected)),
             "Directory path '{}' should be parsed correctly",
             path
         );
@@ -299,10 +2372,10 @@ mod tests {
     #[test]
     fn test_args_no_commands() {
         let args = Args::parse_from(["vibewatch", "."]);
-        assert_eq!(args.on_create, None);
-        assert_eq!(args.on_modify, None);
-        assert_eq!(args.on_delete, None);
-        assert_eq!(args.on_change, None);
+        assert!(args.on_create.is_empty());
+        assert!(args.on_modify.is_empty());
+        assert!(args.on_delete.is_empty());
+        assert!(args.on_change.is_empty());
     }
 
     #[test]
@@ -313,10 +2386,7 @@ mod tests {
             "--on-modify",
             "echo {event_type}: {relative_path}",
         ]);
-        assert_eq!(
-            args.on_modify,
-            Some("echo {event_type}: {relative_path}".to_string())
-        );
+        assert_eq!(args.on_modify, ["echo {event_type}: {relative_path}"]);
     }
 
     #[test]
@@ -327,23 +2397,20 @@ mod tests {
             "--on-change",
             "echo 'File changed: {file_path}'",
         ]);
-        assert_eq!(
-            args.on_change,
-            Some("echo 'File changed: {file_path}'".to_string())
-        );
+        assert_eq!(args.on_change, ["echo 'File changed: {file_path}'"]);
     }
 
     #[test]
     fn test_args_minimal() {
         let args = Args::parse_from(["vibewatch", "."]);
-        assert_eq!(args.directory, PathBuf::from("."));
+        assert_eq!(args.directory, Some(PathBuf::from(".")));
         assert!(args.include.is_empty());
         assert!(args.exclude.is_empty());
         assert!(!args.verbose);
-        assert!(args.on_create.is_none());
-        assert!(args.on_modify.is_none());
-        assert!(args.on_delete.is_none());
-        assert!(args.on_change.is_none());
+        assert!(args.on_create.is_empty());
+        assert!(args.on_modify.is_empty());
+        assert!(args.on_delete.is_empty());
+        assert!(args.on_change.is_empty());
     }
 
     #[test]
@@ -352,16 +2419,17 @@ mod tests {
 
         let temp_dir = TempDir::new().unwrap();
         let args = Args {
-            directory: temp_dir.path().to_path_buf(),
+            directory: Some(temp_dir.path().to_path_buf()),
             exclude: vec![],
             include: vec![],
             verbose: false,
             quiet: false,
             debounce: 0,
-            on_create: None,
-            on_modify: None,
-            on_delete: None,
-            on_change: None,
+            on_create: Vec::new(),
+            on_modify: Vec::new(),
+            on_delete: Vec::new(),
+            on_change: Vec::new(),
+            ..Args::parse_from(["vibewatch", "."])
         };
 
         let result = create_watcher_from_args(args);
@@ -374,16 +2442,17 @@ mod tests {
 
         let temp_dir = TempDir::new().unwrap();
         let args = Args {
-            directory: temp_dir.path().to_path_buf(),
+            directory: Some(temp_dir.path().to_path_buf()),
             exclude: vec!["*.tmp".to_string()],
             include: vec!["*.rs".to_string()],
             verbose: true,
             quiet: false,
             debounce: 100,
-            on_create: Some("echo created".to_string()),
-            on_modify: Some("echo modified".to_string()),
-            on_delete: Some("echo deleted".to_string()),
-            on_change: Some("echo changed".to_string()),
+            on_create: vec!["echo created".to_string()],
+            on_modify: vec!["echo modified".to_string()],
+            on_delete: vec!["echo deleted".to_string()],
+            on_change: vec!["echo changed".to_string()],
+            ..Args::parse_from(["vibewatch", "."])
         };
 
         let result = create_watcher_from_args(args);
@@ -393,16 +2462,17 @@ mod tests {
     #[test]
     fn test_create_watcher_from_args_invalid_directory() {
         let args = Args {
-            directory: PathBuf::from("/nonexistent/path/that/does/not/exist"),
+            directory: Some(PathBuf::from("/nonexistent/path/that/does/not/exist")),
             exclude: vec![],
             include: vec![],
             verbose: false,
             quiet: false,
             debounce: 0,
-            on_create: None,
-            on_modify: None,
-            on_delete: None,
-            on_change: None,
+            on_create: Vec::new(),
+            on_modify: Vec::new(),
+            on_delete: Vec::new(),
+            on_change: Vec::new(),
+            ..Args::parse_from(["vibewatch", "."])
         };
 
         let result = create_watcher_from_args(args);
@@ -415,22 +2485,394 @@ mod tests {
 
         let temp_dir = TempDir::new().unwrap();
         let args = Args {
-            directory: temp_dir.path().to_path_buf(),
+            directory: Some(temp_dir.path().to_path_buf()),
             exclude: vec![],
             include: vec!["[invalid".to_string()],
             verbose: false,
             quiet: false,
             debounce: 0,
-            on_create: None,
-            on_modify: None,
-            on_delete: None,
-            on_change: None,
+            on_create: Vec::new(),
+            on_modify: Vec::new(),
+            on_delete: Vec::new(),
+            on_change: Vec::new(),
+            ..Args::parse_from(["vibewatch", "."])
         };
 
         let result = create_watcher_from_args(args);
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_watch_rules_appends_patterns_from_files() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let include_file = temp_dir.path().join("include.txt");
+        let exclude_file = temp_dir.path().join("exclude.txt");
+        std::fs::write(&include_file, "# shared\n*.ts\n\n*.tsx\n").unwrap();
+        std::fs::write(&exclude_file, "node_modules/**\n").unwrap();
+
+        let args = Args::parse_from([
+            "vibewatch",
+            ".",
+            "--include",
+            "*.rs",
+            "--include-from",
+            include_file.to_str().unwrap(),
+            "--exclude-from",
+            exclude_file.to_str().unwrap(),
+            "--exclude",
+            "target/**",
+        ]);
+
+        let rules = watch_rules(&args).unwrap();
+        assert_eq!(rules.include, ["*.rs", "*.ts", "*.tsx"]);
+        assert_eq!(rules.exclude, ["target/**", "node_modules/**"]);
+    }
+
+    #[test]
+    fn test_create_watcher_from_args_invalid_pattern_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let pattern_file = temp_dir.path().join("exclude.txt");
+        std::fs::write(&pattern_file, "*.tmp\n[invalid\n").unwrap();
+
+        let args = Args::parse_from([
+            "vibewatch",
+            temp_dir.path().to_str().unwrap(),
+            "--exclude-from",
+            pattern_file.to_str().unwrap(),
+        ]);
+
+        let err = create_watcher_from_args(args).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains(&format!("{}:2", pattern_file.display())),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_create_watcher_from_args_with_ignore_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let ignore_file = temp_dir.path().join(".ignore");
+        std::fs::write(&ignore_file, "*.log\n!keep.log\n").unwrap();
+
+        let args = Args::parse_from([
+            "vibewatch",
+            temp_dir.path().to_str().unwrap(),
+            "--ignore-file",
+            ignore_file.to_str().unwrap(),
+        ]);
+
+        let watcher = create_watcher_from_args(args).unwrap();
+        assert!(
+            watcher
+                .explain(Path::new("debug.log"))
+                .contains("ignored (matches rule '*.log'")
+        );
+        assert_eq!(
+            watcher.explain(Path::new("keep.log")),
+            "keep.log: watched (no include patterns and no exclude pattern matches)"
+        );
+    }
+
+    fn resolve_from(argv: &[&str]) -> anyhow::Result<Vec<Args>> {
+        let matches = Args::command().try_get_matches_from(argv).unwrap();
+        let args = Args::from_arg_matches(&matches).unwrap();
+        resolve_config(args, &matches)
+    }
+
+    #[test]
+    fn test_args_directory_required_without_config() {
+        assert!(Args::try_parse_from(["vibewatch"]).is_err());
+
+        let args = Args::parse_from(["vibewatch", "--config", "vibewatch.toml"]);
+        assert!(args.directory.is_none());
+        assert_eq!(args.config, Some(PathBuf::from("vibewatch.toml")));
+    }
+
+    #[test]
+    fn test_resolve_config_without_config_flag() {
+        let resolved = resolve_from(&["vibewatch", ".", "--on-change", "echo hi"]).unwrap();
+
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved[0].directory, Some(PathBuf::from(".")));
+        assert_eq!(resolved[0].on_change, ["echo hi"]);
+    }
+
+    #[test]
+    fn test_resolve_config_populates_args_from_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("vibewatch.toml");
+        std::fs::write(
+            &config_path,
+            "directory = \"src\"\ninclude = [\"*.rs\"]\nexclude = [\"target/**\"]\non_create = \"echo created\"\non_modify = \"cargo check\"\non_delete = \"echo deleted\"\non_change = \"echo changed\"\ndebounce = 250\n",
+        )
+        .unwrap();
+
+        let resolved =
+            resolve_from(&["vibewatch", "--config", config_path.to_str().unwrap()]).unwrap();
+
+        assert_eq!(resolved.len(), 1);
+        let args = &resolved[0];
+        assert_eq!(args.directory, Some(temp_dir.path().join("src")));
+        assert_eq!(args.include, vec!["*.rs"]);
+        assert_eq!(args.exclude, vec!["target/**"]);
+        assert_eq!(args.on_create, ["echo created"]);
+        assert_eq!(args.on_modify, ["cargo check"]);
+        assert_eq!(args.on_delete, ["echo deleted"]);
+        assert_eq!(args.on_change, ["echo changed"]);
+        assert_eq!(args.debounce, 250);
+    }
+
+    #[test]
+    fn test_resolve_config_command_arrays() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("vibewatch.toml");
+        std::fs::write(
+            &config_path,
+            "directory = \".\"\non_modify = [\"cargo fmt\", \"cargo check\"]\n",
+        )
+        .unwrap();
+
+        let resolved =
+            resolve_from(&["vibewatch", "--config", config_path.to_str().unwrap()]).unwrap();
+
+        assert_eq!(resolved[0].on_modify, ["cargo fmt", "cargo check"]);
+    }
+
+    #[test]
+    fn test_resolve_config_cli_flags_override_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("vibewatch.toml");
+        std::fs::write(
+            &config_path,
+            "directory = \"src\"\nexclude = [\"target/**\"]\non_modify = \"cargo check\"\ndebounce = 250\n",
+        )
+        .unwrap();
+
+        let resolved = resolve_from(&[
+            "vibewatch",
+            "other",
+            "--config",
+            config_path.to_str().unwrap(),
+            "--on-modify",
+            "cargo test",
+            "--debounce",
+            "100",
+        ])
+        .unwrap();
+
+        let args = &resolved[0];
+        assert_eq!(args.directory, Some(PathBuf::from("other")));
+        assert_eq!(args.on_modify, ["cargo test"]);
+        // Explicit flag wins even when it matches the default value
+        assert_eq!(args.debounce, 100);
+        // Values not given on the command line still come from the file
+        assert_eq!(args.exclude, vec!["target/**"]);
+    }
+
+    #[test]
+    fn test_print_config_merges_file_and_cli() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir(temp_dir.path().join("src")).unwrap();
+        let config_path = temp_dir.path().join("vibewatch.toml");
+        std::fs::write(
+            &config_path,
+            "directory = \"src\"\ninclude = [\"*.rs\"]\non_change = \"cargo build\"\ndebounce = 250\n",
+        )
+        .unwrap();
+
+        let resolved = resolve_from(&[
+            "vibewatch",
+            "--config",
+            config_path.to_str().unwrap(),
+            "--debounce",
+            "900",
+            "--print-config",
+        ])
+        .unwrap();
+        assert_eq!(resolved[0].print_config, Some(ConfigFormat::Toml));
+
+        let configs: Vec<EffectiveConfig> = resolved
+            .iter()
+            .map(|args| EffectiveConfig::from_args(args).unwrap())
+            .collect();
+        let printed: toml::Table = render_config(&configs, ConfigFormat::Toml)
+            .unwrap()
+            .parse()
+            .unwrap();
+
+        let directory = temp_dir.path().join("src").canonicalize().unwrap();
+        assert_eq!(printed["directory"].as_str(), directory.to_str());
+        // From the file
+        assert_eq!(printed["on_change"][0].as_str(), Some("cargo build"));
+        assert_eq!(printed["include"][0].as_str(), Some("*.rs"));
+        // Overridden on the command line
+        assert_eq!(printed["debounce"].as_integer(), Some(900));
+        assert!(!printed.contains_key("on_modify"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&render_config(&configs, ConfigFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["debounce"], 900);
+        assert_eq!(json["on_change"][0], "cargo build");
+    }
+
+    #[test]
+    fn test_print_config_lists_every_watcher() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let configs: Vec<EffectiveConfig> = ["a", "b"]
+            .into_iter()
+            .map(|name| {
+                std::fs::create_dir(temp_dir.path().join(name)).unwrap();
+                let directory = temp_dir.path().join(name);
+                let args = Args::parse_from(["vibewatch", directory.to_str().unwrap()]);
+                EffectiveConfig::from_args(&args).unwrap()
+            })
+            .collect();
+
+        let printed: toml::Table = render_config(&configs, ConfigFormat::Toml)
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(printed["watch"].as_array().map(Vec::len), Some(2));
+    }
+
+    #[test]
+    fn test_resolve_config_watch_array() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir(temp_dir.path().join("frontend")).unwrap();
+        std::fs::create_dir(temp_dir.path().join("backend")).unwrap();
+        let config_path = temp_dir.path().join("vibewatch.toml");
+        std::fs::write(
+            &config_path,
+            "debounce = 0\n\n[[watch]]\ndirectory = \"frontend\"\non_change = \"npm test\"\n\n[[watch]]\ndirectory = \"backend\"\ninclude = [\"*.rs\"]\non_modify = \"cargo check\"\n",
+        )
+        .unwrap();
+
+        let resolved =
+            resolve_from(&["vibewatch", "--config", config_path.to_str().unwrap()]).unwrap();
+
+        assert_eq!(resolved.len(), 2);
+        assert_eq!(
+            resolved[0].directory,
+            Some(temp_dir.path().join("frontend"))
+        );
+        assert_eq!(resolved[0].on_change, ["npm test"]);
+        assert_eq!(resolved[0].debounce, 0);
+        assert_eq!(resolved[1].directory, Some(temp_dir.path().join("backend")));
+        assert_eq!(resolved[1].include, vec!["*.rs"]);
+        assert!(resolved[1].on_change.is_empty());
+
+        for args in resolved {
+            assert!(create_watcher_from_args(args).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_resolve_config_watch_array_rejects_cli_directory() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("vibewatch.toml");
+        std::fs::write(
+            &config_path,
+            "[[watch]]\ndirectory = \"frontend\"\n\n[[watch]]\ndirectory = \"backend\"\n",
+        )
+        .unwrap();
+
+        let err = resolve_from(&[
+            "vibewatch",
+            "other",
+            "--config",
+            config_path.to_str().unwrap(),
+        ])
+        .unwrap_err();
+        assert!(
+            err.to_string().contains(
+                "DIRECTORY can't be given with a config file that has 2 [[watch]] entries"
+            ),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_reload_rules_rereads_config_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("vibewatch.toml");
+        std::fs::write(&config_path, "directory = \".\"\ninclude = [\"*.rs\"]\n").unwrap();
+
+        let argv = [
+            "vibewatch",
+            "--config",
+            config_path.to_str().unwrap(),
+            "--on-change",
+            "make",
+        ];
+        let matches = Args::command().try_get_matches_from(argv).unwrap();
+        let args = Args::from_arg_matches(&matches).unwrap();
+
+        std::fs::write(
+            &config_path,
+            "directory = \".\"\ninclude = [\"*.md\"]\non_change = \"ignored\"\n",
+        )
+        .unwrap();
+        let rules = reload_rules(&args, &matches, 0).unwrap();
+        assert_eq!(rules.include, ["*.md"]);
+        // Command-line flags still override the file
+        assert_eq!(rules.commands.on_change, ["make"]);
+
+        let err = reload_rules(&args, &matches, 1).unwrap_err();
+        assert!(err.to_string().contains("defines 1 watcher(s)"), "{}", err);
+    }
+
+    #[test]
+    fn test_resolve_config_unknown_key_is_error() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config_path = temp_dir.path().join("vibewatch.toml");
+        std::fs::write(
+            &config_path,
+            "directory = \".\"\non_modfy = \"cargo check\"\n",
+        )
+        .unwrap();
+
+        let err =
+            resolve_from(&["vibewatch", "--config", config_path.to_str().unwrap()]).unwrap_err();
+
+        let message = format!("{:#}", err);
+        assert!(message.contains("Invalid config file"), "{}", message);
+        assert!(message.contains("line 2"), "{}", message);
+    }
+
+    #[test]
+    fn test_create_watcher_from_args_requires_directory() {
+        let args = Args {
+            directory: None,
+            ..Args::parse_from(["vibewatch", "."])
+        };
+
+        let err = create_watcher_from_args(args).err().unwrap();
+        assert!(err.to_string().contains("No directory to watch"));
+    }
+
+    #[test]
+    fn test_create_watcher_from_args_run_on_start_requires_on_change() {
+        let args = Args::parse_from(["vibewatch", ".", "--run-on-start"]);
+        let err = create_watcher_from_args(args).err().unwrap();
+        assert!(err.to_string().contains("--run-on-start requires"));
+
+        let args = Args::parse_from(["vibewatch", ".", "--run-on-start", "--on-change", "make"]);
+        assert!(create_watcher_from_args(args).is_ok());
+    }
+
+    #[rstest]
+    #[case::no_debounce(&["vibewatch", ".", "--batch", "--debounce", "0"])]
+    #[case::leading(&["vibewatch", ".", "--batch", "--debounce-mode", "leading"])]
+    fn test_create_watcher_from_args_batch_requires_trailing_debounce(#[case] argv: &[&str]) {
+        let err = create_watcher_from_args(Args::parse_from(argv))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("--batch requires --debounce"));
+    }
+
     #[test]
     fn test_logger_initialization_verbose() {
         // Test that logger initialization doesn't panic with verbose mode
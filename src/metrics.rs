@@ -0,0 +1,348 @@
+use anyhow::{Context, Result};
+use notify::EventKind;
+use notify::event::{ModifyKind, RenameMode};
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Upper bounds (in seconds) of the command duration histogram buckets
+const DURATION_BUCKETS: [f64; 10] = [0.005, 0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Event type labels, in the order of `Metrics::events`
+const EVENT_TYPES: [&str; 5] = ["create", "modify", "delete", "move", "other"];
+
+/// Largest request head we are willing to buffer before answering
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+/// How long a client has to send its request before the connection is dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Counters and gauges shared between the watcher and the metrics endpoint
+///
+/// Everything is stored in atomics so the watcher can record from any task
+/// without locking, and the endpoint can render a snapshot at any time.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Processed events, indexed like `EVENT_TYPES`
+    events: [AtomicU64; EVENT_TYPES.len()],
+    commands: AtomicU64,
+    command_failures: AtomicU64,
+    /// Non-cumulative bucket counts, indexed like `DURATION_BUCKETS` (+Inf last)
+    duration_buckets: [AtomicU64; DURATION_BUCKETS.len() + 1],
+    duration_sum_micros: AtomicU64,
+    pending_events: AtomicU64,
+}
+
+impl Metrics {
+    /// Record a processed file event
+    pub fn record_event(&self, event_kind: &EventKind) {
+        let index = match event_kind {
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => 3,
+            EventKind::Create(_) => 0,
+            EventKind::Modify(_) => 1,
+            EventKind::Remove(_) => 2,
+            _ => 4,
+        };
+        self.events[index].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a finished command run and how long it took
+    pub fn record_command(&self, duration: Duration, success: bool) {
+        self.commands.fetch_add(1, Ordering::Relaxed);
+        if !success {
+            self.command_failures.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let seconds = duration.as_secs_f64();
+        let bucket = DURATION_BUCKETS
+            .iter()
+            .position(|&bound| seconds <= bound)
+            .unwrap_or(DURATION_BUCKETS.len());
+        self.duration_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.duration_sum_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Set the number of events currently waiting for their debounce period
+    pub fn set_pending_events(&self, pending: usize) {
+        self.pending_events.store(pending as u64, Ordering::Relaxed);
+    }
+
+    /// Render all metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP vibewatch_events_total File events processed, by event type\n");
+        out.push_str("# TYPE vibewatch_events_total counter\n");
+        for (event_type, count) in EVENT_TYPES.iter().zip(&self.events) {
+            let _ = writeln!(
+                out,
+                "vibewatch_events_total{{type=\"{}\"}} {}",
+                event_type,
+                count.load(Ordering::Relaxed)
+            );
+        }
+
+        out.push_str("# HELP vibewatch_commands_total Commands executed\n");
+        out.push_str("# TYPE vibewatch_commands_total counter\n");
+        let _ = writeln!(
+            out,
+            "vibewatch_commands_total {}",
+            self.commands.load(Ordering::Relaxed)
+        );
+
+        out.push_str(
+            "# HELP vibewatch_command_failures_total Commands that failed to run or exited non-zero\n",
+        );
+        out.push_str("# TYPE vibewatch_command_failures_total counter\n");
+        let _ = writeln!(
+            out,
+            "vibewatch_command_failures_total {}",
+            self.command_failures.load(Ordering::Relaxed)
+        );
+
+        out.push_str("# HELP vibewatch_command_duration_seconds Command execution time\n");
+        out.push_str("# TYPE vibewatch_command_duration_seconds histogram\n");
+        let mut cumulative = 0;
+        for (index, count) in self.duration_buckets.iter().enumerate() {
+            cumulative += count.load(Ordering::Relaxed);
+            let bound = DURATION_BUCKETS
+                .get(index)
+                .map_or_else(|| "+Inf".to_string(), |bound| bound.to_string());
+            let _ = writeln!(
+                out,
+                "vibewatch_command_duration_seconds_bucket{{le=\"{}\"}} {}",
+                bound, cumulative
+            );
+        }
+        let sum = self.duration_sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        let _ = writeln!(out, "vibewatch_command_duration_seconds_sum {}", sum);
+        let _ = writeln!(
+            out,
+            "vibewatch_command_duration_seconds_count {}",
+            cumulative
+        );
+
+        out.push_str("# HELP vibewatch_pending_events Events waiting for their debounce period\n");
+        out.push_str("# TYPE vibewatch_pending_events gauge\n");
+        let _ = writeln!(
+            out,
+            "vibewatch_pending_events {}",
+            self.pending_events.load(Ordering::Relaxed)
+        );
+
+        out
+    }
+}
+
+/// Bind the metrics endpoint
+///
+/// Done before the watcher starts so a taken or invalid address is a startup error.
+pub async fn bind(addr: SocketAddr) -> Result<TcpListener> {
+    TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to bind metrics endpoint on {}", addr))
+}
+
+/// Serve `GET /metrics` on the listener until the process exits
+pub async fn serve(listener: TcpListener, metrics: Arc<Metrics>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                let metrics = Arc::clone(&metrics);
+                tokio::spawn(async move {
+                    if let Err(e) = handle_connection(stream, &metrics).await {
+                        log::debug!("Metrics request from {} failed: {}", peer, e);
+                    }
+                });
+            }
+            Err(e) => log::warn!("Failed to accept metrics connection: {}", e),
+        }
+    }
+}
+
+/// Answer a single HTTP/1.x request and close the connection
+///
+/// A client that doesn't send its request within `REQUEST_TIMEOUT` is
+/// dropped, so idle connections don't pile up.
+async fn handle_connection(mut stream: TcpStream, metrics: &Metrics) -> Result<()> {
+    let request = tokio::time::timeout(REQUEST_TIMEOUT, read_request_head(&mut stream))
+        .await
+        .context("Timed out waiting for the request")??;
+
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .split_whitespace();
+    let method = request_line.next().unwrap_or_default();
+    let path = request_line.next().unwrap_or_default();
+
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/metrics") => (
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            metrics.render(),
+        ),
+        ("GET", _) => ("404 Not Found", "text/plain", "Not Found\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "Method Not Allowed\n".to_string(),
+        ),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+
+    Ok(())
+}
+
+/// Read until the end of the request headers, or the size limit
+async fn read_request_head(stream: &mut TcpStream) -> Result<Vec<u8>> {
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 1024];
+
+    // Only the request line matters; read until the end of the headers
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 || request.len() > MAX_REQUEST_SIZE {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+    Ok(request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, DataChange, RemoveKind};
+
+    async fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[test]
+    fn test_render_counts_events_and_commands() {
+        let metrics = Metrics::default();
+        metrics.record_event(&EventKind::Create(CreateKind::File));
+        metrics.record_event(&EventKind::Modify(ModifyKind::Data(DataChange::Any)));
+        metrics.record_event(&EventKind::Modify(ModifyKind::Data(DataChange::Any)));
+        metrics.record_event(&EventKind::Modify(ModifyKind::Name(RenameMode::Both)));
+        metrics.record_event(&EventKind::Remove(RemoveKind::File));
+        metrics.record_command(Duration::from_millis(3), true);
+        metrics.record_command(Duration::from_millis(300), false);
+        metrics.set_pending_events(4);
+
+        let rendered = metrics.render();
+
+        for line in [
+            "vibewatch_events_total{type=\"create\"} 1",
+            "vibewatch_events_total{type=\"modify\"} 2",
+            "vibewatch_events_total{type=\"delete\"} 1",
+            "vibewatch_events_total{type=\"move\"} 1",
+            "vibewatch_events_total{type=\"other\"} 0",
+            "vibewatch_commands_total 2",
+            "vibewatch_command_failures_total 1",
+            "vibewatch_command_duration_seconds_bucket{le=\"0.005\"} 1",
+            "vibewatch_command_duration_seconds_bucket{le=\"0.25\"} 1",
+            "vibewatch_command_duration_seconds_bucket{le=\"0.5\"} 2",
+            "vibewatch_command_duration_seconds_bucket{le=\"+Inf\"} 2",
+            "vibewatch_command_duration_seconds_sum 0.303",
+            "vibewatch_command_duration_seconds_count 2",
+            "vibewatch_pending_events 4",
+        ] {
+            assert!(
+                rendered.lines().any(|l| l == line),
+                "missing '{}' in:\n{}",
+                line,
+                rendered
+            );
+        }
+    }
+
+    #[test]
+    fn test_render_is_well_formed() {
+        let rendered = Metrics::default().render();
+
+        for line in rendered.lines() {
+            if line.starts_with('#') {
+                assert!(line.starts_with("# HELP ") || line.starts_with("# TYPE "));
+                continue;
+            }
+            // Every sample is "<name>[{labels}] <value>"
+            let (name, value) = line.rsplit_once(' ').unwrap();
+            assert!(name.starts_with("vibewatch_"), "bad sample: {}", line);
+            assert!(value.parse::<f64>().is_ok(), "bad value: {}", line);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_endpoint_serves_metrics() {
+        let listener = bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let metrics = Arc::new(Metrics::default());
+        tokio::spawn(serve(listener, Arc::clone(&metrics)));
+
+        metrics.record_event(&EventKind::Create(CreateKind::File));
+        metrics.record_event(&EventKind::Remove(RemoveKind::File));
+        metrics.record_command(Duration::from_millis(20), true);
+
+        let response = get(addr, "/metrics").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Type: text/plain; version=0.0.4"));
+        assert!(response.contains("vibewatch_events_total{type=\"create\"} 1\n"));
+        assert!(response.contains("vibewatch_events_total{type=\"delete\"} 1\n"));
+        assert!(response.contains("vibewatch_commands_total 1\n"));
+
+        let response = get(addr, "/other").await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_silent_client_is_dropped() {
+        let listener = bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, Arc::new(Metrics::default())));
+
+        // Connect and send nothing; the server closes the connection
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let mut response = Vec::new();
+        let read = tokio::time::timeout(REQUEST_TIMEOUT * 2, stream.read_to_end(&mut response))
+            .await
+            .expect("the connection should be closed after the request timeout");
+        assert!(read.is_ok());
+        assert!(response.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_bind_failure_is_clear_error() {
+        let taken = bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let addr = taken.local_addr().unwrap();
+
+        let err = bind(addr).await.unwrap_err();
+        assert!(
+            err.to_string()
+                .contains(&format!("Failed to bind metrics endpoint on {}", addr))
+        );
+    }
+}
@@ -0,0 +1,210 @@
+use notify::event::{CreateKind, ModifyKind, RemoveKind, RenameMode};
+use notify::{Event, EventKind};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// How long to wait for the second half of a rename before giving up on it
+pub const MOVE_CORRELATION_WINDOW: Duration = Duration::from_millis(500);
+
+/// Correlates the two halves of a rename into a single move event
+///
+/// On Linux, inotify reports a move as `Name(From)` on the source followed by
+/// `Name(To)` on the destination, both sharing a tracker (the inotify cookie),
+/// and then a `Name(Both)` event carrying both paths. This tracker folds that
+/// sequence into exactly one `Name(Both)` event with `[old_path, new_path]`.
+///
+/// Halves that never find a partner are downgraded: an unmatched `From` becomes
+/// a delete once the correlation window expires (the file left the tree), and an
+/// unmatched `To` becomes a create (the file entered the tree).
+#[derive(Debug)]
+pub struct MoveTracker {
+    window: Duration,
+    /// Source halves waiting for their destination, keyed by tracker
+    pending_from: HashMap<usize, (PathBuf, Instant)>,
+    /// Trackers already emitted as a move, so the trailing `Both` can be dropped
+    completed: HashMap<usize, Instant>,
+}
+
+impl MoveTracker {
+    /// Create a tracker with the given correlation window
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            pending_from: HashMap::new(),
+            completed: HashMap::new(),
+        }
+    }
+
+    /// Feed a raw event, returning the events that are ready for processing
+    pub fn process(&mut self, event: Event, now: Instant) -> Vec<Event> {
+        let tracker = match (&event.kind, event.tracker()) {
+            (EventKind::Modify(ModifyKind::Name(_)), Some(tracker)) => tracker,
+            // Renames without a tracker use the regular single-path heuristic
+            _ => return vec![event],
+        };
+
+        match event.kind {
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) if event.paths.len() == 1 => {
+                log::debug!(
+                    "Holding rename source {} (tracker {})",
+                    event.paths[0].display(),
+                    tracker
+                );
+                self.pending_from
+                    .insert(tracker, (event.paths[0].clone(), now));
+                Vec::new()
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) if event.paths.len() == 1 => {
+                let destination = event.paths[0].clone();
+                match self.pending_from.remove(&tracker) {
+                    Some((source, _)) => {
+                        self.completed.insert(tracker, now);
+                        vec![Self::move_event(source, destination, tracker)]
+                    }
+                    None => {
+                        log::debug!(
+                            "Rename destination {} has no source, treating as create",
+                            destination.display()
+                        );
+                        vec![Event::new(EventKind::Create(CreateKind::Any)).add_path(destination)]
+                    }
+                }
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                if self.completed.remove(&tracker).is_some() {
+                    // Already emitted when the destination half arrived
+                    return Vec::new();
+                }
+                self.pending_from.remove(&tracker);
+                vec![event]
+            }
+            _ => vec![event],
+        }
+    }
+
+    /// Expire halves older than the correlation window
+    ///
+    /// Unmatched sources are returned as delete events.
+    pub fn expire(&mut self, now: Instant) -> Vec<Event> {
+        let window = self.window;
+        self.completed
+            .retain(|_, seen| now.duration_since(*seen) < window);
+
+        let expired: Vec<usize> = self
+            .pending_from
+            .iter()
+            .filter(|(_, (_, seen))| now.duration_since(*seen) >= window)
+            .map(|(tracker, _)| *tracker)
+            .collect();
+
+        expired
+            .into_iter()
+            .filter_map(|tracker| self.pending_from.remove(&tracker))
+            .map(|(source, _)| {
+                log::debug!(
+                    "Rename source {} was not matched, treating as delete",
+                    source.display()
+                );
+                Event::new(EventKind::Remove(RemoveKind::Any)).add_path(source)
+            })
+            .collect()
+    }
+
+    fn move_event(source: PathBuf, destination: PathBuf, tracker: usize) -> Event {
+        Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+            .add_path(source)
+            .add_path(destination)
+            .set_tracker(tracker)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rename_event(mode: RenameMode, paths: &[&str], tracker: usize) -> Event {
+        let mut event = Event::new(EventKind::Modify(ModifyKind::Name(mode))).set_tracker(tracker);
+        for path in paths {
+            event = event.add_path(PathBuf::from(path));
+        }
+        event
+    }
+
+    #[test]
+    fn test_intra_tree_move_produces_single_move_event() {
+        let mut tracker = MoveTracker::new(MOVE_CORRELATION_WINDOW);
+        let now = Instant::now();
+
+        let mut emitted = Vec::new();
+        emitted.extend(tracker.process(rename_event(RenameMode::From, &["/w/a.txt"], 7), now));
+        emitted.extend(tracker.process(rename_event(RenameMode::To, &["/w/b.txt"], 7), now));
+        emitted.extend(tracker.process(
+            rename_event(RenameMode::Both, &["/w/a.txt", "/w/b.txt"], 7),
+            now,
+        ));
+        emitted.extend(tracker.expire(now + MOVE_CORRELATION_WINDOW));
+
+        assert_eq!(emitted.len(), 1);
+        assert_eq!(
+            emitted[0].kind,
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both))
+        );
+        assert_eq!(
+            emitted[0].paths,
+            vec![PathBuf::from("/w/a.txt"), PathBuf::from("/w/b.txt")]
+        );
+    }
+
+    #[test]
+    fn test_both_event_alone_is_passed_through() {
+        let mut tracker = MoveTracker::new(MOVE_CORRELATION_WINDOW);
+        let event = rename_event(RenameMode::Both, &["/w/a.txt", "/w/b.txt"], 3);
+
+        let emitted = tracker.process(event.clone(), Instant::now());
+        assert_eq!(emitted, vec![event]);
+    }
+
+    #[test]
+    fn test_unmatched_source_becomes_delete_after_window() {
+        let mut tracker = MoveTracker::new(MOVE_CORRELATION_WINDOW);
+        let now = Instant::now();
+
+        assert!(
+            tracker
+                .process(rename_event(RenameMode::From, &["/w/gone.txt"], 1), now)
+                .is_empty()
+        );
+
+        // Still inside the window: nothing is emitted yet
+        assert!(tracker.expire(now + Duration::from_millis(100)).is_empty());
+
+        let emitted = tracker.expire(now + MOVE_CORRELATION_WINDOW);
+        assert_eq!(emitted.len(), 1);
+        assert_eq!(emitted[0].kind, EventKind::Remove(RemoveKind::Any));
+        assert_eq!(emitted[0].paths, vec![PathBuf::from("/w/gone.txt")]);
+    }
+
+    #[test]
+    fn test_unmatched_destination_becomes_create() {
+        let mut tracker = MoveTracker::new(MOVE_CORRELATION_WINDOW);
+
+        let emitted = tracker.process(
+            rename_event(RenameMode::To, &["/w/arrived.txt"], 9),
+            Instant::now(),
+        );
+
+        assert_eq!(emitted.len(), 1);
+        assert_eq!(emitted[0].kind, EventKind::Create(CreateKind::Any));
+        assert_eq!(emitted[0].paths, vec![PathBuf::from("/w/arrived.txt")]);
+    }
+
+    #[test]
+    fn test_events_without_tracker_pass_through() {
+        let mut tracker = MoveTracker::new(MOVE_CORRELATION_WINDOW);
+        let event = Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Any)))
+            .add_path(PathBuf::from("/w/a.txt"));
+
+        assert_eq!(tracker.process(event.clone(), Instant::now()), vec![event]);
+    }
+}
@@ -0,0 +1,528 @@
+use crate::watcher::TemplateContext;
+use notify::EventKind;
+use notify::event::{ModifyKind, RenameMode};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::io::IsTerminal;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Log target of the records only `--log-file` keeps
+///
+/// File events and command output already reach the terminal through
+/// `Output`, so the terminal logger drops this target.
+pub const RECORD_TARGET: &str = "vibewatch::record";
+
+/// How file events and command results are reported
+///
+/// In JSON mode stdout carries nothing but JSON lines, so status messages and
+/// command output are written to stderr instead.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Output {
+    /// Human-readable lines with timestamps
+    #[default]
+    Text,
+    /// One JSON object per line on stdout
+    Json,
+}
+
+/// When human-readable output is colored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorChoice {
+    /// Color when stdout is a terminal and NO_COLOR is not set
+    #[default]
+    Auto,
+    /// Always color, even when redirected
+    Always,
+    /// Never color
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether output written to stdout should carry ANSI colors
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// A single JSON line, tagged with its `type`
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Record<'a> {
+    Event {
+        timestamp: String,
+        event_type: &'static str,
+        relative_path: String,
+        absolute_path: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        old_path: Option<String>,
+    },
+    Command {
+        timestamp: String,
+        command: &'a str,
+        /// None when the command was killed by a signal or failed to start
+        exit_code: Option<i32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+impl Output {
+    /// Report a change to a watched file
+    ///
+    /// `color` only applies to text output, where the event label is colored.
+    pub fn file_event(
+        self,
+        relative_path: &Path,
+        absolute_path: &Path,
+        old_path: Option<&Path>,
+        event_kind: &EventKind,
+        color: bool,
+        name: Option<&str>,
+    ) {
+        match self {
+            Output::Text => println!(
+                "{}",
+                labeled(name, &event_line(relative_path, event_kind, color))
+            ),
+            Output::Json => println!(
+                "{}",
+                event_json(relative_path, absolute_path, old_path, event_kind)
+            ),
+        }
+    }
+
+    /// Report a change to a watched file as a line already built from `--format`
+    pub fn formatted_event(self, line: &str, name: Option<&str>) {
+        println!("{}", labeled(name, line));
+    }
+
+    /// Report an event on the watched directory itself
+    pub fn root_change(self, absolute_path: &Path, event_kind: &EventKind, name: Option<&str>) {
+        let relative_path = Path::new(".");
+        match self {
+            Output::Text => println!(
+                "{}",
+                labeled(
+                    name,
+                    &format!(
+                        "[{}] [ROOT CHANGED] {}",
+                        text_timestamp(),
+                        relative_path.display()
+                    )
+                )
+            ),
+            Output::Json => println!(
+                "{}",
+                event_json(relative_path, absolute_path, None, event_kind)
+            ),
+        }
+    }
+
+    /// Report that a command is about to run (text mode only)
+    pub fn command_started(self, command: &str, name: Option<&str>) {
+        if self == Output::Text {
+            println!(
+                "{}",
+                labeled(
+                    name,
+                    &format!("[{}] Executing command: {}", text_timestamp(), command)
+                )
+            );
+        }
+    }
+
+    /// Report how a command ended
+    pub fn command_finished(
+        self,
+        command: &str,
+        result: &anyhow::Result<std::process::Output>,
+        name: Option<&str>,
+    ) {
+        match self {
+            Output::Text => {
+                let timestamp = text_timestamp();
+                let line = match result {
+                    Ok(output) => match output.status.code() {
+                        Some(code) if output.status.success() => {
+                            format!("[{}] Command succeeded (exit code: {})", timestamp, code)
+                        }
+                        Some(code) => {
+                            format!("[{}] Command failed (exit code: {})", timestamp, code)
+                        }
+                        None => format!("[{}] Command terminated by signal", timestamp),
+                    },
+                    Err(e) => format!("[{}] Command failed to execute: {}", timestamp, e),
+                };
+                println!("{}", labeled(name, &line));
+            }
+            Output::Json => println!("{}", command_json(command, result)),
+        }
+    }
+
+    /// Print a status message such as the startup banner
+    pub fn status(self, message: &str, name: Option<&str>) {
+        let message = labeled(name, message);
+        match self {
+            Output::Text => println!("{}", message),
+            Output::Json => eprintln!("{}", message),
+        }
+    }
+}
+
+/// Forwards a command's output line by line as it arrives, up to a line limit
+///
+/// Stdout and stderr of one command share the limit (`--max-output-lines`).
+/// Lines past it are counted instead of printed, and `finish` reports how
+/// many were left out.
+#[derive(Debug)]
+pub(crate) struct LineSink {
+    output: Output,
+    name: Option<String>,
+    max_lines: Option<usize>,
+    /// Lines printed and lines left out so far
+    counts: Mutex<(usize, usize)>,
+}
+
+impl LineSink {
+    pub(crate) fn new(output: Output, name: Option<&str>, max_lines: Option<usize>) -> Self {
+        Self {
+            output,
+            name: name.map(str::to_string),
+            max_lines,
+            counts: Mutex::new((0, 0)),
+        }
+    }
+
+    /// Print one line of output (with or without its line ending)
+    pub(crate) fn line(&self, line: &[u8], stderr: bool) {
+        // Held while printing, so lines from stdout and stderr don't interleave
+        let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        if self.max_lines.is_some_and(|max| counts.0 >= max) {
+            counts.1 += 1;
+            return;
+        }
+        counts.0 += 1;
+
+        let line = String::from_utf8_lossy(line);
+        let line = labeled(self.name.as_deref(), line.trim_end_matches(['\n', '\r']));
+        if stderr || self.output == Output::Json {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+    }
+
+    /// Print output that was captured whole, e.g. by the persistent shell
+    pub(crate) fn replay(&self, output: &std::process::Output) {
+        for line in output.stdout.split_inclusive(|&byte| byte == b'\n') {
+            self.line(line, false);
+        }
+        for line in output.stderr.split_inclusive(|&byte| byte == b'\n') {
+            self.line(line, true);
+        }
+    }
+
+    /// Report the lines left out by the limit, if any
+    pub(crate) fn finish(&self) {
+        let skipped = self.counts.lock().unwrap_or_else(|e| e.into_inner()).1;
+        if skipped > 0 {
+            self.output.status(
+                &format!("... ({} more lines)", skipped),
+                self.name.as_deref(),
+            );
+        }
+    }
+
+    /// Lines printed and lines left out so far
+    #[cfg(test)]
+    pub(crate) fn counts(&self) -> (usize, usize) {
+        *self.counts.lock().unwrap()
+    }
+}
+
+/// Prefix every non-empty line of `text` with `[name]`, if there is a name
+///
+/// JSON lines are never passed through here, so they stay valid JSON.
+fn labeled<'a>(name: Option<&str>, text: &'a str) -> Cow<'a, str> {
+    match name {
+        Some(name) if !name.is_empty() => Cow::Owned(
+            text.split_inclusive('\n')
+                .map(|line| match line {
+                    "\n" => line.to_string(),
+                    _ => format!("[{}] {}", name, line),
+                })
+                .collect(),
+        ),
+        _ => Cow::Borrowed(text),
+    }
+}
+
+/// Log a file event under `RECORD_TARGET`, e.g. `[CREATED] src/main.rs`
+pub fn record_event(relative_path: &Path, event_kind: &EventKind) {
+    log::info!(
+        target: RECORD_TARGET,
+        "[{}] {}",
+        event_label(event_kind).0,
+        relative_path.display()
+    );
+}
+
+/// Log a finished command and its output under `RECORD_TARGET`
+///
+/// Stdout lines are logged at info level and stderr lines at warn level, as is
+/// a failed command.
+pub fn record_command(command: &str, result: &anyhow::Result<std::process::Output>) {
+    let output = match result {
+        Ok(output) => output,
+        Err(e) => {
+            log::warn!(target: RECORD_TARGET, "Command failed to execute: {}: {}", command, e);
+            return;
+        }
+    };
+
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        log::info!(target: RECORD_TARGET, "stdout: {}", line);
+    }
+    for line in String::from_utf8_lossy(&output.stderr).lines() {
+        log::warn!(target: RECORD_TARGET, "stderr: {}", line);
+    }
+    match output.status.code() {
+        Some(code) if output.status.success() => {
+            log::info!(target: RECORD_TARGET, "Command succeeded (exit code: {}): {}", code, command)
+        }
+        Some(code) => {
+            log::warn!(target: RECORD_TARGET, "Command failed (exit code: {}): {}", code, command)
+        }
+        None => log::warn!(target: RECORD_TARGET, "Command terminated by signal: {}", command),
+    }
+}
+
+/// Label of an event kind and the ANSI color code of the label, if any
+fn event_label(event_kind: &EventKind) -> (&'static str, Option<&'static str>) {
+    match event_kind {
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => ("MOVED", Some("36")),
+        EventKind::Create(_) => ("CREATED", Some("32")),
+        EventKind::Modify(_) => ("MODIFIED", Some("33")),
+        EventKind::Remove(_) => ("DELETED", Some("31")),
+        _ => ("CHANGED", None),
+    }
+}
+
+/// Human-readable line for a file event, e.g. `[2024-05-17T09:30:15] [CREATED] src/main.rs`
+///
+/// With `color`, the label is green for creations, yellow for modifications,
+/// red for deletions and cyan for moves.
+fn event_line(relative_path: &Path, event_kind: &EventKind, color: bool) -> String {
+    let (event_type, ansi) = event_label(event_kind);
+
+    let label = match ansi {
+        Some(code) if color => format!("\x1b[{}m[{}]\x1b[0m", code, event_type),
+        _ => format!("[{}]", event_type),
+    };
+    format!(
+        "[{}] {} {}",
+        text_timestamp(),
+        label,
+        relative_path.display()
+    )
+}
+
+fn text_timestamp() -> String {
+    chrono::Local::now().format("%Y-%m-%dT%H:%M:%S").to_string()
+}
+
+fn json_timestamp() -> String {
+    chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, false)
+}
+
+fn event_json(
+    relative_path: &Path,
+    absolute_path: &Path,
+    old_path: Option<&Path>,
+    event_kind: &EventKind,
+) -> String {
+    to_json(&Record::Event {
+        timestamp: json_timestamp(),
+        event_type: TemplateContext::event_kind_to_str(event_kind),
+        relative_path: TemplateContext::normalize_path(relative_path),
+        absolute_path: TemplateContext::normalize_path(absolute_path),
+        old_path: old_path.map(TemplateContext::normalize_path),
+    })
+}
+
+fn command_json(command: &str, result: &anyhow::Result<std::process::Output>) -> String {
+    let (exit_code, error) = match result {
+        Ok(output) => (output.status.code(), None),
+        Err(e) => (None, Some(e.to_string())),
+    };
+
+    to_json(&Record::Command {
+        timestamp: json_timestamp(),
+        command,
+        exit_code,
+        error,
+    })
+}
+
+fn to_json(record: &Record) -> String {
+    // Records only hold strings and integers, which always serialize
+    serde_json::to_string(record).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, DataChange, RemoveKind};
+    use rstest::rstest;
+    use serde_json::Value;
+
+    #[rstest]
+    #[case(None, 5, (5, 0))]
+    #[case(Some(3), 5, (3, 2))]
+    #[case(Some(0), 2, (0, 2))]
+    fn test_line_sink_caps_lines(
+        #[case] max_lines: Option<usize>,
+        #[case] lines: usize,
+        #[case] expected: (usize, usize),
+    ) {
+        let sink = LineSink::new(Output::Text, None, max_lines);
+        for _ in 0..lines {
+            sink.line(b"line\n", false);
+        }
+        assert_eq!(sink.counts(), expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_line_sink_replay_shares_the_cap() {
+        use std::os::unix::process::ExitStatusExt;
+        let sink = LineSink::new(Output::Text, None, Some(2));
+        sink.replay(&std::process::Output {
+            status: std::process::ExitStatus::from_raw(0),
+            stdout: b"a\nb".to_vec(),
+            stderr: b"c\n".to_vec(),
+        });
+        assert_eq!(sink.counts(), (2, 1));
+    }
+
+    #[test]
+    fn test_event_json_fields() {
+        let line = event_json(
+            Path::new("src/main.rs"),
+            Path::new("/project/src/main.rs"),
+            None,
+            &EventKind::Modify(ModifyKind::Data(DataChange::Any)),
+        );
+        let value: Value = serde_json::from_str(&line).unwrap();
+
+        assert_eq!(value["type"], "event");
+        assert_eq!(value["event_type"], "modify");
+        assert_eq!(value["relative_path"], "src/main.rs");
+        assert_eq!(value["absolute_path"], "/project/src/main.rs");
+        assert!(value.get("old_path").is_none());
+        let timestamp = value["timestamp"].as_str().unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(timestamp).is_ok());
+        assert!(!line.contains('\n'));
+    }
+
+    #[test]
+    fn test_event_json_move_has_old_path() {
+        let line = event_json(
+            Path::new("new.txt"),
+            Path::new("/project/new.txt"),
+            Some(Path::new("/project/old.txt")),
+            &EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+        );
+        let value: Value = serde_json::from_str(&line).unwrap();
+
+        assert_eq!(value["event_type"], "move");
+        assert_eq!(value["old_path"], "/project/old.txt");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_json_exit_code() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let output = std::process::Output {
+            status: std::process::ExitStatus::from_raw(3 << 8),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+        };
+        let value: Value = serde_json::from_str(&command_json("make test", &Ok(output))).unwrap();
+
+        assert_eq!(value["type"], "command");
+        assert_eq!(value["command"], "make test");
+        assert_eq!(value["exit_code"], 3);
+        assert!(value.get("error").is_none());
+    }
+
+    #[test]
+    fn test_command_json_failure_to_execute() {
+        let result = Err(anyhow::anyhow!("Command timed out after 5s"));
+        let value: Value = serde_json::from_str(&command_json("sleep 10", &result)).unwrap();
+
+        assert_eq!(value["exit_code"], Value::Null);
+        assert_eq!(value["error"], "Command timed out after 5s");
+    }
+
+    #[rstest]
+    #[case(EventKind::Create(CreateKind::File), "\x1b[32m[CREATED]\x1b[0m")]
+    #[case(
+        EventKind::Modify(ModifyKind::Data(DataChange::Any)),
+        "\x1b[33m[MODIFIED]\x1b[0m"
+    )]
+    #[case(EventKind::Remove(RemoveKind::File), "\x1b[31m[DELETED]\x1b[0m")]
+    fn test_event_line_colors(#[case] event_kind: EventKind, #[case] colored_label: &str) {
+        let colored = event_line(Path::new("a.txt"), &event_kind, true);
+        assert!(colored.contains(colored_label), "{:?}", colored);
+        assert!(colored.ends_with(" a.txt"));
+
+        let plain = event_line(Path::new("a.txt"), &event_kind, false);
+        assert!(!plain.contains('\x1b'), "{:?}", plain);
+    }
+
+    #[test]
+    fn test_color_choice_never_and_always() {
+        assert!(!ColorChoice::Never.enabled());
+        assert!(ColorChoice::Always.enabled());
+    }
+
+    #[test]
+    fn test_output_modes_do_not_panic() {
+        for output in [Output::Text, Output::Json] {
+            output.file_event(
+                Path::new("test.txt"),
+                Path::new("/w/test.txt"),
+                None,
+                &EventKind::Create(CreateKind::File),
+                false,
+                Some("api"),
+            );
+            output.root_change(Path::new("/w"), &EventKind::Any, None);
+            output.command_started("echo hi", Some("api"));
+            output.status("status", None);
+        }
+    }
+
+    #[rstest]
+    #[case(None, "line\n", "line\n")]
+    #[case(Some(""), "line\n", "line\n")]
+    #[case(Some("api"), "[CREATED] a.txt", "[api] [CREATED] a.txt")]
+    #[case(Some("api"), "one\ntwo\n", "[api] one\n[api] two\n")]
+    #[case(Some("api"), "\n👋 Shutting down", "\n[api] 👋 Shutting down")]
+    fn test_labeled_prefixes_each_line(
+        #[case] name: Option<&str>,
+        #[case] text: &str,
+        #[case] expected: &str,
+    ) {
+        assert_eq!(labeled(name, text), expected);
+    }
+}
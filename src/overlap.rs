@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// What to do with a command triggered while the same command is still running
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OverlapMode {
+    /// Skip the new run
+    Drop,
+    /// Run once more after the current run finishes, keeping only the latest trigger
+    Queue,
+}
+
+/// Ensures only one instance of each command template runs at a time
+///
+/// Runs are keyed by the command template (before substitution), so two events
+/// for different files that trigger the same command do not overlap either.
+#[derive(Debug, Clone)]
+pub struct OverlapGuard<T> {
+    mode: OverlapMode,
+    /// Templates currently running, with the latest queued run (queue mode)
+    running: Arc<Mutex<HashMap<String, Option<T>>>>,
+}
+
+impl<T> OverlapGuard<T> {
+    pub fn new(mode: OverlapMode) -> Self {
+        Self {
+            mode,
+            running: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Ask to start a run for `key`
+    ///
+    /// Returns the run back if it may start now. Otherwise it is dropped or
+    /// queued depending on the mode, and `None` is returned.
+    pub fn admit(&self, key: &str, run: T) -> Option<T> {
+        let mut running = self.lock();
+
+        let Some(queued) = running.get_mut(key) else {
+            running.insert(key.to_string(), None);
+            return Some(run);
+        };
+
+        match self.mode {
+            OverlapMode::Drop => {
+                log::debug!("Skipping command, previous run still active: {}", key);
+            }
+            OverlapMode::Queue => {
+                if queued.is_some() {
+                    log::debug!("Replacing queued run with latest trigger: {}", key);
+                } else {
+                    log::debug!("Queueing command until previous run finishes: {}", key);
+                }
+                *queued = Some(run);
+            }
+        }
+        None
+    }
+
+    /// Mark the current run for `key` as finished
+    ///
+    /// Returns the queued run, if any, which the caller should start right away.
+    /// The key stays marked as running until no run is queued.
+    pub fn finish(&self, key: &str) -> Option<T> {
+        let mut running = self.lock();
+
+        let next = running.get_mut(key).and_then(Option::take);
+        if next.is_none() {
+            running.remove(key);
+        }
+        next
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Option<T>>> {
+        // A poisoned map is still consistent: every update is a single insert/remove
+        self.running
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drop_mode_skips_while_running() {
+        let guard = OverlapGuard::new(OverlapMode::Drop);
+
+        assert_eq!(guard.admit("make", 1), Some(1));
+        assert_eq!(guard.admit("make", 2), None);
+        assert_eq!(guard.admit("make", 3), None);
+
+        // Nothing was queued, so the template is free again
+        assert_eq!(guard.finish("make"), None);
+        assert_eq!(guard.admit("make", 4), Some(4));
+    }
+
+    #[test]
+    fn test_queue_mode_keeps_only_latest() {
+        let guard = OverlapGuard::new(OverlapMode::Queue);
+
+        assert_eq!(guard.admit("make", 1), Some(1));
+        assert_eq!(guard.admit("make", 2), None);
+        assert_eq!(guard.admit("make", 3), None);
+
+        // The latest trigger runs next, and the template stays busy meanwhile
+        assert_eq!(guard.finish("make"), Some(3));
+        assert_eq!(guard.admit("make", 4), None);
+        assert_eq!(guard.finish("make"), Some(4));
+        assert_eq!(guard.finish("make"), None);
+        assert_eq!(guard.admit("make", 5), Some(5));
+    }
+
+    #[test]
+    fn test_different_templates_run_independently() {
+        let guard = OverlapGuard::new(OverlapMode::Drop);
+
+        assert_eq!(guard.admit("make", 1), Some(1));
+        assert_eq!(guard.admit("npm test", 2), Some(2));
+    }
+}
@@ -0,0 +1,312 @@
+use anyhow::{Context, Result};
+use std::process::{ExitStatus, Output, Stdio};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command as TokioCommand};
+use tokio::sync::Mutex;
+
+/// Interpreter used for the long-lived worker shell
+const SHELL_PROGRAM: &str = "sh";
+
+/// Result of running a command through the persistent shell
+#[derive(Debug)]
+pub enum ShellOutcome {
+    /// The command ran to completion
+    Completed(Output),
+    /// The command exceeded its timeout and the shell was killed
+    TimedOut,
+    /// The shell could not be started or sent the command, so the command
+    /// never ran; the caller should spawn it directly
+    Unavailable(anyhow::Error),
+    /// The shell stopped after receiving the command, which may have run in
+    /// part or in full; it is restarted for the next command
+    Lost(anyhow::Error),
+}
+
+/// A long-lived `sh` process that runs commands fed through its stdin
+///
+/// Each command is wrapped in `eval` and followed by a unique marker line on
+/// stdout (carrying the exit code) and on stderr. Output is read back until
+/// both markers are seen, which tells us the command finished.
+///
+/// Caveats: the shell is shared between runs, so `cd`, exported variables and
+/// shell functions persist from one command to the next. A command that exits
+/// the shell is reported as lost, and the next command gets a fresh shell.
+#[derive(Debug)]
+struct PersistentShell {
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    stderr: BufReader<ChildStderr>,
+    marker: String,
+}
+
+impl PersistentShell {
+    /// Start a new worker shell
+    fn spawn() -> Result<Self> {
+        let mut child = TokioCommand::new(SHELL_PROGRAM)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .context("Failed to start persistent shell")?;
+
+        let stdin = child
+            .stdin
+            .take()
+            .context("Persistent shell has no stdin")?;
+        let stdout = child
+            .stdout
+            .take()
+            .context("Persistent shell has no stdout")?;
+        let stderr = child
+            .stderr
+            .take()
+            .context("Persistent shell has no stderr")?;
+
+        let nonce = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        let marker = format!("__vibewatch_done_{}_{}__", std::process::id(), nonce);
+
+        log::debug!("Started persistent shell (marker {})", marker);
+
+        Ok(Self {
+            _child: child,
+            stdin,
+            stdout: BufReader::new(stdout),
+            stderr: BufReader::new(stderr),
+            marker,
+        })
+    }
+
+    /// Send one command to the shell
+    ///
+    /// An error means the shell never got the whole command.
+    async fn send(&mut self, command: &str) -> Result<()> {
+        // eval keeps a malformed command from swallowing the marker lines, and
+        // stdin is detached so the command cannot read our protocol
+        let script = format!(
+            "eval '{command}' < /dev/null\n\
+             __vibewatch_status=$?\n\
+             printf '%s %s\\n' '{marker}' \"$__vibewatch_status\"\n\
+             printf '%s\\n' '{marker}' >&2\n",
+            command = command.replace('\'', r"'\''"),
+            marker = self.marker,
+        );
+
+        self.stdin
+            .write_all(script.as_bytes())
+            .await
+            .context("Failed to write to persistent shell")?;
+        self.stdin
+            .flush()
+            .await
+            .context("Failed to write to persistent shell")
+    }
+
+    /// Collect the output of the command sent last
+    async fn receive(&mut self) -> Result<Output> {
+        // Drain both pipes concurrently so a chatty stderr cannot block stdout
+        let ((stdout, status), (stderr, _)) = tokio::try_join!(
+            read_until_marker(&mut self.stdout, &self.marker),
+            read_until_marker(&mut self.stderr, &self.marker),
+        )?;
+
+        let code: i32 = status
+            .parse()
+            .with_context(|| format!("Invalid exit status from persistent shell: '{}'", status))?;
+
+        Ok(Output {
+            status: exit_status_from_code(code),
+            stdout,
+            stderr,
+        })
+    }
+}
+
+/// Read from a shell pipe until the marker appears
+///
+/// Returns everything before the marker and the remainder of the marker line.
+async fn read_until_marker<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    marker: &str,
+) -> Result<(Vec<u8>, String)> {
+    let mut output = Vec::new();
+
+    loop {
+        let mut line = Vec::new();
+        let read = reader
+            .read_until(b'\n', &mut line)
+            .await
+            .context("Failed to read from persistent shell")?;
+        if read == 0 {
+            anyhow::bail!("Persistent shell exited unexpectedly");
+        }
+
+        if let Some(position) = line
+            .windows(marker.len())
+            .position(|window| window == marker.as_bytes())
+        {
+            output.extend_from_slice(&line[..position]);
+            let rest = String::from_utf8_lossy(&line[position + marker.len()..])
+                .trim()
+                .to_string();
+            return Ok((output, rest));
+        }
+
+        output.extend_from_slice(&line);
+    }
+}
+
+#[cfg(unix)]
+fn exit_status_from_code(code: i32) -> ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+    ExitStatus::from_raw((code & 0xff) << 8)
+}
+
+#[cfg(windows)]
+fn exit_status_from_code(code: i32) -> ExitStatus {
+    use std::os::windows::process::ExitStatusExt;
+    ExitStatus::from_raw(code as u32)
+}
+
+/// Shared handle to a lazily started persistent shell
+///
+/// Commands are serialized: only one runs through the shell at a time. The
+/// shell is restarted on the next command after a timeout or protocol error.
+#[derive(Debug, Clone, Default)]
+pub struct SharedShell {
+    shell: Arc<Mutex<Option<PersistentShell>>>,
+}
+
+impl SharedShell {
+    /// Run a command through the shared shell
+    pub async fn execute(&self, command: &str, timeout: Option<Duration>) -> ShellOutcome {
+        let mut guard = self.shell.lock().await;
+
+        if guard.is_none() {
+            match PersistentShell::spawn() {
+                Ok(shell) => *guard = Some(shell),
+                Err(e) => return ShellOutcome::Unavailable(e),
+            }
+        }
+
+        let Some(shell) = guard.as_mut() else {
+            return ShellOutcome::Unavailable(anyhow::anyhow!("Persistent shell not running"));
+        };
+
+        let run = async {
+            if let Err(e) = shell.send(command).await {
+                return ShellOutcome::Unavailable(e);
+            }
+            match shell.receive().await {
+                Ok(output) => ShellOutcome::Completed(output),
+                Err(e) => ShellOutcome::Lost(e),
+            }
+        };
+        let outcome = match timeout {
+            Some(limit) => tokio::time::timeout(limit, run)
+                .await
+                .unwrap_or(ShellOutcome::TimedOut),
+            None => run.await,
+        };
+
+        // The shell is mid-command or gone; dropping it kills the process
+        if !matches!(outcome, ShellOutcome::Completed(_)) {
+            *guard = None;
+        }
+        outcome
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn completed(outcome: ShellOutcome) -> Output {
+        match outcome {
+            ShellOutcome::Completed(output) => output,
+            other => panic!("Expected completed command, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_persistent_shell_runs_command() {
+        let shell = SharedShell::default();
+
+        let output = completed(shell.execute("echo hello", None).await);
+
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "hello\n");
+        assert!(output.stderr.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_persistent_shell_reports_exit_code_and_stderr() {
+        let shell = SharedShell::default();
+
+        let output = completed(
+            shell
+                .execute("echo oops >&2; exit_code() { return 3; }; exit_code", None)
+                .await,
+        );
+
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "oops\n");
+    }
+
+    #[tokio::test]
+    async fn test_persistent_shell_reuses_process_between_runs() {
+        let shell = SharedShell::default();
+
+        // Shell state persists between runs (documented caveat)
+        completed(shell.execute("VIBEWATCH_SHARED=kept", None).await);
+        let output = completed(shell.execute("echo $VIBEWATCH_SHARED", None).await);
+
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "kept\n");
+    }
+
+    #[tokio::test]
+    async fn test_persistent_shell_supports_quotes_and_output_without_newline() {
+        let shell = SharedShell::default();
+
+        // No trailing newline: the marker lands on the same line as the output
+        let output = completed(shell.execute("printf '%s' \"it's\"", None).await);
+
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "it's");
+        assert!(output.status.success());
+    }
+
+    #[tokio::test]
+    async fn test_persistent_shell_exit_is_lost_and_restarts() {
+        let shell = SharedShell::default();
+
+        assert!(matches!(
+            shell.execute("exit 3", None).await,
+            ShellOutcome::Lost(_)
+        ));
+
+        // A fresh shell is started for the next command
+        let output = completed(shell.execute("echo again", None).await);
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "again\n");
+    }
+
+    #[tokio::test]
+    async fn test_persistent_shell_timeout() {
+        let shell = SharedShell::default();
+
+        assert!(matches!(
+            shell
+                .execute("sleep 10", Some(Duration::from_millis(200)))
+                .await,
+            ShellOutcome::TimedOut
+        ));
+
+        let output = completed(shell.execute("echo recovered", None).await);
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "recovered\n");
+    }
+}
@@ -0,0 +1,215 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::process::{Child, Command as TokioCommand};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+/// How long a process gets to exit after SIGTERM before it is killed
+pub const RESTART_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// A long-running process started by `Restarter`
+#[derive(Debug)]
+struct Running {
+    /// Asks the process to stop (dropping it does too)
+    stop: oneshot::Sender<()>,
+    /// Finishes once the process has stopped
+    done: JoinHandle<()>,
+}
+
+/// Keeps one long-running process per command, replacing it on every trigger
+///
+/// Used with `--restart`, e.g. for dev servers. A restart stops the previous
+/// process with SIGTERM, kills it if it is still running after the grace
+/// period, and only then starts the new one. Processes run in their own
+/// process group so whatever they spawned is stopped along with them, and
+/// their output goes straight to the terminal instead of being collected.
+#[derive(Debug)]
+pub struct Restarter {
+    grace: Duration,
+    /// Processes by command template
+    running: Mutex<HashMap<String, Running>>,
+}
+
+impl Restarter {
+    pub fn new(grace: Duration) -> Self {
+        Self {
+            grace,
+            running: Mutex::default(),
+        }
+    }
+
+    /// Stop the process running for `key`, if any, then start `process` in its place
+    ///
+    /// Returns right away; stopping and starting happen on a background task.
+    /// `command` is only used in log messages.
+    pub fn restart(&self, key: &str, command: String, mut process: TokioCommand) {
+        let (stop, mut stop_rx) = oneshot::channel();
+        let mut running = self.lock();
+        let previous = running.remove(key);
+        let grace = self.grace;
+
+        let done = tokio::spawn(async move {
+            if let Some(previous) = previous {
+                log::info!("Restarting command: {}", command);
+                let _ = previous.stop.send(());
+                let _ = previous.done.await;
+            }
+
+            // A newer trigger or shutdown may have come while the old process stopped
+            if !matches!(stop_rx.try_recv(), Err(oneshot::error::TryRecvError::Empty)) {
+                return;
+            }
+
+            let mut child = match spawn(&mut process) {
+                Ok(child) => child,
+                Err(e) => {
+                    log::error!("Failed to start command '{}': {:#}", command, e);
+                    return;
+                }
+            };
+
+            tokio::select! {
+                status = child.wait() => match status {
+                    Ok(status) if status.success() => log::info!("Command exited: {}", command),
+                    Ok(status) => log::warn!("Command exited with {}: {}", status, command),
+                    Err(e) => log::error!("Failed to wait for command '{}': {}", command, e),
+                },
+                _ = &mut stop_rx => terminate(&mut child, grace, &command).await,
+            }
+        });
+
+        running.insert(key.to_string(), Running { stop, done });
+    }
+
+    /// Stop every running process and wait until they are gone
+    pub async fn stop_all(&self) {
+        let running: Vec<Running> = self.lock().drain().map(|(_, running)| running).collect();
+        for Running { stop, done } in running {
+            let _ = stop.send(());
+            let _ = done.await;
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Running>> {
+        // A poisoned map is still consistent: every update is a single insert/remove
+        self.running.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Start a long-running process in a new process group
+fn spawn(process: &mut TokioCommand) -> Result<Child> {
+    #[cfg(unix)]
+    process.process_group(0);
+    process
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to execute command")
+}
+
+/// Stop a process with SIGTERM, killing it if it doesn't exit within `grace`
+///
+/// Signals go to the whole process group. Outside unix the process is killed
+/// right away.
+async fn terminate(child: &mut Child, grace: Duration, command: &str) {
+    #[cfg(unix)]
+    if let Some(pid) = child.id().and_then(|pid| i32::try_from(pid).ok()) {
+        // SAFETY: kill has no memory safety requirements; a negative pid
+        // addresses the process group the child leads
+        unsafe { libc::kill(-pid, libc::SIGTERM) };
+        if tokio::time::timeout(grace, child.wait()).await.is_ok() {
+            return;
+        }
+        log::warn!(
+            "Command didn't stop within {}s of SIGTERM, killing it: {}",
+            grace.as_secs(),
+            command
+        );
+        // SAFETY: as above
+        unsafe { libc::kill(-pid, libc::SIGKILL) };
+    }
+
+    if let Err(e) = child.kill().await {
+        log::debug!("Failed to kill command '{}': {}", command, e);
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::time::Instant;
+    use tempfile::TempDir;
+
+    /// A command that appends its pid to `log` and then runs until stopped
+    fn sleeper(log: &Path, trap: &str) -> TokioCommand {
+        let mut process = TokioCommand::new("sh");
+        process.arg("-c").arg(format!(
+            "{} echo $$ >> '{}'; while true; do sleep 0.05; done",
+            trap,
+            log.display()
+        ));
+        process
+    }
+
+    fn alive(pid: i32) -> bool {
+        // SAFETY: signal 0 only checks that the process exists
+        unsafe { libc::kill(pid, 0) == 0 }
+    }
+
+    async fn wait_for_pids(log: &Path, count: usize) -> Vec<i32> {
+        for _ in 0..100 {
+            let pids: Vec<i32> = std::fs::read_to_string(log)
+                .unwrap_or_default()
+                .lines()
+                .map(|line| line.parse().unwrap())
+                .collect();
+            if pids.len() >= count {
+                return pids;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("Expected {} processes to start", count);
+    }
+
+    #[tokio::test]
+    async fn test_restart_replaces_the_running_process() {
+        let temp_dir = TempDir::new().unwrap();
+        let log = temp_dir.path().join("pids.log");
+        let restarter = Restarter::new(RESTART_GRACE_PERIOD);
+
+        restarter.restart("server", "server".into(), sleeper(&log, ""));
+        let first = wait_for_pids(&log, 1).await[0];
+        assert!(alive(first));
+
+        restarter.restart("server", "server".into(), sleeper(&log, ""));
+        let pids = wait_for_pids(&log, 2).await;
+        assert!(!alive(first), "The old process should be stopped");
+        assert!(alive(pids[1]));
+
+        restarter.stop_all().await;
+        assert!(!alive(pids[1]));
+    }
+
+    #[tokio::test]
+    async fn test_process_ignoring_sigterm_is_killed_after_grace() {
+        let temp_dir = TempDir::new().unwrap();
+        let log = temp_dir.path().join("pids.log");
+        let restarter = Restarter::new(Duration::from_millis(200));
+
+        restarter.restart(
+            "stubborn",
+            "stubborn".into(),
+            sleeper(&log, "trap '' TERM;"),
+        );
+        let pid = wait_for_pids(&log, 1).await[0];
+
+        let started = Instant::now();
+        restarter.stop_all().await;
+        assert!(!alive(pid));
+        assert!(started.elapsed() >= Duration::from_millis(200));
+    }
+}
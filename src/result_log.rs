@@ -0,0 +1,182 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Appends one JSON line per finished command to a file, for `--result-log`
+///
+/// Commands finish on many tasks at once, so every line is written whole
+/// while holding the lock and lines never interleave.
+#[derive(Debug)]
+pub struct ResultLog {
+    file: Mutex<File>,
+}
+
+/// What is recorded about one command run
+#[derive(Debug, Serialize)]
+struct CommandResult<'a> {
+    timestamp: String,
+    command: &'a str,
+    /// None when the command was killed by a signal or couldn't run
+    exit_code: Option<i32>,
+    success: bool,
+    duration_ms: u64,
+    stdout_bytes: usize,
+    stderr_bytes: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl ResultLog {
+    /// Open `path` for appending, creating it if needed
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open result log: {}", path.display()))?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Append the result of a command that ran for `duration`
+    ///
+    /// Write errors are logged rather than returned, a broken log file
+    /// shouldn't stop commands from running.
+    pub fn record(&self, command: &str, result: &Result<std::process::Output>, duration: Duration) {
+        let (exit_code, success, stdout_bytes, stderr_bytes, error) = match result {
+            Ok(output) => (
+                output.status.code(),
+                output.status.success(),
+                output.stdout.len(),
+                output.stderr.len(),
+                None,
+            ),
+            Err(e) => (None, false, 0, 0, Some(e.to_string())),
+        };
+        let record = CommandResult {
+            timestamp: chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, false),
+            command,
+            exit_code,
+            success,
+            duration_ms: duration.as_millis() as u64,
+            stdout_bytes,
+            stderr_bytes,
+            error,
+        };
+
+        // Records only hold strings and integers, which always serialize
+        let mut line = serde_json::to_string(&record).unwrap_or_default();
+        line.push('\n');
+
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            log::error!("Failed to write to the result log: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    #[cfg(unix)]
+    fn output(code: i32, stdout: &str, stderr: &str) -> std::process::Output {
+        use std::os::unix::process::ExitStatusExt;
+        std::process::Output {
+            status: std::process::ExitStatus::from_raw(code << 8),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: stderr.as_bytes().to_vec(),
+        }
+    }
+
+    fn read_lines(path: &Path) -> Vec<Value> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_record_fields() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("results.jsonl");
+        let log = ResultLog::open(&path).unwrap();
+
+        log.record(
+            "make build",
+            &Ok(output(2, "hello\n", "oops")),
+            Duration::from_millis(1250),
+        );
+        log.record(
+            "missing",
+            &Err(anyhow::anyhow!("not found")),
+            Duration::ZERO,
+        );
+
+        let lines = read_lines(&path);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["command"], "make build");
+        assert_eq!(lines[0]["exit_code"], 2);
+        assert_eq!(lines[0]["success"], false);
+        assert_eq!(lines[0]["duration_ms"], 1250);
+        assert_eq!(lines[0]["stdout_bytes"], 6);
+        assert_eq!(lines[0]["stderr_bytes"], 4);
+        assert!(lines[0].get("error").is_none());
+        assert!(lines[1]["exit_code"].is_null());
+        assert_eq!(lines[1]["error"], "not found");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_concurrent_records_stay_on_separate_lines() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("results.jsonl");
+        let log = Arc::new(ResultLog::open(&path).unwrap());
+
+        let big = "x".repeat(64 * 1024);
+        let threads: Vec<_> = (0..8)
+            .map(|i| {
+                let log = Arc::clone(&log);
+                let big = big.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..25 {
+                        log.record(
+                            &format!("{}{}", i, big),
+                            &Ok(output(0, "", "")),
+                            Duration::ZERO,
+                        );
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(read_lines(&path).len(), 200);
+    }
+
+    #[test]
+    fn test_open_appends_to_existing_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("results.jsonl");
+        std::fs::write(&path, "{}\n").unwrap();
+
+        ResultLog::open(&path).unwrap().record(
+            "true",
+            &Err(anyhow::anyhow!("skipped")),
+            Duration::ZERO,
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+    }
+}
@@ -0,0 +1,296 @@
+use notify::event::ModifyKind;
+use notify::{Event, EventKind};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often a settling file's size and modification time are checked
+pub const SETTLE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Holds content changes until the file stops being written
+///
+/// Commands reading a file right after a modify event can see it half written,
+/// e.g. while a large file is copied. An event is held until two checks of its
+/// file, `interval` apart, find the same size and modification time. Creates
+/// are held too, since a create followed by writes is reported as one create.
+/// Other events pass straight through, and an event whose file disappears
+/// while settling is dropped, as its delete is reported instead.
+///
+/// All methods take the current time so the timing logic can be tested
+/// without sleeping; only the file checks touch the filesystem.
+#[derive(Debug)]
+pub struct Settler {
+    interval: Duration,
+    /// Held events in arrival order
+    pending: Vec<Settling>,
+}
+
+/// An event waiting for its file to stop changing
+#[derive(Debug)]
+struct Settling {
+    event: Event,
+    /// Size and modification time of each path at the last check
+    snapshot: Vec<Option<Snapshot>>,
+    checked: Instant,
+}
+
+/// What is compared between two checks of a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Snapshot {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Settler {
+    /// Check settling files every `interval`
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Feed an event, returning it if it doesn't need to settle
+    pub fn push(&mut self, event: Event, now: Instant) -> Option<Event> {
+        if !needs_settling(&event.kind) {
+            return Some(event);
+        }
+
+        // A file still settling keeps its first event, which may be a create
+        if self
+            .pending
+            .iter()
+            .any(|settling| settling.event.paths == event.paths)
+        {
+            log::debug!("Already waiting for {:?} to settle", event.paths);
+            return None;
+        }
+
+        log::debug!("Waiting for {:?} to settle", event.paths);
+        self.pending.push(Settling {
+            snapshot: snapshot(&event.paths),
+            event,
+            checked: now,
+        });
+        None
+    }
+
+    /// Take the events whose files stopped changing since the last check
+    pub fn ready(&mut self, now: Instant) -> Vec<Event> {
+        let mut ready = Vec::new();
+        self.pending
+            .retain_mut(|settling| match check(settling, now, self.interval) {
+                Check::Settled => {
+                    ready.push(settling.event.clone());
+                    false
+                }
+                Check::Gone => false,
+                Check::Changing => true,
+            });
+        ready
+    }
+
+    /// Take every held event once all of their files stopped changing
+    ///
+    /// Unlike `ready`, nothing is released while any file is still being
+    /// written, so a batch stays together.
+    pub fn ready_batch(&mut self, now: Instant) -> Vec<Event> {
+        let mut settled = true;
+        self.pending
+            .retain_mut(|settling| match check(settling, now, self.interval) {
+                Check::Settled => true,
+                Check::Gone => false,
+                Check::Changing => {
+                    settled = false;
+                    true
+                }
+            });
+
+        if !settled {
+            return Vec::new();
+        }
+        self.pending
+            .drain(..)
+            .map(|settling| settling.event)
+            .collect()
+    }
+
+    /// Number of events waiting for their files to settle
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+/// Outcome of checking a held event's files
+enum Check {
+    Settled,
+    Changing,
+    Gone,
+}
+
+/// Compare an event's files with the last check, if a check is due
+fn check(settling: &mut Settling, now: Instant, interval: Duration) -> Check {
+    if now.duration_since(settling.checked) < interval {
+        return Check::Changing;
+    }
+
+    let current = snapshot(&settling.event.paths);
+    if current.iter().any(Option::is_none) {
+        log::debug!(
+            "{:?} disappeared while settling, dropping the event",
+            settling.event.paths
+        );
+        return Check::Gone;
+    }
+    if current == settling.snapshot {
+        log::debug!("{:?} settled", settling.event.paths);
+        return Check::Settled;
+    }
+
+    settling.snapshot = current;
+    settling.checked = now;
+    Check::Changing
+}
+
+/// Whether an event reports content that may still be being written
+fn needs_settling(kind: &EventKind) -> bool {
+    match kind {
+        EventKind::Create(_) => true,
+        EventKind::Modify(ModifyKind::Name(_)) => false,
+        EventKind::Modify(_) => true,
+        // Close-after-write already means the writer is done
+        _ => false,
+    }
+}
+
+fn snapshot(paths: &[PathBuf]) -> Vec<Option<Snapshot>> {
+    paths.iter().map(|path| file_snapshot(path)).collect()
+}
+
+fn file_snapshot(path: &Path) -> Option<Snapshot> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some(Snapshot {
+        len: metadata.len(),
+        modified: metadata.modified().ok(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{AccessKind, AccessMode, CreateKind, DataChange, RemoveKind};
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    const INTERVAL: Duration = Duration::from_millis(100);
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn modify(path: &Path) -> Event {
+        Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Any))).add_path(path.into())
+    }
+
+    fn append(path: &Path, data: &str) {
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(data.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn test_growing_file_is_released_once_stable() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("big.bin");
+        std::fs::write(&path, "chunk").unwrap();
+
+        let mut settler = Settler::new(INTERVAL);
+        let start = Instant::now();
+        assert!(settler.push(modify(&path), start).is_none());
+
+        // Not checked again before the interval
+        assert!(settler.ready(start + ms(50)).is_empty());
+
+        // Still growing at the next two checks
+        append(&path, "chunk");
+        assert!(settler.ready(start + ms(100)).is_empty());
+        append(&path, "chunk");
+        assert!(settler.ready(start + ms(200)).is_empty());
+        assert_eq!(settler.pending_len(), 1);
+
+        // Two checks in a row see the same file
+        let ready = settler.ready(start + ms(300));
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].paths, vec![path]);
+        assert_eq!(settler.pending_len(), 0);
+    }
+
+    #[test]
+    fn test_other_events_pass_through() {
+        let mut settler = Settler::new(INTERVAL);
+        let now = Instant::now();
+
+        for kind in [
+            EventKind::Remove(RemoveKind::File),
+            EventKind::Access(AccessKind::Close(AccessMode::Write)),
+            EventKind::Modify(ModifyKind::Name(notify::event::RenameMode::Both)),
+        ] {
+            let event = Event::new(kind).add_path(PathBuf::from("/w/a"));
+            assert!(settler.push(event, now).is_some());
+        }
+        assert_eq!(settler.pending_len(), 0);
+    }
+
+    #[test]
+    fn test_settling_file_keeps_first_event() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("new.txt");
+        std::fs::write(&path, "x").unwrap();
+
+        let mut settler = Settler::new(INTERVAL);
+        let start = Instant::now();
+        let create = Event::new(EventKind::Create(CreateKind::File)).add_path(path.clone());
+        assert!(settler.push(create, start).is_none());
+        assert!(settler.push(modify(&path), start + ms(10)).is_none());
+        assert_eq!(settler.pending_len(), 1);
+
+        let ready = settler.ready(start + ms(100));
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].kind, EventKind::Create(CreateKind::File));
+    }
+
+    #[test]
+    fn test_deleted_file_is_dropped() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("tmp.txt");
+        std::fs::write(&path, "x").unwrap();
+
+        let mut settler = Settler::new(INTERVAL);
+        let start = Instant::now();
+        settler.push(modify(&path), start);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(settler.ready(start + ms(100)).is_empty());
+        assert_eq!(settler.pending_len(), 0);
+    }
+
+    #[test]
+    fn test_ready_batch_waits_for_every_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let done = temp_dir.path().join("done.txt");
+        let growing = temp_dir.path().join("growing.txt");
+        std::fs::write(&done, "x").unwrap();
+        std::fs::write(&growing, "x").unwrap();
+
+        let mut settler = Settler::new(INTERVAL);
+        let start = Instant::now();
+        settler.push(modify(&done), start);
+        settler.push(modify(&growing), start);
+
+        append(&growing, "more");
+        assert!(settler.ready_batch(start + ms(100)).is_empty());
+        assert_eq!(settler.pending_len(), 2);
+
+        assert_eq!(settler.ready_batch(start + ms(200)).len(), 2);
+        assert_eq!(settler.pending_len(), 0);
+    }
+}
@@ -0,0 +1,209 @@
+/// Quoting rules of the interpreter a command is handed to with `--shell`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellQuoting {
+    /// sh, bash, zsh and other POSIX shells, and the persistent shell
+    Posix,
+    /// Windows cmd.exe
+    Cmd,
+    /// Windows PowerShell and pwsh
+    PowerShell,
+}
+
+impl ShellQuoting {
+    /// The quoting for an interpreter such as `["sh", "-c"]`, from its program name
+    ///
+    /// Programs that aren't recognized are assumed to be POSIX shells.
+    pub fn for_interpreter(interpreter: &[String]) -> Self {
+        let program = interpreter.first().map(|program| {
+            // Split by hand, `Path` only knows `\` as a separator on Windows
+            let name = program.rsplit(['/', '\\']).next().unwrap_or_default();
+            name.to_ascii_lowercase()
+        });
+        match program.as_deref().map(|name| name.trim_end_matches(".exe")) {
+            Some("cmd") => ShellQuoting::Cmd,
+            Some("powershell" | "pwsh") => ShellQuoting::PowerShell,
+            _ => ShellQuoting::Posix,
+        }
+    }
+}
+
+/// Whether the template text so far ends outside quotes or inside a quoted string
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Quotes {
+    None,
+    Single,
+    Double,
+}
+
+/// Follows the quotes of a command template as it is substituted
+///
+/// Each value is quoted for the spot it lands in, so `{file_path}`,
+/// `'{file_path}'` and `"{file_path}"` all give the shell the path as it is.
+#[derive(Debug, Clone, Copy)]
+pub struct QuoteTracker {
+    quoting: ShellQuoting,
+    quotes: Quotes,
+    /// The last character was an escape, so the next one is taken literally
+    escaped: bool,
+}
+
+impl QuoteTracker {
+    pub fn new(quoting: ShellQuoting) -> Self {
+        Self {
+            quoting,
+            quotes: Quotes::None,
+            escaped: false,
+        }
+    }
+
+    /// Follow literal template text copied into the command
+    pub fn advance(&mut self, text: &str) {
+        use ShellQuoting::{Cmd, Posix, PowerShell};
+
+        for c in text.chars() {
+            if self.escaped {
+                self.escaped = false;
+                continue;
+            }
+            self.quotes = match (self.quoting, self.quotes, c) {
+                (Posix, Quotes::None | Quotes::Double, '\\')
+                | (PowerShell, Quotes::None | Quotes::Double, '`')
+                | (Cmd, Quotes::None, '^') => {
+                    self.escaped = true;
+                    self.quotes
+                }
+                (Posix | PowerShell, Quotes::None, '\'') => Quotes::Single,
+                (Posix | PowerShell, Quotes::Single, '\'') => Quotes::None,
+                (_, Quotes::None, '"') => Quotes::Double,
+                (_, Quotes::Double, '"') => Quotes::None,
+                (_, quotes, _) => quotes,
+            };
+        }
+    }
+
+    /// Quote `value` for where the template currently is
+    pub fn quote(&self, value: &str) -> String {
+        match (self.quoting, self.quotes) {
+            (ShellQuoting::Posix, Quotes::None) => shell_words::quote(value).into_owned(),
+            // A single-quoted string can't hold a quote, so close it around an escaped one
+            (ShellQuoting::Posix, Quotes::Single) => value.replace('\'', r"'\''"),
+            (ShellQuoting::Posix, Quotes::Double) => escape(value, '\\', &['\\', '"', '$', '`']),
+            (ShellQuoting::PowerShell, Quotes::None) => format!("'{}'", value.replace('\'', "''")),
+            (ShellQuoting::PowerShell, Quotes::Single) => value.replace('\'', "''"),
+            (ShellQuoting::PowerShell, Quotes::Double) => escape(value, '`', &['`', '"', '$']),
+            (ShellQuoting::Cmd, Quotes::None) if needs_cmd_quotes(value) => {
+                format!("\"{}\"", value)
+            }
+            // cmd.exe has no escapes within quotes, and file names can't contain `"`
+            (ShellQuoting::Cmd, _) => value.to_string(),
+        }
+    }
+
+    /// Quote a list of values, each its own word unless the template already quotes them
+    pub fn quote_all(&self, values: &[String]) -> String {
+        if self.quotes == Quotes::None {
+            values
+                .iter()
+                .map(|value| self.quote(value))
+                .collect::<Vec<_>>()
+                .join(" ")
+        } else {
+            self.quote(&values.join(" "))
+        }
+    }
+}
+
+/// Put `escape` before every character of `value` in `special`
+fn escape(value: &str, escape: char, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if special.contains(&c) {
+            escaped.push(escape);
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Whether cmd.exe would split `value` or read it as syntax without quotes
+fn needs_cmd_quotes(value: &str) -> bool {
+    value.is_empty()
+        || value
+            .chars()
+            .any(|c| c.is_whitespace() || "&|<>^(),;=".contains(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(&["sh", "-c"], ShellQuoting::Posix)]
+    #[case(&["/usr/bin/bash", "-c"], ShellQuoting::Posix)]
+    #[case(&["cmd", "/C"], ShellQuoting::Cmd)]
+    #[case(&["C:\\Windows\\System32\\CMD.EXE", "/C"], ShellQuoting::Cmd)]
+    #[case(&["powershell", "-Command"], ShellQuoting::PowerShell)]
+    #[case(&["pwsh.exe", "-c"], ShellQuoting::PowerShell)]
+    #[case(&["python3", "-c"], ShellQuoting::Posix)]
+    fn test_for_interpreter(#[case] interpreter: &[&str], #[case] expected: ShellQuoting) {
+        let interpreter: Vec<String> = interpreter.iter().map(|s| s.to_string()).collect();
+        assert_eq!(ShellQuoting::for_interpreter(&interpreter), expected);
+    }
+
+    #[rstest]
+    #[case(ShellQuoting::Posix, "cat ", "cat 'a b'\\''s.txt'")]
+    #[case(ShellQuoting::Posix, "cat '", "cat 'a b'\\''s.txt")]
+    #[case(ShellQuoting::Posix, "cat \"", "cat \"a b's.txt")]
+    #[case(
+        ShellQuoting::Posix,
+        "echo 'it''s' \\' ",
+        "echo 'it''s' \\' 'a b'\\''s.txt'"
+    )]
+    #[case(ShellQuoting::Cmd, "type ", "type \"a b's.txt\"")]
+    #[case(ShellQuoting::Cmd, "type \"", "type \"a b's.txt")]
+    #[case(ShellQuoting::PowerShell, "cat ", "cat 'a b''s.txt'")]
+    #[case(ShellQuoting::PowerShell, "cat '", "cat 'a b''s.txt")]
+    #[case(ShellQuoting::PowerShell, "cat \"", "cat \"a b's.txt")]
+    fn test_quote_follows_template_quotes(
+        #[case] quoting: ShellQuoting,
+        #[case] before: &str,
+        #[case] expected: &str,
+    ) {
+        let mut tracker = QuoteTracker::new(quoting);
+        tracker.advance(before);
+        assert_eq!(
+            format!("{}{}", before, tracker.quote("a b's.txt")),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_quote_escapes_double_quoted_specials() {
+        let mut tracker = QuoteTracker::new(ShellQuoting::Posix);
+        tracker.advance("echo \"");
+        assert_eq!(tracker.quote("$(x) `y` \"z\\"), "\\$(x) \\`y\\` \\\"z\\\\");
+
+        let mut tracker = QuoteTracker::new(ShellQuoting::PowerShell);
+        tracker.advance("echo \"");
+        assert_eq!(tracker.quote("$x `y` \"z\""), "`$x ``y`` `\"z`\"");
+    }
+
+    #[test]
+    fn test_cmd_quotes_only_when_needed() {
+        let tracker = QuoteTracker::new(ShellQuoting::Cmd);
+        assert_eq!(tracker.quote("C:/src/main.rs"), "C:/src/main.rs");
+        assert_eq!(tracker.quote("C:/a b.txt"), "\"C:/a b.txt\"");
+        assert_eq!(tracker.quote("a&b.txt"), "\"a&b.txt\"");
+        assert_eq!(tracker.quote(""), "\"\"");
+    }
+
+    #[test]
+    fn test_quote_all() {
+        let files = vec!["a b.txt".to_string(), "c.txt".to_string()];
+        let mut tracker = QuoteTracker::new(ShellQuoting::Posix);
+        assert_eq!(tracker.quote_all(&files), "'a b.txt' c.txt");
+        tracker.advance("\"");
+        assert_eq!(tracker.quote_all(&files), "a b.txt c.txt");
+    }
+}
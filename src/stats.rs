@@ -0,0 +1,212 @@
+use notify::EventKind;
+use notify::event::{ModifyKind, RenameMode};
+use std::fmt;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Event and command counters printed with `--stats` and `--summary-on-exit`
+///
+/// Shared with running command tasks, so everything is stored in atomics.
+#[derive(Debug, Default)]
+pub struct Stats {
+    received: AtomicU64,
+    filtered: AtomicU64,
+    commands: AtomicU64,
+    failed: AtomicU64,
+    /// File changes by type: created, modified, deleted and moved
+    changes: [AtomicU64; 4],
+    command_micros: AtomicU64,
+    /// When the latest file change was counted, in Unix milliseconds (0 before any)
+    last_change_millis: AtomicI64,
+}
+
+/// Counter values at one point in time
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StatsSnapshot {
+    /// Events that reached the watcher after debouncing
+    pub received: u64,
+    /// Received events that produced no file change (filtered or ignored)
+    pub filtered: u64,
+    pub commands: u64,
+    pub failed: u64,
+}
+
+impl Stats {
+    /// Count a received event, and whether it was filtered out
+    pub fn record_event(&self, filtered: bool) {
+        self.received.fetch_add(1, Ordering::Relaxed);
+        if filtered {
+            self.filtered.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Count a file change by its type
+    pub fn record_change(&self, event_kind: &EventKind) {
+        let index = match event_kind {
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => 3,
+            EventKind::Create(_) => 0,
+            EventKind::Remove(_) => 2,
+            _ => 1,
+        };
+        self.changes[index].fetch_add(1, Ordering::Relaxed);
+        self.last_change_millis
+            .store(chrono::Utc::now().timestamp_millis(), Ordering::Relaxed);
+    }
+
+    /// When the latest file change was counted, if any was
+    pub fn last_change(&self) -> Option<chrono::DateTime<chrono::Local>> {
+        match self.last_change_millis.load(Ordering::Relaxed) {
+            0 => None,
+            millis => chrono::DateTime::from_timestamp_millis(millis)
+                .map(|time| time.with_timezone(&chrono::Local)),
+        }
+    }
+
+    /// Count a finished command run and how long it took
+    pub fn record_command(&self, success: bool, duration: Duration) {
+        self.commands.fetch_add(1, Ordering::Relaxed);
+        if !success {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+        self.command_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            received: self.received.load(Ordering::Relaxed),
+            filtered: self.filtered.load(Ordering::Relaxed),
+            commands: self.commands.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Totals for the report printed on exit
+    pub fn summary(&self) -> Summary {
+        let [created, modified, deleted, moved] = self
+            .changes
+            .each_ref()
+            .map(|count| count.load(Ordering::Relaxed));
+        Summary {
+            stats: self.snapshot(),
+            created,
+            modified,
+            deleted,
+            moved,
+            command_time: Duration::from_micros(self.command_micros.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Session totals printed with `--summary-on-exit`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub stats: StatsSnapshot,
+    pub created: u64,
+    pub modified: u64,
+    pub deleted: u64,
+    pub moved: u64,
+    /// Time spent running commands, summed over all runs
+    pub command_time: Duration,
+}
+
+impl Summary {
+    /// File changes of every type
+    pub fn changes(&self) -> u64 {
+        self.created + self.modified + self.deleted + self.moved
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "📋 Session summary")?;
+        writeln!(
+            f,
+            "   Events:       {} ({} created, {} modified, {} deleted, {} moved)",
+            self.changes(),
+            self.created,
+            self.modified,
+            self.deleted,
+            self.moved
+        )?;
+        writeln!(
+            f,
+            "   Commands:     {} run, {} failed",
+            self.stats.commands, self.stats.failed
+        )?;
+        write!(
+            f,
+            "   Command time: {:.2}s",
+            self.command_time.as_secs_f64()
+        )
+    }
+}
+
+impl fmt::Display for StatsSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "📊 Events: {} received, {} filtered, {} acted on | Commands: {} run, {} failed",
+            self.received,
+            self.filtered,
+            self.received - self.filtered,
+            self.commands,
+            self.failed
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats_counts_events_and_commands() {
+        let stats = Stats::default();
+        assert!(stats.last_change().is_none());
+        stats.record_event(false);
+        stats.record_event(true);
+        stats.record_event(true);
+        stats.record_command(true, Duration::ZERO);
+        stats.record_command(false, Duration::ZERO);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(
+            snapshot,
+            StatsSnapshot {
+                received: 3,
+                filtered: 2,
+                commands: 2,
+                failed: 1,
+            }
+        );
+        assert_eq!(
+            snapshot.to_string(),
+            "📊 Events: 3 received, 2 filtered, 1 acted on | Commands: 2 run, 1 failed"
+        );
+    }
+
+    #[test]
+    fn test_summary_totals_changes_and_command_time() {
+        use notify::event::{CreateKind, DataChange, RemoveKind};
+
+        let stats = Stats::default();
+        stats.record_change(&EventKind::Create(CreateKind::File));
+        stats.record_change(&EventKind::Modify(ModifyKind::Data(DataChange::Any)));
+        stats.record_change(&EventKind::Modify(ModifyKind::Data(DataChange::Any)));
+        stats.record_change(&EventKind::Remove(RemoveKind::File));
+        stats.record_change(&EventKind::Modify(ModifyKind::Name(RenameMode::Both)));
+        stats.record_command(true, Duration::from_millis(1500));
+        stats.record_command(false, Duration::from_millis(250));
+
+        assert!(stats.last_change().is_some());
+
+        let summary = stats.summary();
+        assert_eq!(summary.changes(), 5);
+        assert_eq!(summary.command_time, Duration::from_millis(1750));
+        assert_eq!(
+            summary.to_string(),
+            "📋 Session summary\n   Events:       5 (1 created, 2 modified, 1 deleted, 1 moved)\n   Commands:     2 run, 1 failed\n   Command time: 1.75s"
+        );
+    }
+}
@@ -0,0 +1,235 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How often the status file is rewritten while watching
+pub const STATUS_FILE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Keeps a JSON status file up to date for supervisors, for `--status-file`
+///
+/// Shared by all watchers: each one reports its own numbers into a slot, and
+/// the file holds their totals. A supervisor can tell vibewatch is stuck or
+/// gone when `updated_at` stops moving. The file is written to a temporary
+/// sibling and renamed over the old one, so readers never see half of it.
+#[derive(Debug)]
+pub struct StatusFile {
+    path: PathBuf,
+    started: Instant,
+    started_at: DateTime<Local>,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Latest numbers by watcher slot
+    watchers: Vec<WatcherStatus>,
+    /// When the file was last written
+    written: Option<Instant>,
+}
+
+/// What one watcher reports into the status file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WatcherStatus {
+    /// Events that reached the watcher after debouncing
+    pub events: u64,
+    /// File changes that were acted on
+    pub changes: u64,
+    pub commands: u64,
+    pub failed_commands: u64,
+    pub last_event_at: Option<DateTime<Local>>,
+    /// Events waiting for their debounce window or for files to settle
+    pub pending_events: usize,
+}
+
+/// The file's contents
+#[derive(Debug, Serialize)]
+struct Status {
+    pid: u32,
+    started_at: String,
+    updated_at: String,
+    uptime_secs: u64,
+    events: u64,
+    changes: u64,
+    commands: u64,
+    failed_commands: u64,
+    last_event_at: Option<String>,
+    pending_events: usize,
+}
+
+impl StatusFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            started: Instant::now(),
+            started_at: Local::now(),
+            state: Mutex::default(),
+        }
+    }
+
+    /// Reserve a slot for one watcher's numbers
+    pub fn register(&self) -> usize {
+        let mut state = self.lock();
+        state.watchers.push(WatcherStatus::default());
+        state.watchers.len() - 1
+    }
+
+    /// Store the numbers for `slot` and rewrite the file if it is due
+    ///
+    /// The file is rewritten at most once per `STATUS_FILE_INTERVAL` unless
+    /// `force` is set.
+    pub fn update(
+        &self,
+        slot: usize,
+        status: WatcherStatus,
+        now: Instant,
+        force: bool,
+    ) -> Result<()> {
+        let mut state = self.lock();
+        if let Some(current) = state.watchers.get_mut(slot) {
+            *current = status;
+        }
+        let due = state
+            .written
+            .is_none_or(|written| now.duration_since(written) >= STATUS_FILE_INTERVAL);
+        if !force && !due {
+            return Ok(());
+        }
+
+        // Written while holding the lock so watchers never race on the temp file
+        self.write(&state.watchers, now)?;
+        state.written = Some(now);
+        Ok(())
+    }
+
+    /// Replace the file with the totals of `watchers`
+    fn write(&self, watchers: &[WatcherStatus], now: Instant) -> Result<()> {
+        let status = Status {
+            pid: std::process::id(),
+            started_at: timestamp(self.started_at),
+            updated_at: timestamp(Local::now()),
+            uptime_secs: now.saturating_duration_since(self.started).as_secs(),
+            events: watchers.iter().map(|w| w.events).sum(),
+            changes: watchers.iter().map(|w| w.changes).sum(),
+            commands: watchers.iter().map(|w| w.commands).sum(),
+            failed_commands: watchers.iter().map(|w| w.failed_commands).sum(),
+            last_event_at: watchers
+                .iter()
+                .filter_map(|w| w.last_event_at)
+                .max()
+                .map(timestamp),
+            pending_events: watchers.iter().map(|w| w.pending_events).sum(),
+        };
+        // The status only holds strings and integers, which always serialize
+        let mut contents = serde_json::to_string_pretty(&status).unwrap_or_default();
+        contents.push('\n');
+
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        std::fs::write(&temp, contents)
+            .with_context(|| format!("Failed to write status file: {}", temp.display()))?;
+        std::fs::rename(&temp, &self.path)
+            .with_context(|| format!("Failed to replace status file: {}", self.path.display()))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn timestamp(time: DateTime<Local>) -> String {
+    time.to_rfc3339_opts(chrono::SecondsFormat::Millis, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use tempfile::TempDir;
+
+    fn read(path: &std::path::Path) -> Value {
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_status_file_sums_watchers() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("status.json");
+        let status_file = StatusFile::new(path.clone());
+        let first = status_file.register();
+        let second = status_file.register();
+        let now = Instant::now();
+
+        let last_event_at = Local::now();
+        let status = WatcherStatus {
+            events: 3,
+            changes: 2,
+            commands: 2,
+            failed_commands: 1,
+            last_event_at: Some(last_event_at),
+            pending_events: 1,
+        };
+        status_file.update(first, status, now, true).unwrap();
+        status_file
+            .update(
+                second,
+                WatcherStatus {
+                    events: 4,
+                    ..status
+                },
+                now,
+                true,
+            )
+            .unwrap();
+
+        let json = read(&path);
+        assert_eq!(json["pid"], std::process::id());
+        assert_eq!(json["events"], 7);
+        assert_eq!(json["changes"], 4);
+        assert_eq!(json["failed_commands"], 2);
+        assert_eq!(json["pending_events"], 2);
+        assert_eq!(json["last_event_at"], timestamp(last_event_at));
+        assert!(!temp_dir.path().join("status.json.tmp").exists());
+    }
+
+    #[test]
+    fn test_status_file_is_rewritten_once_per_interval() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("status.json");
+        let status_file = StatusFile::new(path.clone());
+        let slot = status_file.register();
+        let now = Instant::now();
+
+        status_file
+            .update(slot, WatcherStatus::default(), now, false)
+            .unwrap();
+        assert!(read(&path)["last_event_at"].is_null());
+
+        let status = WatcherStatus {
+            events: 1,
+            ..WatcherStatus::default()
+        };
+        status_file.update(slot, status, now, false).unwrap();
+        assert_eq!(read(&path)["events"], 0);
+
+        status_file
+            .update(slot, status, now + STATUS_FILE_INTERVAL, false)
+            .unwrap();
+        assert_eq!(read(&path)["events"], 1);
+    }
+
+    #[test]
+    fn test_status_file_reports_unwritable_path() {
+        let temp_dir = TempDir::new().unwrap();
+        let status_file = StatusFile::new(temp_dir.path().join("missing/status.json"));
+        let slot = status_file.register();
+
+        let error = status_file
+            .update(slot, WatcherStatus::default(), Instant::now(), true)
+            .unwrap_err();
+        assert!(error.to_string().contains("Failed to write status file"));
+    }
+}
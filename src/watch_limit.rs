@@ -0,0 +1,126 @@
+use std::ffi::OsString;
+use std::path::Path;
+
+/// Where Linux keeps the per-user inotify watch limit
+const MAX_USER_WATCHES: &str = "/proc/sys/fs/inotify/max_user_watches";
+
+/// What to do when the watched tree likely needs more inotify watches than allowed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum WatchLimitMode {
+    /// Log a warning and keep watching
+    #[default]
+    Warn,
+    /// Refuse to start
+    Error,
+    /// Don't check
+    Off,
+}
+
+/// The per-user inotify watch limit, if it can be read
+///
+/// Only Linux has one; elsewhere this is always `None`.
+pub(crate) fn max_user_watches() -> Option<usize> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    match std::fs::read_to_string(MAX_USER_WATCHES) {
+        Ok(contents) => contents.trim().parse().ok(),
+        Err(e) => {
+            log::debug!("Could not read {}: {}", MAX_USER_WATCHES, e);
+            None
+        }
+    }
+}
+
+/// Explain the problem when watching `root` likely takes more than `limit` watches
+///
+/// inotify needs one watch per directory, the root included. Directories
+/// named in `excluded` aren't counted, nor are their subtrees. The walk stops
+/// as soon as the limit is exceeded, so large trees are never fully listed.
+pub(crate) fn check(root: &Path, excluded: &[OsString], limit: usize) -> Option<String> {
+    let directories = count_directories(root, excluded, limit.saturating_add(1));
+    if directories <= limit {
+        log::debug!(
+            "Watching {} directories, within the inotify limit of {}",
+            directories,
+            limit
+        );
+        return None;
+    }
+
+    let suggested = (limit.saturating_mul(2)).max(524_288);
+    Some(format!(
+        "{} has more than {} directories, the inotify watch limit \
+         (fs.inotify.max_user_watches), so changes in some of them will go unnoticed. \
+         Raise the limit with `sudo sysctl fs.inotify.max_user_watches={}`, \
+         or leave out large directories with --exclude-dir",
+        root.display(),
+        limit,
+        suggested
+    ))
+}
+
+/// Count the directories in the tree at `root`, the root included, up to `bound`
+///
+/// Symlinked directories are not entered, and directories that can't be read
+/// count without their contents.
+fn count_directories(root: &Path, excluded: &[OsString], bound: usize) -> usize {
+    let mut count = 1;
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if count >= bound {
+                return count;
+            }
+            if entry.file_type().is_ok_and(|file_type| file_type.is_dir())
+                && !excluded.contains(&entry.file_name())
+            {
+                count += 1;
+                stack.push(entry.path());
+            }
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A root with `a`, `a/b` and `c` below it
+    fn tree() -> TempDir {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("a/b")).unwrap();
+        std::fs::create_dir(temp_dir.path().join("c")).unwrap();
+        std::fs::write(temp_dir.path().join("a/file.txt"), "").unwrap();
+        temp_dir
+    }
+
+    #[test]
+    fn test_count_directories() {
+        let temp_dir = tree();
+        assert_eq!(count_directories(temp_dir.path(), &[], usize::MAX), 4);
+        assert_eq!(
+            count_directories(temp_dir.path(), &["a".into()], usize::MAX),
+            2
+        );
+        // The walk stops once the bound is reached
+        assert_eq!(count_directories(temp_dir.path(), &[], 2), 2);
+    }
+
+    #[test]
+    fn test_check_warns_when_tree_exceeds_limit() {
+        let temp_dir = tree();
+
+        let warning = check(temp_dir.path(), &[], 3).expect("4 directories exceed 3 watches");
+        assert!(warning.contains("fs.inotify.max_user_watches"));
+        assert!(warning.contains("sysctl"));
+
+        assert!(check(temp_dir.path(), &[], 4).is_none());
+        assert!(check(temp_dir.path(), &["a".into()], 3).is_none());
+    }
+}
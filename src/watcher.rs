@@ -1,41 +1,509 @@
 use anyhow::{Context, Result};
-use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-use std::collections::HashMap;
+use glob::Pattern;
+use notify::event::{CreateKind, ModifyKind, RemoveKind, RenameMode};
+use notify::{Config, Event, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::ffi::{OsStr, OsString};
+use std::hash::{DefaultHasher, Hasher};
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::process::Stdio;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context as TaskContext, Poll};
 use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt};
 use tokio::process::Command as TokioCommand;
-use tokio::sync::mpsc;
-
-use crate::filter::PatternFilter;
+use tokio::sync::{Semaphore, mpsc};
+use tokio::task::{JoinHandle, JoinSet};
+use tokio_stream::Stream;
+
+use crate::coalesce::Coalescer;
+use crate::debounce::{DebounceMode, DebounceWindows, Debouncer};
+use crate::filter::{PatternFilter, expand_braces};
+use crate::metrics::Metrics;
+use crate::move_tracker::{MOVE_CORRELATION_WINDOW, MoveTracker};
+use crate::output::{self, ColorChoice, LineSink, Output};
+use crate::overlap::{OverlapGuard, OverlapMode};
+use crate::persistent_shell::{SharedShell, ShellOutcome};
+use crate::restart::{RESTART_GRACE_PERIOD, Restarter};
+use crate::result_log::ResultLog;
+use crate::settle::{SETTLE_POLL_INTERVAL, Settler};
+use crate::shell_quote::{QuoteTracker, ShellQuoting};
+use crate::stats::Stats;
+use crate::status_file::{StatusFile, WatcherStatus};
+use crate::watch_limit::{self, WatchLimitMode};
 
 /// Configuration for command execution on file events
-#[derive(Debug, Clone)]
+///
+/// Each event has a list of commands that all run, in order, when it fires.
+/// An empty list falls back to the less specific event's commands.
+#[derive(Debug, Clone, Default)]
 pub struct CommandConfig {
-    pub on_create: Option<String>,
-    pub on_modify: Option<String>,
-    pub on_delete: Option<String>,
-    pub on_change: Option<String>,
+    pub on_create: Vec<String>,
+    pub on_modify: Vec<String>,
+    pub on_delete: Vec<String>,
+    pub on_change: Vec<String>,
+    /// Commands for created directories (falls back to on_create)
+    pub on_create_dir: Vec<String>,
+    /// Commands for deleted directories (falls back to on_delete)
+    pub on_delete_dir: Vec<String>,
+    /// Commands for files moved within the watched tree (falls back to on_modify)
+    pub on_move: Vec<String>,
+    /// Timeout in seconds for commands triggered by create events (0 = no timeout)
+    pub timeout_create: Option<u64>,
+    /// Timeout in seconds for commands triggered by modify events (0 = no timeout)
+    pub timeout_modify: Option<u64>,
+    /// Timeout in seconds for commands triggered by delete events (0 = no timeout)
+    pub timeout_delete: Option<u64>,
+    /// Timeout in seconds for any command without a per-event timeout (0 = no timeout)
+    pub command_timeout: Option<u64>,
+    /// Run commands through one long-lived shell instead of spawning per event
+    pub persistent_shell: bool,
+    /// Pass event details to commands as VIBEWATCH_* environment variables
+    pub export_env: bool,
+    /// Commands for events on the watched directory itself (dropped when empty)
+    pub on_root_change: Vec<String>,
+    /// Commands to run after a command exits successfully
+    pub on_command_success: Vec<String>,
+    /// Commands to run after a command fails, times out or can't be started
+    pub on_command_failure: Vec<String>,
+    /// Interpreter that receives the whole command as one argument, e.g. "sh -c"
+    /// (unset or "none" splits the command into program and arguments)
+    pub shell: Option<String>,
+    /// Keep each command template to one running instance at a time
+    pub no_overlap: Option<OverlapMode>,
+    /// Most commands running at once; further runs wait for a slot (0 = unbounded)
+    pub max_concurrent: usize,
+    /// Run commands one at a time, in the order their events arrived
+    pub sequential: bool,
+    /// How many times to rerun a failed command (0 = never)
+    pub retries: u32,
+    /// Milliseconds to wait before each retry
+    pub retry_delay_ms: u64,
+    /// Also retry commands killed by a timeout
+    pub retry_timeouts: bool,
+    /// Directory commands run in (unset = the watched directory)
+    pub working_dir: Option<WorkingDir>,
+    /// Glob and command pairs; a change runs the command of the first glob
+    /// matching its relative path instead of its on_* commands
+    pub routes: Vec<(String, String)>,
+}
+
+/// Directory a command runs in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WorkingDir {
+    /// A fixed directory
+    Path(PathBuf),
+    /// The parent directory of the changed file, written `{dir_of_file}`
+    DirOfFile,
+}
+
+impl FromStr for WorkingDir {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(if s == "{dir_of_file}" {
+            WorkingDir::DirOfFile
+        } else {
+            WorkingDir::Path(PathBuf::from(s))
+        })
+    }
+}
+
+/// Whether changes to files, directories or both are reported
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EntryFilter {
+    /// Files and directories
+    #[default]
+    All,
+    /// Only files
+    FilesOnly,
+    /// Only directories
+    DirsOnly,
+}
+
+impl EntryFilter {
+    /// Whether a change of `kind` to `path` passes the filter
+    fn allows(self, path: &Path, kind: &EventKind) -> bool {
+        let is_dir = match kind {
+            EventKind::Create(CreateKind::Folder) | EventKind::Remove(RemoveKind::Folder) => true,
+            EventKind::Create(CreateKind::File) | EventKind::Remove(RemoveKind::File) => false,
+            // Other kinds don't say, so look at the path; one that is gone counts as a file
+            _ => path.is_dir(),
+        };
+
+        match self {
+            EntryFilter::All => true,
+            EntryFilter::FilesOnly => !is_dir,
+            EntryFilter::DirsOnly => is_dir,
+        }
+    }
+}
+
+/// Which kinds of file system events are processed
+///
+/// Parsed from a comma-separated list such as `create,modify`, or `all`.
+/// Renames count as `modify`, and so do the close-after-write events inotify
+/// reports when a file is saved. Permission, ownership and timestamp changes
+/// are `metadata`. By default every kind except `access` is processed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventKinds {
+    pub create: bool,
+    pub modify: bool,
+    pub metadata: bool,
+    pub delete: bool,
+    /// Files being opened, read or closed without writing
+    pub access: bool,
+}
+
+impl Default for EventKinds {
+    fn default() -> Self {
+        Self {
+            create: true,
+            modify: true,
+            metadata: true,
+            delete: true,
+            access: false,
+        }
+    }
+}
+
+impl EventKinds {
+    /// Every kind, `access` included
+    pub fn all() -> Self {
+        Self {
+            create: true,
+            modify: true,
+            metadata: true,
+            delete: true,
+            access: true,
+        }
+    }
+
+    /// Whether events of `kind` are processed
+    pub fn accepts(&self, kind: &EventKind) -> bool {
+        match kind {
+            EventKind::Create(_) => self.create,
+            EventKind::Modify(ModifyKind::Metadata(_)) => self.metadata,
+            EventKind::Modify(_) => self.modify,
+            EventKind::Remove(_) => self.delete,
+            // On Linux, inotify sends Access(Close(Write)) for file writes, which we treat as Modify
+            EventKind::Access(notify::event::AccessKind::Close(
+                notify::event::AccessMode::Write,
+            )) => self.modify,
+            EventKind::Access(_) => self.access,
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for EventKinds {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut kinds = Self {
+            create: false,
+            modify: false,
+            metadata: false,
+            delete: false,
+            access: false,
+        };
+        for name in s.split(',').map(str::trim) {
+            match name {
+                "all" => kinds = Self::all(),
+                "create" => kinds.create = true,
+                "modify" => kinds.modify = true,
+                "metadata" => kinds.metadata = true,
+                "delete" => kinds.delete = true,
+                "access" => kinds.access = true,
+                _ => {
+                    return Err(format!(
+                        "unknown event kind '{}' (expected create, modify, metadata, delete, access or all)",
+                        name
+                    ));
+                }
+            }
+        }
+        Ok(kinds)
+    }
+}
+
+/// A command run with its retries and hooks, waiting in the sequential queue
+type CommandTask = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Largest file `--skip-unchanged` hashes; bigger files always count as changed
+const MAX_HASHED_SIZE: u64 = 16 * 1024 * 1024;
+
+/// What `--skip-unchanged` remembers about a file's content
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ContentStamp {
+    len: u64,
+    hash: u64,
+}
+
+/// Hash a file's content without reading it into memory at once
+fn hash_file(path: &Path) -> std::io::Result<u64> {
+    use std::io::Read;
+
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = DefaultHasher::new();
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        match file.read(&mut buffer)? {
+            0 => return Ok(hasher.finish()),
+            read => hasher.write(&buffer[..read]),
+        }
+    }
+}
+
+/// Error returned when a command is killed for running past its timeout
+#[derive(Debug)]
+struct CommandTimedOut(Duration);
+
+impl std::fmt::Display for CommandTimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Command timed out after {}s", self.0.as_secs())
+    }
+}
+
+impl std::error::Error for CommandTimedOut {}
+
+/// Error returned when a command's program doesn't exist
+#[derive(Debug)]
+struct CommandNotFound(String);
+
+impl std::fmt::Display for CommandNotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Command not found: {}", self.0)
+    }
+}
+
+impl std::error::Error for CommandNotFound {}
+
+/// How a command run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunOutcome {
+    Succeeded,
+    /// Non-zero exit status (None when killed by a signal), or the command couldn't be started
+    Failed(Option<i32>),
+    TimedOut,
+}
+
+impl RunOutcome {
+    /// Whether a retry is worth attempting, given whether timeouts are retried
+    fn should_retry(self, retry_timeouts: bool) -> bool {
+        match self {
+            RunOutcome::Succeeded => false,
+            RunOutcome::Failed(_) => true,
+            RunOutcome::TimedOut => retry_timeouts,
+        }
+    }
+
+    /// Exit code of the command, if it exited on its own
+    fn exit_code(self) -> Option<i32> {
+        match self {
+            RunOutcome::Succeeded => Some(0),
+            RunOutcome::Failed(code) => code,
+            RunOutcome::TimedOut => None,
+        }
+    }
+}
+
+/// Hooks to run once a command finishes, with the event that triggered it
+#[derive(Debug, Clone)]
+struct FollowUp {
+    context: TemplateContext,
+    on_success: Vec<String>,
+    on_failure: Vec<String>,
+    /// How hooks quote values for their shell (see `FileWatcher::shell_quoting`)
+    quoting: Option<ShellQuoting>,
+}
+
+impl FollowUp {
+    /// The hook commands for an outcome, substituted with the event and {exit_code}
+    fn commands(&self, outcome: RunOutcome) -> Vec<String> {
+        let templates = match outcome {
+            RunOutcome::Succeeded => &self.on_success,
+            _ => &self.on_failure,
+        };
+        let context = self.context.clone().with_exit_code(outcome.exit_code());
+        templates
+            .iter()
+            .map(|template| context.substitute_command(template, self.quoting))
+            .collect()
+    }
+}
+
+/// Where finished command runs are recorded, when enabled
+#[derive(Debug, Clone, Copy, Default)]
+struct RunRecorders<'a> {
+    metrics: Option<&'a Metrics>,
+    result_log: Option<&'a ResultLog>,
+}
+
+/// How a command run and its output are printed
+#[derive(Debug, Clone, Copy)]
+struct RunDisplay<'a> {
+    output: Output,
+    name: Option<&'a str>,
+    /// Don't print the command's output (it is still logged at debug level)
+    quiet: bool,
+    max_output_lines: Option<usize>,
+    /// Programs already reported missing, so each is only warned about once
+    missing_programs: Option<&'a Mutex<HashSet<String>>>,
+}
+
+/// A substituted command ready to run
+#[derive(Debug, Clone)]
+struct CommandRun {
+    command: String,
+    timeout: Option<Duration>,
+    /// Working directory of the command
+    dir: PathBuf,
+    /// Extra environment variables for the command
+    env: Vec<(&'static str, String)>,
+    /// Success and failure hooks (never set for the hooks themselves)
+    follow_up: Option<FollowUp>,
 }
 
 impl CommandConfig {
-    /// Get the appropriate command for an event kind
-    pub fn get_command_for_event(&self, event_kind: &EventKind) -> Option<&String> {
-        match event_kind {
-            EventKind::Create(_) => self.on_create.as_ref().or(self.on_change.as_ref()),
-            EventKind::Modify(_) => self.on_modify.as_ref().or(self.on_change.as_ref()),
-            EventKind::Remove(_) => self.on_delete.as_ref().or(self.on_change.as_ref()),
-            _ => self.on_change.as_ref(),
+    /// Get the commands to run for an event kind, in configured order
+    ///
+    /// Empty when no command applies to the event.
+    pub fn get_command_for_event(&self, event_kind: &EventKind) -> &[String] {
+        // Most specific first
+        let candidates: &[&Vec<String>] = match event_kind {
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                &[&self.on_move, &self.on_modify, &self.on_change]
+            }
+            EventKind::Create(CreateKind::Folder) => {
+                &[&self.on_create_dir, &self.on_create, &self.on_change]
+            }
+            EventKind::Create(_) => &[&self.on_create, &self.on_change],
+            EventKind::Modify(_) => &[&self.on_modify, &self.on_change],
+            EventKind::Remove(RemoveKind::Folder) => {
+                &[&self.on_delete_dir, &self.on_delete, &self.on_change]
+            }
+            EventKind::Remove(_) => &[&self.on_delete, &self.on_change],
+            _ => &[&self.on_change],
+        };
+
+        candidates
+            .iter()
+            .find(|commands| !commands.is_empty())
+            .map_or(&[], |commands| commands.as_slice())
+    }
+
+    /// Get the command timeout for an event kind, if one is configured
+    ///
+    /// Per-event timeouts take precedence over the global command timeout.
+    pub fn get_timeout_for_event(&self, event_kind: &EventKind) -> Option<Duration> {
+        let seconds = match event_kind {
+            EventKind::Create(_) => self.timeout_create,
+            EventKind::Modify(_) => self.timeout_modify,
+            EventKind::Remove(_) => self.timeout_delete,
+            _ => None,
         }
+        .or(self.command_timeout);
+
+        seconds.filter(|&s| s > 0).map(Duration::from_secs)
+    }
+
+    /// Parse the configured interpreter into program and leading arguments
+    pub fn interpreter(&self) -> Result<Option<Vec<String>>> {
+        match self.shell.as_deref().map(str::trim) {
+            None | Some("none") => Ok(None),
+            Some(shell) => {
+                let argv = shell_words::split(shell)
+                    .with_context(|| format!("Invalid shell: {}", shell))?;
+                if argv.is_empty() {
+                    anyhow::bail!("Shell must name an interpreter, e.g. 'sh -c'");
+                }
+                Ok(Some(argv))
+            }
+        }
+    }
+}
+
+/// How a part of a chained command follows the one before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Chain {
+    /// After `;` (or first): always runs
+    Always,
+    /// After `&&`: runs only if the last part that ran succeeded
+    OnSuccess,
+}
+
+/// Split a command into the parts chained with `&&` and `;`, for running without a shell
+///
+/// Separators inside quotes or escaped with a backslash are left alone, so
+/// `find . -exec rm {} \;` stays one command. Blank parts are dropped.
+fn split_chain(command: &str) -> Vec<(Chain, &str)> {
+    // (link, start, end) of each part, trimmed and filtered at the end
+    let mut spans = Vec::new();
+    let bytes = command.as_bytes();
+    let (mut start, mut link) = (0, Chain::Always);
+    let (mut single, mut double, mut escaped) = (false, false, false);
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            _ if escaped => escaped = false,
+            b'\'' if !double => single = !single,
+            _ if single => {}
+            b'\\' => escaped = true,
+            b'"' => double = !double,
+            _ if double => {}
+            separator @ (b';' | b'&') if separator == b';' || bytes.get(i + 1) == Some(&b'&') => {
+                spans.push((link, start, i));
+                let (next, width) = if separator == b';' {
+                    (Chain::Always, 1)
+                } else {
+                    (Chain::OnSuccess, 2)
+                };
+                link = next;
+                i += width;
+                start = i;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
     }
+    spans.push((link, start, bytes.len()));
+
+    spans
+        .into_iter()
+        .map(|(link, start, end)| (link, command[start..end].trim()))
+        .filter(|(_, part)| !part.is_empty())
+        .collect()
 }
 
 /// Template context for command substitution
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct TemplateContext {
     file_path: String,
     relative_path: String,
     event_type: &'static str,
     absolute_path: String,
+    old_path: String,
+    /// Destination of a move (empty for other events)
+    new_path: String,
+    file_name: String,
+    file_stem: String,
+    file_ext: String,
+    changed_files: String,
+    /// Include pattern the file matched (empty without include patterns)
+    matched_pattern: String,
+    /// When the context was built, in RFC 3339 format
+    event_time: String,
+    /// Exit code of the finished command, for success and failure hooks
+    exit_code: String,
+    /// Sequence number of the event, counting from 0 since vibewatch started
+    count: String,
 }
 
 impl TemplateContext {
@@ -52,18 +520,123 @@ impl TemplateContext {
             relative_path: Self::normalize_path(relative_path),
             event_type: Self::event_kind_to_str(event_kind),
             absolute_path: Self::normalize_path(&absolute_path),
+            old_path: String::new(),
+            new_path: String::new(),
+            file_name: Self::file_component(file_path, Path::file_name),
+            file_stem: Self::file_component(file_path, Path::file_stem),
+            file_ext: Self::file_component(file_path, Path::extension),
+            changed_files: shell_words::quote(&Self::normalize_path(relative_path)).into_owned(),
+            matched_pattern: String::new(),
+            event_time: Self::format_time(chrono::Local::now()),
+            exit_code: String::new(),
+            count: String::new(),
+        }
+    }
+
+    /// Context for the startup run, which has no file to describe
+    ///
+    /// {event_type} is "start" and every path placeholder expands to an empty string.
+    pub fn startup() -> Self {
+        Self {
+            file_path: String::new(),
+            relative_path: String::new(),
+            event_type: "start",
+            absolute_path: String::new(),
+            old_path: String::new(),
+            new_path: String::new(),
+            file_name: String::new(),
+            file_stem: String::new(),
+            file_ext: String::new(),
+            changed_files: String::new(),
+            matched_pattern: String::new(),
+            event_time: Self::format_time(chrono::Local::now()),
+            exit_code: String::new(),
+            count: String::new(),
         }
     }
 
+    /// Event details as environment variables, as exported with `export_env`
+    pub fn env_vars(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("VIBEWATCH_EVENT_TYPE", self.event_type.to_string()),
+            ("VIBEWATCH_FILE_PATH", self.file_path.clone()),
+            ("VIBEWATCH_RELATIVE_PATH", self.relative_path.clone()),
+            ("VIBEWATCH_ABSOLUTE_PATH", self.absolute_path.clone()),
+        ]
+    }
+
+    /// Override when the event happened, exposed as {timestamp}
+    pub fn with_event_time<Tz>(mut self, time: chrono::DateTime<Tz>) -> Self
+    where
+        Tz: chrono::TimeZone,
+        Tz::Offset: std::fmt::Display,
+    {
+        self.event_time = Self::format_time(time);
+        self
+    }
+
+    fn format_time<Tz>(time: chrono::DateTime<Tz>) -> String
+    where
+        Tz: chrono::TimeZone,
+        Tz::Offset: std::fmt::Display,
+    {
+        time.to_rfc3339_opts(chrono::SecondsFormat::Millis, false)
+    }
+
+    /// Set the relative paths of a batch of changes, exposed as {changed_files}
+    pub fn with_changed_files<'a>(
+        mut self,
+        relative_paths: impl IntoIterator<Item = &'a Path>,
+    ) -> Self {
+        let paths: Vec<String> = relative_paths
+            .into_iter()
+            .map(Self::normalize_path)
+            .collect();
+        self.changed_files = shell_words::join(paths);
+        self
+    }
+
+    /// Set the exit code of a finished command, exposed as {exit_code}
+    ///
+    /// Empty when the command didn't exit on its own (not started, timed out or killed).
+    pub fn with_exit_code(mut self, exit_code: Option<i32>) -> Self {
+        self.exit_code = exit_code.map(|code| code.to_string()).unwrap_or_default();
+        self
+    }
+
+    /// Set the event's sequence number, exposed as {count}
+    pub fn with_count(mut self, count: u64) -> Self {
+        self.count = count.to_string();
+        self
+    }
+
+    /// Set the include pattern that matched the file, exposed as {matched_pattern}
+    pub fn with_matched_pattern(mut self, pattern: &str) -> Self {
+        self.matched_pattern = pattern.to_string();
+        self
+    }
+
+    /// Set the source path of a move, exposed as {old_path}
+    ///
+    /// The file path is the destination, which is also exposed as {new_path}.
+    pub fn with_old_path(mut self, old_path: &Path) -> Self {
+        self.old_path = Self::normalize_path(old_path);
+        self.new_path = self.file_path.clone();
+        self
+    }
+
     /// Normalize path to use forward slashes
     ///
     /// On Unix systems, avoids string replacement (just converts to string).
-    /// On Windows, replaces backslashes with forward slashes.
+    /// On Windows, strips the verbatim `\\?\` prefix and replaces backslashes
+    /// with forward slashes.
     ///
     /// Performance: On Unix/macOS (no backslashes), this is a simple to_string().
     /// On Windows (has backslashes), performs replace operation.
-    fn normalize_path(path: &Path) -> String {
+    pub(crate) fn normalize_path(path: &Path) -> String {
         let path_str = path.display().to_string();
+        #[cfg(windows)]
+        let path_str = Self::strip_verbatim_prefix(path_str);
 
         // Check if path contains backslashes (Windows-specific)
         if path_str.contains('\\') {
@@ -75,8 +648,38 @@ impl TemplateContext {
         }
     }
 
+    /// Drop the verbatim prefix `canonicalize` adds on Windows
+    ///
+    /// Many programs reject `\\?\C:\dir`, so it becomes `C:\dir`, and
+    /// `\\?\UNC\server\share` becomes `\\server\share`.
+    #[cfg(windows)]
+    fn strip_verbatim_prefix(path: String) -> String {
+        if let Some(rest) = path.strip_prefix(r"\\?\UNC\") {
+            format!(r"\\{}", rest)
+        } else if let Some(rest) = path.strip_prefix(r"\\?\") {
+            rest.to_string()
+        } else {
+            path
+        }
+    }
+
+    /// Part of the final path component (name, stem or extension)
+    ///
+    /// Empty for paths ending in a separator or lacking the requested part.
+    fn file_component(path: &Path, component: fn(&Path) -> Option<&OsStr>) -> String {
+        let path_str = path.as_os_str().to_string_lossy();
+        if path_str.ends_with('/') || path_str.ends_with('\\') {
+            return String::new();
+        }
+
+        component(path)
+            .map(|part| Self::normalize_path(Path::new(part)))
+            .unwrap_or_default()
+    }
+
     pub fn event_kind_to_str(event_kind: &EventKind) -> &'static str {
         match event_kind {
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => "move",
             EventKind::Create(_) => "create",
             EventKind::Modify(_) => "modify",
             EventKind::Remove(_) => "delete",
@@ -84,11 +687,96 @@ impl TemplateContext {
         }
     }
 
+    /// Check if a name can be expanded as `${NAME}`
+    fn is_env_var_name(name: &str) -> bool {
+        let mut chars = name.chars();
+        chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+
     /// Substitute template variables in a command string
     ///
     /// Uses a single-pass algorithm with pre-allocated capacity for better performance.
-    /// Supports: {file_path}, {relative_path}, {event_type}, {absolute_path}
+    /// Supports: {file_path}, {relative_path}, {event_type}, {absolute_path}, {old_path}, {new_path},
+    /// {file_name}, {file_stem}, {file_ext}, {changed_files}, {timestamp}, {matched_pattern},
+    /// {exit_code}, {count}
+    ///
+    /// `${VAR}` references are replaced with the environment variable's value (empty
+    /// if unset). Only the template itself is expanded, never substituted paths, and
+    /// other shell syntax such as `$VAR` or `${VAR:-default}` is left untouched.
+    ///
+    /// `{{` and `}}` produce a literal `{` and `}`, e.g. `jq '{{key: .val}}'`.
     pub fn substitute_template(&self, template: &str) -> String {
+        self.substitute(template, &self.changed_files, Some(&env_var), None)
+    }
+
+    /// Split a template into arguments and substitute placeholders within each one
+    ///
+    /// Values never change argument boundaries, so `{file_path}` stays one argument
+    /// even when the path contains spaces or quotes. An argument that is exactly
+    /// `{changed_files}` expands to one argument per changed file.
+    pub fn substitute_args(&self, template: &str) -> Result<Vec<String>> {
+        let tokens = shell_words::split(template).context("Failed to parse command")?;
+        // Always splits, it was built with shell_words::join
+        let changed_files = shell_words::split(&self.changed_files).unwrap_or_default();
+
+        let mut args = Vec::with_capacity(tokens.len());
+        for token in tokens {
+            if token == "{changed_files}" {
+                args.extend(changed_files.iter().cloned());
+            } else {
+                args.push(self.substitute(&token, &changed_files.join(" "), Some(&env_var), None));
+            }
+        }
+        Ok(args)
+    }
+
+    /// Substitute a command template for running with or without a shell
+    ///
+    /// Commands run directly are substituted per argument and re-quoted, so
+    /// splitting the result gives back exactly those arguments, and chained parts
+    /// keep their `&&` and `;` separators. Commands handed to a shell (`quoting`
+    /// is set) get every value quoted for that shell and for the quotes the
+    /// template puts around it, so a file named `a b.txt` or `$(cmd).txt` stays
+    /// one word and never runs as code; `${VAR}` is left for the shell to
+    /// expand, as it may be a shell or loop variable. A template that can't be
+    /// split is substituted as text, and fails to parse when it runs.
+    fn substitute_command(&self, template: &str, quoting: Option<ShellQuoting>) -> String {
+        if quoting.is_some() {
+            return self.substitute(template, &self.changed_files, None, quoting);
+        }
+
+        // Each chained part is substituted alone, so values can't add separators
+        let mut command = String::with_capacity(template.len() + 128);
+        for (index, (link, part)) in split_chain(template).into_iter().enumerate() {
+            let Ok(args) = self.substitute_args(part) else {
+                return self.substitute_template(template);
+            };
+            if index > 0 {
+                command.push_str(match link {
+                    Chain::Always => "; ",
+                    Chain::OnSuccess => " && ",
+                });
+            }
+            command.push_str(&shell_words::join(args));
+        }
+        command
+    }
+
+    /// Expand a template, using `changed_files` for the {changed_files} placeholder
+    ///
+    /// `${VAR}` is looked up with `env`, and kept as it is without one. With
+    /// `quoting`, values are quoted for that shell and `changed_files` is
+    /// ignored: each changed file is quoted on its own.
+    fn substitute(
+        &self,
+        template: &str,
+        changed_files: &str,
+        env: Option<EnvLookup>,
+        quoting: Option<ShellQuoting>,
+    ) -> String {
         // Pre-allocate with template size + estimated expansion (128 bytes for paths)
         let mut result = String::with_capacity(template.len() + 128);
         let mut last_end = 0;
@@ -97,8 +785,38 @@ impl TemplateContext {
         let bytes = template.as_bytes();
         let mut i = 0;
 
+        // Template text before `quoted_up_to` has been checked for quotes
+        let mut quotes = quoting.map(QuoteTracker::new);
+        let mut quoted_up_to = 0;
+
         while i < bytes.len() {
-            if bytes[i] == b'{' {
+            if let Some(env) = env
+                && bytes[i] == b'$'
+                && bytes.get(i + 1) == Some(&b'{')
+                && let Some(end) = template[i + 2..].find('}')
+                && Self::is_env_var_name(&template[i + 2..i + 2 + end])
+            {
+                // Environment variable reference
+                result.push_str(&template[last_end..i]);
+
+                let name = &template[i + 2..i + 2 + end];
+                match env(name) {
+                    Some(value) => result.push_str(&value),
+                    None => log::debug!(
+                        "Environment variable {} is not set, expanding to an empty string",
+                        name
+                    ),
+                }
+
+                last_end = i + 2 + end + 1;
+                i = last_end;
+            } else if (bytes[i] == b'{' || bytes[i] == b'}') && bytes.get(i + 1) == Some(&bytes[i])
+            {
+                // Escaped brace - keep one of the pair
+                result.push_str(&template[last_end..=i]);
+                last_end = i + 2;
+                i = last_end;
+            } else if bytes[i] == b'{' {
                 // Found potential placeholder start
                 // Append literal text before placeholder
                 result.push_str(&template[last_end..i]);
@@ -109,12 +827,41 @@ impl TemplateContext {
                     let placeholder = &template[i + 1..placeholder_end];
 
                     // Match and substitute placeholder
-                    match placeholder {
-                        "file_path" => result.push_str(&self.file_path),
-                        "relative_path" => result.push_str(&self.relative_path),
-                        "event_type" => result.push_str(self.event_type),
-                        "absolute_path" => result.push_str(&self.absolute_path),
-                        _ => {
+                    let value = match placeholder {
+                        "file_path" => Some(self.file_path.as_str()),
+                        "relative_path" => Some(self.relative_path.as_str()),
+                        "event_type" => Some(self.event_type),
+                        "absolute_path" => Some(self.absolute_path.as_str()),
+                        "old_path" => Some(self.old_path.as_str()),
+                        "new_path" => Some(self.new_path.as_str()),
+                        "file_name" => Some(self.file_name.as_str()),
+                        "file_stem" => Some(self.file_stem.as_str()),
+                        "file_ext" => Some(self.file_ext.as_str()),
+                        "timestamp" => Some(self.event_time.as_str()),
+                        "matched_pattern" => Some(self.matched_pattern.as_str()),
+                        "exit_code" => Some(self.exit_code.as_str()),
+                        "count" => Some(self.count.as_str()),
+                        _ => None,
+                    };
+                    // Unknown placeholders stay template text, quotes included
+                    if (value.is_some() || placeholder == "changed_files")
+                        && let Some(quotes) = &mut quotes
+                    {
+                        quotes.advance(&template[quoted_up_to..i]);
+                        quoted_up_to = placeholder_end + 1;
+                    }
+                    match (value, &quotes) {
+                        (Some(value), Some(quotes)) => result.push_str(&quotes.quote(value)),
+                        (Some(value), None) => result.push_str(value),
+                        (None, Some(quotes)) if placeholder == "changed_files" => {
+                            // Always splits, it was built with shell_words::join
+                            let files = shell_words::split(&self.changed_files).unwrap_or_default();
+                            result.push_str(&quotes.quote_all(&files));
+                        }
+                        (None, None) if placeholder == "changed_files" => {
+                            result.push_str(changed_files)
+                        }
+                        (None, _) => {
                             // Unknown placeholder - keep as-is
                             result.push('{');
                             result.push_str(placeholder);
@@ -141,750 +888,6248 @@ impl TemplateContext {
     }
 }
 
-/// Main file watcher that monitors directory changes
+/// Every variable `TemplateContext::env_vars` can set
+///
+/// The persistent shell unsets them all before each command, so a variable
+/// from an earlier event never leaks into a later one.
+const ENV_VAR_NAMES: [&str; 4] = [
+    "VIBEWATCH_EVENT_TYPE",
+    "VIBEWATCH_FILE_PATH",
+    "VIBEWATCH_RELATIVE_PATH",
+    "VIBEWATCH_ABSOLUTE_PATH",
+];
+
+/// Looks up the value of a `${VAR}` reference in a template
+type EnvLookup<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+/// vibewatch's own value of an environment variable
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+}
+
+/// A filtered, normalized change to a single watched file
 #[derive(Debug)]
-pub struct FileWatcher {
-    watch_path: PathBuf,
-    filter: PatternFilter,
-    command_config: CommandConfig,
-    debounce_ms: u64,
-    quiet: bool,
+struct FileChange {
+    path: PathBuf,
+    relative_path: PathBuf,
+    kind: EventKind,
+    /// Source path of a move
+    old_path: Option<PathBuf>,
+    /// When the change was picked up, so every command for it sees the same {timestamp}
+    time: chrono::DateTime<chrono::Local>,
+    /// Include pattern the path matched, if include patterns are configured
+    matched_pattern: Option<String>,
 }
 
-impl FileWatcher {
-    /// Create a new file watcher instance
-    pub fn new(
-        watch_path: PathBuf,
-        include_patterns: Vec<String>,
-        exclude_patterns: Vec<String>,
-        command_config: CommandConfig,
-        debounce_ms: u64,
-        _verbose: bool,
-        quiet: bool,
-    ) -> Result<Self> {
-        // Ensure the watch path exists
-        if !watch_path.exists() {
-            anyhow::bail!("Directory does not exist: {}", watch_path.display());
+impl FileChange {
+    fn template_context(&self, watch_path: &Path) -> TemplateContext {
+        let mut context =
+            TemplateContext::new(&self.path, &self.relative_path, &self.kind, watch_path)
+                .with_event_time(self.time);
+        if let Some(pattern) = &self.matched_pattern {
+            context = context.with_matched_pattern(pattern);
         }
-
-        if !watch_path.is_dir() {
-            anyhow::bail!("Path is not a directory: {}", watch_path.display());
+        match &self.old_path {
+            Some(old_path) => context.with_old_path(old_path),
+            None => context,
         }
+    }
+}
 
-        // Convert to absolute path to match what notify gives us
-        let watch_path = watch_path
-            .canonicalize()
-            .context("Failed to get absolute path of watch directory")?;
+/// Details of a file change passed to handlers registered with `FileWatcher::on_event`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventContext {
+    relative_path: PathBuf,
+    absolute_path: PathBuf,
+    old_path: Option<PathBuf>,
+    event_kind: EventKind,
+}
 
-        let filter = PatternFilter::new(include_patterns, exclude_patterns)?;
+impl EventContext {
+    /// Path relative to the watched directory
+    pub fn relative_path(&self) -> &Path {
+        &self.relative_path
+    }
 
-        Ok(Self {
-            watch_path,
-            filter,
-            command_config,
-            debounce_ms,
-            quiet,
-        })
+    /// Absolute path of the changed file
+    pub fn absolute_path(&self) -> &Path {
+        &self.absolute_path
     }
 
-    /// Start watching for file changes
-    pub async fn start_watching(&mut self) -> Result<()> {
-        let (tx, mut rx) = mpsc::unbounded_channel();
-
-        // Create watcher with recommended configuration
-        let mut watcher = RecommendedWatcher::new(
-            move |res: Result<Event, notify::Error>| {
-                // Just forward events to the channel
-                if let Err(e) = tx.send(res) {
-                    eprintln!("Failed to send watch event: {}", e);
+    /// Source path of a moved file
+    pub fn old_path(&self) -> Option<&Path> {
+        self.old_path.as_deref()
+    }
+
+    /// The normalized event kind
+    pub fn event_kind(&self) -> &EventKind {
+        &self.event_kind
+    }
+
+    /// Event type as used by {event_type}: create, modify, delete, move or change
+    pub fn event_type(&self) -> &'static str {
+        TemplateContext::event_kind_to_str(&self.event_kind)
+    }
+}
+
+/// A file change yielded by `FileWatcher::into_event_stream`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileEvent {
+    /// Path relative to the watched directory
+    pub relative_path: PathBuf,
+    pub absolute_path: PathBuf,
+    /// Source path of a moved file
+    pub old_path: Option<PathBuf>,
+    /// The normalized event kind
+    pub kind: EventKind,
+}
+
+impl FileEvent {
+    /// Event type as used by {event_type}: create, modify, delete, move or change
+    pub fn event_type(&self) -> &'static str {
+        TemplateContext::event_kind_to_str(&self.kind)
+    }
+}
+
+/// A callback registered with `FileWatcher::on_event`
+struct EventHandler(Box<dyn Fn(&EventContext) + Send>);
+
+impl std::fmt::Debug for EventHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EventHandler")
+    }
+}
+
+/// What the notify callback sends to the event loop
+type WatchResult = Result<Event, notify::Error>;
+
+/// Sending half of the channel from the notify callback to the event loop
+///
+/// The callback is synchronous and must not block, so a bounded channel that
+/// is full drops the new event and counts it instead.
+#[derive(Debug)]
+enum EventSender {
+    Unbounded(mpsc::UnboundedSender<WatchResult>),
+    Bounded {
+        tx: mpsc::Sender<WatchResult>,
+        dropped: Arc<AtomicU64>,
+    },
+}
+
+impl EventSender {
+    fn send(&self, res: WatchResult) {
+        let result = match self {
+            EventSender::Unbounded(tx) => tx.send(res).map_err(|e| e.to_string()),
+            EventSender::Bounded { tx, dropped } => match tx.try_send(res) {
+                Err(mpsc::error::TrySendError::Full(_)) => {
+                    dropped.fetch_add(1, Ordering::Relaxed);
+                    Ok(())
                 }
+                other => other.map_err(|e| e.to_string()),
             },
-            Config::default(),
-        )
-        .context("Failed to create file watcher")?;
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to send watch event: {}", e);
+        }
+    }
+}
 
-        // Start watching the directory recursively
-        watcher
-            .watch(&self.watch_path, RecursiveMode::Recursive)
-            .context("Failed to start watching directory")?;
+/// Receiving half of the channel from the notify callback
+#[derive(Debug)]
+enum EventReceiver {
+    Unbounded(mpsc::UnboundedReceiver<WatchResult>),
+    Bounded(mpsc::Receiver<WatchResult>),
+}
 
-        log::info!("File watcher started successfully");
-        if self.debounce_ms > 0 {
-            log::info!("Debouncing enabled: {}ms", self.debounce_ms);
+impl EventReceiver {
+    async fn recv(&mut self) -> Option<WatchResult> {
+        match self {
+            EventReceiver::Unbounded(rx) => rx.recv().await,
+            EventReceiver::Bounded(rx) => rx.recv().await,
         }
-        println!("🚀 Watching for file changes... Press Ctrl+C to stop");
-
-        // Track pending events for debouncing: path -> (event, last_update_time)
-        let mut pending_events: HashMap<PathBuf, (Event, Instant)> = HashMap::new();
-        let debounce_duration = Duration::from_millis(self.debounce_ms);
+    }
+}
 
-        // Create ticker for checking pending events
-        let check_interval = if self.debounce_ms > 0 {
-            Duration::from_millis(50) // Check frequently when debouncing enabled
-        } else {
-            Duration::from_secs(3600) // Rarely check when debouncing disabled
-        };
-        let mut ticker = tokio::time::interval(check_interval);
-        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+/// Whether converting a path to a string loses bytes that aren't valid UTF-8
+fn is_lossy(path: &Path) -> bool {
+    OsStr::new(path.to_string_lossy().as_ref()) != path.as_os_str()
+}
 
-        // Process events asynchronously with graceful shutdown
-        loop {
-            tokio::select! {
-                // Handle Ctrl+C for graceful shutdown
-                _ = tokio::signal::ctrl_c() => {
-                    log::info!("Received Ctrl+C, shutting down gracefully...");
-                    println!("\n👋 Shutting down vibewatch...");
-                    break;
-                }
-                // Receive file system events
-                Some(res) = rx.recv() => {
-                    match res {
-                        Ok(event) => {
-                            if self.debounce_ms == 0 {
-                                // No debouncing - process immediately
-                                self.handle_event(event);
-                            } else {
-                                // Debouncing enabled - track events
-                                for path in &event.paths {
-                                    pending_events.insert(path.clone(), (event.clone(), Instant::now()));
-                                    log::debug!("Debouncing event for: {}", path.display());
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            log::error!("Watch error: {}", e);
-                        }
-                    }
-                }
-                // Check for events ready to process (exceeded debounce period)
-                _ = ticker.tick() => {
-                    if self.debounce_ms > 0 && !pending_events.is_empty() {
-                        let now = Instant::now();
-                        let ready_paths: Vec<PathBuf> = pending_events
-                            .iter()
-                            .filter(|(_, (_, time))| now.duration_since(*time) >= debounce_duration)
-                            .map(|(path, _)| path.clone())
-                            .collect();
-
-                        for path in ready_paths {
-                            if let Some((event, _)) = pending_events.remove(&path) {
-                                log::debug!("Debounce period elapsed for: {}", path.display());
-                                self.handle_event(event);
-                            }
-                        }
-                    }
-                }
-            }
+/// Create the event channel, holding at most `capacity` events if set
+///
+/// Also returns the counter of events dropped because a bounded channel was full.
+fn event_channel(
+    capacity: Option<NonZeroUsize>,
+) -> (EventSender, EventReceiver, Option<Arc<AtomicU64>>) {
+    match capacity {
+        Some(capacity) => {
+            let (tx, rx) = mpsc::channel(capacity.get());
+            let dropped = Arc::new(AtomicU64::new(0));
+            (
+                EventSender::Bounded {
+                    tx,
+                    dropped: Arc::clone(&dropped),
+                },
+                EventReceiver::Bounded(rx),
+                Some(dropped),
+            )
+        }
+        None => {
+            let (tx, rx) = mpsc::unbounded_channel();
+            (
+                EventSender::Unbounded(tx),
+                EventReceiver::Unbounded(rx),
+                None,
+            )
         }
+    }
+}
 
-        Ok(())
+/// Stream of file changes from `FileWatcher::into_event_stream`
+///
+/// Dropping it stops the watcher.
+#[derive(Debug)]
+struct EventStream {
+    events: mpsc::UnboundedReceiver<FileEvent>,
+    watcher: JoinHandle<()>,
+}
+
+impl Stream for EventStream {
+    type Item = FileEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
+        self.events.poll_recv(cx)
     }
+}
 
-    /// Handle a file system event
-    fn handle_event(&self, event: Event) {
-        log::debug!(
-            "Raw event received: kind={:?}, paths={:?}",
-            event.kind,
-            event.paths
-        );
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        self.watcher.abort();
+    }
+}
 
-        // Filter out events we don't care about
-        // Note: On Linux, inotify sends Access(Close(Write)) for file writes, which we treat as Modify
-        match event.kind {
-            EventKind::Create(_)
-            | EventKind::Modify(_)
-            | EventKind::Remove(_)
-            | EventKind::Access(notify::event::AccessKind::Close(
-                notify::event::AccessMode::Write,
-            )) => {
-                // These are the events we want to process
-                log::debug!("Event ACCEPTED by filter: {:?}", event.kind);
+/// Filter patterns and commands swapped in when the watcher reloads
+#[derive(Debug, Clone, Default)]
+pub struct WatchRules {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub commands: CommandConfig,
+}
+
+/// A callback registered with `FileWatcher::with_reload`
+struct ReloadHandler(Box<dyn Fn() -> Result<WatchRules> + Send>);
+
+impl std::fmt::Debug for ReloadHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ReloadHandler")
+    }
+}
+
+/// Stream of one unix signal, such as SIGHUP to reload or SIGTERM to stop
+#[cfg(unix)]
+type UnixSignal = tokio::signal::unix::Signal;
+
+/// No unix signals elsewhere, so their branches never fire
+#[cfg(not(unix))]
+type UnixSignal = std::convert::Infallible;
+
+/// Start listening for SIGHUP, if the platform has it
+fn listen_for_hangup() -> Option<UnixSignal> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        signal(SignalKind::hangup())
+            .map_err(|e| log::error!("Failed to listen for SIGHUP, reloading is disabled: {}", e))
+            .ok()
+    }
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+/// Start listening for SIGTERM, if the platform has it (Windows only has Ctrl+C)
+fn listen_for_terminate() -> Option<UnixSignal> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        signal(SignalKind::terminate())
+            .map_err(|e| log::error!("Failed to listen for SIGTERM: {}", e))
+            .ok()
+    }
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+/// Wait for the next signal; pends forever without a listener
+async fn next_signal(signal: &mut Option<UnixSignal>) {
+    #[cfg(unix)]
+    if let Some(signal) = signal
+        && signal.recv().await.is_some()
+    {
+        return;
+    }
+    #[cfg(not(unix))]
+    let _ = signal;
+    std::future::pending().await
+}
+
+/// Wait for the next heartbeat tick; pends forever without a heartbeat
+async fn next_heartbeat(heartbeat: &mut Option<tokio::time::Interval>) {
+    match heartbeat {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Read control commands from stdin, one per line, for `stdin_control`
+///
+/// Every line goes to each receiver; reading stops once all are dropped. A
+/// plain thread rather than `tokio::io::stdin`, whose blocking read can't be
+/// cancelled and would keep the runtime from shutting down until Enter is pressed.
+fn read_control_lines(receivers: usize) -> Vec<mpsc::UnboundedReceiver<String>> {
+    let (senders, receivers): (Vec<_>, Vec<_>) =
+        (0..receivers).map(|_| mpsc::unbounded_channel()).unzip();
+    std::thread::spawn(move || {
+        use std::io::BufRead;
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            let sent = senders
+                .iter()
+                .filter(|tx| tx.send(line.clone()).is_ok())
+                .count();
+            if sent == 0 {
+                break;
             }
-            _ => {
-                log::debug!("Event IGNORED by filter: {:?}", event.kind);
-                return; // Ignore other event types
+        }
+    });
+    receivers
+}
+
+/// Wait for the next control line; pends forever without stdin control or once stdin closes
+async fn next_control_line(control: &mut Option<mpsc::UnboundedReceiver<String>>) -> String {
+    if let Some(lines) = control {
+        match lines.recv().await {
+            Some(line) => return line,
+            None => {
+                log::debug!("Stdin closed, no longer reading control commands");
+                *control = None;
             }
         }
+    }
+    std::future::pending().await
+}
 
-        // Process each path in the event
-        for path in event.paths {
-            if let Some(relative_path) = self.get_relative_path(&path)
-                && self.filter.should_watch(&relative_path)
-            {
-                // Normalize event kinds for cross-platform consistency
-                // On Linux, inotify sends Access(Close(Write)) for file writes, treat as Modify
-                let normalized_modify = EventKind::Modify(notify::event::ModifyKind::Data(
-                    notify::event::DataChange::Any,
-                ));
-                let normalized_remove = EventKind::Remove(notify::event::RemoveKind::File);
+/// A command typed on stdin with `stdin_control`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ControlCommand {
+    /// Run the on_change commands now
+    Run,
+    /// Print the event and command counters
+    Stats,
+    /// Stop watching
+    Quit,
+}
 
-                let final_event_kind = match &event.kind {
-                    EventKind::Modify(notify::event::ModifyKind::Name(_)) => {
-                        // If the file no longer exists, treat this as a deletion
-                        if !path.exists() {
-                            &normalized_remove
-                        } else {
-                            &event.kind
-                        }
-                    }
-                    EventKind::Access(notify::event::AccessKind::Close(
-                        notify::event::AccessMode::Write,
-                    )) => {
-                        // Treat Close(Write) as Modify for command execution
-                        &normalized_modify
-                    }
-                    _ => &event.kind,
-                };
+impl FromStr for ControlCommand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "run" => Ok(ControlCommand::Run),
+            "stats" => Ok(ControlCommand::Stats),
+            "quit" => Ok(ControlCommand::Quit),
+            other => Err(format!(
+                "Unknown command '{}', expected run, stats or quit",
+                other
+            )),
+        }
+    }
+}
+
+/// Commands in progress and when the last one finished, for `exclude_writes_for`
+#[derive(Debug, Default)]
+struct CommandActivity {
+    running: usize,
+    last_finished: Option<Instant>,
+}
+
+impl CommandActivity {
+    fn started(&mut self) {
+        self.running += 1;
+    }
+
+    fn finished(&mut self, now: Instant) {
+        self.running = self.running.saturating_sub(1);
+        self.last_finished = Some(now);
+    }
+
+    /// Whether events at `now` may have been caused by a command
+    fn covers(&self, window: Duration, now: Instant) -> bool {
+        self.running > 0
+            || self
+                .last_finished
+                .is_some_and(|finished| now.duration_since(finished) < window)
+    }
+}
+
+/// Main file watcher that monitors directory changes
+#[derive(Debug)]
+pub struct FileWatcher {
+    watch_path: PathBuf,
+    /// The root as given, made absolute without resolving symlinks
+    given_path: PathBuf,
+    /// Show paths below the resolved `watch_path` instead of `given_path`
+    canonicalize: bool,
+    filter: PatternFilter,
+    command_config: CommandConfig,
+    /// Compiled `routes` of the command config, in order
+    routes: Vec<(Pattern, String)>,
+    debounce: DebounceWindows,
+    debounce_mode: DebounceMode,
+    /// Debounce each path separately for every command group it resolves to
+    debounce_per_command_group: bool,
+    batch: bool,
+    run_on_start: bool,
+    quiet: bool,
+    output: Output,
+    /// Label printed before every output line, to tell instances apart
+    name: Option<Arc<str>>,
+    /// Most lines of output printed per command run
+    max_output_lines: Option<usize>,
+    /// Color the labels of text event lines
+    color: bool,
+    persistent_shell: Option<SharedShell>,
+    interpreter: Option<Vec<String>>,
+    /// Resolved working directory for commands
+    working_dir: WorkingDir,
+    /// Tracks running command templates and the runs queued behind them
+    overlap: Option<OverlapGuard<CommandRun>>,
+    /// Command slots with `max_concurrent` (unbounded without)
+    concurrency: Option<Arc<Semaphore>>,
+    /// Feeds the task running commands one at a time with `sequential`,
+    /// started on the first command
+    sequential: Option<OnceLock<mpsc::UnboundedSender<CommandTask>>>,
+    /// Ignore events while commands run and for this long after they finish
+    exclude_writes: Option<(Duration, Arc<Mutex<CommandActivity>>)>,
+    /// Shortest time between two runs of a command template, with each template's last run
+    min_interval: Option<(Duration, Mutex<HashMap<String, Instant>>)>,
+    /// Fixed wait between a change being handled and its command starting
+    exec_delay: Option<Duration>,
+    /// Holds path-independent commands so a burst runs them once
+    coalescer: Option<Coalescer<CommandRun>>,
+    metrics: Option<Arc<Metrics>>,
+    /// Where each command's result is appended as a JSON line
+    result_log: Option<Arc<ResultLog>>,
+    /// Events that ran commands so far, exposed as {count}
+    event_count: AtomicU64,
+    /// Counters for `--stats` and `--summary-on-exit`
+    stats: Option<Arc<Stats>>,
+    /// Programs that failed to spawn as not found, each warned about once
+    missing_programs: Arc<Mutex<HashSet<String>>>,
+    /// Shared `--status-file` and this watcher's slot in it
+    status_file: Option<(Arc<StatusFile>, usize)>,
+    /// How often to print the `--stats` line
+    stats_interval: Option<Duration>,
+    /// Print a summary of the session when watching stops
+    summary_on_exit: bool,
+    /// How often to print a "still watching" line
+    heartbeat: Option<Duration>,
+    /// Read `run`, `stats` and `quit` commands from stdin
+    stdin_control: bool,
+    /// Stdin lines shared with other watchers, set by `share_stdin_control`
+    control_lines: Option<mpsc::UnboundedReceiver<String>>,
+    /// Template for the text line printed for each file change
+    format: Option<String>,
+    /// Keeps commands running as long-lived processes, restarted on each trigger
+    restarter: Option<Restarter>,
+    handlers: Vec<EventHandler>,
+    /// Set by `into_event_stream`: changes are sent here instead of running commands
+    stream: Option<mpsc::UnboundedSender<FileEvent>>,
+    /// Running commands to wait for on shutdown (only tracked with wait-on-exit)
+    in_flight: Option<Mutex<JoinSet<()>>>,
+    /// Longest time to wait for running commands on shutdown (None = no limit)
+    exit_timeout: Option<Duration>,
+    fail_fast: bool,
+    /// Scan for changes at this interval instead of using native OS events
+    poll_interval: Option<Duration>,
+    /// What to do when the tree likely exceeds the inotify watch limit
+    watch_limit: WatchLimitMode,
+    /// Most OS events buffered before new ones are dropped (unbounded without)
+    channel_capacity: Option<NonZeroUsize>,
+    /// Watch subdirectories too (only direct children of the root otherwise)
+    recursive: bool,
+    /// When watching a single file, its name inside `watch_path`
+    file: Option<PathBuf>,
+    /// Descend into symlinked directories and filter symlinks by their target
+    follow_symlinks: bool,
+    /// Report changes to files, directories or both
+    entries: EntryFilter,
+    /// Kinds of events processed; the others are dropped on arrival
+    event_kinds: EventKinds,
+    /// Ignore files more than this many directory levels below the root
+    max_depth: Option<usize>,
+    /// Names of directories that are never watched, at any depth
+    excluded_dirs: Vec<OsString>,
+    /// Stop watching after the first handled file change
+    once: bool,
+    /// Hold content changes until the file stops being written
+    wait_for_close: bool,
+    /// Wait for a deleted watch root to reappear instead of stopping
+    retry_watch: bool,
+    /// Last seen content per file, to skip saves that change nothing
+    content_hashes: Option<Mutex<HashMap<PathBuf, ContentStamp>>>,
+    /// Drop modify events on directories, see `is_dir_modify_noise`
+    ignore_dir_modify: bool,
+    /// Skip files smaller than this many bytes
+    min_size: Option<u64>,
+    /// Skip events for paths that aren't valid UTF-8 instead of only warning
+    strict_utf8: bool,
+    /// Handle the paths of a multi-path event in sorted order
+    sort_paths: bool,
+    /// Reports failed commands to the event loop (set while watching with fail-fast)
+    failure_tx: Option<mpsc::UnboundedSender<String>>,
+    /// Produces fresh rules on SIGHUP
+    reload: Option<ReloadHandler>,
+}
+
+impl FileWatcher {
+    /// Create a new file watcher instance
+    pub fn new(
+        watch_path: PathBuf,
+        include_patterns: Vec<String>,
+        exclude_patterns: Vec<String>,
+        command_config: CommandConfig,
+        debounce_ms: u64,
+        _verbose: bool,
+        quiet: bool,
+    ) -> Result<Self> {
+        // Ensure the watch path exists
+        if !watch_path.exists() {
+            anyhow::bail!("Path does not exist: {}", watch_path.display());
+        }
+
+        // A single file is watched through its parent directory, and every
+        // other file in it is filtered out
+        let (watch_path, file) = if watch_path.is_dir() {
+            (watch_path, None)
+        } else {
+            let file_name = watch_path
+                .file_name()
+                .context("Failed to get the name of the watched file")?;
+            let parent = match watch_path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            (parent.to_path_buf(), Some(PathBuf::from(file_name)))
+        };
+
+        let given_path = std::path::absolute(&watch_path)
+            .context("Failed to get absolute path of watch directory")?;
+
+        // Convert to absolute path to match what notify gives us
+        let watch_path = watch_path
+            .canonicalize()
+            .context("Failed to get absolute path of watch directory")?;
+
+        let filter = PatternFilter::new(include_patterns, exclude_patterns)?;
+
+        // The shell process itself is started lazily on the first command
+        let persistent_shell = command_config.persistent_shell.then(SharedShell::default);
+        let interpreter = command_config.interpreter()?;
+        let overlap = command_config.no_overlap.map(OverlapGuard::new);
+        let concurrency = (command_config.max_concurrent > 0)
+            .then(|| Arc::new(Semaphore::new(command_config.max_concurrent)));
+        let sequential = command_config.sequential.then(OnceLock::new);
+
+        let working_dir = Self::resolve_working_dir(&command_config, &watch_path)?;
+        let routes = Self::compile_routes(&command_config)?;
+
+        Ok(Self {
+            watch_path,
+            given_path,
+            canonicalize: true,
+            file,
+            filter,
+            command_config,
+            routes,
+            debounce: DebounceWindows::new(Duration::from_millis(debounce_ms)),
+            debounce_mode: DebounceMode::default(),
+            debounce_per_command_group: false,
+            batch: false,
+            run_on_start: false,
+            quiet,
+            output: Output::default(),
+            name: None,
+            max_output_lines: None,
+            color: ColorChoice::default().enabled(),
+            persistent_shell,
+            interpreter,
+            working_dir,
+            overlap,
+            concurrency,
+            sequential,
+            exclude_writes: None,
+            min_interval: None,
+            exec_delay: None,
+            coalescer: None,
+            metrics: None,
+            result_log: None,
+            event_count: AtomicU64::new(0),
+            stats: None,
+            missing_programs: Arc::default(),
+            status_file: None,
+            stats_interval: None,
+            summary_on_exit: false,
+            heartbeat: None,
+            stdin_control: false,
+            control_lines: None,
+            format: None,
+            restarter: None,
+            handlers: Vec::new(),
+            stream: None,
+            in_flight: None,
+            exit_timeout: None,
+            fail_fast: false,
+            poll_interval: None,
+            watch_limit: WatchLimitMode::default(),
+            channel_capacity: None,
+            recursive: true,
+            follow_symlinks: false,
+            entries: EntryFilter::default(),
+            event_kinds: EventKinds::default(),
+            max_depth: None,
+            excluded_dirs: Vec::new(),
+            failure_tx: None,
+            once: false,
+            wait_for_close: false,
+            retry_watch: false,
+            content_hashes: None,
+            ignore_dir_modify: false,
+            min_size: None,
+            strict_utf8: false,
+            sort_paths: false,
+            reload: None,
+        })
+    }
 
-                Self::log_file_change(&relative_path, final_event_kind);
+    /// Resolve the configured working directory, defaulting to the watch path
+    fn resolve_working_dir(
+        command_config: &CommandConfig,
+        watch_path: &Path,
+    ) -> Result<WorkingDir> {
+        Ok(match &command_config.working_dir {
+            None => WorkingDir::Path(watch_path.to_path_buf()),
+            Some(WorkingDir::Path(dir)) => {
+                if !dir.is_dir() {
+                    anyhow::bail!("Working directory does not exist: {}", dir.display());
+                }
+                WorkingDir::Path(
+                    dir.canonicalize()
+                        .context("Failed to get absolute path of working directory")?,
+                )
+            }
+            Some(WorkingDir::DirOfFile) => WorkingDir::DirOfFile,
+        })
+    }
 
-                // Execute command if configured
-                self.execute_command_for_event(&path, &relative_path, final_event_kind);
+    /// Compile the route globs, expanding braces like include patterns
+    fn compile_routes(command_config: &CommandConfig) -> Result<Vec<(Pattern, String)>> {
+        let mut routes = Vec::new();
+        for (glob, command) in &command_config.routes {
+            for pattern in expand_braces(glob) {
+                let pattern = Pattern::new(&pattern)
+                    .with_context(|| format!("Invalid route pattern '{}'", glob))?;
+                routes.push((pattern, command.clone()));
             }
         }
+        Ok(routes)
     }
 
-    /// Get relative path from the watch directory
-    fn get_relative_path(&self, path: &Path) -> Option<PathBuf> {
-        path.strip_prefix(&self.watch_path)
-            .ok()
-            .map(|p| p.to_path_buf())
+    /// The commands a change runs: its route's command, or else its on_* commands
+    fn commands_for(&self, change: &FileChange) -> &[String] {
+        self.commands_for_path(&change.relative_path, &change.kind)
     }
 
-    /// Log file change with appropriate formatting (static version)
-    fn log_file_change(path: &Path, event_kind: &EventKind) {
-        let timestamp = chrono::Local::now().format("%Y-%m-%dT%H:%M:%S");
-        let event_type = match event_kind {
-            EventKind::Create(_) => "CREATED",
-            EventKind::Modify(_) => "MODIFIED",
-            EventKind::Remove(_) => "DELETED",
-            _ => "CHANGED",
-        };
+    /// The commands an event of `kind` for a relative path runs
+    fn commands_for_path(&self, relative_path: &Path, kind: &EventKind) -> &[String] {
+        let path = relative_path.to_string_lossy();
+        match self
+            .routes
+            .iter()
+            .find(|(pattern, _)| pattern.matches(&path))
+        {
+            Some((pattern, command)) => {
+                log::debug!("Path '{}' routed by pattern '{}'", path, pattern.as_str());
+                std::slice::from_ref(command)
+            }
+            None => self.command_config.get_command_for_event(kind),
+        }
+    }
 
-        println!("[{}] [{}] {}", timestamp, event_type, path.display());
-        log::debug!("File event: {:?} - {}", event_kind, path.display());
+    /// Choose when debounced commands fire (trailing by default)
+    pub fn with_debounce_mode(mut self, mode: DebounceMode) -> Self {
+        self.debounce_mode = mode;
+        self
     }
 
-    /// Execute command for a file event if configured
-    fn execute_command_for_event(&self, path: &Path, relative_path: &Path, event_kind: &EventKind) {
-        if let Some(command_template) = self.command_config.get_command_for_event(event_kind) {
-            let context = TemplateContext::new(path, relative_path, event_kind, &self.watch_path);
-            let command = context.substitute_template(command_template);
+    /// Debounce each event type with its own window instead of one delay for all
+    pub fn with_debounce_windows(mut self, windows: DebounceWindows) -> Self {
+        self.debounce = windows;
+        self
+    }
 
-            let timestamp = chrono::Local::now().format("%Y-%m-%dT%H:%M:%S");
-            println!("[{}] Executing command: {}", timestamp, command);
+    /// Also skip files ignored by .gitignore files in the watched directory
+    pub fn with_gitignore(mut self) -> Result<Self> {
+        self.filter = self.filter.with_gitignore(&self.watch_path)?;
+        Ok(self)
+    }
 
-            let quiet = self.quiet;
+    /// Also skip files matching the rules of gitignore-syntax files, like `.ignore`
+    ///
+    /// Anchored rules are relative to the watched directory.
+    pub fn with_ignore_files(mut self, files: &[PathBuf]) -> Result<Self> {
+        self.filter = self.filter.with_ignore_files(&self.watch_path, files)?;
+        Ok(self)
+    }
 
-            // Execute command asynchronously
-            tokio::spawn(async move {
-                match Self::execute_shell_command(&command).await {
-                    Ok(output) => {
-                        log::debug!("Command executed successfully");
+    /// Match include/exclude patterns against absolute paths instead of relative ones
+    pub fn with_match_absolute(mut self) -> Self {
+        let root = self.absolute_root();
+        self.filter = self.filter.with_absolute_paths(&root);
+        self
+    }
 
-                        // Show command output unless --quiet flag is set
-                        if !quiet {
-                            if !output.stdout.is_empty() {
-                                let stdout = String::from_utf8_lossy(&output.stdout);
-                                print!("{}", stdout);
-                            }
-                            if !output.stderr.is_empty() {
-                                let stderr = String::from_utf8_lossy(&output.stderr);
-                                eprint!("{}", stderr);
-                            }
-                        } else {
-                            // In quiet mode, still log at debug level
-                            if !output.stdout.is_empty() {
-                                log::debug!(
-                                    "Command stdout: {}",
-                                    String::from_utf8_lossy(&output.stdout)
-                                );
-                            }
-                            if !output.stderr.is_empty() {
-                                log::debug!(
-                                    "Command stderr: {}",
-                                    String::from_utf8_lossy(&output.stderr)
-                                );
-                            }
-                        }
+    /// Match include/exclude patterns against file names instead of relative paths
+    pub fn with_match_basename(mut self) -> Self {
+        self.filter = self.filter.with_basenames();
+        self
+    }
 
-                        // Log command completion with exit code
-                        let timestamp = chrono::Local::now().format("%Y-%m-%dT%H:%M:%S");
-                        if let Some(code) = output.status.code() {
-                            if output.status.success() {
-                                println!("[{}] Command succeeded (exit code: {})", timestamp, code);
-                            } else {
-                                println!("[{}] Command failed (exit code: {})", timestamp, code);
-                            }
-                        } else {
-                            println!("[{}] Command terminated by signal", timestamp);
-                        }
-                    }
-                    Err(e) => {
-                        let timestamp = chrono::Local::now().format("%Y-%m-%dT%H:%M:%S");
-                        println!("[{}] Command failed to execute: {}", timestamp, e);
-                        log::error!("Failed to execute command '{}': {}", command, e);
-                    }
-                }
-            });
+    /// The watch root as shown in {absolute_path}, for absolute pattern matching
+    fn absolute_root(&self) -> PathBuf {
+        PathBuf::from(TemplateContext::normalize_path(self.display_root()))
+    }
+
+    /// The root paths are shown below: resolved, or as given with `canonicalize` off
+    fn display_root(&self) -> &Path {
+        if self.canonicalize {
+            &self.watch_path
+        } else {
+            &self.given_path
         }
     }
 
-    /// Execute a shell command asynchronously
-    async fn execute_shell_command(command: &str) -> Result<std::process::Output> {
-        log::debug!("Executing shell command: {}", command);
+    /// Swap in the rules returned by `reload` whenever the process receives SIGHUP
+    ///
+    /// The directory stays watched throughout. Only filter patterns and commands
+    /// are replaced; the persistent shell, overlap mode, sequential mode and concurrency limit
+    /// keep their startup settings. If `reload` fails or returns invalid rules, the error is logged
+    /// and the current rules stay in place. Without SIGHUP (e.g. on Windows),
+    /// this does nothing.
+    pub fn with_reload(mut self, reload: impl Fn() -> Result<WatchRules> + Send + 'static) -> Self {
+        self.reload = Some(ReloadHandler(Box::new(reload)));
+        self
+    }
 
-        // Parse command with proper quote handling
-        let parts = shell_words::split(command).context("Failed to parse command")?;
-        if parts.is_empty() {
-            anyhow::bail!("Empty command");
+    /// Give every command group its own debounce window per path
+    ///
+    /// Events for one path that resolve to different commands (e.g. a modify
+    /// running `--on-modify` and a create running `--on-change`) then no longer
+    /// merge into a single event that runs only one of them.
+    pub fn with_debounce_per_command_group(mut self, per_command_group: bool) -> Self {
+        self.debounce_per_command_group = per_command_group;
+        self
+    }
+
+    /// Run each command once per settled burst of debounced events
+    pub fn with_batch(mut self, batch: bool) -> Self {
+        self.batch = batch;
+        self
+    }
+
+    /// Run the on_change command once as soon as watching has started
+    pub fn with_run_on_start(mut self, run_on_start: bool) -> Self {
+        self.run_on_start = run_on_start;
+        self
+    }
+
+    /// Choose how file events and command results are reported
+    pub fn with_output(mut self, output: Output) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Print at most `max_lines` lines of each command run's output
+    ///
+    /// Output is printed as the command writes it either way; past the limit
+    /// lines are only counted, and a "... (N more lines)" notice follows once
+    /// the command ends. Stdout and stderr share the limit.
+    pub fn with_max_output_lines(mut self, max_lines: usize) -> Self {
+        self.max_output_lines = Some(max_lines);
+        self
+    }
+
+    /// Prefix every line this watcher prints with `[name]` (no prefix when empty)
+    ///
+    /// JSON lines are left as they are so they stay valid JSON.
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = (!name.is_empty()).then(|| Arc::from(name));
+        self
+    }
+
+    /// Choose when text event lines are colored (when stdout is a terminal by default)
+    pub fn with_color(mut self, color: ColorChoice) -> Self {
+        self.color = color.enabled();
+        self
+    }
+
+    /// Wait for running commands on shutdown, giving up after `timeout` if set
+    pub fn with_wait_on_exit(mut self, timeout: Option<Duration>) -> Self {
+        self.in_flight = Some(Mutex::new(JoinSet::new()));
+        self.exit_timeout = timeout;
+        self
+    }
+
+    /// Stop watching after the first file change that passes the filters
+    ///
+    /// Its commands are waited for before `start_watching` returns, with no
+    /// time limit unless one was set with `with_wait_on_exit`.
+    pub fn with_once(mut self, once: bool) -> Self {
+        self.once = once;
+        if once && self.in_flight.is_none() {
+            self.in_flight = Some(Mutex::new(JoinSet::new()));
         }
+        self
+    }
 
-        let program = &parts[0];
-        let args = &parts[1..];
+    /// Hold creates and modifications until the file stops being written
+    ///
+    /// The file's size and modification time are checked every 100ms, and the
+    /// event is handled once two checks in a row agree, so commands don't read
+    /// a half-written file. This happens after debouncing.
+    pub fn with_wait_for_close(mut self, wait_for_close: bool) -> Self {
+        self.wait_for_close = wait_for_close;
+        self
+    }
 
-        let output = TokioCommand::new(program)
-            .args(args)
-            .output()
-            .await
-            .context("Failed to execute command")?;
+    /// Keep running when the watched directory is deleted
+    ///
+    /// Without this, deleting the watched directory stops the watcher with an
+    /// error. With it, the directory is checked for every 50ms and watched again
+    /// once it exists.
+    pub fn with_retry_watch(mut self, retry_watch: bool) -> Self {
+        self.retry_watch = retry_watch;
+        self
+    }
 
-        // Return output regardless of exit code - caller will check status
-        Ok(output)
+    /// Ignore modifications that leave a file's content unchanged
+    ///
+    /// Files are hashed when created or modified, so the first modification of
+    /// a file that existed before watching started always goes through.
+    pub fn with_skip_unchanged(mut self, skip_unchanged: bool) -> Self {
+        self.content_hashes = skip_unchanged.then(Mutex::default);
+        self
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use notify::event::{CreateKind, ModifyKind, RemoveKind};
-    use rstest::rstest;
-    use std::path::PathBuf;
-    use tempfile::TempDir;
+    /// Drop modify events whose path is a directory
+    ///
+    /// Some platforms report a directory as modified whenever a file inside it
+    /// changes, which runs commands a second time for the same change. Renames
+    /// and permission, ownership or extended attribute changes still go through.
+    pub fn with_ignore_dir_modify(mut self, ignore_dir_modify: bool) -> Self {
+        self.ignore_dir_modify = ignore_dir_modify;
+        self
+    }
 
-    // Parameterized tests for CommandConfig - testing command resolution for different event types
-    #[rstest]
-    // Create event tests
-    #[case(
-        Some("create_cmd"),
-        None,
-        None,
-        Some("fallback"),
-        EventKind::Create(CreateKind::File),
-        Some("create_cmd")
-    )]
-    #[case(
-        None,
-        None,
-        None,
-        Some("fallback"),
-        EventKind::Create(CreateKind::File),
-        Some("fallback")
-    )]
-    #[case(
-        Some("create_cmd"),
-        None,
+    /// Skip events for files smaller than `bytes`, such as lock and pid files
+    ///
+    /// Checked before the include and exclude patterns. Deleted files, directories
+    /// and files whose size can't be read always go through.
+    pub fn with_min_size(mut self, bytes: u64) -> Self {
+        self.min_size = Some(bytes);
+        self
+    }
+
+    /// Skip events for paths that aren't valid UTF-8
+    ///
+    /// Such paths are always reported with a warning, as their invalid bytes
+    /// are replaced in templates and pattern matching. By default the event is
+    /// still processed with the replaced path.
+    pub fn with_strict_utf8(mut self, strict_utf8: bool) -> Self {
+        self.strict_utf8 = strict_utf8;
+        self
+    }
+
+    /// Handle the paths of an event carrying several in sorted order
+    ///
+    /// By default they are handled in the order the OS reported them, which
+    /// can differ between runs. Moves keep their source and destination.
+    pub fn with_sort_paths(mut self, sort_paths: bool) -> Self {
+        self.sort_paths = sort_paths;
+        self
+    }
+
+    /// Stop watching with an error as soon as any command fails
+    pub fn with_fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+
+    /// Detect changes by scanning the directory every `interval`
+    ///
+    /// Slower than native events, but works where those are missed, such as
+    /// network filesystems and some container bind mounts.
+    pub fn with_poll(mut self, interval: Duration) -> Self {
+        self.poll_interval = Some(interval);
+        self
+    }
+
+    /// Choose what happens when the tree likely needs more inotify watches than allowed
+    ///
+    /// On Linux every watched directory takes one watch out of the per-user
+    /// `fs.inotify.max_user_watches`; past it, directories silently go
+    /// unwatched. The check runs at startup, with native events only.
+    pub fn with_watch_limit(mut self, mode: WatchLimitMode) -> Self {
+        self.watch_limit = mode;
+        self
+    }
+
+    /// Buffer at most `capacity` OS events, dropping new ones while it is full
+    ///
+    /// Caps memory during huge bursts such as a large checkout. Dropped events
+    /// are counted and logged; without this the buffer is unbounded.
+    pub fn with_channel_capacity(mut self, capacity: NonZeroUsize) -> Self {
+        self.channel_capacity = Some(capacity);
+        self
+    }
+
+    /// Show paths below the root with symlinks resolved (the default), or below
+    /// the root as it was given
+    ///
+    /// Either way the real location is watched. Without canonicalizing,
+    /// {absolute_path}, printed events, `on_event` handlers and absolute
+    /// patterns use the given root, while {file_path} stays the resolved path.
+    pub fn with_canonicalize(mut self, canonicalize: bool) -> Self {
+        self.canonicalize = canonicalize;
+        self
+    }
+
+    /// Watch subdirectories too (the default), or only the root's direct children
+    pub fn with_recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /// Follow symlinks: watch inside symlinked directories and report symlinked
+    /// files by the path of their target (off by default)
+    ///
+    /// Targets outside the watched directory keep the symlink's own path.
+    pub fn with_follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// Only report changes to files, or only to directories (both by default)
+    pub fn with_entries(mut self, entries: EntryFilter) -> Self {
+        self.entries = entries;
+        self
+    }
+
+    /// Only process these kinds of events (all but `access` by default)
+    pub fn with_event_kinds(mut self, event_kinds: EventKinds) -> Self {
+        self.event_kinds = event_kinds;
+        self
+    }
+
+    /// Never watch directories with these names, nor anything below them
+    ///
+    /// Instead of one recursive watch for the whole tree, every other directory
+    /// gets its own watch and excluded subtrees get none. On Linux each watched
+    /// directory uses up an inotify watch, so pruning e.g. node_modules can save
+    /// thousands. Directories created later are watched as they appear.
+    pub fn with_excluded_dirs(mut self, names: impl IntoIterator<Item = String>) -> Self {
+        self.excluded_dirs = names.into_iter().map(OsString::from).collect();
+        self
+    }
+
+    /// Ignore files more than `max_depth` directory levels below the root
+    ///
+    /// Depth 0 keeps only the root's direct children. Subdirectories are still
+    /// watched; deeper events are dropped when they are filtered.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Record events, commands and pending events into shared metrics
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Append a JSON line with the command, exit code, duration and output sizes
+    /// of every finished command to `path`
+    pub fn with_result_log(mut self, path: &Path) -> Result<Self> {
+        self.result_log = Some(Arc::new(ResultLog::open(path)?));
+        Ok(self)
+    }
+
+    /// Count received, filtered and acted-on events and command runs, printing
+    /// a summary every `interval` and once more on shutdown
+    pub fn with_stats(mut self, interval: Duration) -> Self {
+        self.stats.get_or_insert_default();
+        self.stats_interval = Some(interval);
+        self
+    }
+
+    /// Report events, commands and pending events into a shared status file,
+    /// rewritten about once a second while watching
+    pub fn with_status_file(mut self, status_file: Arc<StatusFile>) -> Self {
+        self.stats.get_or_insert_default();
+        let slot = status_file.register();
+        self.status_file = Some((status_file, slot));
+        self
+    }
+
+    /// Print the number of changes by type, commands run and time spent running
+    /// them once watching stops (not printed with `quiet`)
+    pub fn with_summary_on_exit(mut self, summary_on_exit: bool) -> Self {
+        if summary_on_exit {
+            self.stats.get_or_insert_default();
+        }
+        self.summary_on_exit = summary_on_exit;
+        self
+    }
+
+    /// Print a "still watching" line with the number of events so far every
+    /// `interval`, to show vibewatch is alive while nothing changes (not printed with `quiet`)
+    pub fn with_heartbeat(mut self, interval: Duration) -> Self {
+        self.stats.get_or_insert_default();
+        self.heartbeat = Some(interval);
+        self
+    }
+
+    /// Treat commands as long-running processes, such as dev servers
+    ///
+    /// Each trigger stops the command's running process (SIGTERM, then SIGKILL
+    /// after a grace period) and starts a fresh one. Output isn't collected:
+    /// processes write straight to the terminal. Timeouts, retries, hooks and
+    /// the persistent shell don't apply to these commands.
+    pub fn with_restart(mut self, restart: bool) -> Self {
+        self.restarter = restart.then(|| Restarter::new(RESTART_GRACE_PERIOD));
+        self
+    }
+
+    /// Print each file change as `template`, substituted like a command, instead
+    /// of the default line (text output only; JSON lines keep their fields)
+    pub fn with_format(mut self, template: impl Into<String>) -> Self {
+        self.format = Some(template.into());
+        self
+    }
+
+    /// Read commands from stdin while watching: `run` runs the on_change
+    /// commands, `stats` prints the event and command counters and `quit` stops
+    pub fn with_stdin_control(mut self, stdin_control: bool) -> Self {
+        if stdin_control {
+            self.stats.get_or_insert_default();
+        }
+        self.stdin_control = stdin_control;
+        self
+    }
+
+    /// Read stdin once for every watcher with stdin control, sending each line to all of them
+    ///
+    /// Watchers that read stdin on their own would split the lines between
+    /// them, so `quit` would only stop one. Call it right before the watchers
+    /// start, as reading begins at once.
+    pub fn share_stdin_control(watchers: &mut [FileWatcher]) {
+        let mut controlled: Vec<_> = watchers
+            .iter_mut()
+            .filter(|watcher| watcher.stdin_control)
+            .collect();
+        if controlled.is_empty() {
+            return;
+        }
+        let lines = read_control_lines(controlled.len());
+        for (watcher, lines) in controlled.iter_mut().zip(lines) {
+            watcher.control_lines = Some(lines);
+        }
+    }
+
+    /// Ignore every file event from the moment a command starts until `window`
+    /// after the last running command finishes
+    ///
+    /// Breaks loops where a command writes into the watched directory, at the
+    /// cost of also missing real changes made during that time.
+    pub fn with_exclude_writes_for(mut self, window: Duration) -> Self {
+        self.exclude_writes = Some((window, Arc::default()));
+        self
+    }
+
+    /// Run each command at most once per `interval`, skipping events in between
+    ///
+    /// Unlike debouncing, the interval counts from the command's last run and
+    /// is not extended by new events.
+    pub fn with_min_interval(mut self, interval: Duration) -> Self {
+        self.min_interval = Some((interval, Mutex::default()));
+        self
+    }
+
+    /// Wait `delay` between handling a change and starting each of its commands
+    ///
+    /// Unlike debouncing, nothing is merged: every run is delayed on its own.
+    /// The wait counts as part of the run for `--no-overlap` and `sequential`,
+    /// and doesn't hold a `--max-concurrent` slot. Not used with `--restart`.
+    pub fn with_exec_delay(mut self, delay: Duration) -> Self {
+        self.exec_delay = Some(delay);
+        self
+    }
+
+    /// Run each path-independent command once per `window`, however many files changed
+    ///
+    /// Commands using no placeholder of the changed file, like `cargo build`,
+    /// run once at the end of a window opened by their first event, with the
+    /// latest event's context. Commands using a path still run per file.
+    pub fn with_coalesce_global(mut self, window: Duration) -> Self {
+        self.coalescer = Some(Coalescer::new(window));
+        self
+    }
+
+    /// Call `handler` for every file change, in addition to any configured command
+    ///
+    /// Handlers run on the watcher's task, in registration order, so they should
+    /// return quickly. A panicking handler is logged and does not stop the watcher.
+    pub fn on_event(&mut self, handler: impl Fn(&EventContext) + Send + 'static) {
+        self.handlers.push(EventHandler(Box::new(handler)));
+    }
+
+    /// Start watching on a background task and yield every file change
+    ///
+    /// Changes go through the same filtering, debouncing and move detection as
+    /// `start_watching`, which runs commands for them instead. Configured
+    /// commands don't run here, and signals like Ctrl+C are left to the
+    /// caller: the stream only ends if the watcher fails (the error is logged)
+    /// or the watched directory is deleted. Nothing is printed to stdout, which
+    /// is left to the caller too. Dropping the stream stops the watcher. Must be
+    /// called from within a tokio runtime.
+    pub fn into_event_stream(self) -> impl Stream<Item = FileEvent> + Unpin {
+        let (tx, events) = mpsc::unbounded_channel();
+        let mut watcher = self.streaming_to(tx);
+
+        let watcher = tokio::spawn(async move {
+            if let Err(e) = watcher.start_watching().await {
+                log::error!("File watcher stopped: {:#}", e);
+            }
+        });
+
+        EventStream { events, watcher }
+    }
+
+    /// Send changes to `stream` instead of running commands or printing anything
+    fn streaming_to(mut self, stream: mpsc::UnboundedSender<FileEvent>) -> Self {
+        self.stream = Some(stream);
+        // Both only trigger commands
+        self.run_on_start = false;
+        self.stdin_control = false;
+        self.control_lines = None;
+        // No banner, heartbeat, stats or summary lines
+        self.quiet = true;
+        self.heartbeat = None;
+        self.stats_interval = None;
+        self.summary_on_exit = false;
+        self
+    }
+
+    /// Files currently in the watched directory that pass the filters
+    ///
+    /// Applies the same patterns, depth limit and .gitignore rules as file
+    /// events. Paths are relative to the watched directory and sorted.
+    pub fn matched_files(&self) -> Result<Vec<PathBuf>> {
+        let mut walker = ignore::WalkBuilder::new(&self.watch_path);
+        // Filtering is left to should_watch, so only --gitignore applies .gitignore
+        walker
+            .standard_filters(false)
+            .follow_links(self.follow_symlinks);
+        if !self.watches_subdirectories() {
+            walker.max_depth(Some(1));
+        }
+
+        let mut files = Vec::new();
+        for entry in walker.build() {
+            let entry = entry.context("Failed to list the watched directory")?;
+            if entry.file_type().is_none_or(|file_type| file_type.is_dir()) {
+                continue;
+            }
+            if let Some(relative_path) = self.get_relative_path(entry.path())
+                && self.should_watch(&relative_path)
+            {
+                files.push(relative_path);
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// Start watching for file changes
+    pub async fn start_watching(&mut self) -> Result<()> {
+        let (tx, mut rx, dropped_events) = event_channel(self.channel_capacity);
+
+        // Failed commands are reported back here with fail-fast; otherwise the
+        // sender is dropped and the branch below never fires
+        let (failure_tx, mut failure_rx) = mpsc::unbounded_channel();
+        if self.fail_fast {
+            self.failure_tx = Some(failure_tx);
+        }
+
+        // Just forward events to the channel
+        let forward = move |res: WatchResult| tx.send(res);
+
+        // Both backends feed the same channel, so the loop below doesn't care which runs
+        let config = Config::default().with_follow_symlinks(self.follow_symlinks);
+        let mut watcher: Box<dyn Watcher + Send> = match self.poll_interval {
+            Some(interval) => Box::new(
+                PollWatcher::new(forward, config.with_poll_interval(interval))
+                    .context("Failed to create polling file watcher")?,
+            ),
+            None => Box::new(
+                RecommendedWatcher::new(forward, config)
+                    .context("Failed to create file watcher")?,
+            ),
+        };
+
+        if self.poll_interval.is_none()
+            && let Some(limit) = watch_limit::max_user_watches()
+        {
+            self.check_watch_limit(limit)?;
+        }
+        self.register_watches(watcher.as_mut())
+            .context("Failed to start watching directory")?;
+
+        log::info!("File watcher started successfully");
+        if let Some(interval) = self.poll_interval {
+            log::info!("Polling for changes every {}ms", interval.as_millis());
+        }
+        if !self.debounce.longest().is_zero() {
+            log::info!(
+                "Debouncing enabled: {} ({:?})",
+                self.debounce,
+                self.debounce_mode
+            );
+        }
+        self.status("🚀 Watching for file changes... Press Ctrl+C to stop");
+        // A stream's consumer decides what stops it
+        let handles_signals = self.stream.is_none();
+
+        if self.run_on_start {
+            self.run_on_change_commands("--run-on-start");
+        }
+
+        if self.control_lines.is_none() {
+            Self::share_stdin_control(std::slice::from_mut(self));
+        }
+        let mut control = self.control_lines.take();
+
+        // Coalesces rapid events per path
+        let mut debouncer = Debouncer::with_windows(self.debounce_mode, self.debounce);
+
+        // Correlates rename halves into single move events
+        let mut move_tracker = MoveTracker::new(MOVE_CORRELATION_WINDOW);
+
+        // Holds debounced changes until their files stop being written
+        let mut settler = self
+            .wait_for_close
+            .then(|| Settler::new(SETTLE_POLL_INTERVAL));
+
+        // Only take over SIGHUP when there is something to reload; otherwise it
+        // keeps its default of terminating the process
+        let mut hangup = self
+            .reload
+            .as_ref()
+            .filter(|_| handles_signals)
+            .and_then(|_| listen_for_hangup());
+
+        // Service managers like systemd and Docker stop processes with SIGTERM
+        let mut terminate = if handles_signals {
+            listen_for_terminate()
+        } else {
+            None
+        };
+
+        // Create ticker for checking pending events and expiring unmatched renames
+        let check_interval = Duration::from_millis(50);
+        let mut ticker = tokio::time::interval(check_interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+        let mut stats_printed = Instant::now();
+
+        // Written right away so supervisors can tell startup succeeded
+        self.write_status(&debouncer, settler.as_ref(), true)?;
+
+        // The first beat comes one interval after startup, not right away
+        let mut heartbeat = self.heartbeat.map(|period| {
+            let mut interval =
+                tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            interval
+        });
+        let mut dropped_reported = (0, Instant::now());
+
+        // Set while the watched directory is gone, with retry-watch
+        let mut root_missing = false;
+
+        // Process events asynchronously with graceful shutdown
+        let mut failed_command = None;
+        let mut root_deleted = false;
+        loop {
+            tokio::select! {
+                // Handle Ctrl+C for graceful shutdown
+                _ = tokio::signal::ctrl_c(), if handles_signals => {
+                    log::info!("Received Ctrl+C, shutting down gracefully...");
+                    self.status("\n👋 Shutting down vibewatch...");
+                    break;
+                }
+                // SIGTERM stops just as gracefully
+                _ = next_signal(&mut terminate) => {
+                    log::info!("Received SIGTERM, shutting down gracefully...");
+                    self.status("\n👋 Shutting down vibewatch...");
+                    break;
+                }
+                // Stop on the first failed command with fail-fast
+                Some(command) = failure_rx.recv() => {
+                    log::error!("Command failed, stopping (--fail-fast): {}", command);
+                    self.status("\n💥 Command failed, stopping vibewatch (--fail-fast)");
+                    failed_command = Some(command);
+                    break;
+                }
+                // Re-read the rules on SIGHUP
+                _ = next_signal(&mut hangup) => {
+                    log::info!("Received SIGHUP, reloading watch rules");
+                    match self.reload_rules() {
+                        Ok(()) => self.status("🔄 Reloaded watch rules"),
+                        Err(e) => log::error!("Failed to reload, keeping current rules: {:#}", e),
+                    }
+                }
+                // Commands typed on stdin
+                line = next_control_line(&mut control) => {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    match line.parse() {
+                        Ok(ControlCommand::Run) => self.run_on_change_commands("'run'"),
+                        Ok(ControlCommand::Stats) => {
+                            if let Some(stats) = &self.stats {
+                                self.output
+                                    .status(&stats.snapshot().to_string(), self.name.as_deref());
+                            }
+                        }
+                        Ok(ControlCommand::Quit) => {
+                            log::info!("Received quit on stdin, shutting down gracefully...");
+                            self.status("\n👋 Shutting down vibewatch...");
+                            break;
+                        }
+                        Err(e) => log::warn!("{}", e),
+                    }
+                }
+                // Show that vibewatch is still alive
+                _ = next_heartbeat(&mut heartbeat) => {
+                    self.print_heartbeat();
+                }
+                // Receive file system events
+                Some(res) = rx.recv() => {
+                    match res {
+                        Ok(event) => {
+                            log::trace!("Raw event: {:?}", event);
+                            if self.prunes_directories() {
+                                self.watch_new_directories(watcher.as_mut(), &event);
+                            }
+                            let now = Instant::now();
+                            let on_root = event.paths.contains(&self.watch_path);
+                            if self.is_command_write(now) {
+                                log::debug!(
+                                    "Ignoring event while commands write (--exclude-writes-for): {:?}",
+                                    event.paths
+                                );
+                                continue;
+                            }
+                            let mut handled = false;
+                            for event in move_tracker.process(event, now) {
+                                handled |= self.queue_event(event, &mut debouncer, &mut settler, now);
+                            }
+                            self.record_pending_events(&debouncer, settler.as_ref());
+
+                            if self.once && handled {
+                                log::info!("Handled a file change, stopping (--once)");
+                                break;
+                            }
+                            // The root's own events include its deletion or move away
+                            if on_root && !root_missing && !self.watch_path.exists() {
+                                if !self.root_removed(watcher.as_mut()) {
+                                    root_deleted = true;
+                                    break;
+                                }
+                                root_missing = true;
+                            }
+                        }
+                        Err(e) => {
+                            log::error!("Watch error: {}", e);
+                            // Errors keep coming once the root is gone, e.g. from the poll backend
+                            if !root_missing && !self.watch_path.exists() {
+                                if !self.root_removed(watcher.as_mut()) {
+                                    root_deleted = true;
+                                    break;
+                                }
+                                root_missing = true;
+                            }
+                        }
+                    }
+                }
+                // Check for events ready to process (exceeded debounce period)
+                _ = ticker.tick() => {
+                    self.reap_finished_commands();
+                    self.run_coalesced_commands(Instant::now(), false);
+
+                    if root_missing && self.watch_path.is_dir() {
+                        match self.register_watches(watcher.as_mut()) {
+                            Ok(()) => {
+                                log::info!("Watched directory reappeared, watching it again");
+                                self.status("🔁 Watched directory is back, watching again");
+                                root_missing = false;
+                            }
+                            Err(e) => log::debug!("Watched directory not ready yet: {}", e),
+                        }
+                    }
+
+                    let now = Instant::now();
+                    let mut handled = false;
+                    for event in move_tracker.expire(now) {
+                        handled |= self.queue_event(event, &mut debouncer, &mut settler, now);
+                    }
+
+                    let ready = if self.batch {
+                        debouncer.ready_batch(now)
+                    } else {
+                        debouncer.ready(now)
+                    };
+                    handled |= self.settle_or_handle(ready, &mut settler, self.batch, now);
+
+                    if let Some(settler) = &mut settler {
+                        let settled = if self.batch {
+                            settler.ready_batch(now)
+                        } else {
+                            settler.ready(now)
+                        };
+                        handled |= self.handle_events(settled, self.batch);
+                    }
+
+                    self.record_pending_events(&debouncer, settler.as_ref());
+                    if let Err(e) = self.write_status(&debouncer, settler.as_ref(), false) {
+                        log::warn!("{:#}", e);
+                    }
+
+                    if self.once && handled {
+                        log::info!("Handled a file change, stopping (--once)");
+                        break;
+                    }
+
+                    // Reported at most once a second, a flood would repeat it every tick
+                    if let Some(dropped) = &dropped_events
+                        && now.duration_since(dropped_reported.1) >= Duration::from_secs(1)
+                    {
+                        let total = dropped.load(Ordering::Relaxed);
+                        if total > dropped_reported.0 {
+                            log::warn!(
+                                "Event buffer full, dropped {} events ({} in total, --channel-capacity {})",
+                                total - dropped_reported.0,
+                                total,
+                                self.channel_capacity.map_or(0, NonZeroUsize::get)
+                            );
+                            dropped_reported = (total, now);
+                        }
+                    }
+
+                    if let Some(interval) = self.stats_interval
+                        && now.duration_since(stats_printed) >= interval
+                    {
+                        self.print_stats();
+                        stats_printed = now;
+                    }
+                }
+            }
+        }
+
+        // The change that ended a --once run may still be held back
+        if self.once {
+            self.run_coalesced_commands(Instant::now(), true);
+        }
+        if let Some(restarter) = &self.restarter {
+            restarter.stop_all().await;
+        }
+        self.wait_for_commands().await;
+        if let Err(e) = self.write_status(&debouncer, settler.as_ref(), true) {
+            log::warn!("{:#}", e);
+        }
+        self.print_stats();
+        self.print_summary();
+
+        if let Some(command) = failed_command {
+            anyhow::bail!("Command failed: {}", command);
+        }
+        if root_deleted {
+            anyhow::bail!(
+                "Watched directory was deleted: {} (use --retry-watch to wait for it to come back)",
+                self.watch_path.display()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// React to the watched directory disappearing
+    ///
+    /// Returns whether to keep running, which is only the case with
+    /// retry-watch. The stale watch is dropped so it can be set up again.
+    fn root_removed(&self, watcher: &mut dyn Watcher) -> bool {
+        if !self.retry_watch {
+            log::error!(
+                "Watched directory was deleted: {}",
+                self.watch_path.display()
+            );
+            self.status("\n🗑️  Watched directory was deleted, stopping vibewatch");
+            return false;
+        }
+
+        log::warn!(
+            "Watched directory was deleted, waiting for it to come back: {}",
+            self.watch_path.display()
+        );
+        self.status(
+            "⏳ Watched directory was deleted, waiting for it to come back (--retry-watch)",
+        );
+        // The backend may already have dropped the watch itself
+        if let Err(e) = watcher.unwatch(&self.watch_path) {
+            log::debug!("Failed to remove the stale watch: {}", e);
+        }
+        true
+    }
+
+    /// Run the on_change commands once, without a file event
+    ///
+    /// `trigger` names what asked for the run, for the warning when there is nothing to run.
+    fn run_on_change_commands(&self, trigger: &str) {
+        if self.command_config.on_change.is_empty() {
+            log::warn!("{} has no effect without an on_change command", trigger);
+            return;
+        }
+
+        let context = TemplateContext::startup();
+        for command_template in &self.command_config.on_change {
+            let command = context.substitute_command(command_template, self.shell_quoting());
+            self.spawn_command(
+                command_template,
+                CommandRun {
+                    command,
+                    timeout: self.command_config.get_timeout_for_event(&EventKind::Any),
+                    dir: self.command_dir(None),
+                    env: self.command_env(&context),
+                    follow_up: self.follow_up(&context),
+                },
+            );
+        }
+    }
+
+    /// Drop finished commands from the in-flight set so it doesn't grow unbounded
+    fn reap_finished_commands(&self) {
+        if let Some(in_flight) = &self.in_flight {
+            let mut in_flight = in_flight.lock().unwrap_or_else(|e| e.into_inner());
+            while in_flight.try_join_next().is_some() {}
+        }
+    }
+
+    /// Wait for commands still running at shutdown (with wait-on-exit)
+    ///
+    /// Takes `&mut self` so the watcher doesn't need to be `Sync` across the await.
+    async fn wait_for_commands(&mut self) {
+        let Some(in_flight) = &mut self.in_flight else {
+            return;
+        };
+        let commands = in_flight.get_mut().unwrap_or_else(|e| e.into_inner());
+        while commands.try_join_next().is_some() {}
+
+        if commands.is_empty() {
+            return;
+        }
+
+        log::info!(
+            "Waiting for {} running command(s) to finish",
+            commands.len()
+        );
+        // Field access rather than self.status(), which would borrow all of self
+        if !self.quiet {
+            self.output.status(
+                &format!(
+                    "⏳ Waiting for {} running command(s) to finish...",
+                    commands.len()
+                ),
+                self.name.as_deref(),
+            );
+        }
+
+        let drain = async { while commands.join_next().await.is_some() {} };
+        match self.exit_timeout {
+            Some(timeout) => {
+                if tokio::time::timeout(timeout, drain).await.is_err() {
+                    log::warn!(
+                        "Stopped waiting after {}s; {} command(s) still running",
+                        timeout.as_secs(),
+                        commands.len()
+                    );
+                }
+            }
+            None => drain.await,
+        }
+    }
+
+    /// Replace the filter and commands with the rules from the reload callback
+    ///
+    /// Everything is validated before anything is replaced, so a failed reload
+    /// leaves the watcher unchanged. Unchanged patterns aren't recompiled, and
+    /// .gitignore rules stay as read at startup.
+    fn reload_rules(&mut self) -> Result<()> {
+        let Some(ReloadHandler(reload)) = &self.reload else {
+            return Ok(());
+        };
+        let rules = reload()?;
+
+        let interpreter = rules.commands.interpreter()?;
+        let working_dir = Self::resolve_working_dir(&rules.commands, &self.watch_path)?;
+        let routes = Self::compile_routes(&rules.commands)?;
+        // Last, since it replaces the patterns as soon as they all compile
+        self.filter.rebuild(rules.include, rules.exclude)?;
+
+        self.interpreter = interpreter;
+        self.working_dir = working_dir;
+        self.routes = routes;
+        self.command_config = rules.commands;
+        Ok(())
+    }
+
+    /// Process an event now if the debouncer lets it through, otherwise hold or drop it
+    ///
+    /// Returns whether any file change was handled.
+    fn queue_event(
+        &self,
+        event: Event,
+        debouncer: &mut Debouncer,
+        settler: &mut Option<Settler>,
+        now: Instant,
+    ) -> bool {
+        let events = if self.debounce_per_command_group {
+            debouncer.push_grouped(event, now, |path, kind| self.command_group(path, kind))
+        } else {
+            debouncer.push(event, now)
+        };
+        self.settle_or_handle(events, settler, false, now)
+    }
+
+    /// The commands an event for `path` resolves to, as a debounce key
+    fn command_group(&self, path: &Path, kind: &EventKind) -> Option<String> {
+        let relative = self.get_relative_path(path)?;
+        let commands = self.commands_for_path(&relative, kind);
+        (!commands.is_empty()).then(|| commands.join("\n"))
+    }
+
+    /// Handle events the debouncer released, holding back files still being written
+    ///
+    /// Returns whether any file change was handled.
+    fn settle_or_handle(
+        &self,
+        events: Vec<Event>,
+        settler: &mut Option<Settler>,
+        batch: bool,
+        now: Instant,
+    ) -> bool {
+        let events = match settler {
+            Some(settler) => events
+                .into_iter()
+                .filter_map(|event| settler.push(event, now))
+                .collect(),
+            None => events,
+        };
+        self.handle_events(events, batch)
+    }
+
+    /// Handle events one by one, or together as a batch
+    ///
+    /// Returns whether any file change was handled.
+    fn handle_events(&self, events: Vec<Event>, batch: bool) -> bool {
+        if events.is_empty() {
+            return false;
+        }
+        if batch {
+            return self.handle_batch(events);
+        }
+
+        let mut handled = false;
+        for event in events {
+            handled |= self.handle_event(event);
+        }
+        handled
+    }
+
+    /// Check if an event is a correlated move carrying both source and destination
+    fn is_move(event: &Event) -> bool {
+        matches!(
+            event.kind,
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both))
+        ) && event.paths.len() == 2
+    }
+
+    /// Handle a file system event
+    ///
+    /// Returns whether the event produced any file change.
+    fn handle_event(&self, event: Event) -> bool {
+        let changes = self.collect_changes(event);
+        self.record_stats_event(changes.is_empty());
+        let handled = !changes.is_empty();
+
+        for change in changes {
+            self.dispatch_to_handlers(&change);
+            if !self.send_to_stream(&change) {
+                self.execute_command_for_event(&change);
+            }
+        }
+        handled
+    }
+
+    /// Send a change to the stream of `into_event_stream`
+    ///
+    /// Returns false when there is no stream, and commands should run instead.
+    fn send_to_stream(&self, change: &FileChange) -> bool {
+        let Some(stream) = &self.stream else {
+            return false;
+        };
+        // The stream may be dropping; the watcher task is aborted then
+        let _ = stream.send(FileEvent {
+            relative_path: change.relative_path.clone(),
+            absolute_path: self.display_root().join(&change.relative_path),
+            old_path: change.old_path.clone(),
+            kind: change.kind,
+        });
+        true
+    }
+
+    /// Pass a change to the registered `on_event` handlers
+    fn dispatch_to_handlers(&self, change: &FileChange) {
+        if self.handlers.is_empty() {
+            return;
+        }
+
+        let context = EventContext {
+            relative_path: change.relative_path.clone(),
+            absolute_path: self.display_root().join(&change.relative_path),
+            old_path: change.old_path.clone(),
+            event_kind: change.kind,
+        };
+
+        for EventHandler(handler) in &self.handlers {
+            let result =
+                std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| handler(&context)));
+            if result.is_err() {
+                log::error!(
+                    "Event handler panicked for: {}",
+                    context.relative_path.display()
+                );
+            }
+        }
+    }
+
+    /// Handle debounced events as one batch
+    ///
+    /// Changes are grouped by the command they resolve to, and each command runs
+    /// once with every changed file in {changed_files}. The other placeholders
+    /// describe the most recent change of the group.
+    ///
+    /// Returns whether the batch produced any file change.
+    fn handle_batch(&self, events: Vec<Event>) -> bool {
+        let changes: Vec<FileChange> = events
+            .into_iter()
+            .flat_map(|event| {
+                let changes = self.collect_changes(event);
+                self.record_stats_event(changes.is_empty());
+                changes
+            })
+            .collect();
+
+        let mut streamed = false;
+        for change in &changes {
+            self.dispatch_to_handlers(change);
+            streamed |= self.send_to_stream(change);
+        }
+        if streamed {
+            return true;
+        }
+
+        let mut groups: Vec<(&String, Vec<&FileChange>)> = Vec::new();
+        for change in &changes {
+            for template in self.commands_for(change) {
+                match groups
+                    .iter_mut()
+                    .find(|(existing, _)| *existing == template)
+                {
+                    Some((_, group)) => group.push(change),
+                    None => groups.push((template, vec![change])),
+                }
+            }
+        }
+
+        for (template, group) in groups {
+            let Some(latest) = group.last() else {
+                continue;
+            };
+
+            let mut relative_paths: Vec<&Path> = Vec::with_capacity(group.len());
+            for change in &group {
+                if !relative_paths.contains(&change.relative_path.as_path()) {
+                    relative_paths.push(&change.relative_path);
+                }
+            }
+
+            let context = latest
+                .template_context(self.display_root())
+                .with_changed_files(relative_paths);
+            let command = context.substitute_command(template, self.shell_quoting());
+            self.spawn_command(
+                template,
+                CommandRun {
+                    command,
+                    timeout: self.command_config.get_timeout_for_event(&latest.kind),
+                    dir: self.command_dir(Some(&latest.path)),
+                    env: self.command_env(&context),
+                    follow_up: self.follow_up(&context),
+                },
+            );
+        }
+
+        !changes.is_empty()
+    }
+
+    /// Filter and normalize an event into the file changes it represents
+    ///
+    /// Each change is logged and counted as it is collected. Events on the
+    /// watched root are handled right away and never produce a change.
+    fn collect_changes(&self, event: Event) -> Vec<FileChange> {
+        log::debug!(
+            "Raw event received: kind={:?}, paths={:?}",
+            event.kind,
+            event.paths
+        );
+
+        // Filter out events we don't care about (see --events)
+        if self.event_kinds.accepts(&event.kind) {
+            log::debug!("Event ACCEPTED by filter: {:?}", event.kind);
+        } else {
+            log::debug!("Event IGNORED by filter: {:?}", event.kind);
+            return Vec::new();
+        }
+
+        if !self.accepts_utf8(&event.paths) {
+            return Vec::new();
+        }
+
+        if Self::is_move(&event) {
+            return self
+                .move_change(&event.paths[0], &event.paths[1])
+                .into_iter()
+                .collect();
+        }
+
+        let mut changes = Vec::new();
+
+        let mut paths = event.paths;
+        if self.sort_paths {
+            paths.sort();
+        }
+
+        // Process each path in the event
+        for path in paths {
+            if path == self.watch_path {
+                self.handle_root_change(&path, &event.kind);
+                continue;
+            }
+
+            let path = self.resolve_symlinks(path);
+
+            if self.is_dir_modify_noise(&path, &event.kind) {
+                log::debug!("Ignoring directory modification: {}", path.display());
+                continue;
+            }
+
+            if self.is_below_min_size(&path, &event.kind) {
+                log::debug!("Skipping {} (smaller than --min-size)", path.display());
+                continue;
+            }
+
+            if let Some(relative_path) = self.get_relative_path(&path)
+                && self.should_watch(&relative_path)
+                && self.entries.allows(&path, &event.kind)
+            {
+                // Normalize event kinds for cross-platform consistency
+                let kind = match &event.kind {
+                    EventKind::Modify(notify::event::ModifyKind::Name(_)) => {
+                        // If the file no longer exists, treat this as a deletion
+                        if !path.exists() {
+                            EventKind::Remove(notify::event::RemoveKind::File)
+                        } else {
+                            event.kind
+                        }
+                    }
+                    EventKind::Access(notify::event::AccessKind::Close(
+                        notify::event::AccessMode::Write,
+                    )) => {
+                        // On Linux, inotify sends Access(Close(Write)) for file writes, treat as Modify
+                        EventKind::Modify(notify::event::ModifyKind::Data(
+                            notify::event::DataChange::Any,
+                        ))
+                    }
+                    _ => event.kind,
+                };
+
+                if self.is_unchanged_content(&path, &kind) {
+                    log::debug!("Skipping {} (content unchanged)", relative_path.display());
+                    continue;
+                }
+
+                let matched_pattern = self.matched_pattern(&relative_path);
+                let change = FileChange {
+                    path,
+                    relative_path,
+                    kind,
+                    old_path: None,
+                    time: chrono::Local::now(),
+                    matched_pattern,
+                };
+                self.log_file_change(&change);
+                self.record_event(&change.kind);
+                changes.push(change);
+            }
+        }
+
+        changes
+    }
+
+    /// Whether an event is a directory's contents or timestamps changing (with ignore-dir-modify)
+    fn is_dir_modify_noise(&self, path: &Path, kind: &EventKind) -> bool {
+        use notify::event::MetadataKind;
+
+        let noise = match kind {
+            EventKind::Modify(ModifyKind::Metadata(metadata)) => matches!(
+                metadata,
+                MetadataKind::Any | MetadataKind::WriteTime | MetadataKind::AccessTime
+            ),
+            EventKind::Modify(ModifyKind::Name(_)) => false,
+            EventKind::Modify(_) => true,
+            _ => false,
+        };
+        noise && self.ignore_dir_modify && path.is_dir()
+    }
+
+    /// Warn about paths that aren't valid UTF-8, returning whether to process them
+    ///
+    /// Placeholders and pattern matching see such a path with its invalid bytes
+    /// replaced by U+FFFD, so commands may get a file that doesn't exist.
+    fn accepts_utf8(&self, paths: &[PathBuf]) -> bool {
+        let mut accepted = true;
+        for path in paths.iter().filter(|path| is_lossy(path)) {
+            if self.strict_utf8 {
+                log::warn!("Skipping path that isn't valid UTF-8: {}", path.display());
+                accepted = false;
+            } else {
+                log::warn!(
+                    "Path isn't valid UTF-8, commands and patterns see it as: {}",
+                    path.display()
+                );
+            }
+        }
+        accepted
+    }
+
+    /// Whether a file is too small to report (with min-size)
+    fn is_below_min_size(&self, path: &Path, kind: &EventKind) -> bool {
+        let Some(min_size) = self.min_size else {
+            return false;
+        };
+        if matches!(kind, EventKind::Remove(_)) {
+            return false;
+        }
+        match std::fs::metadata(path) {
+            Ok(metadata) => metadata.is_file() && metadata.len() < min_size,
+            Err(e) => {
+                log::debug!("Could not read the size of {}: {}", path.display(), e);
+                false
+            }
+        }
+    }
+
+    /// Whether a modification left the file's content as last seen (with skip-unchanged)
+    ///
+    /// Also records the content of created files and forgets deleted ones. A
+    /// file that can't be read (e.g. deleted mid-check) is never skipped.
+    /// Files whose size changed since last seen are still hashed so the next
+    /// save has something to compare against, and files over
+    /// `MAX_HASHED_SIZE` are never hashed, so a large build artifact doesn't
+    /// hold up event handling.
+    fn is_unchanged_content(&self, path: &Path, kind: &EventKind) -> bool {
+        let Some(hashes) = &self.content_hashes else {
+            return false;
+        };
+        let lock = || hashes.lock().unwrap_or_else(|e| e.into_inner());
+
+        match kind {
+            EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_)) => {
+                lock().remove(path);
+                false
+            }
+            EventKind::Create(_) | EventKind::Modify(_) => {
+                let len = match std::fs::metadata(path) {
+                    Ok(metadata) if metadata.is_file() && metadata.len() <= MAX_HASHED_SIZE => {
+                        metadata.len()
+                    }
+                    Ok(_) => {
+                        lock().remove(path);
+                        return false;
+                    }
+                    Err(e) => {
+                        log::debug!("Could not read {} to hash it: {}", path.display(), e);
+                        lock().remove(path);
+                        return false;
+                    }
+                };
+
+                // Hashed without holding the lock
+                let hash = match hash_file(path) {
+                    Ok(hash) => hash,
+                    Err(e) => {
+                        log::debug!("Could not read {} to hash it: {}", path.display(), e);
+                        lock().remove(path);
+                        return false;
+                    }
+                };
+
+                let stamp = ContentStamp { len, hash };
+                let previous = lock().insert(path.to_path_buf(), stamp);
+                // A different size is a change whatever the hashes say
+                matches!(kind, EventKind::Modify(_)) && previous == Some(stamp)
+            }
+            _ => false,
+        }
+    }
+
+    /// Resolve a file moved within the watched tree
+    ///
+    /// The destination decides whether the move is watched. If only the source
+    /// matches the filters, the file effectively disappeared and is reported as
+    /// a deletion of the source. If only the destination matches, the move is
+    /// reported as a modification of the destination: editors that save
+    /// atomically (e.g. vim) write a temp file like `main.rs~` and rename it
+    /// over the real file, and for `--include '*.rs'` that rename is the save.
+    fn move_change(&self, from: &Path, to: &Path) -> Option<FileChange> {
+        let change = if let Some(relative_to) = self.get_relative_path(to)
+            && self.should_watch(&relative_to)
+            && self
+                .entries
+                .allows(to, &EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+        {
+            let from_watched = self
+                .get_relative_path(from)
+                .is_some_and(|relative_from| self.should_watch(&relative_from));
+            let (kind, old_path) = if from_watched {
+                (
+                    EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+                    Some(from.to_path_buf()),
+                )
+            } else {
+                log::debug!(
+                    "Treating rename of unwatched {} as a save of {}",
+                    from.display(),
+                    relative_to.display()
+                );
+                (
+                    EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)),
+                    None,
+                )
+            };
+            FileChange {
+                path: to.to_path_buf(),
+                matched_pattern: self.matched_pattern(&relative_to),
+                relative_path: relative_to,
+                kind,
+                old_path,
+                time: chrono::Local::now(),
+            }
+        } else if let Some(relative_from) = self.get_relative_path(from)
+            && self.should_watch(&relative_from)
+            && self
+                .entries
+                .allows(from, &EventKind::Remove(RemoveKind::Any))
+        {
+            FileChange {
+                path: from.to_path_buf(),
+                matched_pattern: self.matched_pattern(&relative_from),
+                relative_path: relative_from,
+                kind: EventKind::Remove(notify::event::RemoveKind::Any),
+                old_path: None,
+                time: chrono::Local::now(),
+            }
+        } else {
+            return None;
+        };
+
+        self.log_file_change(&change);
+        self.record_event(&change.kind);
+        Some(change)
+    }
+
+    /// Handle an event on the watched directory itself
+    ///
+    /// These have no meaningful relative path (e.g. the root's metadata changing),
+    /// so they bypass the filters and the regular commands. They are dropped
+    /// unless `on_root_change` is configured, which runs with `{relative_path}`
+    /// set to `.`.
+    fn handle_root_change(&self, path: &Path, event_kind: &EventKind) {
+        if self.command_config.on_root_change.is_empty() {
+            log::debug!("Ignoring event on watched root: {:?}", event_kind);
+            return;
+        }
+
+        let relative_path = Path::new(".");
+        if self.shows_events() {
+            self.output
+                .root_change(path, event_kind, self.name.as_deref());
+        }
+
+        let context = TemplateContext::new(path, relative_path, event_kind, self.display_root());
+        for command_template in &self.command_config.on_root_change {
+            let command = context.substitute_command(command_template, self.shell_quoting());
+            self.spawn_command(
+                command_template,
+                CommandRun {
+                    command,
+                    timeout: self.command_config.get_timeout_for_event(event_kind),
+                    dir: self.command_dir(None),
+                    env: self.command_env(&context),
+                    follow_up: self.follow_up(&context),
+                },
+            );
+        }
+    }
+
+    /// Success and failure hooks for a command run for `context`, if any are configured
+    fn follow_up(&self, context: &TemplateContext) -> Option<FollowUp> {
+        let config = &self.command_config;
+        if config.on_command_success.is_empty() && config.on_command_failure.is_empty() {
+            return None;
+        }
+
+        Some(FollowUp {
+            context: context.clone(),
+            on_success: config.on_command_success.clone(),
+            on_failure: config.on_command_failure.clone(),
+            quoting: self.shell_quoting(),
+        })
+    }
+
+    /// How placeholder values are quoted for the shell that runs commands
+    ///
+    /// None when commands are spawned as program and arguments, without any
+    /// shell: they are substituted per argument so paths with spaces stay whole.
+    fn shell_quoting(&self) -> Option<ShellQuoting> {
+        if self.persistent_shell.is_some() {
+            Some(ShellQuoting::Posix)
+        } else {
+            self.interpreter
+                .as_deref()
+                .map(ShellQuoting::for_interpreter)
+        }
+    }
+
+    /// Environment variables to pass to a command run for `context`
+    fn command_env(&self, context: &TemplateContext) -> Vec<(&'static str, String)> {
+        if self.command_config.export_env {
+            context.env_vars()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Directory to run a command in, for a change to `file_path` if any
+    ///
+    /// With `{dir_of_file}`, runs without a file (startup, root changes) use
+    /// the watched directory.
+    fn command_dir(&self, file_path: Option<&Path>) -> PathBuf {
+        match &self.working_dir {
+            WorkingDir::Path(dir) => dir.clone(),
+            WorkingDir::DirOfFile => file_path
+                .and_then(Path::parent)
+                .unwrap_or(&self.watch_path)
+                .to_path_buf(),
+        }
+    }
+
+    /// Count a processed file change when metrics or stats are enabled
+    fn record_event(&self, event_kind: &EventKind) {
+        if let Some(metrics) = &self.metrics {
+            metrics.record_event(event_kind);
+        }
+        if let Some(stats) = &self.stats {
+            stats.record_change(event_kind);
+        }
+    }
+
+    /// Whether an event arriving at `now` falls in the `exclude_writes_for` window
+    fn is_command_write(&self, now: Instant) -> bool {
+        self.exclude_writes
+            .as_ref()
+            .is_some_and(|(window, activity)| {
+                activity
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .covers(*window, now)
+            })
+    }
+
+    /// Whether `template` ran less than `min_interval` before `now`
+    ///
+    /// A run that is let through is recorded as the template's last run.
+    fn is_rate_limited(&self, template: &str, now: Instant) -> bool {
+        let Some((interval, last_runs)) = &self.min_interval else {
+            return false;
+        };
+
+        let mut last_runs = last_runs.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(last_run) = last_runs.get(template)
+            && now.duration_since(*last_run) < *interval
+        {
+            log::debug!(
+                "Skipping command, last run was less than {}ms ago: {}",
+                interval.as_millis(),
+                template
+            );
+            return true;
+        }
+
+        last_runs.insert(template.to_string(), now);
+        false
+    }
+
+    /// Count a received event for `--stats`
+    fn record_stats_event(&self, filtered: bool) {
+        if let Some(stats) = &self.stats {
+            stats.record_event(filtered);
+        }
+    }
+
+    /// Print the `--stats` summary line, even with `--quiet`
+    fn print_stats(&self) {
+        if let Some(stats) = &self.stats
+            && self.stats_interval.is_some()
+        {
+            self.output
+                .status(&stats.snapshot().to_string(), self.name.as_deref());
+        }
+    }
+
+    /// Print the `--summary-on-exit` report
+    fn print_summary(&self) {
+        if let Some(stats) = &self.stats
+            && self.summary_on_exit
+        {
+            self.status(&stats.summary().to_string());
+        }
+    }
+
+    /// Print the `--heartbeat` line
+    fn print_heartbeat(&self) {
+        if let Some(stats) = &self.stats {
+            let events = stats.snapshot().received;
+            self.status(&format!(
+                "💓 Still watching ({} {} so far)",
+                events,
+                if events == 1 { "event" } else { "events" }
+            ));
+        }
+    }
+
+    /// Update the pending events gauge when metrics are enabled
+    fn record_pending_events(&self, debouncer: &Debouncer, settler: Option<&Settler>) {
+        if let Some(metrics) = &self.metrics {
+            let settling = settler.map_or(0, Settler::pending_len);
+            metrics.set_pending_events(debouncer.pending_len() + settling);
+        }
+    }
+
+    /// Report this watcher's numbers to the `--status-file`, rewriting it when due
+    fn write_status(
+        &self,
+        debouncer: &Debouncer,
+        settler: Option<&Settler>,
+        force: bool,
+    ) -> Result<()> {
+        let (Some((status_file, slot)), Some(stats)) = (&self.status_file, &self.stats) else {
+            return Ok(());
+        };
+        let summary = stats.summary();
+        let status = WatcherStatus {
+            events: summary.stats.received,
+            changes: summary.changes(),
+            commands: summary.stats.commands,
+            failed_commands: summary.stats.failed,
+            last_event_at: stats.last_change(),
+            pending_events: debouncer.pending_len() + settler.map_or(0, Settler::pending_len),
+        };
+        status_file.update(*slot, status, Instant::now(), force)
+    }
+
+    /// Whether subdirectories of the root are watched too
+    fn watches_subdirectories(&self) -> bool {
+        self.recursive && self.file.is_none()
+    }
+
+    /// Whether directories are watched one by one to leave out excluded ones
+    fn prunes_directories(&self) -> bool {
+        !self.excluded_dirs.is_empty() && self.watches_subdirectories()
+    }
+
+    /// Warn, or fail with `WatchLimitMode::Error`, when the tree likely needs
+    /// more than `limit` inotify watches
+    fn check_watch_limit(&self, limit: usize) -> Result<()> {
+        if self.watch_limit == WatchLimitMode::Off || !self.watches_subdirectories() {
+            return Ok(());
+        }
+        let excluded: &[OsString] = if self.prunes_directories() {
+            &self.excluded_dirs
+        } else {
+            &[]
+        };
+        let Some(problem) = watch_limit::check(&self.watch_path, excluded, limit) else {
+            return Ok(());
+        };
+
+        if self.watch_limit == WatchLimitMode::Error {
+            anyhow::bail!(problem);
+        }
+        log::warn!("{}", problem);
+        Ok(())
+    }
+
+    /// Register the watches covering the watched directory
+    fn register_watches(&self, watcher: &mut dyn Watcher) -> notify::Result<()> {
+        if !self.prunes_directories() {
+            let mode = if self.watches_subdirectories() {
+                RecursiveMode::Recursive
+            } else {
+                RecursiveMode::NonRecursive
+            };
+            return watcher.watch(&self.watch_path, mode);
+        }
+
+        watcher.watch(&self.watch_path, RecursiveMode::NonRecursive)?;
+        let watched = 1 + self.watch_subdirectories(watcher, &self.watch_path);
+        log::info!(
+            "Watching {} directories, skipping {:?} (--exclude-dir)",
+            watched,
+            self.excluded_dirs
+        );
+        Ok(())
+    }
+
+    /// Watch every directory below `dir` except excluded ones and their subtrees
+    ///
+    /// Returns how many directories were watched. Symlinked directories are not
+    /// entered, so links can't make this loop forever. Directories that can't
+    /// be read or disappear meanwhile are skipped.
+    fn watch_subdirectories(&self, watcher: &mut dyn Watcher, dir: &Path) -> usize {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                log::debug!("Could not list {}: {}", dir.display(), e);
+                return 0;
+            }
+        };
+
+        let mut watched = 0;
+        for entry in entries.flatten() {
+            if !entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                continue;
+            }
+            let path = entry.path();
+            if self.excluded_dirs.contains(&entry.file_name()) {
+                log::debug!("Not watching excluded directory {}", path.display());
+                continue;
+            }
+            match watcher.watch(&path, RecursiveMode::NonRecursive) {
+                Ok(()) => watched += 1 + self.watch_subdirectories(watcher, &path),
+                Err(e) => log::debug!("Could not watch {}: {}", path.display(), e),
+            }
+        }
+        watched
+    }
+
+    /// Watch directories created in or moved into the tree, unless excluded
+    fn watch_new_directories(&self, watcher: &mut dyn Watcher, event: &Event) {
+        if !matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_))
+        ) {
+            return;
+        }
+
+        for path in &event.paths {
+            let Some(relative_path) = self.get_relative_path(path) else {
+                continue;
+            };
+            if relative_path.as_os_str().is_empty()
+                || self.in_excluded_dir(&relative_path)
+                || !path
+                    .symlink_metadata()
+                    .is_ok_and(|metadata| metadata.is_dir())
+            {
+                continue;
+            }
+            match watcher.watch(path, RecursiveMode::NonRecursive) {
+                Ok(()) => {
+                    let watched = 1 + self.watch_subdirectories(watcher, path);
+                    log::debug!("Watching {} new directories at {}", watched, path.display());
+                }
+                Err(e) => log::debug!("Could not watch {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    /// Whether a relative path is, or is inside, an excluded directory
+    fn in_excluded_dir(&self, relative_path: &Path) -> bool {
+        relative_path.components().any(|component| {
+            self.excluded_dirs
+                .iter()
+                .any(|name| name == component.as_os_str())
+        })
+    }
+
+    /// Check a relative path against the watched file, the depth limit and the patterns
+    fn should_watch(&self, relative_path: &Path) -> bool {
+        if let Some(reason) = self.path_rejection(relative_path) {
+            log::debug!("Ignoring {} ({})", relative_path.display(), reason);
+            return false;
+        }
+
+        self.filter.should_watch(relative_path)
+    }
+
+    /// Why a path is ignored before its patterns are checked, if it is
+    fn path_rejection(&self, relative_path: &Path) -> Option<String> {
+        if let Some(file) = &self.file
+            && relative_path != file
+        {
+            return Some(format!("only {} is watched", file.display()));
+        }
+
+        // Events can still come from an excluded directory itself, or with a
+        // backend that doesn't prune, e.g. a directory created after startup
+        if self.in_excluded_dir(relative_path) {
+            return Some("in an --exclude-dir".to_string());
+        }
+
+        if let Some(max_depth) = self.max_depth {
+            // A file directly in the root has one component and depth 0
+            let depth = relative_path.components().count().saturating_sub(1);
+            if depth > max_depth {
+                return Some(format!("deeper than --max-depth {}", max_depth));
+            }
+        }
+
+        None
+    }
+
+    /// Explain in one line whether changes to `path` would be handled, and why
+    ///
+    /// Relative paths are taken relative to the watched directory, like the
+    /// paths patterns are matched against. Only path rules are considered, not
+    /// event-specific ones like `--min-size`.
+    pub fn explain(&self, path: &Path) -> String {
+        let relative_path = if path.is_absolute() {
+            let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+            match self.get_relative_path(&path) {
+                Some(relative_path) => relative_path,
+                None => {
+                    return format!(
+                        "{}: ignored (outside the watched directory {})",
+                        path.display(),
+                        self.watch_path.display()
+                    );
+                }
+            }
+        } else {
+            path.to_path_buf()
+        };
+
+        if let Some(reason) = self.path_rejection(&relative_path) {
+            return format!("{}: ignored ({})", relative_path.display(), reason);
+        }
+
+        let decision = self.filter.explain(&relative_path);
+        format!(
+            "{}: {} ({})",
+            relative_path.display(),
+            if decision.is_watched() {
+                "watched"
+            } else {
+                "ignored"
+            },
+            decision
+        )
+    }
+
+    /// Include pattern a watched path matched, for {matched_pattern}
+    fn matched_pattern(&self, relative_path: &Path) -> Option<String> {
+        self.filter
+            .matched_include(relative_path)
+            .map(str::to_string)
+    }
+
+    /// Resolve a symlinked path to its target when following symlinks
+    fn resolve_symlinks(&self, path: PathBuf) -> PathBuf {
+        if !self.follow_symlinks {
+            return path;
+        }
+
+        // Deleted files can't be resolved; a target outside the root must not
+        // escape it, so both keep the reported path
+        match path.canonicalize() {
+            Ok(target) if target.starts_with(&self.watch_path) => target,
+            _ => path,
+        }
+    }
+
+    /// Get relative path from the watch directory
+    fn get_relative_path(&self, path: &Path) -> Option<PathBuf> {
+        path.strip_prefix(&self.watch_path)
+            .ok()
+            .map(|p| p.to_path_buf())
+    }
+
+    /// Report a file change in the configured output format
+    fn log_file_change(&self, change: &FileChange) {
+        output::record_event(&change.relative_path, &change.kind);
+        if self.shows_events() {
+            match self.formatted_change(change) {
+                Some(line) => self.output.formatted_event(&line, self.name.as_deref()),
+                None => self.output.file_event(
+                    &change.relative_path,
+                    &self.display_root().join(&change.relative_path),
+                    change.old_path.as_deref(),
+                    &change.kind,
+                    self.color,
+                    self.name.as_deref(),
+                ),
+            }
+        }
+        log::debug!(
+            "File event: {:?} - {}",
+            change.kind,
+            change.relative_path.display()
+        );
+    }
+
+    /// The line for a file change with a custom format, None for the default line
+    fn formatted_change(&self, change: &FileChange) -> Option<String> {
+        let format = self
+            .format
+            .as_deref()
+            .filter(|_| self.output == Output::Text)?;
+        Some(
+            change
+                .template_context(self.display_root())
+                .substitute_template(format),
+        )
+    }
+
+    /// Whether file changes are printed
+    ///
+    /// Quiet mode drops the human-readable event lines, but JSON lines are
+    /// data rather than decoration and are always printed.
+    fn shows_events(&self) -> bool {
+        !self.quiet || self.output == Output::Json
+    }
+
+    /// Print a status message such as the startup banner, unless quiet
+    fn status(&self, message: &str) {
+        if !self.quiet {
+            self.output.status(message, self.name.as_deref());
+        }
+    }
+
+    /// Execute command for a file change if configured
+    fn execute_command_for_event(&self, change: &FileChange) {
+        let context = change.template_context(self.display_root());
+        let dir = self.command_dir(Some(&change.path));
+        self.execute_command_with_context(context, self.commands_for(change), &change.kind, dir);
+    }
+
+    /// Execute an event's commands using a prepared template context
+    ///
+    /// Each command is spawned in the order it was configured. Events with
+    /// commands are numbered, so every command of one event sees the same {count}.
+    fn execute_command_with_context(
+        &self,
+        context: TemplateContext,
+        commands: &[String],
+        event_kind: &EventKind,
+        dir: PathBuf,
+    ) {
+        if commands.is_empty() {
+            return;
+        }
+        let context = context.with_count(self.event_count.fetch_add(1, Ordering::Relaxed));
+
+        for command_template in commands {
+            let command = context.substitute_command(command_template, self.shell_quoting());
+            let run = CommandRun {
+                command,
+                timeout: self.command_config.get_timeout_for_event(event_kind),
+                dir: dir.clone(),
+                env: self.command_env(&context),
+                follow_up: self.follow_up(&context),
+            };
+            let run = match &self.coalescer {
+                Some(coalescer) => match coalescer.hold(command_template, run, Instant::now()) {
+                    Some(run) => run,
+                    None => continue,
+                },
+                None => run,
+            };
+            if self.is_rate_limited(command_template, Instant::now()) {
+                continue;
+            }
+            self.spawn_command(command_template, run);
+        }
+    }
+
+    /// Run the coalesced commands whose window has closed (with coalesce-global)
+    ///
+    /// At shutdown, `flush` runs every held command right away.
+    fn run_coalesced_commands(&self, now: Instant, flush: bool) {
+        let Some(coalescer) = &self.coalescer else {
+            return;
+        };
+        let ready = if flush {
+            coalescer.drain()
+        } else {
+            coalescer.ready(now)
+        };
+        for (template, run) in ready {
+            if !self.is_rate_limited(&template, now) {
+                self.spawn_command(&template, run);
+            }
+        }
+    }
+
+    /// The queue of the task running commands in order, with `sequential`
+    ///
+    /// The task is started on first use, as it needs the runtime.
+    fn sequential_queue(&self) -> Option<&mpsc::UnboundedSender<CommandTask>> {
+        self.sequential.as_ref().map(|queue| {
+            queue.get_or_init(|| {
+                let (tx, mut rx) = mpsc::unbounded_channel::<CommandTask>();
+                tokio::spawn(async move {
+                    while let Some(task) = rx.recv().await {
+                        task.await;
+                    }
+                });
+                tx
+            })
+        })
+    }
+
+    /// Run an already substituted command in the background and report its result
+    ///
+    /// With `--no-overlap`, a command whose template is still running is dropped
+    /// or queued instead of being spawned concurrently. With `sequential`, it
+    /// waits for every earlier command, including their retries and hooks.
+    fn spawn_command(&self, template: &str, run: CommandRun) {
+        if let Some(restarter) = &self.restarter {
+            self.restart_command(restarter, template, run);
+            return;
+        }
+
+        let run = match &self.overlap {
+            Some(overlap) => match overlap.admit(template, run) {
+                Some(run) => run,
+                None => return,
+            },
+            None => run,
+        };
+
+        let template = template.to_string();
+        let overlap = self.overlap.clone();
+        let concurrency = self.concurrency.clone();
+        let activity = self
+            .exclude_writes
+            .as_ref()
+            .map(|(_, activity)| Arc::clone(activity));
+        let quiet = self.quiet;
+        let persistent_shell = self.persistent_shell.clone();
+        let interpreter = self.interpreter.clone();
+        let metrics = self.metrics.clone();
+        let result_log = self.result_log.clone();
+        let stats = self.stats.clone();
+        let output = self.output;
+        let name = self.name.clone();
+        let max_output_lines = self.max_output_lines;
+        let exec_delay = self.exec_delay;
+        let missing_programs = Arc::clone(&self.missing_programs);
+        let failure_tx = self.failure_tx.clone();
+        let retries = self.command_config.retries;
+        let retry_delay = Duration::from_millis(self.command_config.retry_delay_ms);
+        let retry_timeouts = self.command_config.retry_timeouts;
+
+        // Execute command asynchronously
+        let task = async move {
+            let recorders = RunRecorders {
+                metrics: metrics.as_deref(),
+                result_log: result_log.as_deref(),
+            };
+            let display = RunDisplay {
+                output,
+                name: name.as_deref(),
+                quiet,
+                max_output_lines,
+                missing_programs: Some(&missing_programs),
+            };
+            let mut next = Some(run);
+            while let Some(run) = next {
+                if let Some(delay) = exec_delay {
+                    log::debug!(
+                        "Waiting {}ms before running (--exec-delay): {}",
+                        delay.as_millis(),
+                        run.command
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+
+                // Held until the run finishes; the semaphore is never closed
+                let _permit = match &concurrency {
+                    Some(semaphore) => {
+                        if semaphore.available_permits() == 0 {
+                            log::debug!(
+                                "Waiting for a free command slot (--max-concurrent): {}",
+                                run.command
+                            );
+                        }
+                        semaphore.acquire().await.ok()
+                    }
+                    None => None,
+                };
+
+                if let Some(activity) = &activity {
+                    activity.lock().unwrap_or_else(|e| e.into_inner()).started();
+                }
+
+                let started = Instant::now();
+                let mut outcome = Self::run_and_report(
+                    &run,
+                    interpreter.as_deref(),
+                    persistent_shell.as_ref(),
+                    recorders,
+                    display,
+                )
+                .await;
+
+                let mut attempt = 0;
+                while attempt < retries && outcome.should_retry(retry_timeouts) {
+                    attempt += 1;
+                    log::warn!(
+                        "Retrying command in {}ms (retry {}/{}): {}",
+                        retry_delay.as_millis(),
+                        attempt,
+                        retries,
+                        run.command
+                    );
+                    tokio::time::sleep(retry_delay).await;
+                    outcome = Self::run_and_report(
+                        &run,
+                        interpreter.as_deref(),
+                        persistent_shell.as_ref(),
+                        recorders,
+                        display,
+                    )
+                    .await;
+                }
+
+                let succeeded = outcome == RunOutcome::Succeeded;
+                let duration = started.elapsed();
+                if attempt > 0 {
+                    if succeeded {
+                        log::info!(
+                            "Command succeeded after {} retries: {}",
+                            attempt,
+                            run.command
+                        );
+                    } else {
+                        log::error!("Command failed after {} retries: {}", attempt, run.command);
+                    }
+                }
+
+                // Hooks run right after the command; they have no hooks of their own
+                if let Some(follow_up) = &run.follow_up {
+                    for command in follow_up.commands(outcome) {
+                        let hook = CommandRun {
+                            command,
+                            timeout: run.timeout,
+                            dir: run.dir.clone(),
+                            env: run.env.clone(),
+                            follow_up: None,
+                        };
+                        Self::run_and_report(
+                            &hook,
+                            interpreter.as_deref(),
+                            persistent_shell.as_ref(),
+                            recorders,
+                            display,
+                        )
+                        .await;
+                    }
+                }
+
+                if let Some(activity) = &activity {
+                    activity
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .finished(Instant::now());
+                }
+
+                if let Some(stats) = &stats {
+                    stats.record_command(succeeded, duration);
+                }
+
+                if !succeeded && let Some(failure_tx) = &failure_tx {
+                    // The loop may already be shutting down; nothing left to notify then
+                    let _ = failure_tx.send(run.command);
+                }
+
+                // Start the run queued while this one was active, if any
+                next = overlap
+                    .as_ref()
+                    .and_then(|overlap| overlap.finish(&template));
+            }
+        };
+
+        let task: CommandTask = match self.sequential_queue() {
+            Some(queue) => {
+                // The queue runs the command; what's left is waiting for it
+                let (done_tx, done_rx) = tokio::sync::oneshot::channel();
+                let queued: CommandTask = Box::pin(async move {
+                    task.await;
+                    let _ = done_tx.send(());
+                });
+                if queue.send(queued).is_err() {
+                    log::error!("Sequential command queue stopped, command dropped");
+                }
+                if self.in_flight.is_none() {
+                    return;
+                }
+                Box::pin(async move {
+                    let _ = done_rx.await;
+                })
+            }
+            None => Box::pin(task),
+        };
+
+        match &self.in_flight {
+            Some(in_flight) => {
+                in_flight
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .spawn(task);
+            }
+            None => {
+                tokio::spawn(task);
+            }
+        }
+    }
+
+    /// Replace the running process of a command with a fresh one (restart mode)
+    fn restart_command(&self, restarter: &Restarter, template: &str, run: CommandRun) {
+        let argv = match self.restart_argv(&run.command) {
+            Ok(argv) => argv,
+            Err(e) => {
+                log::error!("Failed to execute command '{}': {:#}", run.command, e);
+                return;
+            }
+        };
+        let Some((program, args)) = argv.split_first() else {
+            return;
+        };
+
+        let mut process = TokioCommand::new(program);
+        process
+            .args(args)
+            .current_dir(&run.dir)
+            .envs(run.env.iter().map(|(name, value)| (name, value)));
+        if self.quiet {
+            process.stdout(Stdio::null()).stderr(Stdio::null());
+        }
+
+        self.output
+            .command_started(&run.command, self.name.as_deref());
+        restarter.restart(template, run.command, process);
+    }
+
+    /// Program and arguments for a command in restart mode
+    fn restart_argv(&self, command: &str) -> Result<Vec<String>> {
+        if let Some(interpreter) = &self.interpreter {
+            let mut argv = interpreter.clone();
+            argv.push(command.to_string());
+            return Ok(argv);
+        }
+        match split_chain(command).as_slice() {
+            [] => anyhow::bail!("Empty command"),
+            [(_, part)] => shell_words::split(part).context("Failed to parse command"),
+            _ => anyhow::bail!("Chaining commands with && or ; in --restart mode needs --shell"),
+        }
+    }
+
+    /// Run a command and report its output and exit status
+    async fn run_and_report(
+        run: &CommandRun,
+        interpreter: Option<&[String]>,
+        persistent_shell: Option<&SharedShell>,
+        recorders: RunRecorders<'_>,
+        display: RunDisplay<'_>,
+    ) -> RunOutcome {
+        let RunDisplay { output, name, .. } = display;
+        let command = run.command.as_str();
+        output.command_started(command, name);
+        log::info!(target: output::RECORD_TARGET, "Executing command: {}", command);
+
+        // Output is printed line by line while the command runs, unless quiet
+        let sink = (!display.quiet)
+            .then(|| Arc::new(LineSink::new(output, name, display.max_output_lines)));
+
+        let started = Instant::now();
+        let result = Self::run_command(
+            command,
+            interpreter,
+            run.timeout,
+            persistent_shell,
+            Some(&run.dir),
+            &run.env,
+            sink.as_ref(),
+        )
+        .await;
+        if let Some(sink) = &sink {
+            sink.finish();
+        }
+
+        let success = matches!(&result, Ok(output) if output.status.success());
+        if let Some(metrics) = recorders.metrics {
+            metrics.record_command(started.elapsed(), success);
+        }
+        if let Some(result_log) = recorders.result_log {
+            result_log.record(command, &result, started.elapsed());
+        }
+
+        match &result {
+            Ok(command_output) => {
+                log::debug!("Command executed successfully");
+
+                // In quiet mode, still log the output at debug level
+                if display.quiet {
+                    if !command_output.stdout.is_empty() {
+                        log::debug!(
+                            "Command stdout: {}",
+                            String::from_utf8_lossy(&command_output.stdout)
+                        );
+                    }
+                    if !command_output.stderr.is_empty() {
+                        log::debug!(
+                            "Command stderr: {}",
+                            String::from_utf8_lossy(&command_output.stderr)
+                        );
+                    }
+                }
+            }
+            Err(e) => match e.downcast_ref::<CommandNotFound>() {
+                // Every event fails the same way, so only the first one is logged loudly
+                Some(CommandNotFound(program))
+                    if display.missing_programs.is_some_and(|missing| {
+                        !missing
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .insert(program.clone())
+                    }) =>
+                {
+                    log::debug!("Failed to execute command '{}': {}", command, e);
+                }
+                Some(_) => log::warn!("{} (check the spelling or PATH)", e),
+                None => log::error!("Failed to execute command '{}': {}", command, e),
+            },
+        }
+
+        // Log command completion with exit code
+        output.command_finished(command, &result, name);
+        output::record_command(command, &result);
+
+        match &result {
+            _ if success => RunOutcome::Succeeded,
+            Ok(output) => RunOutcome::Failed(output.status.code()),
+            Err(e) if e.is::<CommandTimedOut>() => RunOutcome::TimedOut,
+            Err(_) => RunOutcome::Failed(None),
+        }
+    }
+
+    /// Run a command through the persistent shell when enabled, spawning it otherwise
+    ///
+    /// When the persistent shell can't be started or sent the command, the command
+    /// is spawned directly instead. Once the shell has it, the command is never
+    /// run a second time: if the shell dies mid-command, the run fails.
+    async fn run_command(
+        command: &str,
+        interpreter: Option<&[String]>,
+        timeout: Option<Duration>,
+        persistent_shell: Option<&SharedShell>,
+        dir: Option<&Path>,
+        env: &[(&str, String)],
+        sink: Option<&Arc<LineSink>>,
+    ) -> Result<std::process::Output> {
+        if let Some(shell) = persistent_shell {
+            // The shell keeps its cwd and variables between commands, so set both
+            // each time, clearing the previous event's variables first
+            let mut shell_command = format!("unset {}; ", ENV_VAR_NAMES.join(" "));
+            for (name, value) in env {
+                shell_command.push_str(&format!("export {}={}; ", name, shell_words::quote(value)));
+            }
+            if let Some(dir) = dir {
+                shell_command.push_str(&format!(
+                    "cd {} && ",
+                    shell_words::quote(&dir.to_string_lossy())
+                ));
+            }
+            shell_command.push_str(command);
+
+            match shell.execute(&shell_command, timeout).await {
+                ShellOutcome::Completed(output) => {
+                    // The shell hands back output once the command is done
+                    if let Some(sink) = sink {
+                        sink.replay(&output);
+                    }
+                    return Ok(output);
+                }
+                ShellOutcome::TimedOut => {
+                    let limit = timeout.unwrap_or_default();
+                    log::error!(
+                        "Command '{}' timed out after {}s, killing persistent shell",
+                        command,
+                        limit.as_secs()
+                    );
+                    return Err(CommandTimedOut(limit).into());
+                }
+                ShellOutcome::Unavailable(e) => {
+                    log::warn!(
+                        "Persistent shell failed ({:#}), spawning command directly",
+                        e
+                    );
+                }
+                ShellOutcome::Lost(e) => {
+                    log::error!(
+                        "Persistent shell stopped while running '{}', restarting it for the next command",
+                        command
+                    );
+                    return Err(e.context("Persistent shell stopped while running the command"));
+                }
+            }
+        }
+
+        Self::execute_shell_command(command, interpreter, timeout, dir, env, sink).await
+    }
+
+    /// Execute a shell command asynchronously
+    ///
+    /// When a timeout is given, the child process is killed if it does not
+    /// finish in time and an error is returned. Without `dir` the command
+    /// inherits vibewatch's working directory; `env` is added to its environment.
+    /// With a `sink`, output lines are passed to it as they arrive; they are
+    /// collected into the returned output either way.
+    async fn execute_shell_command(
+        command: &str,
+        interpreter: Option<&[String]>,
+        timeout: Option<Duration>,
+        dir: Option<&Path>,
+        env: &[(&str, String)],
+        sink: Option<&Arc<LineSink>>,
+    ) -> Result<std::process::Output> {
+        log::debug!("Executing shell command: {}", command);
+
+        let parts = match interpreter {
+            // Hand the whole command to the interpreter so pipes and && work
+            Some(interpreter) => {
+                if command.trim().is_empty() {
+                    anyhow::bail!("Empty command");
+                }
+                let mut parts = interpreter.to_vec();
+                parts.push(command.to_string());
+                parts
+            }
+            None => match split_chain(command).as_slice() {
+                [] => anyhow::bail!("Empty command"),
+                // Parse command with proper quote handling
+                [(_, part)] => shell_words::split(part).context("Failed to parse command")?,
+                chain => {
+                    return Self::execute_chain(command, chain, timeout, dir, env, sink).await;
+                }
+            },
+        };
+
+        Self::run_process(command, &parts, timeout, dir, env, sink).await
+    }
+
+    /// Run the parts of a chained command one after another, without a shell
+    ///
+    /// Like a shell, a part after `&&` is skipped when the last part that ran
+    /// failed, and a part after `;` always runs. Output is collected from every
+    /// part, the exit status is that of the last part that ran, and the
+    /// timeout covers the whole chain.
+    async fn execute_chain(
+        command: &str,
+        chain: &[(Chain, &str)],
+        timeout: Option<Duration>,
+        dir: Option<&Path>,
+        env: &[(&str, String)],
+        sink: Option<&Arc<LineSink>>,
+    ) -> Result<std::process::Output> {
+        let run = async {
+            let mut combined: Option<std::process::Output> = None;
+            for &(link, part) in chain {
+                if link == Chain::OnSuccess
+                    && combined
+                        .as_ref()
+                        .is_some_and(|output| !output.status.success())
+                {
+                    log::debug!("Skipping '{}' after a failed command", part);
+                    continue;
+                }
+
+                let parts = shell_words::split(part).context("Failed to parse command")?;
+                let output = Self::run_process(part, &parts, None, dir, env, sink).await?;
+                combined = Some(match combined {
+                    Some(mut combined) => {
+                        combined.stdout.extend(output.stdout);
+                        combined.stderr.extend(output.stderr);
+                        combined.status = output.status;
+                        combined
+                    }
+                    None => output,
+                });
+            }
+            combined.context("Empty command")
+        };
+
+        let Some(limit) = timeout else {
+            return run.await;
+        };
+        // Dropping the chain on timeout kills the part that is running
+        match tokio::time::timeout(limit, run).await {
+            Ok(result) => result,
+            Err(_) => {
+                log::error!(
+                    "Command '{}' timed out after {}s, killing it",
+                    command,
+                    limit.as_secs()
+                );
+                Err(CommandTimedOut(limit).into())
+            }
+        }
+    }
+
+    /// Spawn `parts` as program and arguments and collect its output
+    ///
+    /// `command` is only used in log messages.
+    async fn run_process(
+        command: &str,
+        parts: &[String],
+        timeout: Option<Duration>,
+        dir: Option<&Path>,
+        env: &[(&str, String)],
+        sink: Option<&Arc<LineSink>>,
+    ) -> Result<std::process::Output> {
+        let Some((program, args)) = parts.split_first() else {
+            anyhow::bail!("Empty command");
+        };
+
+        let mut process = TokioCommand::new(program);
+        if let Some(dir) = dir {
+            process.current_dir(dir);
+        }
+        process.envs(env.iter().map(|(name, value)| (name, value)));
+
+        // kill_on_drop also terminates the child if this future is dropped early
+        let mut child = match process
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+        {
+            Ok(child) => child,
+            // A missing working directory is reported as not found too
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && dir.is_none_or(Path::is_dir) => {
+                return Err(CommandNotFound(program.clone()).into());
+            }
+            Err(e) => return Err(anyhow::Error::new(e).context("Failed to execute command")),
+        };
+
+        // Drain the pipes in the background so the child never blocks on a full pipe
+        let stdout = Self::read_pipe(child.stdout.take(), sink.cloned(), false);
+        let stderr = Self::read_pipe(child.stderr.take(), sink.cloned(), true);
+
+        let status = match timeout {
+            None => child.wait().await.context("Failed to execute command")?,
+            Some(limit) => match tokio::time::timeout(limit, child.wait()).await {
+                Ok(status) => status.context("Failed to execute command")?,
+                Err(_) => {
+                    log::error!(
+                        "Command '{}' timed out after {}s, killing it",
+                        command,
+                        limit.as_secs()
+                    );
+                    if let Err(e) = child.kill().await {
+                        log::warn!("Failed to kill timed out command '{}': {}", command, e);
+                    }
+                    return Err(CommandTimedOut(limit).into());
+                }
+            },
+        };
+
+        let output = std::process::Output {
+            status,
+            stdout: stdout.await.context("Failed to read command output")??,
+            stderr: stderr.await.context("Failed to read command output")??,
+        };
+
+        // Return output regardless of exit code - caller will check status
+        Ok(output)
+    }
+
+    /// Read a child pipe to the end on a background task
+    ///
+    /// With a `sink`, every line is also passed to it as soon as it is read.
+    fn read_pipe<R>(
+        pipe: Option<R>,
+        sink: Option<Arc<LineSink>>,
+        stderr: bool,
+    ) -> tokio::task::JoinHandle<std::io::Result<Vec<u8>>>
+    where
+        R: tokio::io::AsyncRead + Unpin + Send + 'static,
+    {
+        tokio::spawn(async move {
+            let mut buffer = Vec::new();
+            let Some(mut pipe) = pipe else {
+                return Ok(buffer);
+            };
+            let Some(sink) = sink else {
+                pipe.read_to_end(&mut buffer).await?;
+                return Ok(buffer);
+            };
+
+            let mut reader = tokio::io::BufReader::new(pipe);
+            loop {
+                let start = buffer.len();
+                if reader.read_until(b'\n', &mut buffer).await? == 0 {
+                    return Ok(buffer);
+                }
+                sink.line(&buffer[start..], stderr);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, MetadataKind, ModifyKind, RemoveKind};
+    use rstest::rstest;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    // Parameterized tests for CommandConfig - testing command resolution for different event types
+    #[rstest]
+    // Create event tests
+    #[case(
+        Some("create_cmd"),
+        None,
+        None,
+        Some("fallback"),
+        EventKind::Create(CreateKind::File),
+        Some("create_cmd")
+    )]
+    #[case(
+        None,
+        None,
+        None,
+        Some("fallback"),
+        EventKind::Create(CreateKind::File),
+        Some("fallback")
+    )]
+    #[case(
+        Some("create_cmd"),
+        None,
+        None,
+        None,
+        EventKind::Create(CreateKind::Folder),
+        Some("create_cmd")
+    )]
+    // Modify event tests
+    #[case(
+        None,
+        Some("modify_cmd"),
+        None,
+        Some("fallback"),
+        EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)),
+        Some("modify_cmd")
+    )]
+    #[case(
+        None,
+        None,
+        None,
+        Some("fallback"),
+        EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)),
+        Some("fallback")
+    )]
+    #[case(
+        None,
+        Some("modify_cmd"),
+        None,
+        None,
+        EventKind::Modify(ModifyKind::Name(notify::event::RenameMode::Any)),
+        Some("modify_cmd")
+    )]
+    // Delete event tests
+    #[case(
+        None,
+        None,
+        Some("delete_cmd"),
+        Some("fallback"),
+        EventKind::Remove(RemoveKind::File),
+        Some("delete_cmd")
+    )]
+    #[case(
+        None,
+        None,
+        None,
+        Some("fallback"),
+        EventKind::Remove(RemoveKind::File),
+        Some("fallback")
+    )]
+    #[case(
+        None,
+        None,
+        Some("delete_cmd"),
+        None,
+        EventKind::Remove(RemoveKind::Folder),
+        Some("delete_cmd")
+    )]
+    // No command configured
+    #[case(None, None, None, None, EventKind::Create(CreateKind::File), None)]
+    #[case(None, None, None, None, EventKind::Modify(ModifyKind::Any), None)]
+    #[case(None, None, None, None, EventKind::Remove(RemoveKind::File), None)]
+    // Other event types use fallback
+    #[case(
+        Some("create_cmd"),
+        None,
+        None,
+        Some("fallback"),
+        EventKind::Access(notify::event::AccessKind::Any),
+        Some("fallback")
+    )]
+    #[case(
+        None,
+        Some("modify_cmd"),
+        None,
+        Some("fallback"),
+        EventKind::Any,
+        Some("fallback")
+    )]
+    fn test_command_config_resolution(
+        #[case] on_create: Option<&str>,
+        #[case] on_modify: Option<&str>,
+        #[case] on_delete: Option<&str>,
+        #[case] on_change: Option<&str>,
+        #[case] event: EventKind,
+        #[case] expected: Option<&str>,
+    ) {
+        let config = CommandConfig {
+            on_create: on_create.map(String::from).into_iter().collect(),
+            on_modify: on_modify.map(String::from).into_iter().collect(),
+            on_delete: on_delete.map(String::from).into_iter().collect(),
+            on_change: on_change.map(String::from).into_iter().collect(),
+            ..Default::default()
+        };
+
+        let result = config.get_command_for_event(&event);
+        let expected: Vec<&str> = expected.into_iter().collect();
+        assert_eq!(
+            result, expected,
+            "Config({:?}, {:?}, {:?}, {:?}) with event {:?} should return {:?}",
+            on_create, on_modify, on_delete, on_change, event, expected
+        );
+    }
+
+    // Test TemplateContext with parameterized event types
+    #[rstest]
+    #[case(
+        "/tmp/test/src/main.rs",
+        "src/main.rs",
+        EventKind::Create(CreateKind::File),
+        "create",
+        "/tmp/test/src/main.rs"
+    )]
+    #[case(
+        "/tmp/test/file.txt",
+        "file.txt",
+        EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)),
+        "modify",
+        "/tmp/test/file.txt"
+    )]
+    #[case(
+        "/tmp/test/file.txt",
+        "file.txt",
+        EventKind::Remove(RemoveKind::File),
+        "delete",
+        "/tmp/test/file.txt"
+    )]
+    #[case(
+        "/tmp/test/file.txt",
+        "file.txt",
+        EventKind::Access(notify::event::AccessKind::Any),
+        "change",
+        "/tmp/test/file.txt"
+    )]
+    fn test_template_context_event_types(
+        #[case] file_path_str: &str,
+        #[case] relative_path_str: &str,
+        #[case] event: EventKind,
+        #[case] expected_event_type: &str,
+        #[case] expected_absolute: &str,
+    ) {
+        let file_path = PathBuf::from(file_path_str);
+        let relative_path = PathBuf::from(relative_path_str);
+        let watch_path = PathBuf::from("/tmp/test");
+
+        let ctx = TemplateContext::new(&file_path, &relative_path, &event, &watch_path);
+
+        assert_eq!(ctx.file_path, file_path_str);
+        assert_eq!(ctx.relative_path, relative_path_str);
+        assert_eq!(ctx.event_type, expected_event_type);
+        assert_eq!(ctx.absolute_path, expected_absolute);
+    }
+
+    #[test]
+    fn test_template_substitution_all_variables() {
+        let file_path = PathBuf::from("/home/user/project/src/lib.rs");
+        let relative_path = PathBuf::from("src/lib.rs");
+        let watch_path = PathBuf::from("/home/user/project");
+        let event = EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any));
+
+        let ctx = TemplateContext::new(&file_path, &relative_path, &event, &watch_path);
+
+        let template = "Event: {event_type}, File: {file_path}, Relative: {relative_path}, Absolute: {absolute_path}";
+        let result = ctx.substitute_template(template);
+
+        assert_eq!(
+            result,
+            "Event: modify, File: /home/user/project/src/lib.rs, Relative: src/lib.rs, Absolute: /home/user/project/src/lib.rs"
+        );
+    }
+
+    #[test]
+    fn test_template_substitution_partial() {
+        let file_path = PathBuf::from("/tmp/file.txt");
+        let relative_path = PathBuf::from("file.txt");
+        let watch_path = PathBuf::from("/tmp");
+        let event = EventKind::Create(CreateKind::File);
+
+        let ctx = TemplateContext::new(&file_path, &relative_path, &event, &watch_path);
+
+        let template = "File created: {relative_path}";
+        let result = ctx.substitute_template(template);
+
+        assert_eq!(result, "File created: file.txt");
+    }
+
+    #[test]
+    fn test_template_substitution_no_variables() {
+        let file_path = PathBuf::from("/tmp/file.txt");
+        let relative_path = PathBuf::from("file.txt");
+        let watch_path = PathBuf::from("/tmp");
+        let event = EventKind::Create(CreateKind::File);
+
+        let ctx = TemplateContext::new(&file_path, &relative_path, &event, &watch_path);
+
+        let template = "echo 'Hello World'";
+        let result = ctx.substitute_template(template);
+
+        assert_eq!(result, "echo 'Hello World'");
+    }
+
+    #[test]
+    fn test_template_substitution_multiple_same_variable() {
+        let file_path = PathBuf::from("/tmp/file.txt");
+        let relative_path = PathBuf::from("file.txt");
+        let watch_path = PathBuf::from("/tmp");
+        let event = EventKind::Create(CreateKind::File);
+
+        let ctx = TemplateContext::new(&file_path, &relative_path, &event, &watch_path);
+
+        let template = "{relative_path} -> {relative_path}";
+        let result = ctx.substitute_template(template);
+
+        assert_eq!(result, "file.txt -> file.txt");
+    }
+
+    // Test FileWatcher initialization
+    #[test]
+    fn test_file_watcher_new_valid_directory() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CommandConfig {
+            on_create: Vec::new(),
+            on_modify: Vec::new(),
+            on_delete: Vec::new(),
+            on_change: Vec::new(),
+            ..Default::default()
+        };
+
+        let result = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            config,
+            0,
+            false,
+            false,
+        );
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_file_watcher_new_nonexistent_directory() {
+        let config = CommandConfig {
+            on_create: Vec::new(),
+            on_modify: Vec::new(),
+            on_delete: Vec::new(),
+            on_change: Vec::new(),
+            ..Default::default()
+        };
+
+        let result = FileWatcher::new(
+            PathBuf::from("/nonexistent/path/that/does/not/exist"),
+            vec![],
+            vec![],
+            config,
+            0,
+            false,
+            false,
+        );
+        assert!(result.is_err());
+        let err_msg = result.unwrap_err().to_string();
+        assert!(err_msg.contains("Path does not exist"));
+    }
+
+    #[test]
+    fn test_file_watcher_new_single_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("test_file.txt");
+        std::fs::write(&file_path, "test").unwrap();
+        std::fs::write(temp_dir.path().join("other.txt"), "test").unwrap();
+
+        let watcher = FileWatcher::new(
+            file_path,
+            vec![],
+            vec![],
+            CommandConfig::default(),
+            0,
+            false,
+            false,
+        )
+        .unwrap();
+
+        // The parent directory is watched, without its subdirectories
+        assert_eq!(watcher.watch_path, temp_dir.path().canonicalize().unwrap());
+        assert!(!watcher.watches_subdirectories());
+        assert_eq!(
+            watcher.matched_files().unwrap(),
+            [PathBuf::from("test_file.txt")]
+        );
+    }
+
+    #[rstest]
+    #[case(
+        EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Content)),
+        "modify"
+    )]
+    #[case(EventKind::Remove(RemoveKind::File), "delete")]
+    fn test_single_file_reports_only_that_file(
+        #[case] kind: EventKind,
+        #[case] expected_type: &str,
+    ) {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("config.toml");
+        std::fs::write(&file_path, "key = 1").unwrap();
+
+        let watcher = FileWatcher::new(
+            file_path,
+            vec![],
+            vec![],
+            CommandConfig::default(),
+            0,
+            false,
+            false,
+        )
+        .unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+
+        let sibling = Event::new(kind).add_path(root.join("other.toml"));
+        assert!(watcher.collect_changes(sibling).is_empty());
+
+        let event = Event::new(kind).add_path(root.join("config.toml"));
+        let changes = watcher.collect_changes(event);
+        assert_eq!(changes.len(), 1);
+
+        let context = changes[0].template_context(&watcher.watch_path);
+        assert_eq!(
+            context.substitute_template("{event_type} {relative_path} {file_name}"),
+            format!("{} config.toml config.toml", expected_type)
+        );
+        assert_eq!(
+            context.substitute_template("{file_path}"),
+            root.join("config.toml").display().to_string()
+        );
+    }
+
+    #[test]
+    fn test_file_watcher_with_invalid_include_pattern() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CommandConfig {
+            on_create: Vec::new(),
+            on_modify: Vec::new(),
+            on_delete: Vec::new(),
+            on_change: Vec::new(),
+            ..Default::default()
+        };
+
+        let result = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec!["[invalid".to_string()],
+            vec![],
+            config,
+            0,
+            false,
+            false,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_file_watcher_with_invalid_exclude_pattern() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CommandConfig {
+            on_create: Vec::new(),
+            on_modify: Vec::new(),
+            on_delete: Vec::new(),
+            on_change: Vec::new(),
+            ..Default::default()
+        };
+
+        let result = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec!["[invalid".to_string()],
+            config,
+            0,
+            false,
+            false,
+        );
+        assert!(result.is_err());
+    }
+
+    // Test execute_shell_command
+    #[tokio::test]
+    async fn test_execute_shell_command_success() {
+        let result =
+            FileWatcher::execute_shell_command("echo test", None, None, None, &[], None).await;
+        assert!(result.is_ok());
+        let output = result.unwrap();
+        assert!(output.status.success());
+    }
+
+    #[tokio::test]
+    async fn test_execute_shell_command_with_args() {
+        let result =
+            FileWatcher::execute_shell_command("echo hello world", None, None, None, &[], None)
+                .await;
+        assert!(result.is_ok());
+        let output = result.unwrap();
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).contains("hello world"));
+    }
+
+    #[tokio::test]
+    async fn test_execute_shell_command_failure() {
+        // Use a command that should fail
+        let result = FileWatcher::execute_shell_command("false", None, None, None, &[], None).await;
+        assert!(result.is_ok());
+        let output = result.unwrap();
+        assert!(!output.status.success());
+        assert_eq!(output.status.code(), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_execute_shell_command_empty() {
+        let result = FileWatcher::execute_shell_command("", None, None, None, &[], None).await;
+        assert!(result.is_err());
+        let err_msg = result.unwrap_err().to_string();
+        assert!(err_msg.contains("Empty command"));
+    }
+
+    #[tokio::test]
+    async fn test_execute_shell_command_nonexistent() {
+        let result = FileWatcher::execute_shell_command(
+            "nonexistent_command_12345",
+            None,
+            None,
+            None,
+            &[],
+            None,
+        )
+        .await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_event_kind_to_string_all_types() {
+        assert_eq!(
+            TemplateContext::event_kind_to_str(&EventKind::Create(CreateKind::File)),
+            "create"
+        );
+        assert_eq!(
+            TemplateContext::event_kind_to_str(&EventKind::Modify(ModifyKind::Data(
+                notify::event::DataChange::Any
+            ))),
+            "modify"
+        );
+        assert_eq!(
+            TemplateContext::event_kind_to_str(&EventKind::Remove(RemoveKind::File)),
+            "delete"
+        );
+        assert_eq!(
+            TemplateContext::event_kind_to_str(&EventKind::Access(notify::event::AccessKind::Any)),
+            "change"
+        );
+    }
+
+    #[rstest]
+    #[case("top.txt", true)]
+    #[case("a/depth1.txt", true)]
+    #[case("a/b/depth2.txt", true)]
+    #[case("a/b/c/depth3.txt", false)]
+    fn test_max_depth_filters_deep_files(#[case] relative: &str, #[case] expected: bool) {
+        let temp_dir = TempDir::new().unwrap();
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            CommandConfig::default(),
+            0,
+            false,
+            false,
+        )
+        .unwrap()
+        .with_max_depth(2);
+
+        let path = temp_dir.path().canonicalize().unwrap().join(relative);
+        let event = Event::new(EventKind::Create(CreateKind::File)).add_path(path);
+
+        assert_eq!(watcher.collect_changes(event).len(), usize::from(expected));
+    }
+
+    #[rstest]
+    #[case("create", EventKind::Create(CreateKind::File), true)]
+    #[case(
+        "create",
+        EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)),
+        false
+    )]
+    #[case("modify", EventKind::Modify(ModifyKind::Name(RenameMode::Both)), true)]
+    #[case(
+        "modify",
+        EventKind::Access(notify::event::AccessKind::Close(notify::event::AccessMode::Write)),
+        true
+    )]
+    #[case(
+        "modify",
+        EventKind::Modify(ModifyKind::Metadata(MetadataKind::Permissions)),
+        false
+    )]
+    #[case(
+        "metadata",
+        EventKind::Modify(ModifyKind::Metadata(MetadataKind::Any)),
+        true
+    )]
+    #[case("delete", EventKind::Remove(RemoveKind::File), true)]
+    #[case("access", EventKind::Access(notify::event::AccessKind::Any), true)]
+    #[case("all", EventKind::Access(notify::event::AccessKind::Any), true)]
+    #[case("all", EventKind::Any, false)]
+    fn test_event_kinds_accepts(
+        #[case] kinds: &str,
+        #[case] kind: EventKind,
+        #[case] expected: bool,
+    ) {
+        let kinds: EventKinds = kinds.parse().unwrap();
+        assert_eq!(kinds.accepts(&kind), expected);
+    }
+
+    #[rstest]
+    #[case(false, ["c.txt", "a.txt", "b.txt"])]
+    #[case(true, ["a.txt", "b.txt", "c.txt"])]
+    fn test_sort_paths_orders_multi_path_events(
+        #[case] sort_paths: bool,
+        #[case] expected: [&str; 3],
+    ) {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        let watcher = FileWatcher::new(
+            root.clone(),
+            vec![],
+            vec![],
+            CommandConfig::default(),
+            0,
+            false,
+            false,
+        )
+        .unwrap()
+        .with_sort_paths(sort_paths);
+
+        let event = ["c.txt", "a.txt", "b.txt"].into_iter().fold(
+            Event::new(EventKind::Create(CreateKind::File)),
+            |event, name| event.add_path(root.join(name)),
+        );
+        let changes: Vec<PathBuf> = watcher
+            .collect_changes(event)
+            .into_iter()
+            .map(|change| change.relative_path)
+            .collect();
+
+        assert_eq!(changes, expected.map(PathBuf::from));
+    }
+
+    #[test]
+    fn test_event_kinds_default_skips_access_only() {
+        let kinds = EventKinds::default();
+        assert!(kinds.accepts(&EventKind::Modify(ModifyKind::Metadata(MetadataKind::Any))));
+        assert!(!kinds.accepts(&EventKind::Access(notify::event::AccessKind::Any)));
+        assert!("unknown".parse::<EventKinds>().is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_events_create_ignores_modify() {
+        let temp_dir = TempDir::new().unwrap();
+        let markers = TempDir::new().unwrap();
+        let create_marker = markers.path().join("create.txt");
+        let modify_marker = markers.path().join("modify.txt");
+
+        let config = CommandConfig {
+            on_create: vec![format!("touch {}", create_marker.display())],
+            on_modify: vec![format!("touch {}", modify_marker.display())],
+            ..Default::default()
+        };
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            config,
+            0,
+            false,
+            false,
+        )
+        .unwrap()
+        .with_event_kinds("create".parse().unwrap());
+
+        let path = temp_dir.path().canonicalize().unwrap().join("file.txt");
+        std::fs::write(&path, "content").unwrap();
+        let modify = Event::new(EventKind::Modify(ModifyKind::Data(
+            notify::event::DataChange::Any,
+        )))
+        .add_path(path.clone());
+        assert!(!watcher.handle_event(modify));
+        let create = Event::new(EventKind::Create(CreateKind::File)).add_path(path);
+        assert!(watcher.handle_event(create));
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        assert!(create_marker.exists());
+        assert!(!modify_marker.exists());
+    }
+
+    #[rstest]
+    #[case(EntryFilter::All, true, true)]
+    #[case(EntryFilter::FilesOnly, true, false)]
+    #[case(EntryFilter::DirsOnly, false, true)]
+    fn test_entry_filter_selects_files_or_dirs(
+        #[case] entries: EntryFilter,
+        #[case] file_fires: bool,
+        #[case] dir_fires: bool,
+    ) {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        std::fs::write(root.join("new.txt"), "").unwrap();
+        std::fs::create_dir(root.join("sub")).unwrap();
+
+        let watcher = FileWatcher::new(
+            root.clone(),
+            vec![],
+            vec![],
+            CommandConfig::default(),
+            0,
+            false,
+            false,
+        )
+        .unwrap()
+        .with_entries(entries);
+
+        let cases = [
+            (EventKind::Create(CreateKind::File), "new.txt", file_fires),
+            (EventKind::Create(CreateKind::Folder), "sub", dir_fires),
+            // Modify events carry no file/folder hint, so the path decides
+            (
+                EventKind::Modify(ModifyKind::Metadata(MetadataKind::Any)),
+                "sub",
+                dir_fires,
+            ),
+            (EventKind::Remove(RemoveKind::Folder), "gone", dir_fires),
+        ];
+        for (kind, relative, expected) in cases {
+            let event = Event::new(kind).add_path(root.join(relative));
+            assert_eq!(
+                watcher.collect_changes(event).len(),
+                usize::from(expected),
+                "{kind:?} {relative}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_ignore_dir_modify_drops_parent_directory_noise() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        std::fs::create_dir(root.join("src")).unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+
+        let watcher = |ignore_dir_modify| {
+            FileWatcher::new(
+                root.clone(),
+                vec![],
+                vec![],
+                CommandConfig::default(),
+                0,
+                false,
+                false,
+            )
+            .unwrap()
+            .with_ignore_dir_modify(ignore_dir_modify)
+        };
+        let write = EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Content));
+        let mtime = EventKind::Modify(ModifyKind::Metadata(MetadataKind::WriteTime));
+
+        // Writing a file, then the platform reporting its parent as modified
+        let file_event = Event::new(write).add_path(root.join("src/main.rs"));
+        let parent_event = Event::new(mtime).add_path(root.join("src"));
+        let watcher_on = watcher(true);
+        assert_eq!(watcher_on.collect_changes(file_event.clone()).len(), 1);
+        assert!(watcher_on.collect_changes(parent_event.clone()).is_empty());
+        assert_eq!(watcher(false).collect_changes(parent_event).len(), 1);
+
+        // Changes a user may care about still go through
+        for kind in [
+            EventKind::Modify(ModifyKind::Metadata(MetadataKind::Permissions)),
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)),
+            EventKind::Create(CreateKind::Folder),
+        ] {
+            let event = Event::new(kind).add_path(root.join("src"));
+            assert_eq!(watcher_on.collect_changes(event).len(), 1, "{kind:?}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_strict_utf8_skips_non_utf8_paths() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        let invalid = root.join(OsStr::from_bytes(b"caf\xe9.txt"));
+        let valid = root.join("café.txt");
+        assert!(is_lossy(&invalid));
+        assert!(!is_lossy(&valid));
+
+        let watcher = |strict_utf8| {
+            FileWatcher::new(
+                root.clone(),
+                vec![],
+                vec![],
+                CommandConfig::default(),
+                0,
+                false,
+                false,
+            )
+            .unwrap()
+            .with_strict_utf8(strict_utf8)
+        };
+        let event = |path: &Path| {
+            Event::new(EventKind::Remove(RemoveKind::File)).add_path(path.to_path_buf())
+        };
+
+        assert_eq!(watcher(false).collect_changes(event(&invalid)).len(), 1);
+        assert!(watcher(true).collect_changes(event(&invalid)).is_empty());
+        assert_eq!(watcher(true).collect_changes(event(&valid)).len(), 1);
+    }
+
+    #[test]
+    fn test_min_size_skips_small_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        std::fs::write(root.join("app.pid"), "42").unwrap();
+        std::fs::write(root.join("main.rs"), "fn main() {}").unwrap();
+        std::fs::create_dir(root.join("src")).unwrap();
+
+        let watcher = FileWatcher::new(
+            root.clone(),
+            vec![],
+            vec![],
+            CommandConfig::default(),
+            0,
+            false,
+            false,
+        )
+        .unwrap()
+        .with_min_size(10);
+
+        for (kind, relative, expected) in [
+            (EventKind::Create(CreateKind::File), "app.pid", false),
+            (EventKind::Create(CreateKind::File), "main.rs", true),
+            (EventKind::Create(CreateKind::Folder), "src", true),
+            (EventKind::Remove(RemoveKind::File), "gone.pid", true),
+            // Already gone again by the time it's checked
+            (EventKind::Create(CreateKind::File), "short-lived.tmp", true),
+        ] {
+            let event = Event::new(kind).add_path(root.join(relative));
+            assert_eq!(
+                watcher.collect_changes(event).len(),
+                usize::from(expected),
+                "{relative}"
+            );
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[rstest]
+    #[case(WatchLimitMode::Warn, 3, true)]
+    #[case(WatchLimitMode::Error, 3, false)]
+    #[case(WatchLimitMode::Error, 4, true)]
+    #[case(WatchLimitMode::Off, 1, true)]
+    fn test_watch_limit_check(
+        #[case] mode: WatchLimitMode,
+        #[case] limit: usize,
+        #[case] starts: bool,
+    ) {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        for dir in ["a/b", "c", "node_modules/dep"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+
+        let watcher = FileWatcher::new(
+            root.clone(),
+            vec![],
+            vec![],
+            CommandConfig::default(),
+            0,
+            false,
+            false,
+        )
+        .unwrap()
+        .with_excluded_dirs(vec!["node_modules".to_string()])
+        .with_watch_limit(mode);
+
+        // 4 directories are watched: the root, a, a/b and c
+        let result = watcher.check_watch_limit(limit);
+        assert_eq!(result.is_ok(), starts, "{:?}", result);
+        if let Err(e) = result {
+            assert!(e.to_string().contains("fs.inotify.max_user_watches"));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_explain() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        std::fs::create_dir(root.join("src")).unwrap();
+        std::fs::write(root.join("src/main.rs"), "").unwrap();
+
+        let watcher = FileWatcher::new(
+            root.clone(),
+            vec!["**/*.rs".to_string()],
+            vec!["target/**".to_string()],
+            CommandConfig::default(),
+            0,
+            false,
+            false,
+        )
+        .unwrap()
+        .with_excluded_dirs(vec!["node_modules".to_string()])
+        .with_max_depth(2);
+
+        for (path, expected) in [
+            (
+                PathBuf::from("src/main.rs"),
+                "src/main.rs: watched (matches include pattern '**/*.rs')",
+            ),
+            (
+                root.join("src/main.rs"),
+                "src/main.rs: watched (matches include pattern '**/*.rs')",
+            ),
+            (
+                PathBuf::from("target/build.rs"),
+                "target/build.rs: ignored (matches exclude pattern 'target/**')",
+            ),
+            (
+                PathBuf::from("README.md"),
+                "README.md: ignored (matches no include pattern)",
+            ),
+            (
+                PathBuf::from("node_modules/dep.rs"),
+                "node_modules/dep.rs: ignored (in an --exclude-dir)",
+            ),
+            (
+                PathBuf::from("a/b/c/deep.rs"),
+                "a/b/c/deep.rs: ignored (deeper than --max-depth 2)",
+            ),
+        ] {
+            assert_eq!(watcher.explain(&path), expected);
+        }
+
+        let outside = temp_dir.path().parent().unwrap().to_path_buf();
+        assert!(watcher.explain(&outside).ends_with(&format!(
+            "ignored (outside the watched directory {})",
+            root.display()
+        )));
+    }
+
+    #[test]
+    fn test_match_absolute_excludes_by_absolute_path() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        let exclude = format!("{}/vendor/**", TemplateContext::normalize_path(&root));
+
+        let watcher = FileWatcher::new(
+            root.clone(),
+            vec![],
+            vec![exclude, "**/target/**".to_string()],
+            CommandConfig::default(),
+            0,
+            false,
+            false,
+        )
+        .unwrap()
+        .with_match_absolute();
+
+        for (relative, expected) in [
+            ("vendor/lib.rs", false),
+            ("src/vendor/lib.rs", true),
+            ("target/out.rs", false),
+            ("src/main.rs", true),
+        ] {
+            let event =
+                Event::new(EventKind::Create(CreateKind::File)).add_path(root.join(relative));
+            assert_eq!(
+                watcher.collect_changes(event).len(),
+                usize::from(expected),
+                "{relative}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_matched_files_applies_filters() {
+        let temp_dir = TempDir::new().unwrap();
+        for file in [
+            "main.rs",
+            "notes.md",
+            "src/lib.rs",
+            "src/a/b/deep.rs",
+            "target/out.rs",
+        ] {
+            let path = temp_dir.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec!["**/*.rs".to_string()],
+            vec!["target/**".to_string()],
+            CommandConfig::default(),
+            0,
+            false,
+            false,
+        )
+        .unwrap()
+        .with_max_depth(1);
+
+        assert_eq!(
+            watcher.matched_files().unwrap(),
+            [PathBuf::from("main.rs"), PathBuf::from("src/lib.rs")]
+        );
+        assert_eq!(
+            watcher.with_recursive(false).matched_files().unwrap(),
+            [PathBuf::from("main.rs")]
+        );
+    }
+
+    #[test]
+    fn test_max_depth_zero_keeps_direct_children() {
+        let temp_dir = TempDir::new().unwrap();
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            CommandConfig::default(),
+            0,
+            false,
+            false,
+        )
+        .unwrap()
+        .with_max_depth(0);
+
+        assert!(watcher.should_watch(Path::new("top.txt")));
+        assert!(!watcher.should_watch(Path::new("src/main.rs")));
+    }
+
+    #[test]
+    fn test_get_relative_path_success() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CommandConfig {
+            on_create: Vec::new(),
+            on_modify: Vec::new(),
+            on_delete: Vec::new(),
+            on_change: Vec::new(),
+            ..Default::default()
+        };
+
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            config,
+            0,
+            false,
+            false,
+        )
+        .unwrap();
+
+        // Use canonicalized path since FileWatcher stores canonicalized paths
+        let file_path = temp_dir.path().canonicalize().unwrap().join("test.txt");
+        let relative = watcher.get_relative_path(&file_path);
+
+        assert_eq!(relative, Some(PathBuf::from("test.txt")));
+    }
+
+    #[test]
+    fn test_get_relative_path_nested() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CommandConfig {
+            on_create: Vec::new(),
+            on_modify: Vec::new(),
+            on_delete: Vec::new(),
+            on_change: Vec::new(),
+            ..Default::default()
+        };
+
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            config,
+            0,
+            false,
+            false,
+        )
+        .unwrap();
+
+        // Use canonicalized path since FileWatcher stores canonicalized paths
+        let file_path = temp_dir
+            .path()
+            .canonicalize()
+            .unwrap()
+            .join("src")
+            .join("main.rs");
+        let relative = watcher.get_relative_path(&file_path);
+
+        assert_eq!(relative, Some(PathBuf::from("src/main.rs")));
+    }
+
+    #[test]
+    fn test_get_relative_path_outside_watch_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CommandConfig {
+            on_create: Vec::new(),
+            on_modify: Vec::new(),
+            on_delete: Vec::new(),
+            on_change: Vec::new(),
+            ..Default::default()
+        };
+
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            config,
+            0,
+            false,
+            false,
+        )
+        .unwrap();
+
+        // Try with a path outside the watch directory
+        let outside_path = PathBuf::from("/tmp/outside.txt");
+        let relative = watcher.get_relative_path(&outside_path);
+
+        assert_eq!(relative, None);
+    }
+
+    #[rstest]
+    // Create kind variants
+    #[case(
+        "create",
+        EventKind::Create(CreateKind::File),
+        Some("create"),
+        None,
+        None
+    )]
+    #[case(
+        "create",
+        EventKind::Create(CreateKind::Folder),
+        Some("create"),
+        None,
+        None
+    )]
+    #[case(
+        "create",
+        EventKind::Create(CreateKind::Any),
+        Some("create"),
+        None,
+        None
+    )]
+    // Modify kind variants
+    #[case(
+        "modify",
+        EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)),
+        None,
+        Some("modify"),
+        None
+    )]
+    #[case(
+        "modify",
+        EventKind::Modify(ModifyKind::Name(notify::event::RenameMode::Any)),
+        None,
+        Some("modify"),
+        None
+    )]
+    #[case(
+        "modify",
+        EventKind::Modify(ModifyKind::Any),
+        None,
+        Some("modify"),
+        None
+    )]
+    // Remove kind variants
+    #[case(
+        "delete",
+        EventKind::Remove(RemoveKind::File),
+        None,
+        None,
+        Some("delete")
+    )]
+    #[case(
+        "delete",
+        EventKind::Remove(RemoveKind::Folder),
+        None,
+        None,
+        Some("delete")
+    )]
+    #[case(
+        "delete",
+        EventKind::Remove(RemoveKind::Any),
         None,
         None,
+        Some("delete")
+    )]
+    fn test_command_config_all_event_kind_variants(
+        #[case] expected_cmd: &str,
+        #[case] event: EventKind,
+        #[case] on_create: Option<&str>,
+        #[case] on_modify: Option<&str>,
+        #[case] on_delete: Option<&str>,
+    ) {
+        let config = CommandConfig {
+            on_create: on_create.map(String::from).into_iter().collect(),
+            on_modify: on_modify.map(String::from).into_iter().collect(),
+            on_delete: on_delete.map(String::from).into_iter().collect(),
+            on_change: Vec::new(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            config.get_command_for_event(&event),
+            [expected_cmd],
+            "Event {:?} should return command '{}'",
+            event,
+            expected_cmd
+        );
+    }
+
+    #[test]
+    fn test_template_context_nested_paths() {
+        let file_path = PathBuf::from("/home/user/project/src/deep/nested/file.rs");
+        let relative_path = PathBuf::from("src/deep/nested/file.rs");
+        let watch_path = PathBuf::from("/home/user/project");
+        let event = EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any));
+
+        let ctx = TemplateContext::new(&file_path, &relative_path, &event, &watch_path);
+
+        assert_eq!(ctx.file_path, "/home/user/project/src/deep/nested/file.rs");
+        assert_eq!(ctx.relative_path, "src/deep/nested/file.rs");
+        assert_eq!(
+            ctx.absolute_path,
+            "/home/user/project/src/deep/nested/file.rs"
+        );
+    }
+
+    #[test]
+    fn test_template_substitution_edge_cases() {
+        let file_path = PathBuf::from("/tmp/test.txt");
+        let relative_path = PathBuf::from("test.txt");
+        let watch_path = PathBuf::from("/tmp");
+        let event = EventKind::Create(CreateKind::File);
+
+        let ctx = TemplateContext::new(&file_path, &relative_path, &event, &watch_path);
+
+        // Empty template
+        assert_eq!(ctx.substitute_template(""), "");
+
+        // Template with no placeholders
+        assert_eq!(ctx.substitute_template("static text"), "static text");
+
+        // Template with incomplete placeholder
+        assert_eq!(ctx.substitute_template("{file"), "{file");
+        assert_eq!(ctx.substitute_template("file_path}"), "file_path}");
+
+        // Template with unknown placeholder
+        assert_eq!(ctx.substitute_template("{unknown}"), "{unknown}");
+    }
+
+    #[test]
+    fn test_file_watcher_with_patterns() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CommandConfig {
+            on_create: Vec::new(),
+            on_modify: Vec::new(),
+            on_delete: Vec::new(),
+            on_change: Vec::new(),
+            ..Default::default()
+        };
+
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec!["*.rs".to_string()],
+            vec!["target/**".to_string()],
+            config,
+            0,
+            false,
+            false,
+        );
+
+        assert!(watcher.is_ok());
+    }
+
+    #[rstest]
+    #[case(None, Some(None))]
+    #[case(Some("none"), Some(None))]
+    #[case(Some("sh -c"), Some(Some(vec!["sh", "-c"])))]
+    #[case(Some("bash -c"), Some(Some(vec!["bash", "-c"])))]
+    #[case(Some("'/opt/my shell/bin/sh' -c"), Some(Some(vec!["/opt/my shell/bin/sh", "-c"])))]
+    #[case(Some(""), None)]
+    #[case(Some("'sh -c"), None)]
+    fn test_command_config_interpreter(
+        #[case] shell: Option<&str>,
+        #[case] expected: Option<Option<Vec<&str>>>,
+    ) {
+        let config = CommandConfig {
+            shell: shell.map(|s| s.to_string()),
+            ..Default::default()
+        };
+
+        let result = config.interpreter();
+        match expected {
+            Some(expected) => assert_eq!(
+                result.unwrap(),
+                expected.map(|argv| argv.into_iter().map(String::from).collect())
+            ),
+            None => assert!(result.is_err(), "shell {:?} should be rejected", shell),
+        }
+    }
+
+    #[test]
+    fn test_file_watcher_with_invalid_shell() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CommandConfig {
+            shell: Some("  ".to_string()),
+            ..Default::default()
+        };
+
+        let result = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            config,
+            0,
+            false,
+            false,
+        );
+        assert!(result.is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_execute_shell_command_with_interpreter() {
+        let interpreter = vec!["sh".to_string(), "-c".to_string()];
+
+        let output = FileWatcher::execute_shell_command(
+            "echo a && echo b",
+            Some(&interpreter),
+            None,
+            None,
+            &[],
+            None,
+        )
+        .await
+        .unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "a\nb\n");
+
+        // Without an interpreter && chains commands, but a quoted one is an argument
+        let output =
+            FileWatcher::execute_shell_command("echo a && echo b", None, None, None, &[], None)
+                .await
+                .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "a\nb\n");
+        let output =
+            FileWatcher::execute_shell_command("echo a '&&' echo b", None, None, None, &[], None)
+                .await
+                .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "a && echo b\n");
+
+        let result =
+            FileWatcher::execute_shell_command("  ", Some(&interpreter), None, None, &[], None)
+                .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_execute_shell_command_with_output() {
+        let result =
+            FileWatcher::execute_shell_command("echo test123", None, None, None, &[], None).await;
+        assert!(result.is_ok());
+        let output = result.unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("test123"));
+    }
+
+    #[rstest]
+    #[case(
+        Some("move_cmd"),
+        Some("modify_cmd"),
+        Some("fallback"),
+        Some("move_cmd")
+    )]
+    #[case(None, Some("modify_cmd"), Some("fallback"), Some("modify_cmd"))]
+    #[case(None, None, Some("fallback"), Some("fallback"))]
+    #[case(None, None, None, None)]
+    fn test_command_config_move_resolution(
+        #[case] on_move: Option<&str>,
+        #[case] on_modify: Option<&str>,
+        #[case] on_change: Option<&str>,
+        #[case] expected: Option<&str>,
+    ) {
+        let config = CommandConfig {
+            on_move: on_move.map(String::from).into_iter().collect(),
+            on_modify: on_modify.map(String::from).into_iter().collect(),
+            on_change: on_change.map(String::from).into_iter().collect(),
+            ..Default::default()
+        };
+
+        let event = EventKind::Modify(ModifyKind::Name(notify::event::RenameMode::Both));
+        assert_eq!(
+            config
+                .get_command_for_event(&event)
+                .first()
+                .map(String::as_str),
+            expected
+        );
+    }
+
+    #[rstest]
+    // Directory commands take precedence for folder events
+    #[case(
+        Some("dir_cmd"),
+        Some("file_cmd"),
+        Some("fallback"),
         EventKind::Create(CreateKind::Folder),
-        Some("create_cmd")
+        Some("dir_cmd")
     )]
-    // Modify event tests
     #[case(
-        None,
-        Some("modify_cmd"),
-        None,
+        Some("dir_cmd"),
+        Some("file_cmd"),
         Some("fallback"),
-        EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)),
-        Some("modify_cmd")
+        EventKind::Remove(RemoveKind::Folder),
+        Some("dir_cmd")
     )]
+    // Folder events fall back to the file command, then on_change
     #[case(
         None,
-        None,
-        None,
+        Some("file_cmd"),
         Some("fallback"),
-        EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)),
-        Some("fallback")
+        EventKind::Create(CreateKind::Folder),
+        Some("file_cmd")
     )]
     #[case(
         None,
-        Some("modify_cmd"),
-        None,
-        None,
-        EventKind::Modify(ModifyKind::Name(notify::event::RenameMode::Any)),
-        Some("modify_cmd")
+        Some("file_cmd"),
+        Some("fallback"),
+        EventKind::Remove(RemoveKind::Folder),
+        Some("file_cmd")
     )]
-    // Delete event tests
     #[case(
         None,
         None,
-        Some("delete_cmd"),
         Some("fallback"),
-        EventKind::Remove(RemoveKind::File),
-        Some("delete_cmd")
+        EventKind::Create(CreateKind::Folder),
+        Some("fallback")
     )]
     #[case(
-        None,
         None,
         None,
         Some("fallback"),
-        EventKind::Remove(RemoveKind::File),
+        EventKind::Remove(RemoveKind::Folder),
         Some("fallback")
     )]
+    #[case(None, None, None, EventKind::Remove(RemoveKind::Folder), None)]
+    // Directory commands never run for files
     #[case(
+        Some("dir_cmd"),
+        Some("file_cmd"),
         None,
-        None,
-        Some("delete_cmd"),
-        None,
-        EventKind::Remove(RemoveKind::Folder),
-        Some("delete_cmd")
+        EventKind::Create(CreateKind::File),
+        Some("file_cmd")
+    )]
+    #[case(Some("dir_cmd"), None, None, EventKind::Remove(RemoveKind::File), None)]
+    fn test_command_config_dir_resolution(
+        #[case] dir_cmd: Option<&str>,
+        #[case] file_cmd: Option<&str>,
+        #[case] on_change: Option<&str>,
+        #[case] event: EventKind,
+        #[case] expected: Option<&str>,
+    ) {
+        let is_create = matches!(event, EventKind::Create(_));
+        let (on_create_dir, on_delete_dir) = if is_create {
+            (dir_cmd, None)
+        } else {
+            (None, dir_cmd)
+        };
+        let (on_create, on_delete) = if is_create {
+            (file_cmd, None)
+        } else {
+            (None, file_cmd)
+        };
+
+        let config = CommandConfig {
+            on_create: on_create.map(String::from).into_iter().collect(),
+            on_delete: on_delete.map(String::from).into_iter().collect(),
+            on_create_dir: on_create_dir.map(String::from).into_iter().collect(),
+            on_delete_dir: on_delete_dir.map(String::from).into_iter().collect(),
+            on_change: on_change.map(String::from).into_iter().collect(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            config
+                .get_command_for_event(&event)
+                .first()
+                .map(String::as_str),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case("/tmp/test/image.png", "image.png")]
+    #[case("/tmp/test/src/main.rs", "main.rs")]
+    #[case("/tmp/test/Makefile", "Makefile")]
+    #[case("/tmp/test/dir/", "")]
+    #[case("/", "")]
+    #[case("/tmp/test/..", "")]
+    fn test_template_substitution_file_name(#[case] file_path: &str, #[case] expected: &str) {
+        let file_path = PathBuf::from(file_path);
+        let watch_path = PathBuf::from("/tmp/test");
+        let event = EventKind::Modify(ModifyKind::Any);
+
+        let ctx = TemplateContext::new(&file_path, Path::new("x"), &event, &watch_path);
+
+        assert_eq!(
+            ctx.substitute_template("convert {file_name}.png"),
+            format!("convert {}.png", expected)
+        );
+    }
+
+    #[rstest]
+    #[case("/tmp/test/styles/main.scss", "main", "scss")]
+    #[case("/tmp/test/archive.tar.gz", "archive.tar", "gz")]
+    #[case("/tmp/test/Makefile", "Makefile", "")]
+    #[case("/tmp/test/.gitignore", ".gitignore", "")]
+    #[case("/tmp/test/dir/", "", "")]
+    fn test_template_context_file_stem_and_ext(
+        #[case] file_path_str: &str,
+        #[case] expected_stem: &str,
+        #[case] expected_ext: &str,
+    ) {
+        let file_path = PathBuf::from(file_path_str);
+        let relative_path = PathBuf::from("styles/main.scss");
+        let watch_path = PathBuf::from("/tmp/test");
+        let event = EventKind::Modify(ModifyKind::Any);
+
+        let ctx = TemplateContext::new(&file_path, &relative_path, &event, &watch_path);
+
+        assert_eq!(ctx.file_stem, expected_stem);
+        assert_eq!(ctx.file_ext, expected_ext);
+        assert_eq!(
+            ctx.substitute_template("sass {file_path} {file_stem}.css {file_ext} {unknown}"),
+            format!(
+                "sass {} {}.css {} {{unknown}}",
+                file_path_str, expected_stem, expected_ext
+            )
+        );
+    }
+
+    #[test]
+    fn test_template_substitution_old_path() {
+        let file_path = PathBuf::from("/tmp/project/new.txt");
+        let relative_path = PathBuf::from("new.txt");
+        let watch_path = PathBuf::from("/tmp/project");
+        let event = EventKind::Modify(ModifyKind::Name(notify::event::RenameMode::Both));
+
+        let ctx = TemplateContext::new(&file_path, &relative_path, &event, &watch_path)
+            .with_old_path(Path::new("/tmp/project/old.txt"));
+
+        assert_eq!(
+            ctx.substitute_template("{event_type}: {old_path} -> {file_path}"),
+            "move: /tmp/project/old.txt -> /tmp/project/new.txt"
+        );
+        assert_eq!(
+            ctx.substitute_template("{new_path}"),
+            "/tmp/project/new.txt"
+        );
+    }
+
+    #[test]
+    fn test_template_old_path_empty_for_non_move_events() {
+        let ctx = TemplateContext::new(
+            Path::new("/tmp/file.txt"),
+            Path::new("file.txt"),
+            &EventKind::Create(CreateKind::File),
+            Path::new("/tmp"),
+        );
+
+        assert_eq!(ctx.substitute_template("[{old_path}]"), "[]");
+        assert_eq!(ctx.substitute_template("[{new_path}]"), "[]");
+    }
+
+    #[cfg(unix)]
+    #[rstest]
+    #[case::not_followed(false, "link.txt", "link.txt")]
+    #[case::followed(true, "link.txt", "real.txt")]
+    #[case::outside_root_not_escaped(true, "outside.txt", "outside.txt")]
+    fn test_symlinked_file_relative_path(
+        #[case] follow: bool,
+        #[case] link: &str,
+        #[case] expected: &str,
+    ) {
+        use std::os::unix::fs::symlink;
+
+        let temp_dir = TempDir::new().unwrap();
+        let outside_dir = TempDir::new().unwrap();
+        let canonical = temp_dir.path().canonicalize().unwrap();
+        std::fs::write(canonical.join("real.txt"), "content").unwrap();
+        std::fs::write(outside_dir.path().join("target.txt"), "content").unwrap();
+        symlink(canonical.join("real.txt"), canonical.join("link.txt")).unwrap();
+        symlink(
+            outside_dir.path().join("target.txt"),
+            canonical.join("outside.txt"),
+        )
+        .unwrap();
+
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            CommandConfig::default(),
+            0,
+            false,
+            true,
+        )
+        .unwrap()
+        .with_follow_symlinks(follow);
+
+        let changes = watcher.collect_changes(
+            Event::new(EventKind::Modify(ModifyKind::Data(
+                notify::event::DataChange::Any,
+            )))
+            .add_path(canonical.join(link)),
+        );
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].relative_path, PathBuf::from(expected));
+    }
+
+    #[cfg(unix)]
+    #[rstest]
+    #[case::canonicalized(true)]
+    #[case::as_given(false)]
+    fn test_symlinked_root_paths(#[case] canonicalize: bool) {
+        use std::os::unix::fs::symlink;
+
+        let temp_dir = TempDir::new().unwrap();
+        let canonical = temp_dir.path().canonicalize().unwrap();
+        std::fs::create_dir(canonical.join("real")).unwrap();
+        symlink(canonical.join("real"), canonical.join("link")).unwrap();
+        let link = temp_dir.path().join("link");
+
+        let watcher = FileWatcher::new(
+            link.clone(),
+            vec![],
+            vec![],
+            CommandConfig::default(),
+            0,
+            false,
+            true,
+        )
+        .unwrap()
+        .with_canonicalize(canonicalize);
+        // The real location is watched either way
+        assert_eq!(watcher.watch_path, canonical.join("real"));
+
+        let real_file = canonical.join("real/src/main.rs");
+        let changes = watcher.collect_changes(
+            Event::new(EventKind::Modify(ModifyKind::Data(
+                notify::event::DataChange::Any,
+            )))
+            .add_path(real_file.clone()),
+        );
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].relative_path, PathBuf::from("src/main.rs"));
+
+        let root = if canonicalize {
+            canonical.join("real")
+        } else {
+            link
+        };
+        let context = changes[0].template_context(watcher.display_root());
+        assert_eq!(
+            context.substitute_template("{absolute_path}"),
+            root.join("src/main.rs").display().to_string()
+        );
+        assert_eq!(
+            context.substitute_template("{file_path}"),
+            real_file.display().to_string()
+        );
+    }
+
+    #[test]
+    fn test_two_path_rename_is_reported_as_one_move() {
+        let temp_dir = TempDir::new().unwrap();
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            CommandConfig::default(),
+            0,
+            false,
+            true,
+        )
+        .unwrap();
+        let canonical = temp_dir.path().canonicalize().unwrap();
+        std::fs::write(canonical.join("new.txt"), "content").unwrap();
+
+        let changes = watcher.collect_changes(
+            Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+                .add_path(canonical.join("old.txt"))
+                .add_path(canonical.join("new.txt")),
+        );
+
+        assert_eq!(changes.len(), 1);
+        let context = changes[0].template_context(&canonical);
+        assert_eq!(
+            context.substitute_template("{event_type} {old_path} {new_path}"),
+            format!(
+                "move {} {}",
+                TemplateContext::normalize_path(&canonical.join("old.txt")),
+                TemplateContext::normalize_path(&canonical.join("new.txt"))
+            )
+        );
+    }
+
+    #[test]
+    fn test_single_path_rename_falls_back_to_existence_check() {
+        let temp_dir = TempDir::new().unwrap();
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            CommandConfig::default(),
+            0,
+            false,
+            true,
+        )
+        .unwrap();
+        let canonical = temp_dir.path().canonicalize().unwrap();
+
+        // The source half of a rename whose destination is unknown
+        let changes = watcher.collect_changes(
+            Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::From)))
+                .add_path(canonical.join("gone.txt")),
+        );
+
+        assert_eq!(changes.len(), 1);
+        assert!(matches!(changes[0].kind, EventKind::Remove(_)));
+        let context = changes[0].template_context(&canonical);
+        assert_eq!(
+            context.substitute_template("[{old_path}][{new_path}]"),
+            "[][]"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_atomic_save_is_reported_as_one_modify() {
+        use std::fs;
+        let temp_dir = TempDir::new().unwrap();
+        let markers = TempDir::new().unwrap();
+        let log = markers.path().join("events.txt");
+
+        let config = CommandConfig {
+            on_change: vec![format!(
+                "sh -c 'echo {{event_type}} {{relative_path}} [{{old_path}}] >> {}'",
+                log.display()
+            )],
+            ..Default::default()
+        };
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec!["*.rs".to_string()],
+            vec![],
+            config,
+            0,
+            false,
+            true,
+        )
+        .unwrap();
+        let canonical = temp_dir.path().canonicalize().unwrap();
+        let temp = canonical.join("main.rs~");
+        let target = canonical.join("main.rs");
+
+        // Write the new content to a temp file, then rename it over the target
+        fs::write(&temp, "fn main() {}").unwrap();
+        watcher
+            .handle_event(Event::new(EventKind::Create(CreateKind::File)).add_path(temp.clone()));
+        watcher.handle_event(
+            Event::new(EventKind::Modify(ModifyKind::Data(
+                notify::event::DataChange::Any,
+            )))
+            .add_path(temp.clone()),
+        );
+        fs::rename(&temp, &target).unwrap();
+        watcher.handle_event(
+            Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+                .add_path(temp)
+                .add_path(target),
+        );
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        assert_eq!(fs::read_to_string(&log).unwrap(), "modify main.rs []\n");
+    }
+
+    #[rstest]
+    #[case(EventKind::Create(CreateKind::File), Some(Duration::from_secs(1)))]
+    #[case(
+        EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)),
+        Some(Duration::from_secs(2))
     )]
-    // No command configured
-    #[case(None, None, None, None, EventKind::Create(CreateKind::File), None)]
-    #[case(None, None, None, None, EventKind::Modify(ModifyKind::Any), None)]
-    #[case(None, None, None, None, EventKind::Remove(RemoveKind::File), None)]
-    // Other event types use fallback
+    #[case(EventKind::Remove(RemoveKind::File), None)]
+    #[case(EventKind::Access(notify::event::AccessKind::Any), None)]
+    fn test_command_config_timeout_resolution(
+        #[case] event: EventKind,
+        #[case] expected: Option<Duration>,
+    ) {
+        let config = CommandConfig {
+            timeout_create: Some(1),
+            timeout_modify: Some(2),
+            // Zero disables the timeout
+            timeout_delete: Some(0),
+            ..Default::default()
+        };
+
+        assert_eq!(config.get_timeout_for_event(&event), expected);
+    }
+
+    #[rstest]
+    #[case(EventKind::Create(CreateKind::File), Some(Duration::from_secs(5)))]
     #[case(
-        Some("create_cmd"),
-        None,
-        None,
-        Some("fallback"),
-        EventKind::Access(notify::event::AccessKind::Any),
-        Some("fallback")
+        EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)),
+        Some(Duration::from_secs(2))
     )]
+    #[case(EventKind::Remove(RemoveKind::File), None)]
     #[case(
-        None,
-        Some("modify_cmd"),
-        None,
-        Some("fallback"),
-        EventKind::Any,
-        Some("fallback")
+        EventKind::Access(notify::event::AccessKind::Any),
+        Some(Duration::from_secs(5))
     )]
-    fn test_command_config_resolution(
-        #[case] on_create: Option<&str>,
-        #[case] on_modify: Option<&str>,
-        #[case] on_delete: Option<&str>,
-        #[case] on_change: Option<&str>,
+    fn test_command_config_global_timeout_fallback(
         #[case] event: EventKind,
-        #[case] expected: Option<&str>,
+        #[case] expected: Option<Duration>,
     ) {
         let config = CommandConfig {
-            on_create: on_create.map(|s| s.to_string()),
-            on_modify: on_modify.map(|s| s.to_string()),
-            on_delete: on_delete.map(|s| s.to_string()),
-            on_change: on_change.map(|s| s.to_string()),
+            timeout_modify: Some(2),
+            // An explicit per-event zero disables the global timeout for that event
+            timeout_delete: Some(0),
+            command_timeout: Some(5),
+            ..Default::default()
+        };
+
+        assert_eq!(config.get_timeout_for_event(&event), expected);
+    }
+
+    #[tokio::test]
+    async fn test_execute_shell_command_global_timeout() {
+        let config = CommandConfig {
+            command_timeout: Some(1),
+            ..Default::default()
+        };
+        let event = EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any));
+
+        let start = Instant::now();
+        let result = FileWatcher::execute_shell_command(
+            "sleep 10",
+            None,
+            config.get_timeout_for_event(&event),
+            None,
+            &[],
+            None,
+        )
+        .await;
+
+        let err = result.unwrap_err();
+        assert!(err.to_string().contains("timed out after 1s"), "{}", err);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[rstest]
+    #[case(EventKind::Create(CreateKind::File))]
+    #[case(EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)))]
+    #[case(EventKind::Remove(RemoveKind::File))]
+    #[tokio::test]
+    async fn test_execute_shell_command_timeout_per_event_type(#[case] event: EventKind) {
+        let config = CommandConfig {
+            timeout_create: Some(1),
+            timeout_modify: Some(1),
+            timeout_delete: Some(1),
+            ..Default::default()
+        };
+
+        let start = Instant::now();
+        let result = FileWatcher::execute_shell_command(
+            "sleep 10",
+            None,
+            config.get_timeout_for_event(&event),
+            None,
+            &[],
+            None,
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("timed out after 1s")
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_command_through_persistent_shell() {
+        let shell = SharedShell::default();
+
+        let output =
+            FileWatcher::run_command("echo a | tr a b", None, None, Some(&shell), None, &[], None)
+                .await
+                .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "b\n");
+
+        // Without the persistent shell, the command is tokenized and spawned directly
+        let output = FileWatcher::run_command("echo a | tr a b", None, None, None, None, &[], None)
+            .await
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "a | tr a b\n");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_command_is_not_rerun_after_shell_exits() {
+        let shell = SharedShell::default();
+        let temp_dir = TempDir::new().unwrap();
+        let runs = temp_dir.path().join("runs.log");
+
+        // `exit` kills the worker shell after the command's side effect happened
+        let command = format!("echo run >> '{}'; exit 3", runs.display());
+        let result =
+            FileWatcher::run_command(&command, None, None, Some(&shell), None, &[], None).await;
+        assert!(result.is_err(), "A lost shell fails the run");
+        assert_eq!(std::fs::read_to_string(&runs).unwrap(), "run\n");
+
+        let output = FileWatcher::run_command(
+            "echo still works",
+            None,
+            None,
+            Some(&shell),
+            None,
+            &[],
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "still works\n");
+    }
+
+    #[tokio::test]
+    async fn test_execute_shell_command_within_timeout() {
+        let result = FileWatcher::execute_shell_command(
+            "echo fast",
+            None,
+            Some(Duration::from_secs(5)),
+            None,
+            &[],
+            None,
+        )
+        .await;
+        assert!(result.is_ok());
+        assert!(result.unwrap().status.success());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_commands_receive_exported_env() {
+        let context = TemplateContext::new(
+            Path::new("/w/src/main.rs"),
+            Path::new("src/main.rs"),
+            &EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)),
+            Path::new("/w"),
+        );
+        let env = context.env_vars();
+        let command = "echo $VIBEWATCH_EVENT_TYPE $VIBEWATCH_RELATIVE_PATH";
+        let interpreter = vec!["sh".to_string(), "-c".to_string()];
+
+        let output =
+            FileWatcher::execute_shell_command(command, Some(&interpreter), None, None, &env, None)
+                .await
+                .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "modify src/main.rs\n"
+        );
+
+        let shell = SharedShell::default();
+        let output = FileWatcher::run_command(command, None, None, Some(&shell), None, &env, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "modify src/main.rs\n"
+        );
+
+        // The next command in the same shell doesn't see the last event's variables
+        let output = FileWatcher::run_command(
+            "echo ${VIBEWATCH_RELATIVE_PATH-unset}",
+            None,
+            None,
+            Some(&shell),
+            None,
+            &[],
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "unset\n");
+
+        let names: Vec<_> = env.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, ENV_VAR_NAMES);
+    }
+
+    #[test]
+    fn test_command_config_returns_every_command_in_order() {
+        let config = CommandConfig {
+            on_modify: vec!["lint".to_string(), "notify".to_string(), "log".to_string()],
+            on_change: vec!["fallback".to_string()],
+            ..Default::default()
+        };
+
+        let modify = EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any));
+        assert_eq!(
+            config.get_command_for_event(&modify),
+            ["lint", "notify", "log"]
+        );
+        // Specific commands replace the fallback rather than adding to it
+        assert_eq!(
+            config.get_command_for_event(&EventKind::Create(CreateKind::File)),
+            ["fallback"]
+        );
+        assert!(
+            CommandConfig::default()
+                .get_command_for_event(&modify)
+                .is_empty()
+        );
+    }
+
+    #[rstest]
+    #[case("{dir_of_file}", WorkingDir::DirOfFile)]
+    #[case("build", WorkingDir::Path(PathBuf::from("build")))]
+    #[case("/tmp", WorkingDir::Path(PathBuf::from("/tmp")))]
+    fn test_working_dir_from_str(#[case] value: &str, #[case] expected: WorkingDir) {
+        assert_eq!(value.parse::<WorkingDir>().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_command_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let build_dir = temp_dir.path().join("build");
+        std::fs::create_dir(&build_dir).unwrap();
+        let watcher_with = |working_dir| {
+            let config = CommandConfig {
+                working_dir,
+                ..Default::default()
+            };
+            FileWatcher::new(
+                temp_dir.path().to_path_buf(),
+                vec![],
+                vec![],
+                config,
+                0,
+                false,
+                false,
+            )
+            .unwrap()
+        };
+        let watch_path = temp_dir.path().canonicalize().unwrap();
+        let file = watch_path.join("src").join("main.rs");
+
+        let watcher = watcher_with(None);
+        assert_eq!(watcher.command_dir(Some(&file)), watch_path);
+
+        let watcher = watcher_with(Some(WorkingDir::Path(build_dir.clone())));
+        assert_eq!(
+            watcher.command_dir(Some(&file)),
+            build_dir.canonicalize().unwrap()
+        );
+
+        let watcher = watcher_with(Some(WorkingDir::DirOfFile));
+        assert_eq!(watcher.command_dir(Some(&file)), watch_path.join("src"));
+        assert_eq!(watcher.command_dir(None), watch_path);
+    }
+
+    #[test]
+    fn test_file_watcher_rejects_missing_working_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CommandConfig {
+            working_dir: Some(WorkingDir::Path(temp_dir.path().join("missing"))),
+            ..Default::default()
+        };
+
+        let result = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            config,
+            0,
+            false,
+            false,
+        );
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("Working directory does not exist")
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_commands_run_in_working_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().canonicalize().unwrap();
+        let expected = format!("{}\n", dir.display());
+
+        let output = FileWatcher::execute_shell_command("pwd", None, None, Some(&dir), &[], None)
+            .await
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+
+        let shell = SharedShell::default();
+        let output =
+            FileWatcher::run_command("pwd", None, None, Some(&shell), Some(&dir), &[], None)
+                .await
+                .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+    }
+
+    // Parameterized test for all event kind to string conversions
+    #[rstest]
+    #[case(EventKind::Create(CreateKind::File), "create")]
+    #[case(EventKind::Create(CreateKind::Folder), "create")]
+    #[case(EventKind::Create(CreateKind::Any), "create")]
+    #[case(EventKind::Modify(ModifyKind::Any), "modify")]
+    #[case(
+        EventKind::Modify(ModifyKind::Name(notify::event::RenameMode::Any)),
+        "modify"
+    )]
+    #[case(
+        EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)),
+        "modify"
+    )]
+    #[case(EventKind::Remove(RemoveKind::File), "delete")]
+    #[case(EventKind::Remove(RemoveKind::Folder), "delete")]
+    #[case(EventKind::Remove(RemoveKind::Any), "delete")]
+    fn test_event_kind_to_string_conversion(#[case] event_kind: EventKind, #[case] expected: &str) {
+        assert_eq!(
+            expected,
+            TemplateContext::event_kind_to_str(&event_kind),
+            "EventKind {:?} should convert to '{}'",
+            event_kind,
+            expected
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_event_with_valid_path() {
+        use std::fs;
+        let temp_dir = TempDir::new().unwrap();
+        let config = CommandConfig {
+            on_create: Vec::new(),
+            on_modify: vec!["echo test".to_string()],
+            on_delete: Vec::new(),
+            on_change: Vec::new(),
+            ..Default::default()
+        };
+
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            config,
+            0,
+            false,
+            false,
+        )
+        .unwrap();
+
+        // Create a test file
+        let test_file = temp_dir.path().join("test.txt");
+        fs::write(&test_file, "test").unwrap();
+
+        // Create an event
+        let event = Event {
+            kind: EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)),
+            paths: vec![test_file.canonicalize().unwrap()],
+            attrs: Default::default(),
+        };
+
+        // This should execute without panic
+        watcher.handle_event(event);
+    }
+
+    #[test]
+    fn test_handle_event_with_filtered_path() {
+        use std::fs;
+        let temp_dir = TempDir::new().unwrap();
+        let config = CommandConfig {
+            on_create: Vec::new(),
+            on_modify: vec!["echo test".to_string()],
+            on_delete: Vec::new(),
+            on_change: Vec::new(),
+            ..Default::default()
+        };
+
+        // Only watch .rs files
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec!["*.rs".to_string()],
+            vec![],
+            config,
+            0,
+            false,
+            false,
+        )
+        .unwrap();
+
+        // Create a non-matching file
+        let test_file = temp_dir.path().join("test.txt");
+        fs::write(&test_file, "test").unwrap();
+
+        // Create an event for non-matching file
+        let event = Event {
+            kind: EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)),
+            paths: vec![test_file.canonicalize().unwrap()],
+            attrs: Default::default(),
+        };
+
+        // This should not execute command (filtered out)
+        watcher.handle_event(event);
+    }
+
+    #[test]
+    fn test_handle_event_ignored_event_types() {
+        use std::fs;
+        let temp_dir = TempDir::new().unwrap();
+        let config = CommandConfig {
+            on_create: Vec::new(),
+            on_modify: vec!["echo test".to_string()],
+            on_delete: Vec::new(),
+            on_change: Vec::new(),
+            ..Default::default()
+        };
+
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            config,
+            0,
+            false,
+            false,
+        )
+        .unwrap();
+
+        let test_file = temp_dir.path().join("test.txt");
+        fs::write(&test_file, "test").unwrap();
+
+        // Create an Access event (should be ignored)
+        let event = Event {
+            kind: EventKind::Access(notify::event::AccessKind::Any),
+            paths: vec![test_file.canonicalize().unwrap()],
+            attrs: Default::default(),
+        };
+
+        // This should return early without processing
+        watcher.handle_event(event);
+    }
+
+    #[tokio::test]
+    async fn test_handle_event_modify_name_with_existing_file() {
+        use std::fs;
+        let temp_dir = TempDir::new().unwrap();
+        let config = CommandConfig {
+            on_create: Vec::new(),
+            on_modify: vec!["echo renamed".to_string()],
+            on_delete: Vec::new(),
+            on_change: Vec::new(),
+            ..Default::default()
         };
 
-        let result = config.get_command_for_event(&event);
-        let expected_str = expected.map(|s| s.to_string());
-        assert_eq!(
-            result,
-            expected_str.as_ref(),
-            "Config({:?}, {:?}, {:?}, {:?}) with event {:?} should return {:?}",
-            on_create,
-            on_modify,
-            on_delete,
-            on_change,
-            event,
-            expected
-        );
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            config,
+            0,
+            false,
+            false,
+        )
+        .unwrap();
+
+        let test_file = temp_dir.path().join("test.txt");
+        fs::write(&test_file, "test").unwrap();
+
+        // Create a ModifyName event with existing file
+        let event = Event {
+            kind: EventKind::Modify(ModifyKind::Name(notify::event::RenameMode::Any)),
+            paths: vec![test_file.canonicalize().unwrap()],
+            attrs: Default::default(),
+        };
+
+        watcher.handle_event(event);
     }
 
-    // Test TemplateContext with parameterized event types
-    #[rstest]
-    #[case(
-        "/tmp/test/src/main.rs",
-        "src/main.rs",
-        EventKind::Create(CreateKind::File),
-        "create",
-        "/tmp/test/src/main.rs"
-    )]
-    #[case(
-        "/tmp/test/file.txt",
-        "file.txt",
-        EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)),
-        "modify",
-        "/tmp/test/file.txt"
-    )]
-    #[case(
-        "/tmp/test/file.txt",
-        "file.txt",
-        EventKind::Remove(RemoveKind::File),
-        "delete",
-        "/tmp/test/file.txt"
-    )]
-    #[case(
-        "/tmp/test/file.txt",
-        "file.txt",
-        EventKind::Access(notify::event::AccessKind::Any),
-        "change",
-        "/tmp/test/file.txt"
-    )]
-    fn test_template_context_event_types(
-        #[case] file_path_str: &str,
-        #[case] relative_path_str: &str,
-        #[case] event: EventKind,
-        #[case] expected_event_type: &str,
-        #[case] expected_absolute: &str,
-    ) {
-        let file_path = PathBuf::from(file_path_str);
-        let relative_path = PathBuf::from(relative_path_str);
-        let watch_path = PathBuf::from("/tmp/test");
+    #[tokio::test]
+    async fn test_handle_event_modify_name_with_nonexistent_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CommandConfig {
+            on_create: Vec::new(),
+            on_modify: Vec::new(),
+            on_delete: vec!["echo deleted".to_string()],
+            on_change: Vec::new(),
+            ..Default::default()
+        };
 
-        let ctx = TemplateContext::new(&file_path, &relative_path, &event, &watch_path);
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            config,
+            0,
+            false,
+            false,
+        )
+        .unwrap();
 
-        assert_eq!(ctx.file_path, file_path_str);
-        assert_eq!(ctx.relative_path, relative_path_str);
-        assert_eq!(ctx.event_type, expected_event_type);
-        assert_eq!(ctx.absolute_path, expected_absolute);
+        // Use a path that doesn't exist
+        let nonexistent_file = temp_dir
+            .path()
+            .canonicalize()
+            .unwrap()
+            .join("nonexistent.txt");
+
+        // Create a ModifyName event (will be treated as delete since file doesn't exist)
+        let event = Event {
+            kind: EventKind::Modify(ModifyKind::Name(notify::event::RenameMode::Any)),
+            paths: vec![nonexistent_file],
+            attrs: Default::default(),
+        };
+
+        watcher.handle_event(event);
     }
 
-    #[test]
-    fn test_template_substitution_all_variables() {
-        let file_path = PathBuf::from("/home/user/project/src/lib.rs");
-        let relative_path = PathBuf::from("src/lib.rs");
-        let watch_path = PathBuf::from("/home/user/project");
-        let event = EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any));
+    #[tokio::test]
+    async fn test_handle_event_create_event() {
+        use std::fs;
+        let temp_dir = TempDir::new().unwrap();
+        let config = CommandConfig {
+            on_create: vec!["echo created".to_string()],
+            on_modify: Vec::new(),
+            on_delete: Vec::new(),
+            on_change: Vec::new(),
+            ..Default::default()
+        };
 
-        let ctx = TemplateContext::new(&file_path, &relative_path, &event, &watch_path);
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            config,
+            0,
+            false,
+            false,
+        )
+        .unwrap();
 
-        let template = "Event: {event_type}, File: {file_path}, Relative: {relative_path}, Absolute: {absolute_path}";
-        let result = ctx.substitute_template(template);
+        let test_file = temp_dir.path().join("new.txt");
+        fs::write(&test_file, "new").unwrap();
 
-        assert_eq!(
-            result,
-            "Event: modify, File: /home/user/project/src/lib.rs, Relative: src/lib.rs, Absolute: /home/user/project/src/lib.rs"
-        );
+        let event = Event {
+            kind: EventKind::Create(CreateKind::File),
+            paths: vec![test_file.canonicalize().unwrap()],
+            attrs: Default::default(),
+        };
+
+        watcher.handle_event(event);
     }
 
-    #[test]
-    fn test_template_substitution_partial() {
-        let file_path = PathBuf::from("/tmp/file.txt");
-        let relative_path = PathBuf::from("file.txt");
-        let watch_path = PathBuf::from("/tmp");
-        let event = EventKind::Create(CreateKind::File);
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_handle_event_on_watched_root_runs_root_command() {
+        let temp_dir = TempDir::new().unwrap();
+        let markers = TempDir::new().unwrap();
+        let root_marker = markers.path().join("root.txt");
+        let change_marker = markers.path().join("change.txt");
 
-        let ctx = TemplateContext::new(&file_path, &relative_path, &event, &watch_path);
+        let config = CommandConfig {
+            on_change: vec![format!("touch {}", change_marker.display())],
+            on_root_change: vec![format!(
+                "sh -c 'echo {{relative_path}} > {}'",
+                root_marker.display()
+            )],
+            ..Default::default()
+        };
 
-        let template = "File created: {relative_path}";
-        let result = ctx.substitute_template(template);
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec!["*.rs".to_string()],
+            vec![],
+            config,
+            0,
+            false,
+            false,
+        )
+        .unwrap();
 
-        assert_eq!(result, "File created: file.txt");
+        // Root events bypass the include filters and the regular commands
+        let event = Event::new(EventKind::Modify(ModifyKind::Metadata(
+            notify::event::MetadataKind::Any,
+        )))
+        .add_path(watcher.watch_path.clone());
+        watcher.handle_event(event);
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        assert_eq!(std::fs::read_to_string(&root_marker).unwrap(), ".\n");
+        assert!(!change_marker.exists());
     }
 
-    #[test]
-    fn test_template_substitution_no_variables() {
-        let file_path = PathBuf::from("/tmp/file.txt");
-        let relative_path = PathBuf::from("file.txt");
-        let watch_path = PathBuf::from("/tmp");
-        let event = EventKind::Create(CreateKind::File);
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_handle_event_on_watched_root_ignored_by_default() {
+        let temp_dir = TempDir::new().unwrap();
+        let markers = TempDir::new().unwrap();
+        let change_marker = markers.path().join("change.txt");
 
-        let ctx = TemplateContext::new(&file_path, &relative_path, &event, &watch_path);
+        let config = CommandConfig {
+            on_change: vec![format!("touch {}", change_marker.display())],
+            ..Default::default()
+        };
 
-        let template = "echo 'Hello World'";
-        let result = ctx.substitute_template(template);
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            config,
+            0,
+            false,
+            false,
+        )
+        .unwrap();
 
-        assert_eq!(result, "echo 'Hello World'");
+        let event = Event::new(EventKind::Modify(ModifyKind::Metadata(
+            notify::event::MetadataKind::Any,
+        )))
+        .add_path(watcher.watch_path.clone());
+        watcher.handle_event(event);
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        assert!(!change_marker.exists());
     }
 
-    #[test]
-    fn test_template_substitution_multiple_same_variable() {
-        let file_path = PathBuf::from("/tmp/file.txt");
-        let relative_path = PathBuf::from("file.txt");
-        let watch_path = PathBuf::from("/tmp");
-        let event = EventKind::Create(CreateKind::File);
+    #[tokio::test]
+    async fn test_handle_event_records_metrics() {
+        use std::fs;
+        let temp_dir = TempDir::new().unwrap();
+        let config = CommandConfig {
+            on_create: vec!["echo created".to_string()],
+            ..Default::default()
+        };
 
-        let ctx = TemplateContext::new(&file_path, &relative_path, &event, &watch_path);
+        let metrics = Arc::new(Metrics::default());
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            config,
+            0,
+            false,
+            true,
+        )
+        .unwrap()
+        .with_metrics(Arc::clone(&metrics));
 
-        let template = "{relative_path} -> {relative_path}";
-        let result = ctx.substitute_template(template);
+        let test_file = temp_dir.path().join("new.txt");
+        fs::write(&test_file, "new").unwrap();
 
-        assert_eq!(result, "file.txt -> file.txt");
+        watcher.handle_event(
+            Event::new(EventKind::Create(CreateKind::File))
+                .add_path(test_file.canonicalize().unwrap()),
+        );
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        let rendered = metrics.render();
+        assert!(rendered.contains("vibewatch_events_total{type=\"create\"} 1\n"));
+        assert!(rendered.contains("vibewatch_commands_total 1\n"));
+        assert!(rendered.contains("vibewatch_command_failures_total 0\n"));
+    }
+
+    #[tokio::test]
+    async fn test_stats_count_received_filtered_and_commands() {
+        use crate::stats::StatsSnapshot;
+
+        let temp_dir = TempDir::new().unwrap();
+        let config = CommandConfig {
+            on_create: vec!["echo created".to_string()],
+            on_modify: vec!["vibewatch-nonexistent-command".to_string()],
+            ..Default::default()
+        };
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec!["*.rs".to_string()],
+            vec![],
+            config,
+            0,
+            false,
+            true,
+        )
+        .unwrap()
+        .with_stats(Duration::from_secs(60));
+
+        let canonical = temp_dir.path().canonicalize().unwrap();
+        std::fs::write(canonical.join("lib.rs"), "changed").unwrap();
+        let events = [
+            Event::new(EventKind::Create(CreateKind::File)).add_path(canonical.join("main.rs")),
+            Event::new(EventKind::Create(CreateKind::File)).add_path(canonical.join("notes.txt")),
+            Event::new(EventKind::Modify(ModifyKind::Data(
+                notify::event::DataChange::Any,
+            )))
+            .add_path(canonical.join("lib.rs")),
+            Event::new(EventKind::Access(notify::event::AccessKind::Open(
+                notify::event::AccessMode::Read,
+            )))
+            .add_path(canonical.join("lib.rs")),
+        ];
+        for event in events {
+            watcher.handle_event(event);
+        }
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        let stats = watcher.stats.as_ref().unwrap();
+        assert_eq!(
+            stats.snapshot(),
+            StatsSnapshot {
+                received: 4,
+                filtered: 2,
+                commands: 2,
+                failed: 1,
+            }
+        );
     }
 
-    // Test FileWatcher initialization
-    #[test]
-    fn test_file_watcher_new_valid_directory() {
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_summary_counts_changes_commands_and_command_time() {
         let temp_dir = TempDir::new().unwrap();
         let config = CommandConfig {
-            on_create: None,
-            on_modify: None,
-            on_delete: None,
-            on_change: None,
+            on_create: vec!["sleep 0.2".to_string()],
+            on_modify: vec!["false".to_string()],
+            ..Default::default()
         };
-
-        let result = FileWatcher::new(
+        let watcher = FileWatcher::new(
             temp_dir.path().to_path_buf(),
             vec![],
             vec![],
             config,
             0,
             false,
-            false,
+            true,
+        )
+        .unwrap()
+        .with_summary_on_exit(true);
+
+        let canonical = temp_dir.path().canonicalize().unwrap();
+        let events = [
+            Event::new(EventKind::Create(CreateKind::File)).add_path(canonical.join("a.txt")),
+            Event::new(EventKind::Create(CreateKind::File)).add_path(canonical.join("b.txt")),
+            Event::new(EventKind::Modify(ModifyKind::Data(
+                notify::event::DataChange::Any,
+            )))
+            .add_path(canonical.join("a.txt")),
+            Event::new(EventKind::Remove(RemoveKind::File)).add_path(canonical.join("b.txt")),
+        ];
+        for event in events {
+            watcher.handle_event(event);
+        }
+
+        tokio::time::sleep(Duration::from_millis(800)).await;
+
+        let summary = watcher.stats.as_ref().unwrap().summary();
+        assert_eq!(
+            (
+                summary.created,
+                summary.modified,
+                summary.deleted,
+                summary.moved
+            ),
+            (2, 1, 1, 0)
+        );
+        assert_eq!(summary.changes(), 4);
+        // The delete has no command
+        assert_eq!((summary.stats.commands, summary.stats.failed), (3, 1));
+        // Both sleeps ran concurrently, but their times add up
+        assert!(
+            summary.command_time >= Duration::from_millis(400),
+            "{:?}",
+            summary.command_time
         );
-        assert!(result.is_ok());
     }
 
-    #[test]
-    fn test_file_watcher_new_nonexistent_directory() {
+    #[tokio::test]
+    async fn test_result_log_writes_a_json_line_per_command() {
+        let temp_dir = TempDir::new().unwrap();
+        let logs = TempDir::new().unwrap();
+        let path = logs.path().join("results.jsonl");
         let config = CommandConfig {
-            on_create: None,
-            on_modify: None,
-            on_delete: None,
-            on_change: None,
+            on_modify: vec!["echo first".to_string(), "echo second".to_string()],
+            ..Default::default()
         };
-
-        let result = FileWatcher::new(
-            PathBuf::from("/nonexistent/path/that/does/not/exist"),
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
             vec![],
             vec![],
             config,
             0,
             false,
-            false,
+            true,
+        )
+        .unwrap()
+        .with_result_log(&path)
+        .unwrap();
+
+        watcher.handle_event(
+            Event::new(EventKind::Modify(ModifyKind::Data(
+                notify::event::DataChange::Any,
+            )))
+            .add_path(temp_dir.path().canonicalize().unwrap().join("a.txt")),
         );
-        assert!(result.is_err());
-        let err_msg = result.unwrap_err().to_string();
-        assert!(err_msg.contains("Directory does not exist"));
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        let mut records: Vec<serde_json::Value> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        // Both commands run concurrently
+        records.sort_by_key(|record| record["command"].to_string());
+        assert_eq!(records.len(), 2);
+        for (record, command) in records.iter().zip(["echo first", "echo second"]) {
+            assert_eq!(record["command"], command);
+            assert_eq!(record["exit_code"], 0);
+            assert_eq!(record["success"], true);
+            assert!(record["duration_ms"].is_u64());
+            assert_eq!(record["stdout_bytes"], command.len() - "echo ".len() + 1);
+            assert_eq!(record["stderr_bytes"], 0);
+        }
     }
 
     #[test]
-    fn test_file_watcher_new_file_not_directory() {
-        let temp_dir = TempDir::new().unwrap();
-        let file_path = temp_dir.path().join("test_file.txt");
-        std::fs::write(&file_path, "test").unwrap();
+    fn test_command_activity_covers_running_commands_and_window() {
+        let window = Duration::from_millis(100);
+        let start = Instant::now();
+        let mut activity = CommandActivity::default();
+        assert!(!activity.covers(window, start));
+
+        activity.started();
+        activity.started();
+        assert!(activity.covers(window, start + Duration::from_secs(60)));
+
+        // Still covered while the second command runs
+        activity.finished(start + Duration::from_millis(10));
+        assert!(activity.covers(window, start + Duration::from_secs(60)));
+
+        activity.finished(start + Duration::from_millis(500));
+        assert!(activity.covers(window, start + Duration::from_millis(599)));
+        assert!(!activity.covers(window, start + Duration::from_millis(600)));
+    }
 
+    #[tokio::test]
+    async fn test_skip_unchanged_runs_command_once_for_identical_writes() {
+        let temp_dir = TempDir::new().unwrap();
         let config = CommandConfig {
-            on_create: None,
-            on_modify: None,
-            on_delete: None,
-            on_change: None,
+            on_modify: vec!["echo modified".to_string()],
+            ..Default::default()
+        };
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            config,
+            0,
+            false,
+            true,
+        )
+        .unwrap()
+        .with_skip_unchanged(true)
+        .with_stats(Duration::from_secs(60));
+
+        let file = temp_dir.path().canonicalize().unwrap().join("notes.txt");
+        let modify = || {
+            Event::new(EventKind::Modify(ModifyKind::Data(
+                notify::event::DataChange::Any,
+            )))
+            .add_path(file.clone())
         };
 
-        let result = FileWatcher::new(file_path, vec![], vec![], config, 0, false, false);
-        assert!(result.is_err());
-        let err_msg = result.unwrap_err().to_string();
-        assert!(err_msg.contains("Path is not a directory"));
+        std::fs::write(&file, "same").unwrap();
+        assert!(watcher.handle_event(modify()));
+        std::fs::write(&file, "same").unwrap();
+        assert!(!watcher.handle_event(modify()));
+
+        // A real change goes through again, whether or not the size changed
+        std::fs::write(&file, "diff").unwrap();
+        assert!(watcher.handle_event(modify()));
+        std::fs::write(&file, "different").unwrap();
+        assert!(watcher.handle_event(modify()));
+        // The first identical write after a size change is skipped too
+        std::fs::write(&file, "different").unwrap();
+        assert!(!watcher.handle_event(modify()));
+
+        // Files too large to hash always count as changed
+        let large = vec![b'x'; MAX_HASHED_SIZE as usize + 1];
+        std::fs::write(&file, &large).unwrap();
+        assert!(watcher.handle_event(modify()));
+        std::fs::write(&file, &large).unwrap();
+        assert!(watcher.handle_event(modify()));
+
+        // A file deleted before it could be read is not skipped
+        std::fs::remove_file(&file).unwrap();
+        assert!(watcher.handle_event(modify()));
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let stats = watcher.stats.as_ref().unwrap();
+        assert_eq!(stats.snapshot().commands, 6);
     }
 
-    #[test]
-    fn test_file_watcher_with_invalid_include_pattern() {
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_max_concurrent_runs_commands_one_at_a_time() {
+        use std::fs;
         let temp_dir = TempDir::new().unwrap();
+        let markers = TempDir::new().unwrap();
+        let log = markers.path().join("log.txt");
+
         let config = CommandConfig {
-            on_create: None,
-            on_modify: None,
-            on_delete: None,
-            on_change: None,
+            on_modify: vec![format!(
+                "sh -c 'echo start >> {log}; sleep 0.3; echo end >> {log}'",
+                log = log.display()
+            )],
+            max_concurrent: 1,
+            ..Default::default()
         };
 
-        let result = FileWatcher::new(
+        let watcher = FileWatcher::new(
             temp_dir.path().to_path_buf(),
-            vec!["[invalid".to_string()],
+            vec![],
             vec![],
             config,
             0,
             false,
-            false,
-        );
-        assert!(result.is_err());
+            true,
+        )
+        .unwrap();
+
+        for name in ["a.txt", "b.txt"] {
+            let file = temp_dir.path().join(name);
+            fs::write(&file, "x").unwrap();
+            watcher.handle_event(
+                Event::new(EventKind::Modify(ModifyKind::Data(
+                    notify::event::DataChange::Any,
+                )))
+                .add_path(file.canonicalize().unwrap()),
+            );
+        }
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+
+        // Overlapping runs would interleave as start, start, end, end
+        let lines: Vec<String> = fs::read_to_string(&log)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect();
+        assert_eq!(lines, ["start", "end", "start", "end"]);
     }
 
-    #[test]
-    fn test_file_watcher_with_invalid_exclude_pattern() {
+    #[cfg(unix)]
+    #[rstest]
+    #[case(OverlapMode::Drop, 1)]
+    #[case(OverlapMode::Queue, 2)]
+    #[tokio::test]
+    async fn test_no_overlap_limits_concurrent_runs(
+        #[case] mode: OverlapMode,
+        #[case] expected_runs: usize,
+    ) {
+        use std::fs;
         let temp_dir = TempDir::new().unwrap();
+        let markers = TempDir::new().unwrap();
+        let runs = markers.path().join("runs.txt");
+
         let config = CommandConfig {
-            on_create: None,
-            on_modify: None,
-            on_delete: None,
-            on_change: None,
+            on_modify: vec![format!(
+                "sh -c 'sleep 0.5; echo {{relative_path}} >> {}'",
+                runs.display()
+            )],
+            no_overlap: Some(mode),
+            ..Default::default()
         };
 
-        let result = FileWatcher::new(
+        let watcher = FileWatcher::new(
             temp_dir.path().to_path_buf(),
             vec![],
-            vec!["[invalid".to_string()],
+            vec![],
             config,
             0,
             false,
-            false,
-        );
-        assert!(result.is_err());
-    }
+            true,
+        )
+        .unwrap();
 
-    // Test execute_shell_command
-    #[tokio::test]
-    async fn test_execute_shell_command_success() {
-        let result = FileWatcher::execute_shell_command("echo test").await;
-        assert!(result.is_ok());
-        let output = result.unwrap();
-        assert!(output.status.success());
-    }
+        // Three events for the same command while the first run is still active
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            let file = temp_dir.path().join(name);
+            fs::write(&file, "x").unwrap();
+            watcher.handle_event(
+                Event::new(EventKind::Modify(ModifyKind::Data(
+                    notify::event::DataChange::Any,
+                )))
+                .add_path(file.canonicalize().unwrap()),
+            );
+        }
 
-    #[tokio::test]
-    async fn test_execute_shell_command_with_args() {
-        let result = FileWatcher::execute_shell_command("echo hello world").await;
-        assert!(result.is_ok());
-        let output = result.unwrap();
-        assert!(output.status.success());
-        assert!(String::from_utf8_lossy(&output.stdout).contains("hello world"));
+        tokio::time::sleep(Duration::from_millis(2000)).await;
+
+        let lines: Vec<String> = fs::read_to_string(&runs)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect();
+        assert_eq!(lines.len(), expected_runs, "runs: {:?}", lines);
+        assert_eq!(lines[0], "a.txt");
+        if mode == OverlapMode::Queue {
+            // Only the latest trigger is kept in the queue
+            assert_eq!(lines[1], "c.txt");
+        }
     }
 
+    #[cfg(unix)]
+    #[rstest]
+    #[case("true", true)]
+    #[case("false", false)]
+    #[case("nonexistent_command_xyz", false)]
     #[tokio::test]
-    async fn test_execute_shell_command_failure() {
-        // Use a command that should fail
-        let result = FileWatcher::execute_shell_command("false").await;
-        assert!(result.is_ok());
-        let output = result.unwrap();
-        assert!(!output.status.success());
-        assert_eq!(output.status.code(), Some(1));
+    async fn test_run_and_report_returns_success(#[case] command: &str, #[case] expected: bool) {
+        let run = CommandRun {
+            command: command.to_string(),
+            timeout: None,
+            dir: std::env::temp_dir(),
+            env: Vec::new(),
+            follow_up: None,
+        };
+        let outcome = FileWatcher::run_and_report(
+            &run,
+            None,
+            None,
+            RunRecorders::default(),
+            RunDisplay {
+                output: Output::Text,
+                name: None,
+                quiet: true,
+                max_output_lines: None,
+                missing_programs: None,
+            },
+        )
+        .await;
+        assert_eq!(outcome == RunOutcome::Succeeded, expected);
     }
 
+    #[cfg(unix)]
     #[tokio::test]
-    async fn test_execute_shell_command_empty() {
-        let result = FileWatcher::execute_shell_command("").await;
-        assert!(result.is_err());
-        let err_msg = result.unwrap_err().to_string();
-        assert!(err_msg.contains("Empty command"));
+    async fn test_streamed_output_is_capped_but_fully_collected() {
+        let sink = Arc::new(LineSink::new(Output::Text, None, Some(10)));
+        let parts = ["seq".to_string(), "1000".to_string()];
+        let output = FileWatcher::run_process("seq 1000", &parts, None, None, &[], Some(&sink))
+            .await
+            .unwrap();
+
+        assert_eq!(sink.counts(), (10, 990));
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout).lines().count(),
+            1000
+        );
     }
 
-    #[tokio::test]
-    async fn test_execute_shell_command_nonexistent() {
-        let result = FileWatcher::execute_shell_command("nonexistent_command_12345").await;
-        assert!(result.is_err());
+    #[cfg(windows)]
+    #[rstest]
+    #[case(r"\\?\C:\Users\dev\project\main.rs", "C:/Users/dev/project/main.rs")]
+    #[case(r"\\?\UNC\server\share\main.rs", "//server/share/main.rs")]
+    #[case(r"C:\Users\dev\main.rs", "C:/Users/dev/main.rs")]
+    fn test_normalize_path_strips_verbatim_prefix(#[case] path: &str, #[case] expected: &str) {
+        assert_eq!(TemplateContext::normalize_path(Path::new(path)), expected);
     }
 
+    #[cfg(windows)]
     #[test]
-    fn test_event_kind_to_string_all_types() {
-        assert_eq!(
-            TemplateContext::event_kind_to_str(&EventKind::Create(CreateKind::File)),
-            "create"
-        );
-        assert_eq!(
-            TemplateContext::event_kind_to_str(&EventKind::Modify(ModifyKind::Data(
-                notify::event::DataChange::Any
-            ))),
-            "modify"
+    fn test_template_absolute_path_without_verbatim_prefix() {
+        let context = TemplateContext::new(
+            Path::new(r"\\?\C:\project\src\main.rs"),
+            Path::new(r"src\main.rs"),
+            &EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)),
+            Path::new(r"\\?\C:\project"),
         );
         assert_eq!(
-            TemplateContext::event_kind_to_str(&EventKind::Remove(RemoveKind::File)),
-            "delete"
+            context.substitute_template("{file_path} {absolute_path} {relative_path}"),
+            "C:/project/src/main.rs C:/project/src/main.rs src/main.rs"
         );
-        assert_eq!(
-            TemplateContext::event_kind_to_str(&EventKind::Access(notify::event::AccessKind::Any)),
-            "change"
+    }
+
+    #[cfg(unix)]
+    #[rstest]
+    #[case("true", "success 0 a.txt")]
+    #[case("false", "failure 1 a.txt")]
+    // The empty {exit_code} collapses in the unquoted echo
+    #[case("nonexistent_command_xyz", "failure a.txt")]
+    #[tokio::test]
+    async fn test_command_hooks_run_for_outcome(#[case] command: &str, #[case] expected: &str) {
+        use std::fs;
+        let temp_dir = TempDir::new().unwrap();
+        let markers = TempDir::new().unwrap();
+        let log = markers.path().join("hooks.txt");
+
+        let hook = |outcome: &str| {
+            format!(
+                "sh -c 'echo {} {{exit_code}} {{relative_path}} >> {}'",
+                outcome,
+                log.display()
+            )
+        };
+        let config = CommandConfig {
+            on_modify: vec![command.to_string()],
+            on_command_success: vec![hook("success")],
+            on_command_failure: vec![hook("failure")],
+            ..Default::default()
+        };
+
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            config,
+            0,
+            false,
+            true,
+        )
+        .unwrap();
+
+        let file = temp_dir.path().join("a.txt");
+        fs::write(&file, "x").unwrap();
+        watcher.handle_event(
+            Event::new(EventKind::Modify(ModifyKind::Data(
+                notify::event::DataChange::Any,
+            )))
+            .add_path(file.canonicalize().unwrap()),
         );
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        // Exactly one hook ran, and the hook's own success triggered nothing
+        let lines: Vec<String> = fs::read_to_string(&log)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect();
+        assert_eq!(lines, [expected]);
     }
 
-    #[test]
-    fn test_get_relative_path_success() {
+    #[tokio::test]
+    async fn test_sequential_runs_commands_in_event_order() {
+        use std::fs;
         let temp_dir = TempDir::new().unwrap();
+        let markers = TempDir::new().unwrap();
+        let log = markers.path().join("order.txt");
+
+        // Each file holds how long its command sleeps, so the first event's
+        // command would finish last if they ran concurrently
         let config = CommandConfig {
-            on_create: None,
-            on_modify: None,
-            on_delete: None,
-            on_change: None,
+            on_modify: vec![format!(
+                "sh -c 'sleep $(cat {{file_path}}); echo {{file_name}} >> {}'",
+                log.display()
+            )],
+            sequential: true,
+            ..Default::default()
         };
+        let mut watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            config,
+            0,
+            false,
+            true,
+        )
+        .unwrap()
+        .with_wait_on_exit(None);
+
+        let root = temp_dir.path().canonicalize().unwrap();
+        for (name, delay) in [("a.txt", "0.3"), ("b.txt", "0.15"), ("c.txt", "0")] {
+            fs::write(root.join(name), delay).unwrap();
+            watcher.handle_event(
+                Event::new(EventKind::Modify(ModifyKind::Data(
+                    notify::event::DataChange::Any,
+                )))
+                .add_path(root.join(name)),
+            );
+        }
+
+        watcher.wait_for_commands().await;
+
+        let lines: Vec<String> = fs::read_to_string(&log)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        assert_eq!(lines, ["a.txt", "b.txt", "c.txt"]);
+    }
+
+    #[tokio::test]
+    async fn test_count_numbers_events_from_zero() {
+        use std::fs;
+        let temp_dir = TempDir::new().unwrap();
+        let markers = TempDir::new().unwrap();
+        let log = markers.path().join("count.txt");
 
+        let config = CommandConfig {
+            on_modify: vec![format!(
+                "sh -c 'echo {{count}} {{file_name}} >> {}'",
+                log.display()
+            )],
+            ..Default::default()
+        };
         let watcher = FileWatcher::new(
             temp_dir.path().to_path_buf(),
             vec![],
@@ -892,25 +7137,170 @@ mod tests {
             config,
             0,
             false,
+            true,
+        )
+        .unwrap();
+
+        let root = temp_dir.path().canonicalize().unwrap();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            watcher.handle_event(
+                Event::new(EventKind::Modify(ModifyKind::Data(
+                    notify::event::DataChange::Any,
+                )))
+                .add_path(root.join(name)),
+            );
+        }
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        // Commands run concurrently, so sort before comparing
+        let mut lines: Vec<String> = fs::read_to_string(&log)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        lines.sort();
+        assert_eq!(lines, ["0 a.txt", "1 b.txt", "2 c.txt"]);
+    }
+
+    #[tokio::test]
+    async fn test_routes_pick_command_by_path() {
+        use std::fs;
+        let temp_dir = TempDir::new().unwrap();
+        let markers = TempDir::new().unwrap();
+        let log = markers.path().join("routes.txt");
+        let record = |label: &str| {
+            format!(
+                "sh -c 'echo {} {{relative_path}} >> {}'",
+                label,
+                log.display()
+            )
+        };
+
+        let config = CommandConfig {
+            on_modify: vec![record("other")],
+            routes: vec![
+                ("*.rs".to_string(), record("rust")),
+                ("*.{md,txt}".to_string(), record("docs")),
+                // Never reached for .rs files, the first matching route wins
+                ("src/**".to_string(), record("src")),
+            ],
+            ..Default::default()
+        };
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            config,
+            0,
             false,
+            true,
         )
         .unwrap();
 
-        // Use canonicalized path since FileWatcher stores canonicalized paths
-        let file_path = temp_dir.path().canonicalize().unwrap().join("test.txt");
-        let relative = watcher.get_relative_path(&file_path);
+        let root = temp_dir.path().canonicalize().unwrap();
+        for name in ["src/lib.rs", "README.md", "notes.txt", "Cargo.toml"] {
+            watcher.handle_event(
+                Event::new(EventKind::Modify(ModifyKind::Data(
+                    notify::event::DataChange::Any,
+                )))
+                .add_path(root.join(name)),
+            );
+        }
 
-        assert_eq!(relative, Some(PathBuf::from("test.txt")));
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        let mut lines: Vec<String> = fs::read_to_string(&log)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        lines.sort();
+        assert_eq!(
+            lines,
+            [
+                "docs README.md",
+                "docs notes.txt",
+                "other Cargo.toml",
+                "rust src/lib.rs"
+            ]
+        );
     }
 
     #[test]
-    fn test_get_relative_path_nested() {
+    fn test_invalid_route_pattern_is_rejected() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CommandConfig {
+            routes: vec![("[unclosed".to_string(), "echo".to_string())],
+            ..Default::default()
+        };
+        let result = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            config,
+            0,
+            false,
+            true,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_template_substitution_count() {
+        let context = TemplateContext::startup();
+        assert_eq!(context.substitute_template("out-{count}.log"), "out-.log");
+        let context = context.with_count(7);
+        assert_eq!(context.substitute_template("out-{count}.log"), "out-7.log");
+    }
+
+    #[rstest]
+    #[case(Some(0), "0")]
+    #[case(Some(2), "2")]
+    #[case(None, "")]
+    fn test_template_substitution_exit_code(#[case] code: Option<i32>, #[case] expected: &str) {
+        let context = TemplateContext::startup().with_exit_code(code);
+        assert_eq!(context.substitute_template("{exit_code}"), expected);
+    }
+
+    #[test]
+    fn test_min_interval_limits_each_template() {
+        let temp_dir = TempDir::new().unwrap();
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            CommandConfig::default(),
+            0,
+            false,
+            true,
+        )
+        .unwrap()
+        .with_min_interval(Duration::from_millis(200));
+        let start = Instant::now();
+
+        assert!(!watcher.is_rate_limited("make", start));
+        assert!(!watcher.is_rate_limited("lint", start));
+        // Skipped runs don't restart the interval
+        assert!(watcher.is_rate_limited("make", start + Duration::from_millis(100)));
+        assert!(watcher.is_rate_limited("make", start + Duration::from_millis(199)));
+        assert!(!watcher.is_rate_limited("make", start + Duration::from_millis(200)));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_min_interval_skips_rapid_events() {
+        use std::fs;
         let temp_dir = TempDir::new().unwrap();
+        let markers = TempDir::new().unwrap();
+        let runs = markers.path().join("runs.txt");
+
         let config = CommandConfig {
-            on_create: None,
-            on_modify: None,
-            on_delete: None,
-            on_change: None,
+            on_modify: vec![format!(
+                "sh -c 'echo {{relative_path}} >> {}'",
+                runs.display()
+            )],
+            ..Default::default()
         };
 
         let watcher = FileWatcher::new(
@@ -920,30 +7310,46 @@ mod tests {
             config,
             0,
             false,
-            false,
+            true,
         )
-        .unwrap();
+        .unwrap()
+        .with_min_interval(Duration::from_secs(60));
+
+        for name in ["a.txt", "b.txt", "c.txt", "d.txt", "e.txt"] {
+            let file = temp_dir.path().join(name);
+            fs::write(&file, "x").unwrap();
+            watcher.handle_event(
+                Event::new(EventKind::Modify(ModifyKind::Data(
+                    notify::event::DataChange::Any,
+                )))
+                .add_path(file.canonicalize().unwrap()),
+            );
+        }
 
-        // Use canonicalized path since FileWatcher stores canonicalized paths
-        let file_path = temp_dir
-            .path()
-            .canonicalize()
-            .unwrap()
-            .join("src")
-            .join("main.rs");
-        let relative = watcher.get_relative_path(&file_path);
+        tokio::time::sleep(Duration::from_millis(500)).await;
 
-        assert_eq!(relative, Some(PathBuf::from("src/main.rs")));
+        let lines: Vec<String> = fs::read_to_string(&runs)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect();
+        assert_eq!(lines, ["a.txt"]);
     }
 
-    #[test]
-    fn test_get_relative_path_outside_watch_dir() {
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_coalesce_global_runs_path_less_command_once() {
+        use std::fs;
         let temp_dir = TempDir::new().unwrap();
+        let markers = TempDir::new().unwrap();
+        let runs = markers.path().join("runs.txt");
+
         let config = CommandConfig {
-            on_create: None,
-            on_modify: None,
-            on_delete: None,
-            on_change: None,
+            on_modify: vec![
+                format!("sh -c 'echo build >> {}'", runs.display()),
+                format!("sh -c 'echo {{relative_path}} >> {}'", runs.display()),
+            ],
+            ..Default::default()
         };
 
         let watcher = FileWatcher::new(
@@ -953,399 +7359,768 @@ mod tests {
             config,
             0,
             false,
-            false,
+            true,
         )
-        .unwrap();
+        .unwrap()
+        .with_coalesce_global(Duration::from_millis(100));
+
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            let file = temp_dir.path().join(name);
+            fs::write(&file, "x").unwrap();
+            watcher.handle_event(
+                Event::new(EventKind::Modify(ModifyKind::Data(
+                    notify::event::DataChange::Any,
+                )))
+                .add_path(file.canonicalize().unwrap()),
+            );
+        }
+        // The loop's ticker does this while watching
+        watcher.run_coalesced_commands(Instant::now() + Duration::from_millis(100), false);
 
-        // Try with a path outside the watch directory
-        let outside_path = PathBuf::from("/tmp/outside.txt");
-        let relative = watcher.get_relative_path(&outside_path);
+        tokio::time::sleep(Duration::from_millis(500)).await;
 
-        assert_eq!(relative, None);
+        let mut lines: Vec<String> = fs::read_to_string(&runs)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect();
+        lines.sort();
+        assert_eq!(lines, ["a.txt", "b.txt", "build", "c.txt"]);
     }
 
-    #[rstest]
-    // Create kind variants
-    #[case(
-        "create",
-        EventKind::Create(CreateKind::File),
-        Some("create"),
-        None,
-        None
-    )]
-    #[case(
-        "create",
-        EventKind::Create(CreateKind::Folder),
-        Some("create"),
-        None,
-        None
-    )]
-    #[case(
-        "create",
-        EventKind::Create(CreateKind::Any),
-        Some("create"),
-        None,
-        None
-    )]
-    // Modify kind variants
-    #[case(
-        "modify",
-        EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)),
-        None,
-        Some("modify"),
-        None
-    )]
-    #[case(
-        "modify",
-        EventKind::Modify(ModifyKind::Name(notify::event::RenameMode::Any)),
-        None,
-        Some("modify"),
-        None
-    )]
-    #[case(
-        "modify",
-        EventKind::Modify(ModifyKind::Any),
-        None,
-        Some("modify"),
-        None
-    )]
-    // Remove kind variants
-    #[case(
-        "delete",
-        EventKind::Remove(RemoveKind::File),
-        None,
-        None,
-        Some("delete")
-    )]
-    #[case(
-        "delete",
-        EventKind::Remove(RemoveKind::Folder),
-        None,
-        None,
-        Some("delete")
-    )]
-    #[case(
-        "delete",
-        EventKind::Remove(RemoveKind::Any),
-        None,
-        None,
-        Some("delete")
-    )]
-    fn test_command_config_all_event_kind_variants(
-        #[case] expected_cmd: &str,
-        #[case] event: EventKind,
-        #[case] on_create: Option<&str>,
-        #[case] on_modify: Option<&str>,
-        #[case] on_delete: Option<&str>,
-    ) {
-        let config = CommandConfig {
-            on_create: on_create.map(|s| s.to_string()),
-            on_modify: on_modify.map(|s| s.to_string()),
-            on_delete: on_delete.map(|s| s.to_string()),
-            on_change: None,
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_and_report_detects_timeout() {
+        let run = CommandRun {
+            command: "sleep 10".to_string(),
+            timeout: Some(Duration::from_millis(100)),
+            dir: std::env::temp_dir(),
+            env: Vec::new(),
+            follow_up: None,
         };
+        let outcome = FileWatcher::run_and_report(
+            &run,
+            None,
+            None,
+            RunRecorders::default(),
+            RunDisplay {
+                output: Output::Text,
+                name: None,
+                quiet: true,
+                max_output_lines: None,
+                missing_programs: None,
+            },
+        )
+        .await;
+        assert_eq!(outcome, RunOutcome::TimedOut);
+    }
 
+    #[tokio::test]
+    async fn test_missing_program_is_reported_as_not_found() {
+        let result = FileWatcher::execute_shell_command(
+            "nonexistent_command_xyz --flag",
+            None,
+            None,
+            None,
+            &[],
+            None,
+        )
+        .await;
+        let error = result.unwrap_err();
+        assert!(error.is::<CommandNotFound>());
         assert_eq!(
-            config.get_command_for_event(&event),
-            Some(&expected_cmd.to_string()),
-            "Event {:?} should return command '{}'",
-            event,
-            expected_cmd
+            error.to_string(),
+            "Command not found: nonexistent_command_xyz"
         );
-    }
-
-    #[test]
-    fn test_template_context_nested_paths() {
-        let file_path = PathBuf::from("/home/user/project/src/deep/nested/file.rs");
-        let relative_path = PathBuf::from("src/deep/nested/file.rs");
-        let watch_path = PathBuf::from("/home/user/project");
-        let event = EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any));
 
-        let ctx = TemplateContext::new(&file_path, &relative_path, &event, &watch_path);
+        // A missing working directory isn't blamed on the program
+        let temp_dir = TempDir::new().unwrap();
+        let missing_dir = temp_dir.path().join("gone");
+        let result =
+            FileWatcher::execute_shell_command("true", None, None, Some(&missing_dir), &[], None)
+                .await;
+        assert!(!result.unwrap_err().is::<CommandNotFound>());
+    }
 
-        assert_eq!(ctx.file_path, "/home/user/project/src/deep/nested/file.rs");
-        assert_eq!(ctx.relative_path, "src/deep/nested/file.rs");
+    #[tokio::test]
+    async fn test_missing_program_is_remembered_once() {
+        let missing_programs = Mutex::default();
+        let display = RunDisplay {
+            output: Output::Text,
+            name: None,
+            quiet: true,
+            max_output_lines: None,
+            missing_programs: Some(&missing_programs),
+        };
+        for command in [
+            "nonexistent_command_xyz a.txt",
+            "nonexistent_command_xyz b.txt",
+        ] {
+            let run = CommandRun {
+                command: command.to_string(),
+                timeout: None,
+                dir: std::env::temp_dir(),
+                env: Vec::new(),
+                follow_up: None,
+            };
+            let outcome =
+                FileWatcher::run_and_report(&run, None, None, RunRecorders::default(), display)
+                    .await;
+            assert_eq!(outcome, RunOutcome::Failed(None));
+        }
         assert_eq!(
-            ctx.absolute_path,
-            "/home/user/project/src/deep/nested/file.rs"
+            *missing_programs.lock().unwrap(),
+            HashSet::from(["nonexistent_command_xyz".to_string()])
         );
     }
 
-    #[test]
-    fn test_template_substitution_edge_cases() {
-        let file_path = PathBuf::from("/tmp/test.txt");
-        let relative_path = PathBuf::from("test.txt");
-        let watch_path = PathBuf::from("/tmp");
-        let event = EventKind::Create(CreateKind::File);
+    #[rstest]
+    #[case(RunOutcome::Succeeded, false, false)]
+    #[case(RunOutcome::Failed(Some(1)), false, true)]
+    #[case(RunOutcome::TimedOut, false, false)]
+    #[case(RunOutcome::TimedOut, true, true)]
+    fn test_run_outcome_should_retry(
+        #[case] outcome: RunOutcome,
+        #[case] retry_timeouts: bool,
+        #[case] expected: bool,
+    ) {
+        assert_eq!(outcome.should_retry(retry_timeouts), expected);
+    }
 
-        let ctx = TemplateContext::new(&file_path, &relative_path, &event, &watch_path);
+    #[cfg(unix)]
+    #[rstest]
+    #[case(1, 2, true)]
+    #[case(0, 1, false)]
+    #[tokio::test]
+    async fn test_retries_rerun_failed_command(
+        #[case] retries: u32,
+        #[case] expected_attempts: usize,
+        #[case] expected_success: bool,
+    ) {
+        use std::fs;
+        let temp_dir = TempDir::new().unwrap();
+        let markers = TempDir::new().unwrap();
+        let attempts = markers.path().join("attempts.txt");
 
-        // Empty template
-        assert_eq!(ctx.substitute_template(""), "");
+        // Fails the first time it runs, succeeds once the attempts file exists
+        let config = CommandConfig {
+            on_modify: vec![format!(
+                "sh -c 'test -f {log}; found=$?; echo attempt >> {log}; exit $found'",
+                log = attempts.display()
+            )],
+            retries,
+            retry_delay_ms: 50,
+            ..Default::default()
+        };
 
-        // Template with no placeholders
-        assert_eq!(ctx.substitute_template("static text"), "static text");
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            config,
+            0,
+            false,
+            true,
+        )
+        .unwrap()
+        .with_stats(Duration::from_secs(60));
+
+        let file = temp_dir.path().join("a.txt");
+        fs::write(&file, "x").unwrap();
+        watcher.handle_event(
+            Event::new(EventKind::Modify(ModifyKind::Data(
+                notify::event::DataChange::Any,
+            )))
+            .add_path(file.canonicalize().unwrap()),
+        );
 
-        // Template with incomplete placeholder
-        assert_eq!(ctx.substitute_template("{file"), "{file");
-        assert_eq!(ctx.substitute_template("file_path}"), "file_path}");
+        tokio::time::sleep(Duration::from_millis(800)).await;
 
-        // Template with unknown placeholder
-        assert_eq!(ctx.substitute_template("{unknown}"), "{unknown}");
+        let runs = fs::read_to_string(&attempts).unwrap().lines().count();
+        assert_eq!(runs, expected_attempts);
+
+        // A retried command counts once, with its final outcome
+        let stats = watcher.stats.as_ref().unwrap();
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.commands, 1);
+        assert_eq!(snapshot.failed, u64::from(!expected_success));
     }
 
-    #[test]
-    fn test_file_watcher_with_patterns() {
+    #[cfg(unix)]
+    #[rstest]
+    #[case(None, true)]
+    #[case(Some(Duration::from_secs(5)), true)]
+    #[case(Some(Duration::from_millis(100)), false)]
+    #[tokio::test]
+    async fn test_wait_for_commands_on_shutdown(
+        #[case] exit_timeout: Option<Duration>,
+        #[case] completes: bool,
+    ) {
         let temp_dir = TempDir::new().unwrap();
+        let markers = TempDir::new().unwrap();
+        let marker = markers.path().join("done");
+
         let config = CommandConfig {
-            on_create: None,
-            on_modify: None,
-            on_delete: None,
-            on_change: None,
+            on_modify: vec![format!("sh -c 'sleep 0.5; touch {}'", marker.display())],
+            ..Default::default()
         };
-
-        let watcher = FileWatcher::new(
+        let mut watcher = FileWatcher::new(
             temp_dir.path().to_path_buf(),
-            vec!["*.rs".to_string()],
-            vec!["target/**".to_string()],
+            vec![],
+            vec![],
             config,
             0,
             false,
-            false,
+            true,
+        )
+        .unwrap()
+        .with_wait_on_exit(exit_timeout);
+
+        let file = temp_dir.path().canonicalize().unwrap().join("a.txt");
+        watcher.handle_event(
+            Event::new(EventKind::Modify(ModifyKind::Data(
+                notify::event::DataChange::Any,
+            )))
+            .add_path(file),
         );
 
-        assert!(watcher.is_ok());
+        // Shutting down right after the command started
+        watcher.wait_for_commands().await;
+        assert_eq!(marker.exists(), completes);
     }
 
     #[tokio::test]
-    async fn test_execute_shell_command_with_output() {
-        let result = FileWatcher::execute_shell_command("echo test123").await;
-        assert!(result.is_ok());
-        let output = result.unwrap();
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        assert!(stdout.contains("test123"));
-    }
+    async fn test_on_event_handler_receives_changes() {
+        use std::sync::Mutex;
+        use std::sync::atomic::{AtomicUsize, Ordering};
 
-    // Parameterized test for all event kind to string conversions
-    #[rstest]
-    #[case(EventKind::Create(CreateKind::File), "create")]
-    #[case(EventKind::Create(CreateKind::Folder), "create")]
-    #[case(EventKind::Create(CreateKind::Any), "create")]
-    #[case(EventKind::Modify(ModifyKind::Any), "modify")]
-    #[case(
-        EventKind::Modify(ModifyKind::Name(notify::event::RenameMode::Any)),
-        "modify"
-    )]
-    #[case(
-        EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)),
-        "modify"
-    )]
-    #[case(EventKind::Remove(RemoveKind::File), "delete")]
-    #[case(EventKind::Remove(RemoveKind::Folder), "delete")]
-    #[case(EventKind::Remove(RemoveKind::Any), "delete")]
-    fn test_event_kind_to_string_conversion(#[case] event_kind: EventKind, #[case] expected: &str) {
-        assert_eq!(
-            expected,
-            TemplateContext::event_kind_to_str(&event_kind),
-            "EventKind {:?} should convert to '{}'",
-            event_kind,
-            expected
-        );
+        let temp_dir = TempDir::new().unwrap();
+        let mut watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec!["*.rs".to_string()],
+            vec![],
+            CommandConfig::default(),
+            0,
+            false,
+            true,
+        )
+        .unwrap();
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        {
+            let calls = Arc::clone(&calls);
+            let seen = Arc::clone(&seen);
+            watcher.on_event(move |context| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                seen.lock().unwrap().push(context.clone());
+            });
+        }
+
+        let canonical = temp_dir.path().canonicalize().unwrap();
+        for name in ["main.rs", "notes.txt"] {
+            watcher.handle_event(
+                Event::new(EventKind::Create(CreateKind::File)).add_path(canonical.join(name)),
+            );
+        }
+
+        // Filtered-out files never reach the handler
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen[0].relative_path(), Path::new("main.rs"));
+        assert_eq!(seen[0].absolute_path(), canonical.join("main.rs"));
+        assert_eq!(seen[0].event_type(), "create");
+        assert_eq!(seen[0].old_path(), None);
     }
 
     #[tokio::test]
-    async fn test_handle_event_with_valid_path() {
-        use std::fs;
+    async fn test_event_stream_yields_filtered_changes() {
+        use tokio_stream::StreamExt;
+
         let temp_dir = TempDir::new().unwrap();
-        let config = CommandConfig {
-            on_create: None,
-            on_modify: Some("echo test".to_string()),
-            on_delete: None,
-            on_change: None,
-        };
+        let markers = TempDir::new().unwrap();
+        let marker = markers.path().join("ran");
+        let canonical = temp_dir.path().canonicalize().unwrap();
+        let mut events = crate::WatcherBuilder::new(temp_dir.path())
+            .include("*.rs")
+            .on_change(format!("touch {}", marker.display()))
+            .quiet(true)
+            .build()
+            .unwrap()
+            .into_event_stream();
+
+        // Give the watcher task time to register with the OS
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        std::fs::write(temp_dir.path().join("notes.txt"), "ignored").unwrap();
+        std::fs::write(temp_dir.path().join("main.rs"), "fn main() {}").unwrap();
+
+        let event = tokio::time::timeout(Duration::from_secs(5), events.next())
+            .await
+            .expect("no event within 5s")
+            .expect("stream ended early");
+        assert_eq!(event.relative_path, Path::new("main.rs"));
+        assert_eq!(event.absolute_path, canonical.join("main.rs"));
+        assert!(matches!(event.event_type(), "create" | "modify"));
+
+        // Debounced into one event, and the .txt file was filtered out
+        let extra = tokio::time::timeout(Duration::from_millis(500), events.next()).await;
+        assert!(extra.is_err(), "unexpected event: {:?}", extra);
+
+        // The stream's consumer handles changes, not the configured command
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    fn test_event_stream_prints_no_status_lines() {
+        let temp_dir = TempDir::new().unwrap();
+        let (tx, _events) = mpsc::unbounded_channel();
+        let watcher = crate::WatcherBuilder::new(temp_dir.path())
+            .build()
+            .unwrap()
+            .with_heartbeat(Duration::from_secs(1))
+            .with_stats(Duration::from_secs(1))
+            .with_summary_on_exit(true)
+            .streaming_to(tx);
+
+        assert!(watcher.quiet);
+        assert_eq!(watcher.heartbeat, None);
+        assert_eq!(watcher.stats_interval, None);
+        assert!(!watcher.summary_on_exit);
+    }
+
+    #[test]
+    fn test_reload_rules_replaces_filter_and_commands() {
+        let temp_dir = TempDir::new().unwrap();
+        let rules = Arc::new(Mutex::new(WatchRules {
+            include: vec!["*.md".to_string()],
+            exclude: vec![],
+            commands: CommandConfig {
+                on_change: vec!["echo reloaded".to_string()],
+                ..Default::default()
+            },
+        }));
+        let reloaded = Arc::clone(&rules);
+        let mut watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec!["*.rs".to_string()],
+            vec![],
+            CommandConfig::default(),
+            0,
+            false,
+            true,
+        )
+        .unwrap()
+        .with_reload(move || Ok(reloaded.lock().unwrap().clone()));
+
+        watcher.reload_rules().unwrap();
+        assert!(watcher.should_watch(Path::new("README.md")));
+        assert!(!watcher.should_watch(Path::new("main.rs")));
+        assert_eq!(watcher.command_config.on_change, ["echo reloaded"]);
+
+        // Invalid rules leave the current ones in place
+        rules.lock().unwrap().include = vec!["[invalid".to_string()];
+        assert!(watcher.reload_rules().is_err());
+        assert!(watcher.should_watch(Path::new("README.md")));
+        assert_eq!(watcher.command_config.on_change, ["echo reloaded"]);
+    }
 
-        let watcher = FileWatcher::new(
+    #[tokio::test]
+    async fn test_on_event_handler_panic_is_contained() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut watcher = FileWatcher::new(
             temp_dir.path().to_path_buf(),
             vec![],
             vec![],
-            config,
+            CommandConfig::default(),
             0,
             false,
-            false,
+            true,
         )
         .unwrap();
 
-        // Create a test file
-        let test_file = temp_dir.path().join("test.txt");
-        fs::write(&test_file, "test").unwrap();
+        let calls = Arc::new(AtomicUsize::new(0));
+        watcher.on_event(|_| panic!("handler bug"));
+        {
+            let calls = Arc::clone(&calls);
+            watcher.on_event(move |_| {
+                calls.fetch_add(1, Ordering::SeqCst);
+            });
+        }
 
-        // Create an event
-        let event = Event {
-            kind: EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)),
-            paths: vec![test_file.canonicalize().unwrap()],
-            attrs: Default::default(),
-        };
+        let file = temp_dir.path().canonicalize().unwrap().join("a.txt");
+        watcher.handle_event(Event::new(EventKind::Remove(RemoveKind::File)).add_path(file));
 
-        // This should execute without panic
-        watcher.handle_event(event);
+        // Later handlers still run after an earlier one panics
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
     }
 
     #[test]
-    fn test_handle_event_with_filtered_path() {
-        use std::fs;
-        let temp_dir = TempDir::new().unwrap();
-        let config = CommandConfig {
-            on_create: None,
-            on_modify: Some("echo test".to_string()),
-            on_delete: None,
-            on_change: None,
-        };
+    fn test_template_context_startup_is_empty() {
+        let context = TemplateContext::startup();
 
-        // Only watch .rs files
-        let watcher = FileWatcher::new(
-            temp_dir.path().to_path_buf(),
-            vec!["*.rs".to_string()],
-            vec![],
-            config,
-            0,
-            false,
-            false,
-        )
-        .unwrap();
+        assert_eq!(
+            context.substitute_template(
+                "build [{file_path}] [{relative_path}] [{absolute_path}] [{file_name}] [{changed_files}] {event_type}"
+            ),
+            "build [] [] [] [] [] start"
+        );
+    }
 
-        // Create a non-matching file
-        let test_file = temp_dir.path().join("test.txt");
-        fs::write(&test_file, "test").unwrap();
+    #[rstest]
+    #[case("echo ${MYVAR}", "echo hello")]
+    #[case("echo ${MYVAR}-{file_name}", "echo hello-a.txt")]
+    #[case("echo ${VIBEWATCH_TEST_UNSET_VAR}", "echo ")]
+    #[case("echo $MYVAR", "echo $MYVAR")]
+    #[case("echo ${MYVAR:-default}", "echo ${MYVAR:-default}")]
+    #[case("echo ${}", "echo ${}")]
+    #[case("echo ${MYVAR", "echo ${MYVAR")]
+    fn test_template_substitution_env_vars(#[case] template: &str, #[case] expected: &str) {
+        let context = TemplateContext::new(
+            Path::new("/w/a.txt"),
+            Path::new("a.txt"),
+            &EventKind::Create(CreateKind::File),
+            Path::new("/w"),
+        );
+        assert_eq!(
+            context.substitute(template, &context.changed_files, Some(&fake_env), None),
+            expected
+        );
+    }
 
-        // Create an event for non-matching file
-        let event = Event {
-            kind: EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)),
-            paths: vec![test_file.canonicalize().unwrap()],
-            attrs: Default::default(),
-        };
+    /// Environment lookup with only MYVAR=hello set
+    fn fake_env(name: &str) -> Option<String> {
+        (name == "MYVAR").then(|| "hello".to_string())
+    }
 
-        // This should not execute command (filtered out)
-        watcher.handle_event(event);
+    #[test]
+    fn test_template_env_vars_not_expanded_in_paths() {
+        let context = TemplateContext::new(
+            Path::new("/w/${MYVAR}.txt"),
+            Path::new("${MYVAR}.txt"),
+            &EventKind::Create(CreateKind::File),
+            Path::new("/w"),
+        );
+        assert_eq!(
+            context.substitute(
+                "cat {file_name}",
+                &context.changed_files,
+                Some(&fake_env),
+                None
+            ),
+            "cat ${MYVAR}.txt"
+        );
     }
 
     #[test]
-    fn test_handle_event_ignored_event_types() {
-        use std::fs;
-        let temp_dir = TempDir::new().unwrap();
-        let config = CommandConfig {
-            on_create: None,
-            on_modify: Some("echo test".to_string()),
-            on_delete: None,
-            on_change: None,
-        };
+    fn test_template_env_vars_left_to_the_shell() {
+        let context = TemplateContext::new(
+            Path::new("/w/a.txt"),
+            Path::new("a.txt"),
+            &EventKind::Create(CreateKind::File),
+            Path::new("/w"),
+        );
+        // f is a loop variable, unset in vibewatch itself
+        let template = "for f in *; do echo ${f} ${HOME}; done";
+        assert_eq!(
+            context.substitute_command(template, Some(ShellQuoting::Posix)),
+            template
+        );
+    }
+
+    #[test]
+    fn test_template_substitution_timestamp() {
+        use chrono::TimeZone;
 
+        let time = chrono::FixedOffset::east_opt(2 * 3600)
+            .unwrap()
+            .with_ymd_and_hms(2024, 5, 17, 9, 30, 15)
+            .unwrap();
+        let context = TemplateContext::new(
+            Path::new("/w/a.txt"),
+            Path::new("a.txt"),
+            &EventKind::Create(CreateKind::File),
+            Path::new("/w"),
+        )
+        .with_event_time(time);
+
+        assert_eq!(
+            context.substitute_template("log {timestamp} {relative_path}"),
+            "log 2024-05-17T09:30:15.000+02:00 a.txt"
+        );
+    }
+
+    #[test]
+    fn test_template_substitution_matched_pattern() {
+        let temp_dir = TempDir::new().unwrap();
         let watcher = FileWatcher::new(
             temp_dir.path().to_path_buf(),
+            vec!["*.rs".to_string(), "*.{md,toml}".to_string()],
             vec![],
-            vec![],
-            config,
+            CommandConfig::default(),
             0,
             false,
             false,
         )
         .unwrap();
 
-        let test_file = temp_dir.path().join("test.txt");
-        fs::write(&test_file, "test").unwrap();
-
-        // Create an Access event (should be ignored)
-        let event = Event {
-            kind: EventKind::Access(notify::event::AccessKind::Any),
-            paths: vec![test_file.canonicalize().unwrap()],
-            attrs: Default::default(),
+        let expand = |relative: &str| {
+            let change = FileChange {
+                path: temp_dir.path().join(relative),
+                relative_path: PathBuf::from(relative),
+                kind: EventKind::Create(CreateKind::File),
+                old_path: None,
+                time: chrono::Local::now(),
+                matched_pattern: watcher.matched_pattern(Path::new(relative)),
+            };
+            change
+                .template_context(temp_dir.path())
+                .substitute_template("[{matched_pattern}]")
         };
+        assert_eq!(expand("main.rs"), "[*.rs]");
+        assert_eq!(expand("Cargo.toml"), "[*.toml]");
+        assert_eq!(expand("README.md"), "[*.md]");
+
+        // Without include patterns, and at startup, it expands to nothing
+        let context = TemplateContext::new(
+            Path::new("/w/a.txt"),
+            Path::new("a.txt"),
+            &EventKind::Create(CreateKind::File),
+            Path::new("/w"),
+        );
+        assert_eq!(context.substitute_template("[{matched_pattern}]"), "[]");
+        assert_eq!(
+            TemplateContext::startup().substitute_template("[{matched_pattern}]"),
+            "[]"
+        );
+    }
 
-        // This should return early without processing
-        watcher.handle_event(event);
+    #[rstest]
+    #[case("echo {{literal}}", "echo {literal}")]
+    #[case("jq '{{key: .val}}' {file_name}", "jq '{key: .val}' a.txt")]
+    #[case("{{{file_name}}}", "{a.txt}")]
+    #[case("{{file_name}}", "{file_name}")]
+    #[case("${{HOME}}", "${HOME}")]
+    #[case("awk '{print $1}' {file_name}", "awk '{print $1}' a.txt")]
+    fn test_template_escaped_braces(#[case] template: &str, #[case] expected: &str) {
+        let context = TemplateContext::new(
+            Path::new("/w/a.txt"),
+            Path::new("a.txt"),
+            &EventKind::Create(CreateKind::File),
+            Path::new("/w"),
+        );
+
+        assert_eq!(context.substitute_template(template), expected);
+    }
+
+    #[test]
+    fn test_template_timestamp_defaults_to_now() {
+        let before = chrono::Local::now();
+        let context = TemplateContext::startup();
+        let after = chrono::Local::now();
+
+        let timestamp = context.substitute_template("{timestamp}");
+        let parsed = chrono::DateTime::parse_from_rfc3339(&timestamp).unwrap();
+        // Millisecond precision, so compare against a truncated lower bound
+        assert!(parsed >= before - chrono::Duration::milliseconds(1));
+        assert!(parsed <= after);
+    }
+
+    #[test]
+    fn test_template_substitution_changed_files() {
+        let context = TemplateContext::new(
+            Path::new("/w/src/main.rs"),
+            Path::new("src/main.rs"),
+            &EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)),
+            Path::new("/w"),
+        );
+        // A single change expands to its own relative path
+        assert_eq!(
+            context.substitute_template("{changed_files}"),
+            "src/main.rs"
+        );
+
+        let context = context.with_changed_files([
+            Path::new("src/lib.rs"),
+            Path::new("docs/my notes.md"),
+            Path::new("src/main.rs"),
+        ]);
+        assert_eq!(
+            context.substitute_template("fmt {changed_files}"),
+            "fmt src/lib.rs 'docs/my notes.md' src/main.rs"
+        );
+    }
+
+    #[rstest]
+    #[case("run", Ok(ControlCommand::Run))]
+    #[case("  stats ", Ok(ControlCommand::Stats))]
+    #[case("quit", Ok(ControlCommand::Quit))]
+    #[case("restart", Err(()))]
+    #[case("RUN", Err(()))]
+    fn test_control_command_parsing(
+        #[case] line: &str,
+        #[case] expected: Result<ControlCommand, ()>,
+    ) {
+        assert_eq!(line.parse::<ControlCommand>().map_err(|_| ()), expected);
+    }
+
+    #[rstest]
+    #[case("make", &[(Chain::Always, "make")])]
+    #[case("echo a && false && echo c", &[(Chain::Always, "echo a"), (Chain::OnSuccess, "false"), (Chain::OnSuccess, "echo c")])]
+    #[case("echo a; false ;echo c", &[(Chain::Always, "echo a"), (Chain::Always, "false"), (Chain::Always, "echo c")])]
+    #[case("echo 'a && b'; echo \"c;d\"", &[(Chain::Always, "echo 'a && b'"), (Chain::Always, "echo \"c;d\"")])]
+    #[case("find . -exec rm {} \\;", &[(Chain::Always, "find . -exec rm {} \\;")])]
+    #[case("sleep 1 & echo", &[(Chain::Always, "sleep 1 & echo")])]
+    #[case(" ; make ;", &[(Chain::Always, "make")])]
+    #[case("", &[])]
+    fn test_split_chain(#[case] command: &str, #[case] expected: &[(Chain, &str)]) {
+        assert_eq!(split_chain(command), expected);
     }
 
+    #[cfg(unix)]
+    #[rstest]
+    #[case("echo a && false && echo c", "a\n", false)]
+    #[case("echo a ; false ; echo c", "a\nc\n", true)]
+    #[case("false && echo b ; echo c", "c\n", true)]
+    #[case("echo a && true", "a\n", true)]
     #[tokio::test]
-    async fn test_handle_event_modify_name_with_existing_file() {
-        use std::fs;
-        let temp_dir = TempDir::new().unwrap();
-        let config = CommandConfig {
-            on_create: None,
-            on_modify: Some("echo renamed".to_string()),
-            on_delete: None,
-            on_change: None,
-        };
+    async fn test_execute_chained_commands(
+        #[case] command: &str,
+        #[case] stdout: &str,
+        #[case] success: bool,
+    ) {
+        let output = FileWatcher::execute_shell_command(command, None, None, None, &[], None)
+            .await
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), stdout);
+        assert_eq!(output.status.success(), success);
+    }
 
-        let watcher = FileWatcher::new(
-            temp_dir.path().to_path_buf(),
-            vec![],
-            vec![],
-            config,
-            0,
-            false,
-            false,
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_chained_commands_share_one_timeout() {
+        let result = FileWatcher::execute_shell_command(
+            "sleep 0.3 ; sleep 0.3 ; sleep 0.3",
+            None,
+            Some(Duration::from_millis(500)),
+            None,
+            &[],
+            None,
         )
-        .unwrap();
+        .await;
+        assert!(result.unwrap_err().is::<CommandTimedOut>());
+    }
 
-        let test_file = temp_dir.path().join("test.txt");
-        fs::write(&test_file, "test").unwrap();
+    #[test]
+    fn test_template_substitution_keeps_argument_boundaries() {
+        let context = TemplateContext::new(
+            Path::new("/w/my file.txt"),
+            Path::new("my file.txt"),
+            &EventKind::Create(CreateKind::File),
+            Path::new("/w"),
+        )
+        .with_changed_files([Path::new("a b.txt"), Path::new("c.txt")]);
 
-        // Create a ModifyName event with existing file
-        let event = Event {
-            kind: EventKind::Modify(ModifyKind::Name(notify::event::RenameMode::Any)),
-            paths: vec![test_file.canonicalize().unwrap()],
-            attrs: Default::default(),
-        };
+        assert_eq!(
+            context
+                .substitute_args("cp {file_path} 'backup/{file_stem} (old).txt'")
+                .unwrap(),
+            ["cp", "/w/my file.txt", "backup/my file (old).txt"]
+        );
+        // A bare {changed_files} becomes one argument per file
+        assert_eq!(
+            context
+                .substitute_args("fmt {changed_files} --files={changed_files}")
+                .unwrap(),
+            ["fmt", "a b.txt", "c.txt", "--files=a b.txt c.txt"]
+        );
+        assert!(context.substitute_args("echo 'unterminated").is_err());
 
-        watcher.handle_event(event);
+        // Shell commands get each value quoted
+        assert_eq!(
+            context.substitute_command("cat {file_name} | wc", Some(ShellQuoting::Posix)),
+            "cat 'my file.txt' | wc"
+        );
+        assert_eq!(
+            context.substitute_command("cat {file_name} | wc", None),
+            "cat 'my file.txt' '|' wc"
+        );
+        // Chains keep their separators, and values can't add new ones
+        assert_eq!(
+            context.substitute_command("touch {file_name} && echo {file_stem};ls", None),
+            "touch 'my file.txt' && echo 'my file'; ls"
+        );
     }
 
+    #[cfg(unix)]
     #[tokio::test]
-    async fn test_handle_event_modify_name_with_nonexistent_file() {
+    async fn test_path_with_spaces_is_passed_as_one_argument() {
+        let context = TemplateContext::new(
+            Path::new("/w/my file.txt"),
+            Path::new("my file.txt"),
+            &EventKind::Create(CreateKind::File),
+            Path::new("/w"),
+        );
+        let command = context.substitute_command("printf [%s] {file_name}", None);
+
+        let output = FileWatcher::execute_shell_command(&command, None, None, None, &[], None)
+            .await
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "[my file.txt]");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_shell_mode_quotes_file_names() {
         let temp_dir = TempDir::new().unwrap();
-        let config = CommandConfig {
-            on_create: None,
-            on_modify: None,
-            on_delete: Some("echo deleted".to_string()),
-            on_change: None,
-        };
+        let marker = temp_dir.path().join("pwned");
+        let name = "a b $(touch pwned).txt";
+        let context = TemplateContext::new(
+            &Path::new("/w").join(name),
+            Path::new(name),
+            &EventKind::Create(CreateKind::File),
+            Path::new("/w"),
+        )
+        .with_changed_files([Path::new(name), Path::new("c.txt")]);
+        // Placeholders the template already quotes get no second pair of quotes
+        let command = context.substitute_command(
+            "printf '[%s]' {file_name} {changed_files} \"{file_stem}\" '{file_name}'",
+            Some(ShellQuoting::Posix),
+        );
 
-        let watcher = FileWatcher::new(
-            temp_dir.path().to_path_buf(),
-            vec![],
-            vec![],
-            config,
-            0,
-            false,
-            false,
+        let interpreter = ["sh".to_string(), "-c".to_string()];
+        let output = FileWatcher::execute_shell_command(
+            &command,
+            Some(&interpreter),
+            None,
+            Some(temp_dir.path()),
+            &[],
+            None,
         )
+        .await
         .unwrap();
-
-        // Use a path that doesn't exist
-        let nonexistent_file = temp_dir
-            .path()
-            .canonicalize()
-            .unwrap()
-            .join("nonexistent.txt");
-
-        // Create a ModifyName event (will be treated as delete since file doesn't exist)
-        let event = Event {
-            kind: EventKind::Modify(ModifyKind::Name(notify::event::RenameMode::Any)),
-            paths: vec![nonexistent_file],
-            attrs: Default::default(),
-        };
-
-        watcher.handle_event(event);
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            format!("[{0}][{0}][c.txt][a b $(touch pwned)][{0}]", name)
+        );
+        assert!(!marker.exists(), "The file name must not run as code");
     }
 
+    #[cfg(unix)]
     #[tokio::test]
-    async fn test_handle_event_create_event() {
+    async fn test_handle_batch_runs_command_once() {
         use std::fs;
         let temp_dir = TempDir::new().unwrap();
+        let markers = TempDir::new().unwrap();
+        let runs = markers.path().join("runs.txt");
+
         let config = CommandConfig {
-            on_create: Some("echo created".to_string()),
-            on_modify: None,
-            on_delete: None,
-            on_change: None,
+            on_modify: vec![format!(
+                "sh -c 'echo {{changed_files}} >> {}'",
+                runs.display()
+            )],
+            ..Default::default()
         };
 
         let watcher = FileWatcher::new(
@@ -1353,32 +8128,42 @@ mod tests {
             vec![],
             vec![],
             config,
-            0,
-            false,
+            100,
             false,
+            true,
         )
-        .unwrap();
-
-        let test_file = temp_dir.path().join("new.txt");
-        fs::write(&test_file, "new").unwrap();
+        .unwrap()
+        .with_batch(true);
+
+        // Repeated changes to a file are listed once
+        let events = ["a.txt", "b.txt", "a.txt", "c.txt"]
+            .into_iter()
+            .map(|name| {
+                let file = temp_dir.path().join(name);
+                fs::write(&file, "x").unwrap();
+                Event::new(EventKind::Modify(ModifyKind::Data(
+                    notify::event::DataChange::Any,
+                )))
+                .add_path(file.canonicalize().unwrap())
+            })
+            .collect();
+        watcher.handle_batch(events);
 
-        let event = Event {
-            kind: EventKind::Create(CreateKind::File),
-            paths: vec![test_file.canonicalize().unwrap()],
-            attrs: Default::default(),
-        };
+        tokio::time::sleep(Duration::from_millis(500)).await;
 
-        watcher.handle_event(event);
+        let contents = fs::read_to_string(&runs).unwrap();
+        assert_eq!(contents, "a.txt b.txt c.txt\n");
     }
 
     #[tokio::test]
     async fn test_handle_event_delete_event() {
         let temp_dir = TempDir::new().unwrap();
         let config = CommandConfig {
-            on_create: None,
-            on_modify: None,
-            on_delete: Some("echo deleted".to_string()),
-            on_change: None,
+            on_create: Vec::new(),
+            on_modify: Vec::new(),
+            on_delete: vec!["echo deleted".to_string()],
+            on_change: Vec::new(),
+            ..Default::default()
         };
 
         let watcher = FileWatcher::new(
@@ -1406,19 +8191,74 @@ mod tests {
 
     #[test]
     fn test_log_file_change_coverage() {
-        use std::path::Path;
+        let temp_dir = TempDir::new().unwrap();
 
-        // Test all event types for log coverage
-        FileWatcher::log_file_change(Path::new("test.txt"), &EventKind::Create(CreateKind::File));
-        FileWatcher::log_file_change(
-            Path::new("test.txt"),
-            &EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)),
-        );
-        FileWatcher::log_file_change(Path::new("test.txt"), &EventKind::Remove(RemoveKind::File));
-        FileWatcher::log_file_change(
-            Path::new("test.txt"),
-            &EventKind::Access(notify::event::AccessKind::Any),
-        );
+        for output in [Output::Text, Output::Json] {
+            let watcher = FileWatcher::new(
+                temp_dir.path().to_path_buf(),
+                vec![],
+                vec![],
+                CommandConfig::default(),
+                0,
+                false,
+                false,
+            )
+            .unwrap()
+            .with_output(output);
+
+            // Test all event types for log coverage
+            for kind in [
+                EventKind::Create(CreateKind::File),
+                EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)),
+                EventKind::Remove(RemoveKind::File),
+                EventKind::Access(notify::event::AccessKind::Any),
+            ] {
+                watcher.log_file_change(&FileChange {
+                    path: temp_dir.path().join("test.txt"),
+                    relative_path: PathBuf::from("test.txt"),
+                    kind,
+                    old_path: None,
+                    time: chrono::Local::now(),
+                    matched_pattern: None,
+                });
+            }
+        }
+    }
+
+    #[rstest]
+    #[case("{event_type} {relative_path}", "modify src/main.rs")]
+    #[case("changed: {file_name} ({file_ext})", "changed: main.rs (rs)")]
+    #[case("[{old_path}]", "[]")]
+    fn test_formatted_change(#[case] format: &str, #[case] expected: &str) {
+        let temp_dir = TempDir::new().unwrap();
+        let watcher = FileWatcher::new(
+            temp_dir.path().to_path_buf(),
+            vec![],
+            vec![],
+            CommandConfig::default(),
+            0,
+            false,
+            false,
+        )
+        .unwrap();
+        let change = FileChange {
+            path: temp_dir.path().join("src/main.rs"),
+            relative_path: PathBuf::from("src/main.rs"),
+            kind: EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)),
+            old_path: None,
+            time: chrono::Local::now(),
+            matched_pattern: None,
+        };
+
+        // Without a format the default line is printed
+        assert_eq!(watcher.formatted_change(&change), None);
+
+        let watcher = watcher.with_format(format);
+        assert_eq!(watcher.formatted_change(&change).as_deref(), Some(expected));
+
+        // JSON lines keep their own shape
+        let watcher = watcher.with_output(Output::Json);
+        assert_eq!(watcher.formatted_change(&change), None);
     }
 
     #[test]
@@ -1426,10 +8266,11 @@ mod tests {
         use std::fs;
         let temp_dir = TempDir::new().unwrap();
         let config = CommandConfig {
-            on_create: None,
-            on_modify: None,
-            on_delete: None,
-            on_change: None,
+            on_create: Vec::new(),
+            on_modify: Vec::new(),
+            on_delete: Vec::new(),
+            on_change: Vec::new(),
+            ..Default::default()
         };
 
         let watcher = FileWatcher::new(
@@ -1448,11 +8289,53 @@ mod tests {
         let canonical = test_file.canonicalize().unwrap();
 
         // Should not panic when no command is configured
-        watcher.execute_command_for_event(
-            &canonical,
-            Path::new("test.txt"),
-            &EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)),
-        );
+        watcher.execute_command_for_event(&FileChange {
+            path: canonical,
+            relative_path: PathBuf::from("test.txt"),
+            kind: EventKind::Modify(ModifyKind::Data(notify::event::DataChange::Any)),
+            old_path: None,
+            time: chrono::Local::now(),
+            matched_pattern: None,
+        });
+    }
+
+    #[tokio::test]
+    async fn test_bounded_event_channel_drops_new_events_when_full() {
+        let (tx, mut rx, dropped) = event_channel(NonZeroUsize::new(2));
+        let dropped = dropped.unwrap();
+        let event = |name: &str| {
+            Ok(Event::new(EventKind::Create(CreateKind::File)).add_path(PathBuf::from(name)))
+        };
+
+        for name in ["/w/a", "/w/b", "/w/c", "/w/d", "/w/e"] {
+            tx.send(event(name));
+        }
+        assert_eq!(dropped.load(Ordering::Relaxed), 3);
+
+        // The oldest events are kept
+        for expected in ["/w/a", "/w/b"] {
+            let received = rx.recv().await.unwrap().unwrap();
+            assert_eq!(received.paths, [PathBuf::from(expected)]);
+        }
+
+        // Once drained, the channel accepts events again
+        tx.send(event("/w/f"));
+        let received = rx.recv().await.unwrap().unwrap();
+        assert_eq!(received.paths, [PathBuf::from("/w/f")]);
+        assert_eq!(dropped.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn test_unbounded_event_channel_never_drops() {
+        let (tx, mut rx, dropped) = event_channel(None);
+        assert!(dropped.is_none());
+
+        for _ in 0..1000 {
+            tx.send(Ok(Event::new(EventKind::Any)));
+        }
+        for _ in 0..1000 {
+            assert!(rx.recv().await.unwrap().is_ok());
+        }
     }
 
     #[test]
@@ -1466,10 +8349,11 @@ mod tests {
         let temp_dir = TempDir::new().unwrap();
 
         let config = CommandConfig {
-            on_create: None,
-            on_modify: None,
-            on_delete: None,
-            on_change: Some("echo test".to_string()),
+            on_create: Vec::new(),
+            on_modify: Vec::new(),
+            on_delete: Vec::new(),
+            on_change: vec!["echo test".to_string()],
+            ..Default::default()
         };
 
         let watcher = FileWatcher::new(
@@ -44,6 +44,16 @@ fn test_cli_version_flag() {
         .stdout(predicate::str::contains("vibewatch"));
 }
 
+#[test]
+fn test_cli_generate_completions() {
+    let mut cmd = Command::cargo_bin("vibewatch").unwrap();
+    cmd.args(["--generate-completions", "bash"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("vibewatch"))
+        .stdout(predicate::str::contains("--include"));
+}
+
 #[test]
 fn test_cli_requires_directory_argument() {
     let mut cmd = Command::cargo_bin("vibewatch").unwrap();
@@ -80,7 +90,10 @@ fn test_cli_rejects_nonexistent_directory() {
     cmd.arg("/nonexistent/directory/that/does/not/exist")
         .timeout(Duration::from_secs(1))
         .assert()
-        .failure();
+        .failure()
+        .stderr(predicate::str::contains(
+            "Path does not exist: /nonexistent/directory/that/does/not/exist",
+        ));
 }
 
 #[test]
@@ -668,3 +681,1471 @@ fn test_filter_brace_expansion_pattern() {
         "Brace expansion pattern *.{{rs,toml}} should match .rs and .toml files"
     );
 }
+
+/// Test that a move within the watched tree fires --on-move once instead of delete + create
+#[cfg(target_os = "linux")]
+#[test]
+fn test_move_within_tree_fires_single_move_event() {
+    let temp_dir = common::setup_test_dir();
+    common::create_test_file(&temp_dir, "before.txt", "content");
+
+    let markers_dir = common::setup_test_dir();
+    let move_marker = markers_dir.child("move_marker.txt");
+    let create_marker = markers_dir.child("create_marker.txt");
+    let delete_marker = markers_dir.child("delete_marker.txt");
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("--on-move")
+        .arg(common::touch_command(
+            &move_marker.path().display().to_string(),
+        ))
+        .arg("--on-create")
+        .arg(common::touch_command(
+            &create_marker.path().display().to_string(),
+        ))
+        .arg("--on-delete")
+        .arg(common::touch_command(
+            &delete_marker.path().display().to_string(),
+        ))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+
+    std::fs::rename(
+        temp_dir.child("before.txt").path(),
+        temp_dir.child("after.txt").path(),
+    )
+    .unwrap();
+
+    let move_fired = common::wait_for_file(move_marker.path(), common::MARKER_FILE_POLL_TIMEOUT);
+    thread::sleep(common::COMMAND_EXECUTION_TIME);
+
+    child.kill().expect("Failed to kill vibewatch");
+
+    assert!(
+        move_fired,
+        "Move command should run for an intra-tree rename"
+    );
+    assert!(
+        !create_marker.path().exists(),
+        "Create command should not run for a correlated move"
+    );
+    assert!(
+        !delete_marker.path().exists(),
+        "Delete command should not run for a correlated move"
+    );
+}
+
+/// Test that --batch runs the command once for a burst of changes across several files
+#[cfg(target_os = "linux")]
+#[test]
+fn test_batch_runs_command_once_for_burst() {
+    let temp_dir = common::setup_test_dir();
+    common::create_test_files(&temp_dir, &[("a.txt", "a"), ("b.txt", "b"), ("c.txt", "c")]);
+
+    let markers_dir = common::setup_test_dir();
+    let runs = markers_dir.child("runs.txt");
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("--debounce")
+        .arg("500")
+        .arg("--batch")
+        .arg("--shell")
+        .arg("sh -c")
+        .arg("--on-change")
+        .arg(format!(
+            "echo {{changed_files}} >> {}",
+            runs.path().display()
+        ))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        common::modify_test_file(&temp_dir, name, "changed");
+    }
+
+    let ran = common::wait_for_file(runs.path(), common::MARKER_FILE_POLL_TIMEOUT);
+    thread::sleep(common::EVENT_DETECTION_TIME);
+
+    child.kill().expect("Failed to kill vibewatch");
+
+    assert!(ran, "Batch command should run after the burst settles");
+    let contents = std::fs::read_to_string(runs.path()).unwrap();
+    let lines: Vec<&str> = contents.lines().collect();
+    assert_eq!(
+        lines.len(),
+        1,
+        "Command should run exactly once: {:?}",
+        lines
+    );
+
+    let mut files: Vec<&str> = lines[0].split_whitespace().collect();
+    files.sort_unstable();
+    assert_eq!(files, ["a.txt", "b.txt", "c.txt"]);
+}
+
+/// Test that --json prints one parseable JSON object per event on stdout
+#[cfg(target_os = "linux")]
+#[test]
+fn test_json_output_emits_event_lines() {
+    use std::io::Read;
+
+    let temp_dir = common::setup_test_dir();
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("--json")
+        .arg("--on-change")
+        .arg("true")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+    common::create_test_file(&temp_dir, "new.txt", "content");
+    thread::sleep(common::EVENT_DETECTION_TIME);
+
+    child.kill().expect("Failed to kill vibewatch");
+    let mut stdout = String::new();
+    child
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_string(&mut stdout)
+        .unwrap();
+
+    let lines: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("every stdout line should be JSON"))
+        .collect();
+
+    // Debouncing may coalesce create + modify, so only the path is checked
+    let event = lines
+        .iter()
+        .find(|line| line["type"] == "event" && line["relative_path"] == "new.txt")
+        .expect("event line for new.txt");
+    assert!(event["event_type"].is_string());
+    assert!(
+        event["absolute_path"]
+            .as_str()
+            .unwrap()
+            .ends_with("/new.txt")
+    );
+
+    let command = lines
+        .iter()
+        .find(|line| line["type"] == "command")
+        .expect("command line");
+    assert_eq!(command["command"], "true");
+    assert_eq!(command["exit_code"], 0);
+}
+
+/// Test that --wait-on-exit lets a command started just before Ctrl+C finish
+#[cfg(target_os = "linux")]
+#[test]
+fn test_wait_on_exit_lets_running_command_finish() {
+    let temp_dir = common::setup_test_dir();
+    let markers_dir = common::setup_test_dir();
+    let marker = markers_dir.child("done.txt");
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("--wait-on-exit=10")
+        .arg("--shell")
+        .arg("sh -c")
+        .arg("--on-change")
+        .arg(format!("sleep 1; touch {}", marker.path().display()))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+    common::create_test_file(&temp_dir, "trigger.txt", "content");
+    thread::sleep(common::COMMAND_EXECUTION_TIME);
+
+    // Interrupt while the command is still sleeping
+    let status = StdCommand::new("kill")
+        .arg("-INT")
+        .arg(child.id().to_string())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let exit = child.wait().expect("vibewatch should exit after Ctrl+C");
+    assert!(exit.success(), "vibewatch should exit cleanly: {:?}", exit);
+    assert!(
+        marker.path().exists(),
+        "Command running at shutdown should have completed"
+    );
+}
+
+/// Test that --quiet hides the banner and event lines but still runs commands
+#[test]
+fn test_quiet_suppresses_banner_and_event_lines() {
+    let temp_dir = common::setup_test_dir();
+    let markers_dir = common::setup_test_dir();
+    let marker = markers_dir.child("ran.txt");
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("--quiet")
+        .arg("--on-change")
+        .arg(common::touch_command(&marker.path().display().to_string()))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+    common::create_test_file(&temp_dir, "quiet.txt", "content");
+    let ran = common::wait_for_file(marker.path(), common::MARKER_FILE_POLL_TIMEOUT);
+
+    child.kill().expect("Failed to kill vibewatch");
+    let output = child.wait_with_output().expect("Failed to read output");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(ran, "Commands should still run with --quiet");
+    assert!(!stdout.contains("Watching for file changes"), "{}", stdout);
+    assert!(!stdout.contains("quiet.txt"), "{}", stdout);
+}
+
+/// Test that --name prefixes both the banner and file change lines
+#[test]
+fn test_name_prefixes_banner_and_change_lines() {
+    let temp_dir = common::setup_test_dir();
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("--name")
+        .arg("api")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+    common::create_test_file(&temp_dir, "named.txt", "content");
+    thread::sleep(common::EVENT_DETECTION_TIME);
+
+    child.kill().expect("Failed to kill vibewatch");
+    let output = child.wait_with_output().expect("Failed to read output");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(
+        stdout.contains("[api] 🚀 Watching for file changes"),
+        "{}",
+        stdout
+    );
+    let change = stdout
+        .lines()
+        .find(|line| line.ends_with(" named.txt"))
+        .unwrap_or_else(|| panic!("No change line in {}", stdout));
+    assert!(change.starts_with("[api] "), "{}", change);
+}
+
+/// Test that every repeated --on-modify command runs for a modify event
+#[test]
+fn test_multiple_on_modify_commands_all_run() {
+    let temp_dir = common::setup_test_dir();
+    let markers_dir = common::setup_test_dir();
+    let first = markers_dir.child("first.txt");
+    let second = markers_dir.child("second.txt");
+    common::create_test_file(&temp_dir, "existing.txt", "initial");
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("--on-modify")
+        .arg(common::touch_command(&first.path().display().to_string()))
+        .arg("--on-modify")
+        .arg(common::touch_command(&second.path().display().to_string()))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+    common::modify_test_file(&temp_dir, "existing.txt", "modified");
+
+    let first_ran = common::wait_for_file(first.path(), common::MARKER_FILE_POLL_TIMEOUT);
+    let second_ran = common::wait_for_file(second.path(), common::MARKER_FILE_POLL_TIMEOUT);
+
+    child.kill().expect("Failed to kill vibewatch");
+
+    assert!(first_ran, "First --on-modify command should run");
+    assert!(second_ran, "Second --on-modify command should run");
+}
+
+/// Test that --no-recursive ignores files in subdirectories
+#[test]
+fn test_no_recursive_ignores_subdirectories() {
+    let temp_dir = common::setup_test_dir();
+    let markers_dir = common::setup_test_dir();
+    temp_dir.child("sub").create_dir_all().unwrap();
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("--no-recursive")
+        .arg("--on-change")
+        .arg(common::touch_command(&format!(
+            "{}/{{file_name}}.ran",
+            markers_dir.path().display()
+        )))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+    common::create_test_file(&temp_dir, "sub/nested.txt", "content");
+    common::create_test_file(&temp_dir, "top.txt", "content");
+
+    let top_ran = common::wait_for_file(
+        &markers_dir.path().join("top.txt.ran"),
+        common::MARKER_FILE_POLL_TIMEOUT,
+    );
+    thread::sleep(common::COMMAND_EXECUTION_TIME);
+
+    child.kill().expect("Failed to kill vibewatch");
+
+    assert!(top_ran, "Top-level file should trigger the command");
+    assert!(
+        !markers_dir.path().join("nested.txt.ran").exists(),
+        "File in a subdirectory should not trigger the command"
+    );
+}
+
+/// Test that --files-only ignores new directories but not new files
+#[test]
+fn test_files_only_ignores_new_directories() {
+    let temp_dir = common::setup_test_dir();
+    let markers_dir = common::setup_test_dir();
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("--files-only")
+        .arg("--on-change")
+        .arg(common::touch_command(&format!(
+            "{}/{{file_name}}.ran",
+            markers_dir.path().display()
+        )))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+    temp_dir.child("newdir").create_dir_all().unwrap();
+    common::create_test_file(&temp_dir, "new.txt", "content");
+
+    let file_ran = common::wait_for_file(
+        &markers_dir.path().join("new.txt.ran"),
+        common::MARKER_FILE_POLL_TIMEOUT,
+    );
+    thread::sleep(common::COMMAND_EXECUTION_TIME);
+
+    child.kill().expect("Failed to kill vibewatch");
+
+    assert!(file_ran, "A new file should trigger the command");
+    assert!(
+        !markers_dir.path().join("newdir.ran").exists(),
+        "A new directory should not trigger the command"
+    );
+}
+
+/// Test that the --poll backend reports create, modify and delete events
+#[test]
+fn test_poll_detects_file_events() {
+    let temp_dir = common::setup_test_dir();
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("--poll")
+        .arg("--poll-interval")
+        .arg("100")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+    common::create_test_file(&temp_dir, "polled.txt", "content");
+    thread::sleep(common::EVENT_DETECTION_TIME);
+    // Modification times can have one-second granularity
+    thread::sleep(Duration::from_secs(1));
+    common::modify_test_file(&temp_dir, "polled.txt", "changed content");
+    thread::sleep(common::EVENT_DETECTION_TIME);
+    common::delete_test_file(&temp_dir, "polled.txt");
+    thread::sleep(common::EVENT_DETECTION_TIME);
+
+    child.kill().expect("Failed to kill vibewatch");
+    let output = child.wait_with_output().expect("Failed to read output");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(stdout.contains("[CREATED] polled.txt"), "{}", stdout);
+    assert!(stdout.contains("[MODIFIED] polled.txt"), "{}", stdout);
+    assert!(stdout.contains("[DELETED] polled.txt"), "{}", stdout);
+}
+
+/// Test that --export-env passes the event type to commands
+#[cfg(unix)]
+#[test]
+fn test_export_env_sets_event_variables() {
+    let temp_dir = common::setup_test_dir();
+    let markers_dir = common::setup_test_dir();
+    let marker = markers_dir.child("event.txt");
+    common::create_test_file(&temp_dir, "existing.txt", "initial");
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("--export-env")
+        .arg("--shell")
+        .arg("sh -c")
+        .arg("--on-modify")
+        .arg(format!(
+            "echo $VIBEWATCH_EVENT_TYPE > '{}'",
+            marker.path().display()
+        ))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+    common::modify_test_file(&temp_dir, "existing.txt", "modified");
+
+    let found = common::wait_for_file(marker.path(), common::MARKER_FILE_POLL_TIMEOUT);
+    thread::sleep(common::COMMAND_EXECUTION_TIME);
+
+    child.kill().expect("Failed to kill vibewatch");
+
+    assert!(found, "Command should run for the modify event");
+    let contents = std::fs::read_to_string(marker.path()).unwrap();
+    assert_eq!(contents.trim(), "modify");
+}
+
+/// Test that SIGHUP re-reads the config file and applies a new include pattern
+#[cfg(unix)]
+#[test]
+fn test_sighup_reloads_config_patterns() {
+    let temp_dir = common::setup_test_dir();
+    let config_dir = common::setup_test_dir();
+    let markers_dir = common::setup_test_dir();
+    let config = config_dir.child("vibewatch.toml");
+    let write_config = |include: &str| {
+        config
+            .write_str(&format!(
+                "directory = '{}'\ninclude = ['{}']\non_change = \"touch {}/{{file_name}}.ran\"\n",
+                temp_dir.path().display(),
+                include,
+                markers_dir.path().display()
+            ))
+            .unwrap();
+    };
+    write_config("*.txt");
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg("--config")
+        .arg(config.path())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+    common::create_test_file(&temp_dir, "before.md", "content");
+    thread::sleep(common::EVENT_DETECTION_TIME);
+
+    write_config("*.md");
+    let status = StdCommand::new("kill")
+        .arg("-HUP")
+        .arg(child.id().to_string())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    thread::sleep(common::COMMAND_EXECUTION_TIME);
+
+    common::create_test_file(&temp_dir, "after.md", "content");
+    let reloaded = common::wait_for_file(
+        &markers_dir.path().join("after.md.ran"),
+        common::MARKER_FILE_POLL_TIMEOUT,
+    );
+
+    let still_running = child.try_wait().unwrap().is_none();
+    child.kill().expect("Failed to kill vibewatch");
+
+    assert!(still_running, "SIGHUP should not stop vibewatch");
+    assert!(
+        !markers_dir.path().join("before.md.ran").exists(),
+        "*.md files should be ignored before the reload"
+    );
+    assert!(reloaded, "The reloaded include pattern should take effect");
+}
+
+/// Test that --exclude-writes-for stops a command's own writes from re-triggering it
+#[cfg(unix)]
+#[test]
+fn test_exclude_writes_for_breaks_feedback_loop() {
+    let temp_dir = common::setup_test_dir();
+    let markers_dir = common::setup_test_dir();
+    let runs = markers_dir.child("runs.txt");
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("--exclude-writes-for")
+        .arg("500")
+        .arg("--shell")
+        .arg("sh -c")
+        .arg("--on-change")
+        .arg(format!(
+            "echo run >> '{}'; echo generated > '{}/generated.txt'",
+            runs.path().display(),
+            temp_dir.path().display()
+        ))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+    common::create_test_file(&temp_dir, "trigger.txt", "content");
+    let ran = common::wait_for_file(runs.path(), common::MARKER_FILE_POLL_TIMEOUT);
+    // Without the window, each run's write would trigger the next one
+    thread::sleep(common::EVENT_DETECTION_TIME);
+
+    child.kill().expect("Failed to kill vibewatch");
+
+    assert!(ran, "The command should run for the trigger file");
+    let contents = std::fs::read_to_string(runs.path()).unwrap();
+    assert_eq!(contents.lines().count(), 1, "runs: {:?}", contents);
+}
+
+/// Test that --once exits on its own after the first file change and its command
+#[test]
+fn test_once_exits_after_first_event() {
+    let temp_dir = common::setup_test_dir();
+    let markers_dir = common::setup_test_dir();
+    let marker = markers_dir.child("ran.txt");
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("--once")
+        .arg("--on-change")
+        .arg(common::touch_command(&marker.path().display().to_string()))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+    common::create_test_file(&temp_dir, "first.txt", "content");
+
+    let start = std::time::Instant::now();
+    let mut exit = None;
+    while start.elapsed() < common::MARKER_FILE_POLL_TIMEOUT {
+        exit = child.try_wait().unwrap();
+        if exit.is_some() {
+            break;
+        }
+        thread::sleep(common::POLL_INTERVAL);
+    }
+    if exit.is_none() {
+        child.kill().expect("Failed to kill vibewatch");
+    }
+
+    let exit = exit.expect("vibewatch should exit on its own with --once");
+    assert!(exit.success(), "vibewatch should exit cleanly: {:?}", exit);
+    assert!(
+        marker.path().exists(),
+        "The command should finish before vibewatch exits"
+    );
+}
+
+/// Test that --once exits after the first change even when the config has several watchers
+#[test]
+fn test_once_exits_with_several_config_watchers() {
+    let first_dir = common::setup_test_dir();
+    let second_dir = common::setup_test_dir();
+    let config_dir = common::setup_test_dir();
+    let config = config_dir.child("vibewatch.toml");
+    config
+        .write_str(&format!(
+            "[[watch]]\ndirectory = '{}'\n\n[[watch]]\ndirectory = '{}'\n",
+            first_dir.path().display(),
+            second_dir.path().display()
+        ))
+        .unwrap();
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg("--config")
+        .arg(config.path())
+        .arg("--once")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+    common::create_test_file(&first_dir, "first.txt", "content");
+
+    let start = std::time::Instant::now();
+    let mut exit = None;
+    while start.elapsed() < common::MARKER_FILE_POLL_TIMEOUT {
+        exit = child.try_wait().unwrap();
+        if exit.is_some() {
+            break;
+        }
+        thread::sleep(common::POLL_INTERVAL);
+    }
+    if exit.is_none() {
+        child.kill().expect("Failed to kill vibewatch");
+    }
+
+    let exit = exit.expect("vibewatch should exit once any watcher saw a change");
+    assert!(exit.success(), "vibewatch should exit cleanly: {:?}", exit);
+}
+
+/// Test that --max-output-lines prints the first lines of a command's output and a notice
+#[cfg(unix)]
+#[test]
+fn test_max_output_lines_truncates_command_output() {
+    let temp_dir = common::setup_test_dir();
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("--once")
+        .arg("--on-change")
+        .arg("seq 1000")
+        .arg("--max-output-lines")
+        .arg("10")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+    common::create_test_file(&temp_dir, "noisy.txt", "content");
+
+    let start = std::time::Instant::now();
+    while start.elapsed() < common::MARKER_FILE_POLL_TIMEOUT {
+        if child.try_wait().unwrap().is_some() {
+            break;
+        }
+        thread::sleep(common::POLL_INTERVAL);
+    }
+    let _ = child.kill();
+    let output = child.wait_with_output().expect("Failed to read output");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    let numbers: Vec<&str> = stdout
+        .lines()
+        .filter(|line| line.parse::<u32>().is_ok())
+        .collect();
+    let expected: Vec<String> = (1..=10).map(|n| n.to_string()).collect();
+    assert_eq!(numbers, expected, "stdout: {}", stdout);
+    assert!(
+        stdout.contains("... (990 more lines)"),
+        "Expected a truncation notice, stdout: {}",
+        stdout
+    );
+}
+
+/// Test that --no-canonicalize shows paths below a symlinked root as given
+#[cfg(unix)]
+#[test]
+fn test_no_canonicalize_keeps_symlinked_root() {
+    let temp_dir = common::setup_test_dir();
+    let real_dir = temp_dir.path().canonicalize().unwrap().join("real");
+    std::fs::create_dir(&real_dir).unwrap();
+    let link = temp_dir.path().join("link");
+    std::os::unix::fs::symlink(&real_dir, &link).unwrap();
+
+    let absolute_path = |name: &str, extra_args: &[&str]| -> String {
+        let mut child = StdCommand::cargo_bin("vibewatch")
+            .unwrap()
+            .arg(&link)
+            .arg("--once")
+            .arg("--on-change")
+            .arg("echo ABSOLUTE={absolute_path}")
+            .args(extra_args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Failed to start vibewatch");
+
+        thread::sleep(common::WATCHER_STARTUP_TIME);
+        std::fs::write(real_dir.join(name), "content").unwrap();
+
+        let start = std::time::Instant::now();
+        while start.elapsed() < common::MARKER_FILE_POLL_TIMEOUT {
+            if child.try_wait().unwrap().is_some() {
+                break;
+            }
+            thread::sleep(common::POLL_INTERVAL);
+        }
+        let _ = child.kill();
+        let output = child.wait_with_output().expect("Failed to read output");
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        stdout
+            .lines()
+            .find_map(|line| line.strip_prefix("ABSOLUTE="))
+            .unwrap_or_else(|| panic!("Expected the command output, stdout: {}", stdout))
+            .to_string()
+    };
+
+    assert_eq!(
+        absolute_path("resolved.txt", &[]),
+        real_dir.join("resolved.txt").display().to_string()
+    );
+    assert_eq!(
+        absolute_path("given.txt", &["--no-canonicalize"]),
+        link.join("given.txt").display().to_string()
+    );
+}
+
+/// Test that a missing program is warned about once, however many events run it
+#[test]
+fn test_missing_program_is_warned_about_once() {
+    let temp_dir = common::setup_test_dir();
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("--on-change")
+        .arg("nonexistent_command_xyz {file_path}")
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+    for name in ["one.txt", "two.txt", "three.txt"] {
+        common::create_test_file(&temp_dir, name, "content");
+        thread::sleep(common::COMMAND_EXECUTION_TIME);
+    }
+
+    let _ = child.kill();
+    let output = child.wait_with_output().expect("Failed to read output");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(
+        stderr
+            .matches("Command not found: nonexistent_command_xyz")
+            .count(),
+        1,
+        "stderr: {}",
+        stderr
+    );
+}
+
+/// Test that commands start no sooner than --exec-delay after the change
+#[test]
+fn test_exec_delay_postpones_command() {
+    let temp_dir = common::setup_test_dir();
+    let markers_dir = common::setup_test_dir();
+    let marker_file = markers_dir.child("delayed.txt");
+    let exec_delay = Duration::from_millis(1500);
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("--on-change")
+        .arg(common::touch_command(
+            &marker_file.path().display().to_string(),
+        ))
+        .arg("--exec-delay")
+        .arg(exec_delay.as_millis().to_string())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+    let changed = std::time::Instant::now();
+    common::create_test_file(&temp_dir, "trigger.txt", "content");
+
+    let marker_exists = common::wait_for_file(
+        marker_file.path(),
+        exec_delay + common::MARKER_FILE_POLL_TIMEOUT,
+    );
+    let elapsed = changed.elapsed();
+    let _ = child.kill();
+    let _ = child.wait();
+
+    assert!(marker_exists, "The delayed command should still run");
+    assert!(
+        elapsed >= exec_delay,
+        "The command ran {}ms after the change, before the {}ms delay",
+        elapsed.as_millis(),
+        exec_delay.as_millis()
+    );
+}
+
+/// Test that the status file is written at startup and counts events as they come
+#[test]
+fn test_status_file_counts_events() {
+    let temp_dir = common::setup_test_dir();
+    let status_dir = common::setup_test_dir();
+    let status_path = status_dir.child("status.json").path().to_path_buf();
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("--status-file")
+        .arg(&status_path)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    let read_events = || -> Option<u64> {
+        let contents = std::fs::read_to_string(&status_path).ok()?;
+        let status: serde_json::Value =
+            serde_json::from_str(&contents).expect("status file should be complete JSON");
+        status["events"].as_u64()
+    };
+    let wait_for_events = |more_than: u64| -> Option<u64> {
+        let start = std::time::Instant::now();
+        while start.elapsed() < common::MARKER_FILE_POLL_TIMEOUT {
+            if let Some(events) = read_events().filter(|events| *events > more_than) {
+                return Some(events);
+            }
+            thread::sleep(common::POLL_INTERVAL);
+        }
+        None
+    };
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+    let initial = read_events();
+
+    common::create_test_file(&temp_dir, "first.txt", "content");
+    let first = wait_for_events(0);
+    common::create_test_file(&temp_dir, "second.txt", "content");
+    let second = first.and_then(wait_for_events);
+
+    let _ = child.kill();
+    let _ = child.wait();
+
+    assert_eq!(
+        initial,
+        Some(0),
+        "The status file should be written at startup"
+    );
+    let first = first.expect("The status file should count the first event");
+    let second = second.expect("The status file should count the second event");
+    assert!(second > first);
+}
+
+/// Test that a file path watches only that file, through modify and delete
+#[test]
+fn test_single_file_watches_only_that_file() {
+    let temp_dir = common::setup_test_dir();
+    let markers_dir = common::setup_test_dir();
+    common::create_test_files(
+        &temp_dir,
+        &[("config.toml", "a = 1"), ("other.toml", "b = 1")],
+    );
+    let marker = |name: &str| markers_dir.child(name).path().to_path_buf();
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.child("config.toml").path())
+        .arg("--on-change")
+        .arg(common::touch_command(
+            &marker("{event_type}-{relative_path}").display().to_string(),
+        ))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+    common::modify_test_file(&temp_dir, "other.toml", "b = 2");
+    common::modify_test_file(&temp_dir, "config.toml", "a = 2");
+    let modified = common::wait_for_file(
+        &marker("modify-config.toml"),
+        common::MARKER_FILE_POLL_TIMEOUT,
+    );
+
+    common::delete_test_file(&temp_dir, "config.toml");
+    let deleted = common::wait_for_file(
+        &marker("delete-config.toml"),
+        common::MARKER_FILE_POLL_TIMEOUT,
+    );
+
+    child.kill().expect("Failed to kill vibewatch");
+
+    assert!(
+        modified,
+        "Modifying the watched file should run the command"
+    );
+    assert!(deleted, "Deleting the watched file should run the command");
+    assert!(
+        !marker("modify-other.toml").exists(),
+        "Other files in the directory should be ignored"
+    );
+}
+
+/// Test that --list-matched prints the filtered files and exits
+#[test]
+fn test_list_matched_prints_filtered_files() {
+    let temp_dir = common::setup_test_dir();
+    common::create_test_files(
+        &temp_dir,
+        &[
+            ("main.rs", "fn main() {}"),
+            ("README.md", "# readme"),
+            ("src/lib.rs", ""),
+            ("target/debug/build.rs", ""),
+        ],
+    );
+
+    let mut cmd = Command::cargo_bin("vibewatch").unwrap();
+    cmd.arg(temp_dir.path())
+        .args(["--include", "**/*.rs", "--exclude", "target/**"])
+        .arg("--list-matched")
+        .timeout(Duration::from_secs(5))
+        .assert()
+        .success()
+        .stdout(format!(
+            "main.rs\n{}\n",
+            std::path::Path::new("src").join("lib.rs").display()
+        ));
+}
+
+/// Test that --explain prints which rule decides whether a path is watched, then exits
+#[cfg(unix)]
+#[test]
+fn test_explain_prints_deciding_rule() {
+    let temp_dir = common::setup_test_dir();
+
+    for (path, reason) in [
+        (
+            "target/debug/build.rs",
+            "ignored (matches exclude pattern 'target/**')",
+        ),
+        ("main.rs", "watched (matches include pattern '**/*.rs')"),
+    ] {
+        let mut cmd = Command::cargo_bin("vibewatch").unwrap();
+        cmd.arg(temp_dir.path())
+            .args(["--include", "**/*.rs", "--exclude", "target/**"])
+            .args(["--explain", path])
+            .timeout(Duration::from_secs(5))
+            .assert()
+            .success()
+            .stdout(format!("{}: {}\n", path, reason));
+    }
+}
+
+/// Test that --run-on-start runs the command once without any file change
+#[test]
+fn test_run_on_start_runs_command_at_startup() {
+    let temp_dir = common::setup_test_dir();
+    let markers_dir = common::setup_test_dir();
+    let marker = markers_dir.child("started.txt");
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("--run-on-start")
+        .arg("--on-change")
+        .arg(common::touch_command(&marker.path().display().to_string()))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    let started = common::wait_for_file(marker.path(), common::MARKER_FILE_POLL_TIMEOUT);
+
+    child.kill().expect("Failed to kill vibewatch");
+
+    assert!(started, "Startup command should run without file changes");
+}
+
+/// Test that --fail-fast exits non-zero when a command fails
+#[test]
+fn test_fail_fast_exits_non_zero_on_command_failure() {
+    let temp_dir = common::setup_test_dir();
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("--fail-fast")
+        .arg("--on-change")
+        .arg("false")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+    common::create_test_file(&temp_dir, "trigger.txt", "content");
+
+    let mut exit = None;
+    let deadline = std::time::Instant::now() + common::MARKER_FILE_POLL_TIMEOUT;
+    while exit.is_none() && std::time::Instant::now() < deadline {
+        exit = child.try_wait().unwrap();
+        thread::sleep(common::POLL_INTERVAL);
+    }
+
+    if exit.is_none() {
+        child.kill().expect("Failed to kill vibewatch");
+    }
+
+    let exit = exit.expect("vibewatch should exit after a failed command");
+    assert!(
+        !exit.success(),
+        "Exit status should be non-zero: {:?}",
+        exit
+    );
+}
+
+/// Test that deleting the watched directory stops vibewatch with a clear error
+#[test]
+fn test_deleted_watch_root_exits_with_error() {
+    let temp_dir = common::setup_test_dir();
+    let watched = temp_dir.child("watched");
+    watched.create_dir_all().unwrap();
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(watched.path())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+    std::fs::remove_dir_all(watched.path()).unwrap();
+
+    let mut exit = None;
+    let deadline = std::time::Instant::now() + common::MARKER_FILE_POLL_TIMEOUT;
+    while exit.is_none() && std::time::Instant::now() < deadline {
+        exit = child.try_wait().unwrap();
+        thread::sleep(common::POLL_INTERVAL);
+    }
+
+    if exit.is_none() {
+        child.kill().expect("Failed to kill vibewatch");
+    }
+
+    let output = child.wait_with_output().unwrap();
+    assert!(
+        exit.is_some(),
+        "vibewatch should exit once the watched directory is gone"
+    );
+    assert!(!output.status.success(), "Exit status should be non-zero");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Watched directory was deleted"),
+        "stderr should explain why vibewatch stopped: {}",
+        stderr
+    );
+}
+
+/// Test that --log-file records file events and command output
+#[test]
+fn test_log_file_records_events_and_command_output() {
+    let temp_dir = common::setup_test_dir();
+    let logs_dir = common::setup_test_dir();
+    let log_file = logs_dir.child("vibewatch.log");
+    let markers_dir = common::setup_test_dir();
+    let marker = markers_dir.child("ran.txt");
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("--log-file")
+        .arg(log_file.path())
+        .arg("--on-change")
+        .arg("echo hello-from-command")
+        .arg("--on-change")
+        .arg(common::touch_command(&marker.path().display().to_string()))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+    common::create_test_file(&temp_dir, "audited.txt", "content");
+
+    let ran = common::wait_for_file(marker.path(), common::MARKER_FILE_POLL_TIMEOUT);
+    thread::sleep(common::COMMAND_EXECUTION_TIME);
+    child.kill().expect("Failed to kill vibewatch");
+    assert!(ran, "The command should run for the new file");
+
+    let log = std::fs::read_to_string(log_file.path()).unwrap();
+    assert!(
+        log.lines().any(|line| line.contains("vibewatch::record")
+            && (line.ends_with("] [CREATED] audited.txt")
+                || line.ends_with("] [MODIFIED] audited.txt"))),
+        "The log should record the event: {}",
+        log
+    );
+    assert!(
+        log.contains("stdout: hello-from-command"),
+        "The log should record command output: {}",
+        log
+    );
+    assert!(
+        log.contains("File watcher started successfully"),
+        "The log should also get regular log lines: {}",
+        log
+    );
+}
+
+/// Test that an unusable --log-file path fails at startup
+#[test]
+fn test_log_file_that_cannot_be_opened_fails_at_startup() {
+    let temp_dir = common::setup_test_dir();
+
+    Command::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("--log-file")
+        .arg(temp_dir.path().join("missing").join("vibewatch.log"))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Failed to open log file"));
+}
+
+/// Test that --exclude-dir keeps a directory's events from ever arriving,
+/// while its siblings are still watched
+#[test]
+fn test_exclude_dir_prunes_directory_from_watching() {
+    let temp_dir = common::setup_test_dir();
+    temp_dir.child("node_modules/dep").create_dir_all().unwrap();
+    temp_dir.child("src").create_dir_all().unwrap();
+    let markers_dir = common::setup_test_dir();
+    let marker = markers_dir.child("src-changed.txt");
+
+    // Trace logging shows every raw event the OS delivers
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("-vvv")
+        .arg("--exclude-dir")
+        .arg("node_modules")
+        .arg("--include")
+        .arg("src/**")
+        .arg("--on-change")
+        .arg(common::touch_command(&marker.path().display().to_string()))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+    common::create_test_file(&temp_dir, "node_modules/dep/index.js", "ignored");
+    thread::sleep(common::EVENT_DETECTION_TIME);
+    common::create_test_file(&temp_dir, "src/main.rs", "watched");
+
+    let fired = common::wait_for_file(marker.path(), common::MARKER_FILE_POLL_TIMEOUT);
+    child.kill().expect("Failed to kill vibewatch");
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(fired, "Changes in sibling directories should still fire");
+    assert!(
+        stderr.contains("Raw event") && !stderr.contains("index.js"),
+        "No event from the excluded directory should arrive: {}",
+        stderr
+    );
+}
+
+#[test]
+fn test_heartbeat_is_printed_while_idle() {
+    let temp_dir = common::setup_test_dir();
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("--heartbeat")
+        .arg("1")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    // Nothing changes, so only the heartbeat has anything to say
+    thread::sleep(common::WATCHER_STARTUP_TIME + Duration::from_millis(2500));
+    child.kill().expect("Failed to kill vibewatch");
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(
+        stdout.contains("Still watching (0 events so far)"),
+        "An idle watcher should print a heartbeat: {}",
+        stdout
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_stdin_control_runs_command_then_quits() {
+    let temp_dir = common::setup_test_dir();
+    let markers_dir = common::setup_test_dir();
+    let log = markers_dir.child("runs.log");
+
+    Command::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("--stdin-control")
+        .arg("--wait-on-exit")
+        .arg("--shell")
+        .arg("sh -c")
+        .arg("--on-change")
+        .arg(format!("echo ran >> {}", log.path().display()))
+        .write_stdin("run\nquit\n")
+        .timeout(Duration::from_secs(10))
+        .assert()
+        .success();
+
+    log.assert("ran\n");
+}
+
+/// Test that stdin commands reach every watcher of a config with several entries
+#[cfg(unix)]
+#[test]
+fn test_stdin_control_reaches_every_config_watcher() {
+    let first_dir = common::setup_test_dir();
+    let second_dir = common::setup_test_dir();
+    let config_dir = common::setup_test_dir();
+    let markers_dir = common::setup_test_dir();
+    let log = markers_dir.child("runs.log");
+    let config = config_dir.child("vibewatch.toml");
+    config
+        .write_str(&format!(
+            "on_change = \"echo ran >> {}\"\n\n[[watch]]\ndirectory = '{}'\n\n[[watch]]\ndirectory = '{}'\n",
+            log.path().display(),
+            first_dir.path().display(),
+            second_dir.path().display()
+        ))
+        .unwrap();
+
+    Command::cargo_bin("vibewatch")
+        .unwrap()
+        .arg("--config")
+        .arg(config.path())
+        .arg("--stdin-control")
+        .arg("--wait-on-exit")
+        .arg("--shell")
+        .arg("sh -c")
+        .write_stdin("run\nquit\n")
+        .timeout(Duration::from_secs(10))
+        .assert()
+        .success();
+
+    log.assert("ran\nran\n");
+}
+
+#[test]
+fn test_format_prints_custom_change_line() {
+    let temp_dir = common::setup_test_dir();
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("--format")
+        .arg("changed: {relative_path} ({file_ext})")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+    common::create_test_file(&temp_dir, "notes.txt", "hello");
+    thread::sleep(common::EVENT_DETECTION_TIME);
+
+    child.kill().expect("Failed to kill vibewatch");
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(
+        stdout
+            .lines()
+            .any(|line| line == "changed: notes.txt (txt)"),
+        "The change should be printed with the custom format: {}",
+        stdout
+    );
+}
+
+/// Test that SIGTERM shuts down like Ctrl+C, waiting for commands with --wait-on-exit
+#[cfg(unix)]
+#[test]
+fn test_sigterm_shuts_down_gracefully() {
+    let temp_dir = common::setup_test_dir();
+    let markers_dir = common::setup_test_dir();
+    let marker = markers_dir.child("finished.txt");
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("--shell")
+        .arg("sh -c")
+        .arg("--run-on-start")
+        .arg("--on-change")
+        .arg(format!("sleep 1; touch {}", marker.path().display()))
+        .arg("--wait-on-exit")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+    let status = StdCommand::new("kill")
+        .arg("-TERM")
+        .arg(child.id().to_string())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let mut exit_status = None;
+    for _ in 0..100 {
+        exit_status = child.try_wait().unwrap();
+        if exit_status.is_some() {
+            break;
+        }
+        thread::sleep(common::POLL_INTERVAL);
+    }
+    let Some(exit_status) = exit_status else {
+        child.kill().expect("Failed to kill vibewatch");
+        panic!("vibewatch should exit after SIGTERM");
+    };
+    let output = child.wait_with_output().unwrap();
+
+    assert_eq!(exit_status.code(), Some(0), "SIGTERM should exit cleanly");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Shutting down vibewatch"));
+    assert!(
+        marker.path().exists(),
+        "The running command should finish before exiting"
+    );
+}
+
+/// Test that --restart stops the running process and starts a new one on the next change
+#[cfg(unix)]
+#[test]
+fn test_restart_replaces_running_process() {
+    let temp_dir = common::setup_test_dir();
+    let markers_dir = common::setup_test_dir();
+    let pids = markers_dir.child("pids.log");
+    let read_pids = || -> Vec<String> {
+        std::fs::read_to_string(pids.path())
+            .unwrap_or_default()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    };
+    let wait_for_pids = |count: usize| {
+        for _ in 0..50 {
+            if read_pids().len() >= count {
+                return true;
+            }
+            thread::sleep(common::POLL_INTERVAL);
+        }
+        false
+    };
+    let alive = |pid: &str| {
+        StdCommand::new("kill")
+            .args(["-0", pid])
+            .stderr(Stdio::null())
+            .status()
+            .unwrap()
+            .success()
+    };
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .arg("--restart")
+        .arg("--shell")
+        .arg("sh -c")
+        .arg("--on-change")
+        .arg(format!(
+            "echo $$ >> {}; exec sleep 30",
+            pids.path().display()
+        ))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+    common::create_test_file(&temp_dir, "first.txt", "one");
+    let started = wait_for_pids(1);
+    thread::sleep(common::EVENT_DETECTION_TIME);
+    common::create_test_file(&temp_dir, "second.txt", "two");
+    let restarted = wait_for_pids(2);
+    let pids = read_pids();
+
+    let old_stopped = started && !alive(&pids[0]);
+    let new_running = restarted && alive(&pids[pids.len() - 1]);
+    child.kill().expect("Failed to kill vibewatch");
+    child.wait().unwrap();
+    // The server outlives the killed watcher in its own process group
+    if let Some(pid) = pids.last() {
+        let _ = StdCommand::new("kill").arg(pid).status();
+    }
+
+    assert!(started, "The first change should start the process");
+    assert!(restarted, "The second change should start a new process");
+    assert!(old_stopped, "The old process should be stopped");
+    assert!(new_running, "The new process should keep running");
+}
+
+/// Test that a file name that isn't valid UTF-8 is reported with a warning
+#[cfg(unix)]
+#[test]
+fn test_non_utf8_path_is_warned_about() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let temp_dir = common::setup_test_dir();
+
+    let mut child = StdCommand::cargo_bin("vibewatch")
+        .unwrap()
+        .arg(temp_dir.path())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start vibewatch");
+
+    thread::sleep(common::WATCHER_STARTUP_TIME);
+    std::fs::write(temp_dir.path().join(OsStr::from_bytes(b"caf\xe9.txt")), "x").unwrap();
+    thread::sleep(common::EVENT_DETECTION_TIME);
+    child.kill().expect("Failed to kill vibewatch");
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(
+        stderr.contains("Path isn't valid UTF-8"),
+        "A non-UTF-8 file name should be warned about: {}",
+        stderr
+    );
+}